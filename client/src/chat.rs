@@ -7,8 +7,8 @@ use crate::ws;
 use crate::AppState;
 use chrono::Utc;
 use dioxus::prelude::*;
-use shared::domain::event::JoinRequest;
 use shared::domain::event::{ClientEvent, ServerEvent};
+use shared::domain::event::{Hello, JoinRequest};
 
 #[derive(PartialEq, Props)]
 pub struct ChatPageProps {
@@ -22,8 +22,8 @@ pub fn ChatPage(cx: Scope<ChatPageProps>) -> Element {
     cx.render(rsx!(
         div { class: "flex flex-wrap items-center justify-end gap-2 p-8",
             div { class: "max-w-sm mx-auto bg-base-300 shadow-2xl p-8",
-                if let Some(access_toked) = state.read().access_toked.clone() {
-                    rsx!( Chat { room_id: cx.props.room_id.clone(), access_toked: access_toked } )
+                if let Some(csrf_token) = state.read().csrf_token.clone() {
+                    rsx!( Chat { room_id: cx.props.room_id.clone(), csrf_token: csrf_token } )
                 } else {
                     rsx!( SignInPage {} )
                 }
@@ -35,17 +35,10 @@ pub fn ChatPage(cx: Scope<ChatPageProps>) -> Element {
 #[derive(PartialEq, Props)]
 pub struct ChatProps {
     pub room_id: String,
-    pub access_toked: String,
+    pub csrf_token: String,
 }
 #[allow(non_snake_case)]
 pub fn Chat(cx: Scope<ChatProps>) -> Element {
-    let (sender, receiver) = cx.use_hook(|| {
-        ws::connect(&format!(
-            "ws://localhost:8000/api/ws/{}?token={}",
-            cx.props.room_id, cx.props.access_toked
-        ))
-    });
-
     // if sender.is_none() {
     //     let nav = use_navigator(cx);
     //     nav.push(Route::PageNotFound { route: vec![] });
@@ -55,22 +48,37 @@ pub fn Chat(cx: Scope<ChatProps>) -> Element {
     let chat = use_shared_state::<ChatState>(cx)?;
 
     let sender = use_coroutine(cx, |rx: UnboundedReceiver<ClientEvent>| {
-        ws::write(rx, sender.take())
+        let room_id = cx.props.room_id.clone();
+        let csrf_token = cx.props.csrf_token.clone();
+        to_owned![chat];
+        async move {
+            let (sender, receiver) = match ws::ticket(&csrf_token).await {
+                Ok(ticket) => ws::connect(&format!(
+                    "ws://localhost:8000/api/ws/{}?ticket={}",
+                    room_id, ticket
+                )),
+                Err(e) => {
+                    log::error!("failed to get socket ticket:{}", e);
+                    (None, None)
+                }
+            };
+            // Events sent before the socket opened wait in `rx`.
+            futures::join!(
+                ws::write(rx, sender),
+                ws::read::<ServerEvent>(receiver, move |ev| {
+                    chat.write().apply(ev);
+                })
+            );
+        }
     });
 
     cx.use_hook(|| {
+        sender.send(ClientEvent::Hello(Hello::default()));
         sender.send(ClientEvent::Join(JoinRequest {
             join_at: Utc::now(),
         }))
     });
 
-    let _sync: &Coroutine<()> = use_coroutine(cx, |_| {
-        to_owned![chat];
-        ws::read::<ServerEvent>(receiver.take(), move |ev| {
-            chat.write().apply(ev);
-        })
-    });
-
     cx.render(rsx!(
         Messages {}
         SendMessage {}
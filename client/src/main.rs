@@ -27,8 +27,8 @@ fn main() {
 
 #[derive(Debug, Default)]
 pub struct AppState {
-    pub access_toked: Option<String>,
-    pub refresh_toked: Option<String>,
+    /// Set once signed in, the tokens themselves are kept in HttpOnly cookies.
+    pub csrf_token: Option<String>,
 }
 
 fn app(cx: Scope) -> Element {
@@ -21,9 +21,7 @@ pub fn SignInPage(cx: Scope) -> Element {
 
         cx.spawn(async move {
             if let Ok(res) = login(email, password).await {
-                let mut state = state.write();
-                state.access_toked = Some(res.access_token);
-                state.refresh_toked = Some(res.refresh_token);
+                state.write().csrf_token = Some(res.csrf_token);
             }
         })
     };
@@ -57,14 +55,14 @@ struct LoginRequest {
 
 #[derive(Deserialize)]
 pub struct LoginResponse {
-    pub access_token: String,
-    pub refresh_token: String,
+    pub csrf_token: String,
 }
 
 async fn login(email: String, password: String) -> reqwest::Result<LoginResponse> {
     reqwest::Client::new()
         .post("http://localhost:8080/api/login")
         .header(CONTENT_TYPE, "application/json")
+        .header("X-Auth-Mode", "cookie")
         .json(&LoginRequest { email, password })
         .send()
         .await?
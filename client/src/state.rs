@@ -1,6 +1,6 @@
 use crate::message::MessageProps;
 use shared::domain::event::{JoinResponse, ServerEvent, UserJoinResponse};
-use shared::domain::{Message, MessageContent, User, UserId};
+use shared::domain::{Message, MessageContent, Notification, User, UserId};
 use std::collections::HashMap;
 
 pub trait EventSourced<Ev: ?Sized> {
@@ -12,6 +12,7 @@ pub struct ChatState {
     pub user_id: UserId,
     pub users: HashMap<UserId, User>,
     pub messages: Vec<Message>,
+    pub notifications: Vec<Notification>,
 }
 
 impl ChatState {
@@ -57,13 +58,25 @@ impl EventSourced<UserJoinResponse> for ChatState {
     fn apply(&mut self, ev: UserJoinResponse) {}
 }
 
+impl EventSourced<Notification> for ChatState {
+    fn apply(&mut self, ev: Notification) {
+        self.notifications.push(ev)
+    }
+}
+
 impl EventSourced<ServerEvent> for ChatState {
     fn apply(&mut self, ev: ServerEvent) {
         match ev {
+            ServerEvent::Welcome(ev) => log::debug!("negotiated protocol:{:?}", ev),
             ServerEvent::ErrMessage(_) => todo!(),
             ServerEvent::Join(ev) => self.apply(ev),
             ServerEvent::ReceivedMessage(ev) => self.apply(ev),
             ServerEvent::UserJoin(ev) => self.apply(ev),
+            ServerEvent::Notification(ev) => self.apply(ev),
+            ServerEvent::SessionRevoked(ev) => log::info!("session revoked:{:?}", ev),
+            ServerEvent::CommandReply(ev) => log::info!("command reply:{:?}", ev),
+            ServerEvent::RoomUpdated(ev) => log::info!("room updated:{:?}", ev),
+            ServerEvent::RemovedFromRoom(ev) => log::info!("removed from room:{:?}", ev),
         }
     }
 }
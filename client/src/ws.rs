@@ -6,6 +6,25 @@ use futures::{
 };
 use gloo_net::websocket::{futures::WebSocket, Message};
 use serde::{Deserialize, Serialize};
+use shared::domain::event::Tolerant;
+
+#[derive(Deserialize)]
+struct TicketResponse {
+    ticket: String,
+}
+
+/// Single-use ticket to open a socket with, requested with the session cookies.
+pub async fn ticket(csrf_token: &str) -> reqwest::Result<String> {
+    let res = reqwest::Client::new()
+        .post("http://localhost:8080/api/ws/ticket")
+        .header("X-CSRF-Token", csrf_token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<TicketResponse>()
+        .await?;
+    Ok(res.ticket)
+}
 
 pub fn connect(
     url: &str,
@@ -32,8 +51,12 @@ pub async fn read<T: for<'a> Deserialize<'a>>(
     if let Some(mut receiver) = receiver {
         while let Some(Ok(Message::Text(s))) = receiver.next().await {
             log::debug!("received:{}", &s);
-            if let Ok(event) = serde_json::from_str::<T>(&s) {
-                handler(event)
+            match serde_json::from_str::<Tolerant<T>>(&s) {
+                Ok(Tolerant::Known(event)) => handler(event),
+                Ok(Tolerant::Unknown(event)) => {
+                    log::debug!("skipped unknown event:{:?}", event.name)
+                }
+                Err(e) => log::error!("failed parse:{}", e),
             }
         }
     }
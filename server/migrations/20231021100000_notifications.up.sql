@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS notifications (
+    notification_id UUID DEFAULT uuid_generate_v4()  PRIMARY KEY,
+    user_id         UUID NOT NULL REFERENCES users ON DELETE CASCADE ,
+    sender_id       UUID NOT NULL REFERENCES users ON DELETE CASCADE ,
+    room_id         UUID NOT NULL REFERENCES rooms ON DELETE CASCADE ,
+    message_id      UUID NOT NULL REFERENCES messages ON DELETE CASCADE ,
+    created_at      TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    read_at         TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS notifications_user_id_created_at_idx
+    ON notifications (user_id, created_at DESC);
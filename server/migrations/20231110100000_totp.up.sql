@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS user_totp (
+    user_id         UUID PRIMARY KEY REFERENCES users ON DELETE CASCADE,
+    -- Encrypted with the key from configuration.
+    secret          BYTEA NOT NULL,
+    confirmed_at    TIMESTAMP WITH TIME ZONE,
+    -- Codes are single-use: only codes of later steps are accepted.
+    last_used_step  BIGINT,
+    created_at      TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS recovery_codes (
+    user_id     UUID NOT NULL REFERENCES users ON DELETE CASCADE,
+    code_hash   TEXT NOT NULL,
+    used_at     TIMESTAMP WITH TIME ZONE,
+    PRIMARY KEY (user_id, code_hash)
+);
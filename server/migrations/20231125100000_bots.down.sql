@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS api_keys;
+
+ALTER TABLE messages DROP COLUMN kind;
+
+ALTER TABLE users DROP COLUMN is_bot;
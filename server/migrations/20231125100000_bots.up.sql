@@ -0,0 +1,17 @@
+ALTER TABLE users ADD COLUMN is_bot BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE messages ADD COLUMN kind TEXT NOT NULL DEFAULT 'user' CHECK ( kind IN ('user', 'bot') );
+
+CREATE TABLE IF NOT EXISTS api_keys (
+    key_id          UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    bot_id          UUID NOT NULL REFERENCES users ON DELETE CASCADE,
+    key_hash        TEXT NOT NULL UNIQUE,
+    prefix          TEXT NOT NULL,
+    room_ids        UUID[] NOT NULL,
+    can_send        BOOLEAN NOT NULL,
+    can_read        BOOLEAN NOT NULL,
+    created_at      TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    revoked_at      TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS api_keys_bot_id_idx ON api_keys (bot_id);
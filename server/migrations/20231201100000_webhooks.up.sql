@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS webhooks (
+    webhook_id      UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    room_id         UUID NOT NULL REFERENCES rooms ON DELETE CASCADE,
+    bot_id          UUID NOT NULL REFERENCES users ON DELETE CASCADE,
+    token_hash      TEXT NOT NULL,
+    created_at      TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    revoked_at      TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS webhooks_room_id_idx ON webhooks (room_id);
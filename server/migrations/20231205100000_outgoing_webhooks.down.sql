@@ -0,0 +1,4 @@
+DROP VIEW IF EXISTS dead_webhook_deliveries;
+DROP TABLE IF EXISTS webhook_deliveries;
+DROP TABLE IF EXISTS outgoing_webhooks;
+ALTER TABLE members DROP COLUMN IF EXISTS is_admin;
@@ -0,0 +1,35 @@
+ALTER TABLE members ADD COLUMN IF NOT EXISTS is_admin BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS outgoing_webhooks (
+    endpoint_id         UUID PRIMARY KEY,
+    room_id             UUID NOT NULL REFERENCES rooms ON DELETE CASCADE,
+    url                 TEXT NOT NULL,
+    events              TEXT[] NOT NULL,
+    encrypted_secret    BYTEA NOT NULL,
+    created_at          TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS outgoing_webhooks_room_id_idx ON outgoing_webhooks (room_id);
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    delivery_id         UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    endpoint_id         UUID NOT NULL REFERENCES outgoing_webhooks ON DELETE CASCADE,
+    event               TEXT NOT NULL,
+    body                TEXT NOT NULL,
+    attempts            INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at     TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_error          TEXT,
+    created_at          TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    delivered_at        TIMESTAMP WITH TIME ZONE,
+    dead_at             TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_due_idx ON webhook_deliveries (next_attempt_at)
+    WHERE delivered_at IS NULL AND dead_at IS NULL;
+
+CREATE OR REPLACE VIEW dead_webhook_deliveries AS
+    SELECT d.delivery_id, d.endpoint_id, e.room_id, e.url, d.event, d.body, d.attempts,
+        d.last_error, d.created_at, d.dead_at
+    FROM webhook_deliveries AS d
+    JOIN outgoing_webhooks AS e USING (endpoint_id)
+    WHERE d.dead_at IS NOT NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE members DROP COLUMN IF EXISTS muted_until;
+ALTER TABLE rooms DROP COLUMN IF EXISTS topic;
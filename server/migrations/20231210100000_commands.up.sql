@@ -0,0 +1,2 @@
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS topic TEXT;
+ALTER TABLE members ADD COLUMN IF NOT EXISTS muted_until TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,9 @@
+DELETE FROM messages WHERE kind = 'system';
+ALTER TABLE messages DROP CONSTRAINT IF EXISTS messages_kind_check;
+ALTER TABLE messages ADD CONSTRAINT messages_kind_check CHECK ( kind IN ('user', 'bot') );
+
+ALTER TABLE rooms DROP COLUMN IF EXISTS created_by;
+ALTER TABLE rooms DROP COLUMN IF EXISTS avatar_id;
+ALTER TABLE rooms DROP COLUMN IF EXISTS description;
+
+DROP TABLE IF EXISTS attachments;
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS attachments (
+    attachment_id   UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    uploaded_by     UUID REFERENCES users ON DELETE SET NULL,
+    content_type    TEXT NOT NULL CHECK ( content_type <> '' ),
+    data            BYTEA NOT NULL,
+    created_at      TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS description TEXT;
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS avatar_id UUID REFERENCES attachments ON DELETE SET NULL;
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS created_by UUID REFERENCES users ON DELETE SET NULL;
+
+ALTER TABLE messages DROP CONSTRAINT IF EXISTS messages_kind_check;
+ALTER TABLE messages ADD CONSTRAINT messages_kind_check CHECK ( kind IN ('user', 'bot', 'system') );
@@ -2,12 +2,16 @@ use anyhow::Context;
 use base64::engine::general_purpose;
 use base64::Engine;
 use deadpool_redis::redis::IntoConnectionInfo;
-use jsonwebtoken::{DecodingKey, EncodingKey};
+use jsonwebtoken::Algorithm;
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
+use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
+use std::path::PathBuf;
+
+use crate::service::{KeySet, Permissions, SecretCipher, LEGACY_KEY_ID};
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
@@ -15,6 +19,10 @@ pub struct Settings {
     pub application: ApplicationSettings,
     pub auth: AuthSettings,
     pub redis: RedisSettings,
+    pub rate_limit: RateLimitSettings,
+    pub email: EmailSettings,
+    pub webhooks: WebhookSettings,
+    pub commands: CommandSettings,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -81,27 +89,373 @@ impl RedisSettings {
 
 #[derive(serde::Deserialize, Clone)]
 pub struct AuthSettings {
-    //TODO: add refresh keys
+    /// Key pair new tokens are signed with.
     pub eddsa_private_key_pem: Secret<String>,
     pub eddsa_public_key_pem: String,
+    /// `kid` of the signing key. Tokens without a `kid` were signed by the key with the
+    /// default id.
+    #[serde(default = "default_key_id")]
+    pub key_id: String,
+    /// Other public keys, all published in the JWKS. To rotate, publish the next key here
+    /// first, then make it the signing key and keep the retired one here until the tokens
+    /// it signed expire.
+    #[serde(default)]
+    pub verification_keys: Vec<VerificationKeySettings>,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub access_toked_duration: u64,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub refresh_toked_duration: u64,
+    /// Seconds a socket ticket can be redeemed for.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ws_ticket_duration: u64,
+    /// Seconds a password reset token can be used for.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub password_reset_duration: u64,
+    /// Page of the client that resets passwords, sent with the token in a `token` parameter.
+    pub password_reset_url: String,
+    pub email_verification: EmailVerificationSettings,
+    pub mfa: MfaSettings,
+    pub lockout: LockoutSettings,
+    pub password: PasswordSettings,
+    #[serde(default)]
+    pub oidc: OidcSettings,
+    /// `iss` claim of issued tokens, tokens from other issuers are rejected.
+    pub issuer: String,
+    /// `aud` claim of issued tokens, tokens for other audiences are rejected.
+    pub audience: String,
+    #[serde(default)]
+    pub revocation: RevocationSettings,
+    #[serde(default)]
+    pub cookies: CookieSettings,
+}
+
+/// Lets browser clients keep their tokens in HttpOnly cookies instead of handling them.
+/// Clients opt in per login with the `X-Auth-Mode: cookie` header.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct CookieSettings {
+    pub enabled: bool,
+    /// Only send the cookies over HTTPS. Disable for local development over plain HTTP.
+    pub secure: bool,
+    pub same_site: SameSiteSetting,
+}
+
+impl Default for CookieSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            secure: true,
+            same_site: SameSiteSetting::Strict,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSiteSetting {
+    Strict,
+    Lax,
+}
+
+/// Accounts are unverified until the link emailed on signup is opened.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct EmailVerificationSettings {
+    /// Seconds a verification link can be used for.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub token_duration: u64,
+    /// Page of the client that verifies emails, sent with the token in a `token` parameter.
+    pub verify_url: String,
+    /// What unverified accounts may do.
+    pub unverified: Permissions,
+}
+
+/// OpenID Connect providers users can sign in with instead of a password, by name.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct OidcSettings {
+    /// Seconds between starting a login at a provider and finishing it.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub login_duration: u64,
+    #[serde(default)]
+    pub providers: HashMap<String, OidcProviderSettings>,
+}
+
+impl Default for OidcSettings {
+    fn default() -> Self {
+        Self {
+            login_duration: 600,
+            providers: HashMap::new(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct OidcProviderSettings {
+    /// Discovery is read from `{issuer_url}/.well-known/openid-configuration`.
+    pub issuer_url: String,
+    pub client_id: String,
+    /// Unset for public clients, which rely on PKCE alone.
+    #[serde(default)]
+    pub client_secret: Option<Secret<String>>,
+    /// Page of the client the provider redirects to. It posts the `code` and `state` it
+    /// receives to `/api/oidc/{provider}/callback`.
+    pub redirect_url: String,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    /// Algorithms ID tokens may be signed with, others are rejected whatever their header
+    /// claims. RS256 is the one every provider supports.
+    #[serde(default = "default_id_token_algorithms")]
+    pub id_token_algorithms: Vec<Algorithm>,
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    ["openid", "email", "profile"].map(String::from).to_vec()
+}
+
+fn default_id_token_algorithms() -> Vec<Algorithm> {
+    vec![Algorithm::RS256]
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct PasswordSettings {
+    pub argon2: Argon2Settings,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_length: usize,
+    /// Passwords known from breaches that may not be chosen, one per line.
+    #[serde(default)]
+    pub breached_list_path: Option<PathBuf>,
+}
+
+/// Cost of new password hashes. Hashes with other parameters are replaced on login.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct Argon2Settings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub memory_kib: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub iterations: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub parallelism: u32,
+}
+
+impl Argon2Settings {
+    pub fn params(&self) -> Result<argon2::Params, anyhow::Error> {
+        argon2::Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .map_err(|e| anyhow::anyhow!("Invalid Argon2 parameters: {}", e))
+    }
+}
+
+/// Failed logins are counted per account and per IP. Past `free_attempts` each failure
+/// delays the next attempt, doubling up to `max_delay_ms`; at the threshold the account or
+/// IP is locked for `lockout_duration`.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct LockoutSettings {
+    /// Seconds after the last failure that the failures are forgotten.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub failure_window: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub free_attempts: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub base_delay_ms: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_delay_ms: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub account_threshold: u64,
+    /// Higher than the account threshold, many users may share an IP.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ip_threshold: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_duration: u64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct MfaSettings {
+    /// Base64 of the 32 byte key TOTP secrets are encrypted with in the database.
+    pub encryption_key: Secret<String>,
+    /// Shown next to the account in authenticator apps.
+    pub issuer: String,
+    /// Seconds between the password and the second factor of a login.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub challenge_duration: u64,
+}
+
+impl MfaSettings {
+    pub fn cipher(&self) -> Result<SecretCipher, anyhow::Error> {
+        let key = general_purpose::STANDARD
+            .decode(self.encryption_key.expose_secret())
+            .context("Invalid MFA encryption key base64.")?;
+        SecretCipher::new(&key)
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct VerificationKeySettings {
+    pub key_id: String,
+    pub eddsa_public_key_pem: String,
+}
+
+fn default_key_id() -> String {
+    LEGACY_KEY_ID.to_string()
+}
+
+/// Checking access tokens against revocations costs a Redis round trip per request.
+/// The local cache trades some of that latency for revocations taking up to
+/// `cache_ttl_ms` to reach every instance.
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+pub struct RevocationSettings {
+    pub enabled: bool,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cache_ttl_ms: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cache_capacity: usize,
 }
 
 impl AuthSettings {
-    pub fn encoding_key(&self) -> Result<EncodingKey, anyhow::Error> {
-        let bytes = general_purpose::STANDARD.decode(self.eddsa_private_key_pem.expose_secret())?;
-        EncodingKey::from_ed_pem(&bytes).context("Invalid privet key pem.")
+    pub fn key_set(&self) -> Result<KeySet, anyhow::Error> {
+        let private_key = general_purpose::STANDARD
+            .decode(self.eddsa_private_key_pem.expose_secret())
+            .context("Invalid private key base64.")?;
+
+        let current = (self.key_id.clone(), &self.eddsa_public_key_pem);
+        let retired = self
+            .verification_keys
+            .iter()
+            .map(|key| (key.key_id.clone(), &key.eddsa_public_key_pem));
+        let public_keys = std::iter::once(current)
+            .chain(retired)
+            .map(|(key_id, pem)| {
+                let pem = general_purpose::STANDARD
+                    .decode(pem)
+                    .with_context(|| format!("Invalid public key base64 of key {}.", key_id))?;
+                Ok((key_id, pem))
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+        KeySet::new(&self.key_id, &private_key, &public_keys)
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct EmailSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    /// `From` of sent emails, e.g. `Chat <no-reply@example.com>`.
+    pub sender: String,
+    /// Upgrade the connection with STARTTLS. Disable for a local mock server.
+    pub starttls: bool,
+}
+
+/// Delivery of room events to the endpoints registered by room admins.
+#[derive(serde::Deserialize, Clone)]
+pub struct WebhookSettings {
+    /// Base64 of the 32 byte key signing secrets are encrypted with in the database.
+    pub encryption_key: Secret<String>,
+    /// Milliseconds between polls of an empty delivery queue.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub poll_interval_ms: u64,
+    /// Deliveries sent at once.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub batch_size: i64,
+    /// Milliseconds an endpoint has to answer.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_ms: u64,
+    /// Attempts before a delivery is moved to the dead letters.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts: i32,
+    /// Seconds before the first retry, doubled for every further one.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub base_backoff_secs: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_backoff_secs: u64,
+    /// Whether endpoints may be on loopback, private or link-local addresses. Off, so
+    /// that room admins cannot reach into the network of the server.
+    #[serde(default)]
+    pub allow_private_addresses: bool,
+}
+
+impl WebhookSettings {
+    pub fn cipher(&self) -> Result<SecretCipher, anyhow::Error> {
+        let key = general_purpose::STANDARD
+            .decode(self.encryption_key.expose_secret())
+            .context("Invalid webhook encryption key base64.")?;
+        SecretCipher::new(&key)
     }
+}
+
+/// Slash commands beyond the built-in ones.
+#[derive(serde::Deserialize, Clone)]
+pub struct CommandSettings {
+    /// Milliseconds an HTTP command has to answer.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_ms: u64,
+    /// Commands answered by an integration, by name.
+    #[serde(default)]
+    pub http: HashMap<String, HttpCommandSettings>,
+}
 
-    pub fn decoding_key(&self) -> Result<DecodingKey, anyhow::Error> {
-        let bytes = general_purpose::STANDARD.decode(&self.eddsa_public_key_pem)?;
-        DecodingKey::from_ed_pem(&bytes).context("Invalid public key pem.")
+#[derive(serde::Deserialize, Clone)]
+pub struct HttpCommandSettings {
+    /// Receives the invocation as signed JSON, like outgoing webhooks.
+    pub url: String,
+    /// Shown by `/help`.
+    pub description: String,
+    #[serde(default)]
+    pub admin_only: bool,
+    pub secret: Secret<String>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    /// Socket messages of a user in a room.
+    pub message: TokenBucketSettings,
+    /// Auth requests from an IP.
+    pub auth_ip: TokenBucketSettings,
+    /// Login attempts on an account.
+    pub auth_account: TokenBucketSettings,
+    /// Where the IP of auth requests is read from.
+    #[serde(default)]
+    pub client_ip: ClientIpSource,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct TokenBucketSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub capacity: u32,
+    /// Must be positive, the wait for the next token is divided by it.
+    #[serde(deserialize_with = "deserialize_refill_rate")]
+    pub refill_per_second: f64,
+}
+
+fn deserialize_refill_rate<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let rate: f64 = deserialize_number_from_string(deserializer)?;
+    if rate.is_finite() && rate > 0.0 {
+        Ok(rate)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "refill_per_second must be a positive number, got {}",
+            rate
+        )))
     }
 }
 
+/// Behind a reverse proxy the peer of every connection is the proxy, so the client IP
+/// has to come from a header the proxy sets. Only trust a header the proxy overwrites or
+/// appends to, clients can send any value.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientIpSource {
+    /// The peer address of the connection.
+    #[default]
+    Peer,
+    /// The last address of `X-Forwarded-For`, the one the proxy appended.
+    XForwardedFor,
+    /// `X-Real-IP`, as set by the proxy.
+    XRealIp,
+}
+
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     let base_path = std::env::current_dir().expect("Failed to determine the current directory");
     let configuration_directory = base_path.join("configuration");
@@ -156,3 +510,20 @@ impl TryFrom<String> for Environment {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refill_rate_must_be_positive() {
+        for rate in ["0", "-1", "0.0"] {
+            let bucket = serde_json::json!({ "capacity": 5, "refill_per_second": rate });
+            assert!(serde_json::from_value::<TokenBucketSettings>(bucket).is_err());
+        }
+
+        let bucket = serde_json::json!({ "capacity": 5, "refill_per_second": "0.05" });
+        let bucket: TokenBucketSettings = serde_json::from_value(bucket).unwrap();
+        assert_eq!(bucket.refill_per_second, 0.05);
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use reqwest::header::CONTENT_TYPE;
+
+use crate::configuration::CommandSettings;
+use crate::service::{CommandClient, WebhookRequest};
+
+#[derive(Clone)]
+pub struct HttpCommandClient {
+    http: reqwest::Client,
+}
+
+impl HttpCommandClient {
+    pub fn new(settings: &CommandSettings) -> Result<Self, anyhow::Error> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_millis(settings.timeout_ms))
+            .build()
+            .context("Failed to build command HTTP client.")?;
+        Ok(Self { http })
+    }
+}
+
+#[async_trait]
+impl CommandClient for HttpCommandClient {
+    #[tracing::instrument(name = "Calling command integration", skip_all, fields(url = %request.url))]
+    async fn call(&self, request: &WebhookRequest) -> Result<String, anyhow::Error> {
+        let mut builder = self
+            .http
+            .post(&request.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(request.body.clone());
+        for (name, value) in &request.headers {
+            builder = builder.header(*name, value);
+        }
+
+        builder
+            .send()
+            .await
+            .context("Failed to reach command integration.")?
+            .error_for_status()
+            .context("Command integration rejected invocation.")?
+            .text()
+            .await
+            .context("Failed to read command integration answer.")
+    }
+}
@@ -0,0 +1,5 @@
+mod command;
+mod webhook;
+
+pub use command::HttpCommandClient;
+pub use webhook::HttpWebhookSender;
@@ -0,0 +1,156 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::redirect::Policy;
+use url::{Host, Url};
+
+use crate::configuration::WebhookSettings;
+use crate::service::{WebhookRequest, WebhookSender};
+
+#[derive(Clone)]
+pub struct HttpWebhookSender {
+    http: reqwest::Client,
+    allow_private_addresses: bool,
+}
+
+impl HttpWebhookSender {
+    pub fn new(settings: &WebhookSettings) -> Result<Self, anyhow::Error> {
+        let allow_private_addresses = settings.allow_private_addresses;
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_millis(settings.timeout_ms))
+            // A redirect could point anywhere, past the address checks.
+            .redirect(Policy::none())
+            .dns_resolver(Arc::new(CheckedResolver {
+                allow_private_addresses,
+            }))
+            .build()
+            .context("Failed to build webhook HTTP client.")?;
+        Ok(Self {
+            http,
+            allow_private_addresses,
+        })
+    }
+}
+
+#[async_trait]
+impl WebhookSender for HttpWebhookSender {
+    async fn check_url(&self, url: &str) -> Result<(), anyhow::Error> {
+        let url = Url::parse(url).context("Invalid URL.")?;
+        let host = url.host().context("URL has no host.")?;
+        let port = url.port_or_known_default().unwrap_or(80);
+        resolve(host, port, self.allow_private_addresses).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Sending webhook", skip_all, fields(url = %request.url))]
+    async fn send(&self, request: &WebhookRequest) -> Result<(), anyhow::Error> {
+        // Names are checked as the client resolves them, addresses are never resolved.
+        let url = Url::parse(&request.url).context("Invalid webhook URL.")?;
+        match url.host() {
+            Some(Host::Ipv4(ip)) => check_address(ip.into(), self.allow_private_addresses)?,
+            Some(Host::Ipv6(ip)) => check_address(ip.into(), self.allow_private_addresses)?,
+            _ => {}
+        }
+
+        let mut builder = self
+            .http
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(request.body.clone());
+        for (name, value) in &request.headers {
+            builder = builder.header(*name, value);
+        }
+
+        let response = builder
+            .send()
+            .await
+            .context("Failed to reach webhook endpoint.")?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!("Webhook endpoint answered {}.", status);
+        }
+        Ok(())
+    }
+}
+
+/// Resolves names for the client, refusing those with addresses that are not allowed.
+struct CheckedResolver {
+    allow_private_addresses: bool,
+}
+
+impl Resolve for CheckedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allow_private_addresses = self.allow_private_addresses;
+        Box::pin(async move {
+            // The client puts in the port of the URL.
+            let addrs = resolve(Host::Domain(name.as_str()), 0, allow_private_addresses).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+async fn resolve(
+    host: Host<&str>,
+    port: u16,
+    allow_private_addresses: bool,
+) -> Result<Vec<SocketAddr>, anyhow::Error> {
+    let addrs: Vec<SocketAddr> = match host {
+        Host::Ipv4(ip) => vec![SocketAddr::new(ip.into(), port)],
+        Host::Ipv6(ip) => vec![SocketAddr::new(ip.into(), port)],
+        Host::Domain(domain) => tokio::net::lookup_host((domain, port))
+            .await
+            .with_context(|| format!("Failed to resolve {}.", domain))?
+            .collect(),
+    };
+    for addr in &addrs {
+        check_address(addr.ip(), allow_private_addresses)?;
+    }
+    Ok(addrs)
+}
+
+fn check_address(ip: IpAddr, allow_private_addresses: bool) -> Result<(), anyhow::Error> {
+    if !allow_private_addresses && !is_public(ip) {
+        bail!("{} is not a public address.", ip);
+    }
+    Ok(())
+}
+
+/// Whether the address is out on the internet, rather than the server itself or its
+/// networks.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                // "This network", 0.0.0.0/8.
+                || a == 0
+                // Shared address space of carrier-grade NAT, 100.64.0.0/10.
+                || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(ip.into());
+            }
+            let first = ip.segments()[0];
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                // Unique local, fc00::/7.
+                || first & 0xfe00 == 0xfc00
+                // Link-local, fe80::/10.
+                || first & 0xffc0 == 0xfe80)
+        }
+    }
+}
@@ -1,2 +1,5 @@
+pub mod http;
+pub mod oidc;
 pub mod postgres;
 pub mod redis;
+pub mod smtp;
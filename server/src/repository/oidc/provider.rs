@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, ensure, Context};
+use async_trait::async_trait;
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, DecodingKey, Validation};
+use reqwest::Url;
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+
+use crate::configuration::{OidcProviderSettings, OidcSettings};
+use crate::service::{self, AuthorizationRequest, ExternalIdentity};
+
+/// Provider keys are fetched again after this long.
+const JWKS_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+/// A token naming an unknown key fetches the keys again, at most this often.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(60);
+
+/// Parts of the discovery document the authorization code flow needs.
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    nonce: Option<String>,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+    name: Option<String>,
+    preferred_username: Option<String>,
+}
+
+struct ProviderKeys {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+#[derive(Clone)]
+pub struct HttpOidcClient {
+    http: reqwest::Client,
+    providers: Arc<HashMap<String, OidcProviderSettings>>,
+    /// Discovery documents by provider, read on first use.
+    metadata: Arc<RwLock<HashMap<String, Arc<ProviderMetadata>>>>,
+    /// Signing keys by `jwks_uri`.
+    keys: Arc<RwLock<HashMap<String, Arc<ProviderKeys>>>>,
+}
+
+impl HttpOidcClient {
+    pub fn new(settings: &OidcSettings) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            providers: Arc::new(settings.providers.clone()),
+            metadata: Arc::new(RwLock::new(HashMap::new())),
+            keys: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    fn provider(&self, provider: &str) -> Result<&OidcProviderSettings, anyhow::Error> {
+        self.providers
+            .get(provider)
+            .with_context(|| format!("Unknown OIDC provider {}.", provider))
+    }
+
+    async fn metadata(&self, provider: &str) -> Result<Arc<ProviderMetadata>, anyhow::Error> {
+        if let Some(metadata) = self.metadata.read().unwrap().get(provider) {
+            return Ok(metadata.clone());
+        }
+
+        let settings = self.provider(provider)?;
+        let issuer = settings.issuer_url.trim_end_matches('/');
+        let metadata: ProviderMetadata = self
+            .http
+            .get(format!("{}/.well-known/openid-configuration", issuer))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("Failed fetch OIDC discovery document.")?
+            .json()
+            .await
+            .context("Invalid OIDC discovery document.")?;
+        ensure!(
+            metadata.issuer.trim_end_matches('/') == issuer,
+            "Discovery document of {} is for issuer {}.",
+            issuer,
+            metadata.issuer
+        );
+
+        let metadata = Arc::new(metadata);
+        self.metadata
+            .write()
+            .unwrap()
+            .insert(provider.to_string(), metadata.clone());
+        Ok(metadata)
+    }
+
+    /// Signing key `kid` of the provider. Keys are cached, a key missing from the cache
+    /// fetches them again in case the provider rotated its keys.
+    async fn signing_key(
+        &self,
+        metadata: &ProviderMetadata,
+        kid: Option<&str>,
+    ) -> Result<Jwk, anyhow::Error> {
+        let cached = self.keys.read().unwrap().get(&metadata.jwks_uri).cloned();
+        if let Some(cached) = cached {
+            let age = cached.fetched_at.elapsed();
+            match find_key(&cached.keys, kid) {
+                Some(jwk) if age < JWKS_MAX_AGE => return Ok(jwk.clone()),
+                None if age < JWKS_MIN_REFRESH => bail!("Unknown ID token key {:?}.", kid),
+                _ => {}
+            }
+        }
+
+        let keys: JwkSet = self
+            .http
+            .get(&metadata.jwks_uri)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("Failed fetch provider keys.")?
+            .json()
+            .await
+            .context("Invalid provider keys.")?;
+        let jwk = find_key(&keys, kid).cloned();
+        self.keys.write().unwrap().insert(
+            metadata.jwks_uri.clone(),
+            Arc::new(ProviderKeys {
+                keys,
+                fetched_at: Instant::now(),
+            }),
+        );
+        jwk.with_context(|| format!("Unknown ID token key {:?}.", kid))
+    }
+
+    async fn validate_id_token(
+        &self,
+        id_token: &str,
+        metadata: &ProviderMetadata,
+        settings: &OidcProviderSettings,
+    ) -> Result<IdTokenClaims, anyhow::Error> {
+        let header = decode_header(id_token).context("Invalid ID token header.")?;
+        ensure!(
+            settings.id_token_algorithms.contains(&header.alg),
+            "ID token signed with {:?}, which is not allowed.",
+            header.alg
+        );
+        let jwk = self.signing_key(metadata, header.kid.as_deref()).await?;
+        let key = DecodingKey::from_jwk(&jwk).context("Unsupported provider key.")?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.algorithms = settings.id_token_algorithms.clone();
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&settings.client_id]);
+        Ok(decode::<IdTokenClaims>(id_token, &key, &validation)
+            .context("Invalid ID token.")?
+            .claims)
+    }
+}
+
+#[async_trait]
+impl service::OidcClient for HttpOidcClient {
+    async fn authorization_url(
+        &self,
+        provider: &str,
+        request: &AuthorizationRequest,
+    ) -> Result<String, anyhow::Error> {
+        let settings = self.provider(provider)?;
+        let metadata = self.metadata(provider).await?;
+
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &settings.client_id),
+                ("redirect_uri", &settings.redirect_url),
+                ("scope", &settings.scopes.join(" ")),
+                ("state", &request.state),
+                ("nonce", &request.nonce),
+                ("code_challenge", &request.code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .context("Invalid authorization endpoint.")?;
+        Ok(url.into())
+    }
+
+    #[tracing::instrument(name = "Exchange OIDC code", skip(self, code, code_verifier, nonce))]
+    async fn exchange_code(
+        &self,
+        provider: &str,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<ExternalIdentity, anyhow::Error> {
+        let settings = self.provider(provider)?;
+        let metadata = self.metadata(provider).await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &settings.redirect_url),
+            ("client_id", &settings.client_id),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(client_secret) = &settings.client_secret {
+            form.push(("client_secret", client_secret.expose_secret()));
+        }
+        let tokens: TokenResponse = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("Failed exchange authorization code.")?
+            .json()
+            .await
+            .context("Invalid token response.")?;
+
+        let claims = self
+            .validate_id_token(&tokens.id_token, &metadata, settings)
+            .await?;
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(anyhow!("ID token nonce does not match."));
+        }
+
+        Ok(ExternalIdentity {
+            provider: provider.to_string(),
+            subject: claims.sub,
+            email: claims.email,
+            email_verified: claims.email_verified,
+            name: claims.name.or(claims.preferred_username),
+        })
+    }
+}
+
+fn find_key<'a>(keys: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    match kid {
+        Some(kid) => keys.find(kid),
+        None if keys.keys.len() == 1 => keys.keys.first(),
+        None => None,
+    }
+}
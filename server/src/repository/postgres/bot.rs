@@ -0,0 +1,307 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use secrecy::{ExposeSecret, Secret};
+use shared::domain::{RoomId, User, UserCode, UserEmail, UserId, UserName};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::service::{ApiKey, ApiKeyScope, BotRepository, Webhook};
+
+use super::model::{ApiKeyRow, UserRow, WebhookRow};
+
+#[derive(Clone)]
+pub struct BotAdapter {
+    pool: PgPool,
+}
+
+impl BotAdapter {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl BotRepository for BotAdapter {
+    async fn is_admin(&self, user_id: &UserId) -> Result<bool, anyhow::Error> {
+        let is_admin = sqlx::query!(
+            r#"
+                SELECT is_admin
+                FROM users
+                WHERE user_id = $1;
+            "#,
+            user_id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get user role from database.")?
+        .is_some_and(|row| row.is_admin);
+        Ok(is_admin)
+    }
+
+    async fn is_bot(&self, user_id: &UserId) -> Result<bool, anyhow::Error> {
+        let is_bot = sqlx::query!(
+            r#"
+                SELECT is_bot
+                FROM users
+                WHERE user_id = $1;
+            "#,
+            user_id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get user role from database.")?
+        .is_some_and(|row| row.is_bot);
+        Ok(is_bot)
+    }
+
+    #[tracing::instrument(name = "Saving new bot in the database", skip(self, password_hash))]
+    async fn create_bot(
+        &self,
+        name: &UserName,
+        email: &UserEmail,
+        password_hash: Secret<String>,
+        code: &UserCode,
+    ) -> Result<User, anyhow::Error> {
+        let bot = sqlx::query_as!(
+            UserRow,
+            r#"
+                INSERT INTO users (username, email, code, hashed_password, is_bot, email_verified_at)
+                VALUES ( $1, $2, $3, $4, TRUE, NOW() )
+                RETURNING user_id, username, email, code, created_at
+            "#,
+            name.as_ref(),
+            email.as_ref(),
+            code.as_ref(),
+            password_hash.expose_secret()
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to save bot in database.")?;
+
+        bot.try_into()
+    }
+
+    #[tracing::instrument(name = "Saving new API key in the database", skip(self, key_hash))]
+    async fn create_api_key(
+        &self,
+        bot_id: &UserId,
+        key_hash: &str,
+        prefix: &str,
+        scope: &ApiKeyScope,
+    ) -> Result<(ApiKey, Vec<RoomId>), anyhow::Error> {
+        let room_ids: Vec<Uuid> = scope.room_ids.iter().map(|id| *id.as_ref()).collect();
+        let mut transaction = self.pool.begin().await?;
+
+        let key = sqlx::query_as!(
+            ApiKeyRow,
+            r#"
+                INSERT INTO api_keys (bot_id, key_hash, prefix, room_ids, can_send, can_read)
+                VALUES ( $1, $2, $3, $4, $5, $6 )
+                RETURNING key_id, bot_id, prefix, room_ids, can_send, can_read, created_at, revoked_at
+            "#,
+            bot_id.as_ref(),
+            key_hash,
+            prefix,
+            &room_ids,
+            scope.send,
+            scope.read
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .context("Failed to save API key in database.")?;
+
+        let joined = add_bot_to_rooms(&mut transaction, bot_id, &room_ids).await?;
+        transaction.commit().await?;
+
+        Ok((key.into(), joined))
+    }
+
+    async fn missing_rooms(&self, room_ids: &[RoomId]) -> Result<Vec<RoomId>, anyhow::Error> {
+        let room_ids: Vec<Uuid> = room_ids.iter().map(|id| *id.as_ref()).collect();
+        let missing = sqlx::query!(
+            r#"
+                SELECT requested.room_id AS "room_id!"
+                FROM UNNEST($1::uuid[]) AS requested(room_id)
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM rooms WHERE rooms.room_id = requested.room_id
+                );
+            "#,
+            &room_ids
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to check rooms in database.")?
+        .into_iter()
+        .map(|row| row.room_id.into())
+        .collect();
+        Ok(missing)
+    }
+
+    async fn get_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, anyhow::Error> {
+        let key = sqlx::query_as!(
+            ApiKeyRow,
+            r#"
+                SELECT key_id, bot_id, prefix, room_ids, can_send, can_read, created_at, revoked_at
+                FROM api_keys
+                WHERE key_hash = $1;
+            "#,
+            key_hash
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get API key from database.")?;
+
+        Ok(key.map(ApiKey::from))
+    }
+
+    async fn list_api_keys(&self, bot_id: &UserId) -> Result<Vec<ApiKey>, anyhow::Error> {
+        let keys = sqlx::query_as!(
+            ApiKeyRow,
+            r#"
+                SELECT key_id, bot_id, prefix, room_ids, can_send, can_read, created_at, revoked_at
+                FROM api_keys
+                WHERE bot_id = $1
+                ORDER BY created_at;
+            "#,
+            bot_id.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to list API keys from database.")?;
+
+        Ok(keys.into_iter().map(ApiKey::from).collect())
+    }
+
+    async fn revoke_api_key(&self, bot_id: &UserId, key_id: &Uuid) -> Result<bool, anyhow::Error> {
+        let result = sqlx::query!(
+            r#"
+                UPDATE api_keys
+                SET revoked_at = NOW()
+                WHERE key_id = $1 AND bot_id = $2 AND revoked_at IS NULL;
+            "#,
+            key_id,
+            bot_id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to revoke API key in database.")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(name = "Saving new webhook in the database", skip(self, token_hash))]
+    async fn create_webhook(
+        &self,
+        room_id: &RoomId,
+        bot_id: &UserId,
+        token_hash: &str,
+    ) -> Result<(Webhook, bool), anyhow::Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        let webhook = sqlx::query_as!(
+            WebhookRow,
+            r#"
+                INSERT INTO webhooks (room_id, bot_id, token_hash)
+                VALUES ( $1, $2, $3 )
+                RETURNING webhook_id, room_id, bot_id, created_at, revoked_at
+            "#,
+            room_id.as_ref(),
+            bot_id.as_ref(),
+            token_hash
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .context("Failed to save webhook in database.")?;
+
+        let joined = add_bot_to_rooms(&mut transaction, bot_id, &[*room_id.as_ref()]).await?;
+        transaction.commit().await?;
+
+        Ok((webhook.into(), !joined.is_empty()))
+    }
+
+    async fn get_webhook(
+        &self,
+        webhook_id: &Uuid,
+        token_hash: &str,
+    ) -> Result<Option<Webhook>, anyhow::Error> {
+        let webhook = sqlx::query_as!(
+            WebhookRow,
+            r#"
+                SELECT webhook_id, room_id, bot_id, created_at, revoked_at
+                FROM webhooks
+                WHERE webhook_id = $1 AND token_hash = $2;
+            "#,
+            webhook_id,
+            token_hash
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get webhook from database.")?;
+
+        Ok(webhook.map(Webhook::from))
+    }
+
+    async fn list_webhooks(&self, room_id: &RoomId) -> Result<Vec<Webhook>, anyhow::Error> {
+        let webhooks = sqlx::query_as!(
+            WebhookRow,
+            r#"
+                SELECT webhook_id, room_id, bot_id, created_at, revoked_at
+                FROM webhooks
+                WHERE room_id = $1
+                ORDER BY created_at;
+            "#,
+            room_id.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to list webhooks from database.")?;
+
+        Ok(webhooks.into_iter().map(Webhook::from).collect())
+    }
+
+    async fn revoke_webhook(
+        &self,
+        room_id: &RoomId,
+        webhook_id: &Uuid,
+    ) -> Result<bool, anyhow::Error> {
+        let result = sqlx::query!(
+            r#"
+                UPDATE webhooks
+                SET revoked_at = NOW()
+                WHERE webhook_id = $1 AND room_id = $2 AND revoked_at IS NULL;
+            "#,
+            webhook_id,
+            room_id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to revoke webhook in database.")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Returns the rooms the bot was not a member of yet.
+async fn add_bot_to_rooms(
+    transaction: &mut Transaction<'_, Postgres>,
+    bot_id: &UserId,
+    room_ids: &[Uuid],
+) -> Result<Vec<RoomId>, anyhow::Error> {
+    let joined = sqlx::query!(
+        r#"
+            INSERT INTO members (user_id, room_id, code)
+            SELECT users.user_id, requested.room_id, users.code
+            FROM users, UNNEST($2::uuid[]) AS requested(room_id)
+            WHERE users.user_id = $1
+            ON CONFLICT DO NOTHING
+            RETURNING room_id
+        "#,
+        bot_id.as_ref(),
+        room_ids
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .context("Failed to add bot to rooms in database.")?;
+
+    Ok(joined.into_iter().map(|row| row.room_id.into()).collect())
+}
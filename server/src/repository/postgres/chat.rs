@@ -1,9 +1,12 @@
 use anyhow::Context;
 use async_trait::async_trait;
-use shared::domain::{Message, MessageContent, Room, RoomCode, RoomId, RoomName, User, UserId};
+use chrono::{DateTime, Utc};
+use shared::domain::{
+    AttachmentId, Message, NewMessage, Room, RoomCode, RoomId, RoomName, User, UserId,
+};
 use sqlx::PgPool;
 
-use crate::service::ChatRepository;
+use crate::service::{Attachment, ChatRepository, RoomChange};
 
 use super::model::{MessageRow, RoomRow, UserRow};
 
@@ -58,32 +61,299 @@ impl ChatRepository for ChatAdapter {
 
         Ok(result)
     }
-}
 
-impl ChatAdapter {
-    pub async fn create_message(
-        &self,
-        sender_id: UserId,
-        room_id: RoomId,
-        content: MessageContent,
-    ) -> Result<Message, anyhow::Error> {
+    async fn create_message(&self, new_message: &NewMessage) -> Result<Message, anyhow::Error> {
         let result = sqlx::query_as!(
             MessageRow,
             r#"
-                INSERT INTO messages (room_id, user_id, content)
-                VALUES ($1, $2, $3)
-                RETURNING message_id,room_id, content, user_id, created_at
+                INSERT INTO messages (room_id, user_id, content, kind)
+                SELECT $1, $2, $3, CASE WHEN is_bot THEN 'bot' ELSE 'user' END
+                FROM users
+                WHERE user_id = $2
+                RETURNING message_id,room_id, content, user_id, created_at, kind
             "#,
-            room_id.as_ref(),
-            sender_id.as_ref(),
-            content.as_ref(),
+            new_message.room_id.as_ref(),
+            new_message.user_id.as_ref(),
+            new_message.content.as_ref(),
         )
         .fetch_one(&self.pool)
-        .await?;
+        .await
+        .context("Failed to store message in database.")?;
 
         result.try_into()
     }
 
+    async fn muted_until(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+    ) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+        let muted_until = sqlx::query!(
+            r#"
+                SELECT muted_until
+                FROM members
+                WHERE room_id = $1 AND user_id = $2
+            "#,
+            room_id.as_ref(),
+            user_id.as_ref(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get member mute from database.")?
+        .and_then(|row| row.muted_until);
+
+        Ok(muted_until)
+    }
+
+    async fn get_messages(
+        &self,
+        room_id: &RoomId,
+        limit: i64,
+    ) -> Result<Vec<Message>, anyhow::Error> {
+        let mut messages = sqlx::query_as!(
+            MessageRow,
+            r#"
+                SELECT message_id, room_id, content, user_id, created_at, kind
+                FROM messages
+                WHERE room_id = $1
+                ORDER BY created_at DESC
+                LIMIT $2
+            "#,
+            room_id.as_ref(),
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get messages from database.")?;
+        messages.reverse();
+
+        messages.into_iter().map(Message::try_from).collect()
+    }
+
+    async fn get_user_rooms(&self, user_id: &UserId) -> Result<Vec<Room>, anyhow::Error> {
+        let rooms = sqlx::query_as!(
+            RoomRow,
+            r#"
+                SELECT room_id, room_name, code, topic, description, avatar_id, created_at, created_by
+                FROM rooms WHERE room_id IN (
+                    SELECT room_id FROM members WHERE user_id = $1
+                ) 
+            "#,
+            user_id.as_ref(),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get user rooms from database.")?;
+
+        rooms.into_iter().map(Room::try_from).collect()
+    }
+
+    async fn get_room(&self, room_id: &RoomId) -> Result<Option<Room>, anyhow::Error> {
+        let room = sqlx::query_as!(
+            RoomRow,
+            r#"
+                SELECT room_id, room_name, code, topic, description, avatar_id, created_at, created_by
+                FROM rooms
+                WHERE room_id = $1
+            "#,
+            room_id.as_ref(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get room from database.")?;
+
+        room.map(Room::try_from).transpose()
+    }
+
+    async fn can_manage_room(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+    ) -> Result<bool, anyhow::Error> {
+        let row = sqlx::query!(
+            r#"
+                SELECT
+                    EXISTS (SELECT 1 FROM users WHERE user_id = $1 AND is_admin)
+                    OR EXISTS (
+                        SELECT 1 FROM members WHERE user_id = $1 AND room_id = $2 AND is_admin
+                    ) AS "allowed!"
+            "#,
+            user_id.as_ref(),
+            room_id.as_ref(),
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to get room role from database.")?;
+
+        Ok(row.allowed)
+    }
+
+    async fn update_room(
+        &self,
+        change: &RoomChange,
+        record: &NewMessage,
+    ) -> Result<Option<(Room, Message)>, anyhow::Error> {
+        let room_id = record.room_id.as_ref();
+        let mut transaction = self.pool.begin().await?;
+
+        match change {
+            RoomChange::Topic(topic) => {
+                sqlx::query!(
+                    "UPDATE rooms SET topic = $2 WHERE room_id = $1",
+                    room_id,
+                    topic.as_ref().map(AsRef::as_ref),
+                )
+                .execute(&mut *transaction)
+                .await
+                .context("Failed to update room topic in database.")?;
+            }
+            RoomChange::Description(description) => {
+                sqlx::query!(
+                    "UPDATE rooms SET description = $2 WHERE room_id = $1",
+                    room_id,
+                    description.as_ref().map(AsRef::as_ref),
+                )
+                .execute(&mut *transaction)
+                .await
+                .context("Failed to update room description in database.")?;
+            }
+            RoomChange::Avatar(avatar) => {
+                // The previous avatar is not referenced anywhere else.
+                sqlx::query!(
+                    r#"
+                        DELETE FROM attachments
+                        WHERE attachment_id = (SELECT avatar_id FROM rooms WHERE room_id = $1)
+                    "#,
+                    room_id,
+                )
+                .execute(&mut *transaction)
+                .await
+                .context("Failed to delete previous room avatar from database.")?;
+
+                let avatar_id = match avatar {
+                    Some(avatar) => Some(
+                        sqlx::query!(
+                            r#"
+                                INSERT INTO attachments (uploaded_by, content_type, data)
+                                VALUES ($1, $2, $3)
+                                RETURNING attachment_id
+                            "#,
+                            record.user_id.as_ref(),
+                            avatar.content_type,
+                            avatar.data,
+                        )
+                        .fetch_one(&mut *transaction)
+                        .await
+                        .context("Failed to store room avatar in database.")?
+                        .attachment_id,
+                    ),
+                    None => None,
+                };
+                sqlx::query!(
+                    "UPDATE rooms SET avatar_id = $2 WHERE room_id = $1",
+                    room_id,
+                    avatar_id,
+                )
+                .execute(&mut *transaction)
+                .await
+                .context("Failed to update room avatar in database.")?;
+            }
+        }
+
+        let room = sqlx::query_as!(
+            RoomRow,
+            r#"
+                SELECT room_id, room_name, code, topic, description, avatar_id, created_at, created_by
+                FROM rooms
+                WHERE room_id = $1
+            "#,
+            room_id,
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to update room in database.")?;
+        // Dropping the transaction rolls back an avatar stored for the missing room.
+        let Some(room) = room else {
+            return Ok(None);
+        };
+
+        let message = sqlx::query_as!(
+            MessageRow,
+            r#"
+                INSERT INTO messages (room_id, user_id, content, kind)
+                VALUES ($1, $2, $3, 'system')
+                RETURNING message_id, room_id, content, user_id, created_at, kind
+            "#,
+            room_id,
+            record.user_id.as_ref(),
+            record.content.as_ref(),
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .context("Failed to store system message in database.")?;
+
+        transaction.commit().await?;
+
+        Ok(Some((room.try_into()?, message.try_into()?)))
+    }
+
+    async fn get_attachment(
+        &self,
+        attachment_id: &AttachmentId,
+        user_id: &UserId,
+    ) -> Result<Option<Attachment>, anyhow::Error> {
+        let attachment = sqlx::query!(
+            r#"
+                SELECT content_type, data
+                FROM attachments
+                WHERE attachment_id = $1
+                    AND EXISTS (
+                        SELECT 1
+                        FROM rooms
+                        JOIN members ON members.room_id = rooms.room_id
+                        WHERE rooms.avatar_id = attachments.attachment_id
+                            AND members.user_id = $2
+                    )
+            "#,
+            attachment_id.as_ref(),
+            user_id.as_ref(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get attachment from database.")?
+        .map(|row| Attachment {
+            content_type: row.content_type,
+            data: row.data,
+        });
+
+        Ok(attachment)
+    }
+
+    async fn set_room_admin(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        is_admin: bool,
+    ) -> Result<bool, anyhow::Error> {
+        let result = sqlx::query!(
+            r#"
+                UPDATE members
+                SET is_admin = $3
+                WHERE room_id = $1 AND user_id = $2
+            "#,
+            room_id.as_ref(),
+            user_id.as_ref(),
+            is_admin,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to update room admin in database.")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+impl ChatAdapter {
     pub async fn create_room(
         &self,
         user_id: UserId,
@@ -95,26 +365,30 @@ impl ChatAdapter {
         let room = sqlx::query_as!(
             RoomRow,
             r#"
-                INSERT INTO rooms (room_name, code)
-                VALUES ($1, $2)
-                RETURNING room_id, room_name, code
+                INSERT INTO rooms (room_name, code, created_by)
+                VALUES ($1, $2, $3)
+                RETURNING room_id, room_name, code, topic, description, avatar_id, created_at, created_by
             "#,
             room_name.as_ref(),
             room_code.as_ref(),
+            user_id.as_ref(),
         )
         .fetch_one(&mut *transaction)
-        .await?;
+        .await
+        .context("Failed to store room in database.")?;
 
         sqlx::query!(
             r#"
-                INSERT INTO members (user_id, room_id)
-                VALUES ($1, $2)
+                INSERT INTO members (user_id, room_id, code, is_admin)
+                VALUES ($1, $2, $3, TRUE)
             "#,
             user_id.as_ref(),
             room.room_id,
+            uuid::Uuid::new_v4().to_string(),
         )
-        .fetch_one(&mut *transaction)
-        .await?;
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to store room creator membership in database.")?;
 
         transaction.commit().await?;
 
@@ -128,7 +402,7 @@ impl ChatAdapter {
         let messages = sqlx::query_as!(
             MessageRow,
             r#"
-                SELECT message_id, room_id, content, user_id, created_at
+                SELECT message_id, room_id, content, user_id, created_at, kind
                 FROM messages
                 WHERE room_id = $1
             "#,
@@ -139,20 +413,4 @@ impl ChatAdapter {
 
         messages.into_iter().map(Message::try_from).collect()
     }
-
-    pub async fn get_user_rooms(&self, user_id: UserId) -> Result<Vec<Room>, anyhow::Error> {
-        let messages = sqlx::query_as!(
-            RoomRow,
-            r#"
-                SELECT room_id, room_name, code FROM rooms WHERE room_id IN (
-                    SELECT room_id FROM members WHERE user_id = $1
-                ) 
-            "#,
-            user_id.as_ref(),
-        )
-        .fetch_all(&self.pool)
-        .await?;
-
-        messages.into_iter().map(Room::try_from).collect()
-    }
 }
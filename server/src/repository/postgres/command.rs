@@ -0,0 +1,142 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use shared::domain::{RoomId, User, UserId, UserName};
+use sqlx::PgPool;
+
+use crate::service::CommandRepository;
+
+use super::model::UserRow;
+
+#[derive(Clone)]
+pub struct CommandAdapter {
+    pool: PgPool,
+}
+
+impl CommandAdapter {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl CommandRepository for CommandAdapter {
+    async fn can_manage_room(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+    ) -> Result<bool, anyhow::Error> {
+        let row = sqlx::query!(
+            r#"
+                SELECT
+                    EXISTS (SELECT 1 FROM users WHERE user_id = $1 AND is_admin)
+                    OR EXISTS (
+                        SELECT 1 FROM members WHERE user_id = $1 AND room_id = $2 AND is_admin
+                    ) AS "allowed!"
+            "#,
+            user_id.as_ref(),
+            room_id.as_ref(),
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to get room role from database.")?;
+
+        Ok(row.allowed)
+    }
+
+    async fn get_user(&self, user_id: &UserId) -> Result<Option<User>, anyhow::Error> {
+        let row = sqlx::query_as!(
+            UserRow,
+            r#"
+                SELECT user_id, username, email, code, created_at
+                FROM users
+                WHERE user_id = $1
+            "#,
+            user_id.as_ref(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get user from database.")?;
+
+        row.map(User::try_from).transpose()
+    }
+
+    async fn get_user_by_name(&self, name: &UserName) -> Result<Option<User>, anyhow::Error> {
+        let row = sqlx::query_as!(
+            UserRow,
+            r#"
+                SELECT user_id, username, email, code, created_at
+                FROM users
+                WHERE username = $1
+            "#,
+            name.as_ref(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get user by name from database.")?;
+
+        row.map(User::try_from).transpose()
+    }
+
+    async fn add_member(&self, room_id: &RoomId, user_id: &UserId) -> Result<bool, anyhow::Error> {
+        let result = sqlx::query!(
+            r#"
+                INSERT INTO members (user_id, room_id, code)
+                SELECT user_id, $2, code
+                FROM users
+                WHERE user_id = $1
+                ON CONFLICT DO NOTHING
+            "#,
+            user_id.as_ref(),
+            room_id.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to add member in database.")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn remove_member(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+    ) -> Result<bool, anyhow::Error> {
+        let result = sqlx::query!(
+            r#"
+                DELETE FROM members
+                WHERE room_id = $1 AND user_id = $2
+            "#,
+            room_id.as_ref(),
+            user_id.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to remove member from database.")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn mute_member(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<bool, anyhow::Error> {
+        let result = sqlx::query!(
+            r#"
+                UPDATE members
+                SET muted_until = $3
+                WHERE room_id = $1 AND user_id = $2
+            "#,
+            room_id.as_ref(),
+            user_id.as_ref(),
+            until,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to mute member in database.")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
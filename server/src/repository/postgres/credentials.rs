@@ -1,9 +1,9 @@
 use super::model::UserRow;
-use crate::service;
+use crate::service::{self, ExternalIdentity, TotpRecord};
 use anyhow::Context;
 use async_trait::async_trait;
 use secrecy::{ExposeSecret, Secret};
-use shared::domain::{User, UserCode, UserEmail, UserName};
+use shared::domain::{User, UserCode, UserEmail, UserId, UserName};
 use sqlx::PgPool;
 use uuid::Uuid;
 
@@ -84,4 +84,350 @@ impl service::CredentialsRepository for CredentialsAdapter {
         .context("Failed get user by email from database.")?;
         result.map(UserRow::try_into).transpose()
     }
+
+    async fn get_user(&self, user_id: &UserId) -> Result<Option<User>, anyhow::Error> {
+        let result = sqlx::query_as!(
+            UserRow,
+            r#"
+                SELECT user_id, username, email, code, created_at
+                FROM users
+                WHERE user_id = $1;
+            "#,
+            user_id.as_ref(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed get user from database.")?;
+        result.map(UserRow::try_into).transpose()
+    }
+
+    async fn get_password_hash(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<Secret<String>>, anyhow::Error> {
+        let hash = sqlx::query_scalar!(
+            r#"
+                SELECT hashed_password
+                FROM users
+                WHERE user_id = $1;
+            "#,
+            user_id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get password hash from database.")?
+        .map(Secret::new);
+        Ok(hash)
+    }
+
+    #[tracing::instrument(name = "Updating password in the database", skip(self, password_hash))]
+    async fn update_password(
+        &self,
+        user_id: &UserId,
+        password_hash: Secret<String>,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"
+                UPDATE users
+                SET hashed_password = $2
+                WHERE user_id = $1;
+            "#,
+            user_id.as_ref(),
+            password_hash.expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to update password in database.")?;
+        Ok(())
+    }
+
+    async fn get_email_status(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<(String, bool)>, anyhow::Error> {
+        let status = sqlx::query!(
+            r#"
+                SELECT email, email_verified_at IS NOT NULL AS "verified!"
+                FROM users
+                WHERE user_id = $1;
+            "#,
+            user_id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get email status from database.")?
+        .map(|row| (row.email, row.verified));
+        Ok(status)
+    }
+
+    #[tracing::instrument(name = "Marking email verified in the database", skip(self))]
+    async fn verify_email(&self, user_id: &UserId) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"
+                UPDATE users
+                SET email_verified_at = NOW()
+                WHERE user_id = $1 AND email_verified_at IS NULL;
+            "#,
+            user_id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to mark email verified in database.")?;
+        Ok(())
+    }
+
+    async fn is_admin(&self, user_id: &UserId) -> Result<bool, anyhow::Error> {
+        let is_admin = sqlx::query!(
+            r#"
+                SELECT is_admin
+                FROM users
+                WHERE user_id = $1;
+            "#,
+            user_id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get user role from database.")?
+        .is_some_and(|row| row.is_admin);
+        Ok(is_admin)
+    }
+
+    async fn get_totp(&self, user_id: &UserId) -> Result<Option<TotpRecord>, anyhow::Error> {
+        let record = sqlx::query!(
+            r#"
+                SELECT secret, confirmed_at IS NOT NULL AS "confirmed!"
+                FROM user_totp
+                WHERE user_id = $1;
+            "#,
+            user_id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get TOTP secret from database.")?
+        .map(|row| TotpRecord {
+            secret: row.secret,
+            confirmed: row.confirmed,
+        });
+        Ok(record)
+    }
+
+    #[tracing::instrument(name = "Storing TOTP secret in the database", skip(self, secret))]
+    async fn store_totp(&self, user_id: &UserId, secret: &[u8]) -> Result<bool, anyhow::Error> {
+        let result = sqlx::query!(
+            r#"
+                INSERT INTO user_totp (user_id, secret)
+                VALUES ( $1, $2 )
+                ON CONFLICT (user_id) DO UPDATE
+                SET secret = EXCLUDED.secret, created_at = NOW()
+                WHERE user_totp.confirmed_at IS NULL;
+            "#,
+            user_id.as_ref(),
+            secret
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to store TOTP secret in database.")?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[tracing::instrument(
+        name = "Confirming TOTP in the database",
+        skip(self, recovery_code_hashes)
+    )]
+    async fn confirm_totp(
+        &self,
+        user_id: &UserId,
+        step: u64,
+        recovery_code_hashes: &[String],
+    ) -> Result<(), anyhow::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            r#"
+                UPDATE user_totp
+                SET confirmed_at = NOW(), last_used_step = $2
+                WHERE user_id = $1;
+            "#,
+            user_id.as_ref(),
+            step as i64
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Failed to confirm TOTP in database.")?;
+        sqlx::query!(
+            r#"
+                DELETE FROM recovery_codes
+                WHERE user_id = $1;
+            "#,
+            user_id.as_ref()
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Failed to delete recovery codes from database.")?;
+        sqlx::query!(
+            r#"
+                INSERT INTO recovery_codes (user_id, code_hash)
+                SELECT $1, * FROM UNNEST($2::text[]);
+            "#,
+            user_id.as_ref(),
+            recovery_code_hashes
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Failed to store recovery codes in database.")?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn use_totp_step(&self, user_id: &UserId, step: u64) -> Result<bool, anyhow::Error> {
+        let result = sqlx::query!(
+            r#"
+                UPDATE user_totp
+                SET last_used_step = $2
+                WHERE user_id = $1
+                    AND confirmed_at IS NOT NULL
+                    AND (last_used_step IS NULL OR last_used_step < $2);
+            "#,
+            user_id.as_ref(),
+            step as i64
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to record TOTP use in database.")?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn use_recovery_code(
+        &self,
+        user_id: &UserId,
+        code_hash: &str,
+    ) -> Result<bool, anyhow::Error> {
+        let result = sqlx::query!(
+            r#"
+                UPDATE recovery_codes
+                SET used_at = NOW()
+                WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL;
+            "#,
+            user_id.as_ref(),
+            code_hash
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to use recovery code in database.")?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[tracing::instrument(name = "Deleting TOTP from the database", skip(self))]
+    async fn delete_totp(&self, user_id: &UserId) -> Result<(), anyhow::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            r#"
+                DELETE FROM recovery_codes
+                WHERE user_id = $1;
+            "#,
+            user_id.as_ref()
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Failed to delete recovery codes from database.")?;
+        sqlx::query!(
+            r#"
+                DELETE FROM user_totp
+                WHERE user_id = $1;
+            "#,
+            user_id.as_ref()
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Failed to delete TOTP secret from database.")?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_identity_user(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<UserId>, anyhow::Error> {
+        let user_id = sqlx::query_scalar!(
+            r#"
+                SELECT user_id
+                FROM user_identities
+                WHERE provider = $1 AND subject = $2;
+            "#,
+            provider,
+            subject
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get identity from database.")?;
+        Ok(user_id.map(UserId::from))
+    }
+
+    #[tracing::instrument(name = "Linking identity in the database", skip(self))]
+    async fn link_identity(
+        &self,
+        user_id: &UserId,
+        identity: &ExternalIdentity,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"
+                INSERT INTO user_identities (provider, subject, user_id)
+                VALUES ( $1, $2, $3 )
+                ON CONFLICT DO NOTHING;
+            "#,
+            identity.provider,
+            identity.subject,
+            user_id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to link identity in database.")?;
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "Saving new external user in the database",
+        skip(self, password_hash)
+    )]
+    async fn create_external_user(
+        &self,
+        name: &UserName,
+        email: &UserEmail,
+        password_hash: Secret<String>,
+        code: &UserCode,
+        identity: &ExternalIdentity,
+    ) -> Result<User, anyhow::Error> {
+        let mut tx = self.pool.begin().await?;
+        let user = sqlx::query_as!(
+            UserRow,
+            r#"
+                INSERT INTO users (username, email, code, hashed_password, email_verified_at)
+                VALUES ( $1, $2, $3, $4, CASE WHEN $5 THEN NOW() END )
+                RETURNING user_id, username, email, code, created_at
+            "#,
+            name.as_ref(),
+            email.as_ref(),
+            code.as_ref(),
+            password_hash.expose_secret(),
+            identity.email_verified
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .context("Failed to signup external user in database.")?;
+        sqlx::query!(
+            r#"
+                INSERT INTO user_identities (provider, subject, user_id)
+                VALUES ( $1, $2, $3 );
+            "#,
+            identity.provider,
+            identity.subject,
+            user.user_id
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Failed to link identity in database.")?;
+        tx.commit().await?;
+
+        user.try_into()
+    }
 }
@@ -1,8 +1,16 @@
+mod bot;
 mod chat;
+mod command;
 mod credentials;
 mod model;
+mod notification;
+mod outgoing_webhook;
 mod postgres_pool;
 
+pub use bot::BotAdapter;
 pub use chat::ChatAdapter;
+pub use command::CommandAdapter;
 pub use credentials::CredentialsAdapter;
+pub use notification::NotificationAdapter;
+pub use outgoing_webhook::OutgoingWebhookAdapter;
 pub use postgres_pool::get_connection_pool;
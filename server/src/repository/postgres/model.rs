@@ -1,7 +1,10 @@
+use anyhow::anyhow;
 use chrono::{DateTime, Utc};
-use shared::domain::{Message, Room, User};
+use shared::domain::{Message, MessageKind, Notification, Room, User};
 use uuid::Uuid;
 
+use crate::service::{ApiKey, ApiKeyScope, Webhook, WebhookDelivery, WebhookEndpoint};
+
 pub struct UserRow {
     pub user_id: uuid::Uuid,
     pub username: String,
@@ -28,6 +31,11 @@ pub struct RoomRow {
     pub room_id: Uuid,
     pub room_name: String,
     pub code: String,
+    pub topic: Option<String>,
+    pub description: Option<String>,
+    pub avatar_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub created_by: Option<Uuid>,
 }
 
 impl TryFrom<RoomRow> for Room {
@@ -38,12 +46,22 @@ impl TryFrom<RoomRow> for Room {
             room_id,
             room_name,
             code,
+            topic,
+            description,
+            avatar_id,
+            created_at,
+            created_by,
         } = r;
 
         Ok(Self {
             id: room_id.into(),
             name: room_name.try_into()?,
             code: code.try_into()?,
+            topic: topic.map(TryInto::try_into).transpose()?,
+            description: description.map(TryInto::try_into).transpose()?,
+            avatar: avatar_id.map(Into::into),
+            created_at,
+            created_by: created_by.map(Into::into),
         })
     }
 }
@@ -54,6 +72,7 @@ pub struct MessageRow {
     pub room_id: Uuid,
     pub content: String,
     pub created_at: DateTime<Utc>,
+    pub kind: String,
 }
 
 impl TryFrom<MessageRow> for Message {
@@ -66,6 +85,7 @@ impl TryFrom<MessageRow> for Message {
             room_id,
             content,
             created_at,
+            kind,
         } = m;
 
         Ok(Self {
@@ -74,6 +94,189 @@ impl TryFrom<MessageRow> for Message {
             room_id: room_id.into(),
             content: content.try_into()?,
             created_at,
+            kind: message_kind(&kind)?,
+        })
+    }
+}
+
+fn message_kind(kind: &str) -> Result<MessageKind, anyhow::Error> {
+    match kind {
+        "user" => Ok(MessageKind::User),
+        "bot" => Ok(MessageKind::Bot),
+        "system" => Ok(MessageKind::System),
+        _ => Err(anyhow!("Unknown message kind {}.", kind)),
+    }
+}
+
+pub struct NotificationRow {
+    pub notification_id: Uuid,
+    pub user_id: Uuid,
+    pub sender_id: Uuid,
+    pub room_id: Uuid,
+    pub message_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub read_at: Option<DateTime<Utc>>,
+}
+
+impl From<NotificationRow> for Notification {
+    fn from(n: NotificationRow) -> Self {
+        let NotificationRow {
+            notification_id,
+            user_id,
+            sender_id,
+            room_id,
+            message_id,
+            created_at,
+            read_at,
+        } = n;
+
+        Self {
+            id: notification_id.into(),
+            user_id: user_id.into(),
+            sender_id: sender_id.into(),
+            room_id: room_id.into(),
+            message_id: message_id.into(),
+            created_at,
+            read_at,
+        }
+    }
+}
+
+pub struct ApiKeyRow {
+    pub key_id: Uuid,
+    pub bot_id: Uuid,
+    pub prefix: String,
+    pub room_ids: Vec<Uuid>,
+    pub can_send: bool,
+    pub can_read: bool,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<ApiKeyRow> for ApiKey {
+    fn from(k: ApiKeyRow) -> Self {
+        let ApiKeyRow {
+            key_id,
+            bot_id,
+            prefix,
+            room_ids,
+            can_send,
+            can_read,
+            created_at,
+            revoked_at,
+        } = k;
+
+        Self {
+            key_id,
+            bot_id: bot_id.into(),
+            prefix,
+            scope: ApiKeyScope {
+                room_ids: room_ids.into_iter().map(Into::into).collect(),
+                send: can_send,
+                read: can_read,
+            },
+            created_at,
+            revoked_at,
+        }
+    }
+}
+
+pub struct WebhookRow {
+    pub webhook_id: Uuid,
+    pub room_id: Uuid,
+    pub bot_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<WebhookRow> for Webhook {
+    fn from(w: WebhookRow) -> Self {
+        let WebhookRow {
+            webhook_id,
+            room_id,
+            bot_id,
+            created_at,
+            revoked_at,
+        } = w;
+
+        Self {
+            webhook_id,
+            room_id: room_id.into(),
+            bot_id: bot_id.into(),
+            created_at,
+            revoked_at,
+        }
+    }
+}
+
+pub struct WebhookEndpointRow {
+    pub endpoint_id: Uuid,
+    pub room_id: Uuid,
+    pub url: String,
+    pub events: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TryFrom<WebhookEndpointRow> for WebhookEndpoint {
+    type Error = anyhow::Error;
+
+    fn try_from(e: WebhookEndpointRow) -> Result<Self, Self::Error> {
+        let WebhookEndpointRow {
+            endpoint_id,
+            room_id,
+            url,
+            events,
+            created_at,
+        } = e;
+
+        Ok(Self {
+            endpoint_id,
+            room_id: room_id.into(),
+            url,
+            events: events
+                .iter()
+                .map(|event| event.parse())
+                .collect::<Result<_, _>>()?,
+            created_at,
+        })
+    }
+}
+
+pub struct WebhookDeliveryRow {
+    pub delivery_id: Uuid,
+    pub endpoint_id: Uuid,
+    pub url: String,
+    pub event: String,
+    pub body: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TryFrom<WebhookDeliveryRow> for WebhookDelivery {
+    type Error = anyhow::Error;
+
+    fn try_from(d: WebhookDeliveryRow) -> Result<Self, Self::Error> {
+        let WebhookDeliveryRow {
+            delivery_id,
+            endpoint_id,
+            url,
+            event,
+            body,
+            attempts,
+            last_error,
+            created_at,
+        } = d;
+
+        Ok(Self {
+            delivery_id,
+            endpoint_id,
+            url,
+            event: event.parse()?,
+            body,
+            attempts,
+            last_error,
+            created_at,
         })
     }
 }
@@ -0,0 +1,116 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use shared::domain::{Message, Notification, NotificationId, UserId};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::service::NotificationRepository;
+
+use super::model::NotificationRow;
+
+#[derive(Clone)]
+pub struct NotificationAdapter {
+    pool: PgPool,
+}
+
+impl NotificationAdapter {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl NotificationRepository for NotificationAdapter {
+    async fn create_for_mentions(
+        &self,
+        message: &Message,
+        names: &[String],
+    ) -> Result<Vec<Notification>, anyhow::Error> {
+        let rows = sqlx::query_as!(
+            NotificationRow,
+            r#"
+                INSERT INTO notifications (user_id, sender_id, room_id, message_id)
+                SELECT m.user_id, $1, m.room_id, $3
+                FROM members AS m
+                JOIN users AS u using(user_id)
+                WHERE m.room_id = $2 AND u.username = ANY($4) AND m.user_id <> $1
+                RETURNING notification_id, user_id, sender_id, room_id, message_id,
+                    created_at, read_at
+            "#,
+            message.user_id.as_ref(),
+            message.room_id.as_ref(),
+            message.id.as_ref(),
+            names,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to store notifications in database.")?;
+
+        Ok(rows.into_iter().map(Notification::from).collect())
+    }
+
+    async fn list(
+        &self,
+        user_id: &UserId,
+        unread_only: bool,
+        limit: i64,
+    ) -> Result<Vec<Notification>, anyhow::Error> {
+        let rows = sqlx::query_as!(
+            NotificationRow,
+            r#"
+                SELECT notification_id, user_id, sender_id, room_id, message_id,
+                    created_at, read_at
+                FROM notifications
+                WHERE user_id = $1 AND (NOT $2 OR read_at IS NULL)
+                ORDER BY created_at DESC
+                LIMIT $3
+            "#,
+            user_id.as_ref(),
+            unread_only,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed get notifications from database.")?;
+
+        Ok(rows.into_iter().map(Notification::from).collect())
+    }
+
+    async fn mark_read(
+        &self,
+        user_id: &UserId,
+        ids: &[NotificationId],
+    ) -> Result<u64, anyhow::Error> {
+        let ids: Vec<Uuid> = ids.iter().map(|id| *id.as_ref()).collect();
+        let result = sqlx::query!(
+            r#"
+                UPDATE notifications
+                SET read_at = NOW()
+                WHERE user_id = $1 AND notification_id = ANY($2) AND read_at IS NULL
+            "#,
+            user_id.as_ref(),
+            &ids,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed mark notifications as read.")?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn mark_all_read(&self, user_id: &UserId) -> Result<u64, anyhow::Error> {
+        let result = sqlx::query!(
+            r#"
+                UPDATE notifications
+                SET read_at = NOW()
+                WHERE user_id = $1 AND read_at IS NULL
+            "#,
+            user_id.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed mark notifications as read.")?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,285 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use shared::domain::{RoomId, UserId};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::service::{
+    ClaimedDelivery, HookEventKind, OutgoingWebhookRepository, WebhookDelivery, WebhookEndpoint,
+};
+
+use super::model::{WebhookDeliveryRow, WebhookEndpointRow};
+
+#[derive(Clone)]
+pub struct OutgoingWebhookAdapter {
+    pool: PgPool,
+}
+
+impl OutgoingWebhookAdapter {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl OutgoingWebhookRepository for OutgoingWebhookAdapter {
+    async fn can_manage_room(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+    ) -> Result<bool, anyhow::Error> {
+        let row = sqlx::query!(
+            r#"
+                SELECT
+                    EXISTS (SELECT 1 FROM users WHERE user_id = $1 AND is_admin)
+                    OR EXISTS (
+                        SELECT 1 FROM members WHERE user_id = $1 AND room_id = $2 AND is_admin
+                    ) AS "allowed!"
+            "#,
+            user_id.as_ref(),
+            room_id.as_ref(),
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to get room role from database.")?;
+
+        Ok(row.allowed)
+    }
+
+    #[tracing::instrument(
+        name = "Saving new webhook endpoint in the database",
+        skip(self, encrypted_secret)
+    )]
+    async fn create_endpoint(
+        &self,
+        endpoint_id: &Uuid,
+        room_id: &RoomId,
+        url: &str,
+        events: &[HookEventKind],
+        encrypted_secret: &[u8],
+    ) -> Result<WebhookEndpoint, anyhow::Error> {
+        let events: Vec<String> = events.iter().map(|e| e.as_str().to_string()).collect();
+        let row = sqlx::query_as!(
+            WebhookEndpointRow,
+            r#"
+                INSERT INTO outgoing_webhooks (endpoint_id, room_id, url, events, encrypted_secret)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING endpoint_id, room_id, url, events, created_at
+            "#,
+            endpoint_id,
+            room_id.as_ref(),
+            url,
+            &events,
+            encrypted_secret,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to store webhook endpoint in database.")?;
+
+        row.try_into()
+    }
+
+    async fn list_endpoints(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<WebhookEndpoint>, anyhow::Error> {
+        let rows = sqlx::query_as!(
+            WebhookEndpointRow,
+            r#"
+                SELECT endpoint_id, room_id, url, events, created_at
+                FROM outgoing_webhooks
+                WHERE room_id = $1
+                ORDER BY created_at
+            "#,
+            room_id.as_ref(),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get webhook endpoints from database.")?;
+
+        rows.into_iter().map(WebhookEndpoint::try_from).collect()
+    }
+
+    async fn delete_endpoint(
+        &self,
+        room_id: &RoomId,
+        endpoint_id: &Uuid,
+    ) -> Result<bool, anyhow::Error> {
+        let result = sqlx::query!(
+            r#"
+                DELETE FROM outgoing_webhooks
+                WHERE room_id = $1 AND endpoint_id = $2
+            "#,
+            room_id.as_ref(),
+            endpoint_id,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to delete webhook endpoint from database.")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn enqueue(
+        &self,
+        room_id: &RoomId,
+        event: HookEventKind,
+        body: &str,
+    ) -> Result<u64, anyhow::Error> {
+        let result = sqlx::query!(
+            r#"
+                INSERT INTO webhook_deliveries (endpoint_id, event, body)
+                SELECT endpoint_id, $2, $3
+                FROM outgoing_webhooks
+                WHERE room_id = $1 AND $2 = ANY(events)
+            "#,
+            room_id.as_ref(),
+            event.as_str(),
+            body,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to queue webhook deliveries in database.")?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn claim_due(
+        &self,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<ClaimedDelivery>, anyhow::Error> {
+        let rows = sqlx::query!(
+            r#"
+                WITH due AS (
+                    SELECT delivery_id
+                    FROM webhook_deliveries
+                    WHERE delivered_at IS NULL AND dead_at IS NULL AND next_attempt_at <= NOW()
+                    ORDER BY next_attempt_at
+                    LIMIT $1
+                    FOR UPDATE SKIP LOCKED
+                )
+                UPDATE webhook_deliveries AS d
+                SET next_attempt_at = NOW() + $2 * INTERVAL '1 second'
+                FROM due, outgoing_webhooks AS e
+                WHERE d.delivery_id = due.delivery_id AND e.endpoint_id = d.endpoint_id
+                RETURNING d.delivery_id, d.endpoint_id, e.url, d.event, d.body, d.attempts,
+                    d.last_error, d.created_at, e.encrypted_secret
+            "#,
+            limit,
+            lease.as_secs_f64(),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to claim webhook deliveries in database.")?;
+
+        rows.into_iter()
+            .map(|row| {
+                let delivery = WebhookDeliveryRow {
+                    delivery_id: row.delivery_id,
+                    endpoint_id: row.endpoint_id,
+                    url: row.url,
+                    event: row.event,
+                    body: row.body,
+                    attempts: row.attempts,
+                    last_error: row.last_error,
+                    created_at: row.created_at,
+                };
+                Ok(ClaimedDelivery {
+                    delivery: delivery.try_into()?,
+                    encrypted_secret: row.encrypted_secret,
+                })
+            })
+            .collect()
+    }
+
+    async fn mark_delivered(&self, delivery_id: &Uuid) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"
+                UPDATE webhook_deliveries
+                SET delivered_at = NOW()
+                WHERE delivery_id = $1
+            "#,
+            delivery_id,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to mark webhook delivery as delivered in database.")?;
+
+        Ok(())
+    }
+
+    async fn mark_failed(
+        &self,
+        delivery_id: &Uuid,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"
+                UPDATE webhook_deliveries
+                SET attempts = attempts + 1,
+                    last_error = $2,
+                    next_attempt_at = COALESCE($3, next_attempt_at),
+                    dead_at = CASE WHEN $3::TIMESTAMPTZ IS NULL THEN NOW() END
+                WHERE delivery_id = $1
+            "#,
+            delivery_id,
+            error,
+            retry_at,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to mark webhook delivery as failed in database.")?;
+
+        Ok(())
+    }
+
+    async fn list_dead(&self, room_id: &RoomId) -> Result<Vec<WebhookDelivery>, anyhow::Error> {
+        let rows = sqlx::query_as!(
+            WebhookDeliveryRow,
+            r#"
+                SELECT delivery_id AS "delivery_id!", endpoint_id AS "endpoint_id!",
+                    url AS "url!", event AS "event!", body AS "body!",
+                    attempts AS "attempts!", last_error, created_at AS "created_at!"
+                FROM dead_webhook_deliveries
+                WHERE room_id = $1
+                ORDER BY dead_at DESC
+            "#,
+            room_id.as_ref(),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get dead webhook deliveries from database.")?;
+
+        rows.into_iter().map(WebhookDelivery::try_from).collect()
+    }
+
+    async fn retry_dead(
+        &self,
+        room_id: &RoomId,
+        delivery_id: &Uuid,
+    ) -> Result<bool, anyhow::Error> {
+        let result = sqlx::query!(
+            r#"
+                UPDATE webhook_deliveries AS d
+                SET dead_at = NULL, attempts = 0, next_attempt_at = NOW()
+                FROM outgoing_webhooks AS e
+                WHERE d.endpoint_id = e.endpoint_id
+                    AND e.room_id = $1
+                    AND d.delivery_id = $2
+                    AND d.dead_at IS NOT NULL
+            "#,
+            room_id.as_ref(),
+            delivery_id,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to retry dead webhook delivery in database.")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,57 @@
+use std::str::FromStr;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use deadpool_redis::redis::AsyncCommands;
+use deadpool_redis::{Connection, Pool};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use shared::domain::{event::ServerEvent, UserId};
+
+use crate::service;
+
+const USER_EVENTS_PREFIX: &str = "user_events:";
+
+#[derive(Clone)]
+pub struct UserEventAdapter {
+    pool: Pool,
+}
+
+impl UserEventAdapter {
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl service::UserEventBus for UserEventAdapter {
+    async fn publish(&self, user_id: &UserId, event: &ServerEvent) -> anyhow::Result<()> {
+        let mut conn = self.pool.get().await?;
+
+        let channel = format!("{}{}", USER_EVENTS_PREFIX, user_id.as_ref());
+        let payload = serde_json::to_string(event)?;
+        conn.publish(channel, payload).await?;
+        Ok(())
+    }
+
+    async fn subscribe(&self) -> anyhow::Result<BoxStream<'static, (UserId, ServerEvent)>> {
+        let conn = self.pool.get().await?;
+
+        let mut pubsub = Connection::take(conn).into_pubsub();
+        pubsub
+            .psubscribe(format!("{}*", USER_EVENTS_PREFIX))
+            .await
+            .context("Failed subscribe to user events.")?;
+
+        let stream = pubsub.into_on_message().filter_map(|msg| async move {
+            let user_id = msg
+                .get_channel_name()
+                .strip_prefix(USER_EVENTS_PREFIX)
+                .and_then(|id| UserId::from_str(id).ok())?;
+            let payload = msg.get_payload::<String>().ok()?;
+            let event = serde_json::from_str::<ServerEvent>(&payload).ok()?;
+            Some((user_id, event))
+        });
+        Ok(stream.boxed())
+    }
+}
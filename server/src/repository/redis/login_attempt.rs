@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use deadpool_redis::redis::{self, AsyncCommands};
+use deadpool_redis::Pool;
+
+use crate::service;
+
+const FAILURES_PREFIX: &str = "login_failures:";
+const LOCK_PREFIX: &str = "login_lock:";
+
+#[derive(Clone)]
+pub struct LoginAttemptAdapter {
+    pool: Pool,
+}
+
+impl LoginAttemptAdapter {
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl service::LoginAttemptRepository for LoginAttemptAdapter {
+    async fn record_failure(&self, key: &str, window: Duration) -> anyhow::Result<u64> {
+        let mut conn = self.pool.get().await?;
+
+        let key = format!("{}{}", FAILURES_PREFIX, key);
+        let (failures, _): (u64, i64) = redis::pipe()
+            .atomic()
+            .incr(&key, 1)
+            .expire(&key, window.as_secs().max(1) as usize)
+            .query_async(&mut conn)
+            .await?;
+        Ok(failures)
+    }
+
+    async fn lock(&self, key: &str, duration: Duration) -> anyhow::Result<()> {
+        let mut conn = self.pool.get().await?;
+
+        conn.pset_ex(
+            format!("{}{}", LOCK_PREFIX, key),
+            1,
+            duration.as_millis().max(1) as usize,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn locked_for(&self, key: &str) -> anyhow::Result<Option<Duration>> {
+        let mut conn = self.pool.get().await?;
+
+        // Negative when the key does not exist or has no expiry.
+        let ttl_ms: i64 = conn.pttl(format!("{}{}", LOCK_PREFIX, key)).await?;
+        Ok((ttl_ms > 0).then(|| Duration::from_millis(ttl_ms as u64)))
+    }
+
+    async fn clear(&self, key: &str) -> anyhow::Result<()> {
+        let mut conn = self.pool.get().await?;
+
+        conn.del(&[
+            format!("{}{}", FAILURES_PREFIX, key),
+            format!("{}{}", LOCK_PREFIX, key),
+        ])
+        .await?;
+        Ok(())
+    }
+}
@@ -1,5 +1,11 @@
+mod events;
+mod login_attempt;
+mod rate_limit;
 mod redis_pool;
 mod token;
 
+pub use events::UserEventAdapter;
+pub use login_attempt::LoginAttemptAdapter;
+pub use rate_limit::RateLimitAdapter;
 pub use redis_pool::get_redis_pool;
 pub use token::TokenAdapter;
@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use deadpool_redis::redis::Script;
+use deadpool_redis::Pool;
+
+use crate::configuration::TokenBucketSettings;
+use crate::service;
+
+const RATE_LIMIT_PREFIX: &str = "rate_limit:";
+
+/// Refills the bucket for the time elapsed since the last call and takes a token.
+/// Uses the Redis clock so every instance sees the same time.
+/// Returns `{allowed, retry_after_ms}`.
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local capacity = tonumber(ARGV[1])
+local refill_per_second = tonumber(ARGV[2])
+
+local time = redis.call('TIME')
+local now = tonumber(time[1]) + tonumber(time[2]) / 1000000
+
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at')
+local tokens = tonumber(bucket[1]) or capacity
+local updated_at = tonumber(bucket[2]) or now
+
+tokens = math.min(capacity, tokens + math.max(0, now - updated_at) * refill_per_second)
+
+local allowed = 0
+local retry_after_ms = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+else
+    retry_after_ms = math.ceil((1 - tokens) / refill_per_second * 1000)
+end
+
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated_at', tostring(now))
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / refill_per_second * 1000))
+
+return {allowed, retry_after_ms}
+"#;
+
+#[derive(Clone)]
+pub struct RateLimitAdapter {
+    pool: Pool,
+    script: Script,
+}
+
+impl RateLimitAdapter {
+    pub fn new(pool: Pool) -> Self {
+        Self {
+            pool,
+            script: Script::new(TOKEN_BUCKET_SCRIPT),
+        }
+    }
+}
+
+#[async_trait]
+impl service::RateLimitRepository for RateLimitAdapter {
+    async fn acquire(
+        &self,
+        key: &str,
+        bucket: &TokenBucketSettings,
+    ) -> anyhow::Result<Option<Duration>> {
+        let mut conn = self.pool.get().await?;
+
+        let (allowed, retry_after_ms): (i64, u64) = self
+            .script
+            .key(format!("{}{}", RATE_LIMIT_PREFIX, key))
+            .arg(bucket.capacity)
+            .arg(bucket.refill_per_second)
+            .invoke_async(&mut conn)
+            .await?;
+
+        if allowed == 1 {
+            Ok(None)
+        } else {
+            Ok(Some(Duration::from_millis(retry_after_ms)))
+        }
+    }
+}
@@ -1,45 +1,399 @@
-use std::str::FromStr;
+use std::collections::HashMap;
+use std::time::Duration;
 
+use anyhow::Context;
 use async_trait::async_trait;
-use deadpool_redis::redis::AsyncCommands;
+use chrono::{DateTime, TimeZone, Utc};
+use deadpool_redis::redis::{self, AsyncCommands, Script};
 use deadpool_redis::Pool;
 use shared::domain::UserId;
 use uuid::Uuid;
 
-use crate::service;
+use crate::service::{self, ClientInfo, OidcLogin, Rotation, Session, SocketTicket, UserTokenKind};
+
+/// Swaps the current token of a family if the presented one is current.
+/// Returns 1 when rotated, 2 when the token was already rotated out, 0 for unknown families.
+const ROTATE_SCRIPT: &str = r#"
+local current = redis.call('HGET', KEYS[1], 'current')
+if not current then
+    return 0
+end
+if current ~= ARGV[1] then
+    return 2
+end
+redis.call('HSET', KEYS[1], 'current', ARGV[2], 'last_used_at', ARGV[4], 'ip', ARGV[5], 'user_agent', ARGV[6])
+redis.call('EXPIRE', KEYS[1], ARGV[3])
+redis.call('EXPIRE', KEYS[2], ARGV[3])
+return 1
+"#;
+
+const CURRENT: &str = "current";
+const CREATED_AT: &str = "created_at";
+const LAST_USED_AT: &str = "last_used_at";
+const DEVICE_LABEL: &str = "device_label";
+const IP: &str = "ip";
+const USER_AGENT: &str = "user_agent";
 
 #[derive(Clone)]
 pub struct TokenAdapter {
     pool: Pool,
+    rotate_script: Script,
 }
 
 impl TokenAdapter {
     pub fn new(pool: Pool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            rotate_script: Script::new(ROTATE_SCRIPT),
+        }
+    }
+}
+
+fn family_key(family_id: &Uuid, user_id: &UserId) -> String {
+    format!("refresh_family:{}:{}", user_id.as_ref(), family_id)
+}
+
+/// Set of the family ids of a user. It is kept alive as long as its newest family, so
+/// it can hold ids of families that already expired.
+fn families_key(user_id: &UserId) -> String {
+    format!("refresh_families:{}", user_id.as_ref())
+}
+
+fn revoked_token_key(token_id: &Uuid) -> String {
+    format!("revoked_token:{}", token_id)
+}
+
+/// Unix time in seconds up to which every access token of the user is revoked.
+fn revoked_before_key(user_id: &UserId) -> String {
+    format!("revoked_before:{}", user_id.as_ref())
+}
+
+fn ticket_key(ticket: &str) -> String {
+    format!("ws_ticket:{}", ticket)
+}
+
+fn oidc_login_key(state: &str) -> String {
+    format!("oidc_login:{}", state)
+}
+
+fn user_token_key(kind: UserTokenKind, token: &str) -> String {
+    let prefix = match kind {
+        UserTokenKind::PasswordReset => "password_reset",
+        UserTokenKind::EmailVerification => "email_verification",
+    };
+    format!("{}:{}", prefix, token)
+}
+
+fn ttl_seconds(ttl: Duration) -> usize {
+    ttl.as_secs().max(1) as usize
+}
+
+fn ip_field(client: &ClientInfo) -> String {
+    client.ip.map(|ip| ip.to_string()).unwrap_or_default()
+}
+
+fn parse_session(
+    family_id: Uuid,
+    user_id: &UserId,
+    fields: HashMap<String, String>,
+) -> anyhow::Result<Option<Session>> {
+    if fields.is_empty() {
+        return Ok(None);
     }
+    let timestamp = |field: &str| -> anyhow::Result<DateTime<Utc>> {
+        let secs = fields
+            .get(field)
+            .with_context(|| format!("Missing {} of refresh family.", field))?
+            .parse()?;
+        Utc.timestamp_opt(secs, 0)
+            .single()
+            .context("Invalid timestamp.")
+    };
+
+    // Missing client fields are stored as empty strings.
+    let text = |field: &str| fields.get(field).filter(|v| !v.is_empty()).cloned();
+
+    Ok(Some(Session {
+        family_id,
+        user_id: *user_id,
+        client: ClientInfo {
+            device_label: text(DEVICE_LABEL),
+            ip: text(IP).and_then(|ip| ip.parse().ok()),
+            user_agent: text(USER_AGENT),
+        },
+        created_at: timestamp(CREATED_AT)?,
+        last_used_at: timestamp(LAST_USED_AT)?,
+    }))
 }
 
 #[async_trait]
 impl service::TokenRepository for TokenAdapter {
-    async fn create(&self, token_id: &Uuid, user_id: &UserId) -> anyhow::Result<()> {
+    async fn create(
+        &self,
+        family_id: &Uuid,
+        token_id: &Uuid,
+        user_id: &UserId,
+        ttl: Duration,
+        client: &ClientInfo,
+    ) -> anyhow::Result<()> {
         let mut conn = self.pool.get().await?;
 
-        let key = format!("{}:{}", user_id.as_ref(), token_id);
-        conn.set_ex(key, user_id.as_ref().to_string(), 3600).await?;
+        let key = family_key(family_id, user_id);
+        let families = families_key(user_id);
+        let now = Utc::now().timestamp();
+        redis::pipe()
+            .atomic()
+            .hset_multiple(
+                &key,
+                &[
+                    (CURRENT, token_id.to_string()),
+                    (CREATED_AT, now.to_string()),
+                    (LAST_USED_AT, now.to_string()),
+                    (
+                        DEVICE_LABEL,
+                        client.device_label.clone().unwrap_or_default(),
+                    ),
+                    (IP, ip_field(client)),
+                    (USER_AGENT, client.user_agent.clone().unwrap_or_default()),
+                ],
+            )
+            .expire(&key, ttl_seconds(ttl))
+            .sadd(&families, family_id.to_string())
+            .expire(&families, ttl_seconds(ttl))
+            .query_async::<_, ()>(&mut conn)
+            .await?;
         Ok(())
     }
-    async fn exist(&self, token_id: &Uuid, user_id: &UserId) -> anyhow::Result<Option<UserId>> {
+
+    async fn rotate(
+        &self,
+        family_id: &Uuid,
+        token_id: &Uuid,
+        new_token_id: &Uuid,
+        user_id: &UserId,
+        ttl: Duration,
+        client: &ClientInfo,
+    ) -> anyhow::Result<Rotation> {
         let mut conn = self.pool.get().await?;
 
-        let key = format!("{}:{}", user_id.as_ref(), token_id);
-        let res = conn.get::<String, String>(key).await?;
-        Ok(Some(UserId::from_str(&res)?))
+        let res: i64 = self
+            .rotate_script
+            .key(family_key(family_id, user_id))
+            .key(families_key(user_id))
+            .arg(token_id.to_string())
+            .arg(new_token_id.to_string())
+            .arg(ttl_seconds(ttl))
+            .arg(Utc::now().timestamp())
+            .arg(ip_field(client))
+            .arg(client.user_agent.clone().unwrap_or_default())
+            .invoke_async(&mut conn)
+            .await?;
+
+        Ok(match res {
+            1 => Rotation::Rotated,
+            2 => Rotation::Reused,
+            _ => Rotation::Unknown,
+        })
     }
-    async fn delete(&self, token_id: &Uuid, user_id: &UserId) -> anyhow::Result<()> {
+
+    async fn get(&self, family_id: &Uuid, user_id: &UserId) -> anyhow::Result<Option<Session>> {
+        let mut conn = self.pool.get().await?;
+
+        let fields: HashMap<String, String> = conn.hgetall(family_key(family_id, user_id)).await?;
+        parse_session(*family_id, user_id, fields)
+    }
+
+    async fn list(&self, user_id: &UserId) -> anyhow::Result<Vec<Session>> {
         let mut conn = self.pool.get().await?;
 
-        let key = format!("{}:{}", user_id.as_ref(), token_id);
-        conn.del(key).await?;
+        let family_ids: Vec<String> = conn.smembers(families_key(user_id)).await?;
+        let mut sessions = Vec::with_capacity(family_ids.len());
+        let mut expired = Vec::new();
+        for family_id in family_ids {
+            let id = Uuid::parse_str(&family_id)?;
+            let fields: HashMap<String, String> = conn.hgetall(family_key(&id, user_id)).await?;
+            match parse_session(id, user_id, fields)? {
+                Some(session) => sessions.push(session),
+                None => expired.push(family_id),
+            }
+        }
+        if !expired.is_empty() {
+            conn.srem(families_key(user_id), expired).await?;
+        }
+
+        sessions.sort_by(|a, b| b.last_used_at.cmp(&a.last_used_at));
+        Ok(sessions)
+    }
+
+    async fn revoke_family(
+        &self,
+        family_id: &Uuid,
+        user_id: &UserId,
+    ) -> anyhow::Result<Option<Uuid>> {
+        let mut conn = self.pool.get().await?;
+
+        let key = family_key(family_id, user_id);
+        let (current, _, _): (Option<String>, i64, i64) = redis::pipe()
+            .atomic()
+            .hget(&key, CURRENT)
+            .del(&key)
+            .srem(families_key(user_id), family_id.to_string())
+            .query_async(&mut conn)
+            .await?;
+        Ok(current.map(|id| Uuid::parse_str(&id)).transpose()?)
+    }
+
+    async fn revoke_all(&self, user_id: &UserId) -> anyhow::Result<u64> {
+        let mut conn = self.pool.get().await?;
+
+        let family_ids: Vec<String> = conn.smembers(families_key(user_id)).await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for family_id in family_ids.iter() {
+            pipe.del(family_key(&Uuid::parse_str(family_id)?, user_id));
+        }
+        pipe.del(families_key(user_id));
+        let deleted: Vec<u64> = pipe.query_async(&mut conn).await?;
+
+        // The last reply is the deletion of the set itself.
+        Ok(deleted.iter().rev().skip(1).sum())
+    }
+
+    async fn revoke_access(&self, token_id: &Uuid, ttl: Duration) -> anyhow::Result<()> {
+        let mut conn = self.pool.get().await?;
+
+        conn.set_ex(revoked_token_key(token_id), 1, ttl_seconds(ttl))
+            .await?;
         Ok(())
     }
+
+    async fn revoke_user_access(&self, user_id: &UserId, ttl: Duration) -> anyhow::Result<()> {
+        let mut conn = self.pool.get().await?;
+
+        conn.set_ex(
+            revoked_before_key(user_id),
+            Utc::now().timestamp(),
+            ttl_seconds(ttl),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn is_access_revoked(
+        &self,
+        token_id: &Uuid,
+        user_id: &UserId,
+        issued_at: u64,
+    ) -> anyhow::Result<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let (revoked, revoked_before): (Option<i64>, Option<u64>) = redis::pipe()
+            .get(revoked_token_key(token_id))
+            .get(revoked_before_key(user_id))
+            .query_async(&mut conn)
+            .await?;
+
+        // `iat` has whole seconds, tokens issued in the second of the revocation are kept
+        // so that signing in again right after a password change works.
+        Ok(revoked.is_some() || revoked_before.map_or(false, |before| issued_at < before))
+    }
+
+    async fn store_ticket(
+        &self,
+        ticket: &str,
+        owner: &SocketTicket,
+        ttl: Duration,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.pool.get().await?;
+
+        conn.set_ex(
+            ticket_key(ticket),
+            serde_json::to_string(owner)?,
+            ttl_seconds(ttl),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn take_ticket(&self, ticket: &str) -> anyhow::Result<Option<SocketTicket>> {
+        let mut conn = self.pool.get().await?;
+
+        let key = ticket_key(ticket);
+        let (owner, _): (Option<String>, i64) = redis::pipe()
+            .atomic()
+            .get(&key)
+            .del(&key)
+            .query_async(&mut conn)
+            .await?;
+        Ok(owner
+            .map(|owner| serde_json::from_str(&owner))
+            .transpose()?)
+    }
+
+    async fn store_oidc_login(
+        &self,
+        state: &str,
+        login: &OidcLogin,
+        ttl: Duration,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.pool.get().await?;
+
+        conn.set_ex(
+            oidc_login_key(state),
+            serde_json::to_string(login)?,
+            ttl_seconds(ttl),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn take_oidc_login(&self, state: &str) -> anyhow::Result<Option<OidcLogin>> {
+        let mut conn = self.pool.get().await?;
+
+        let key = oidc_login_key(state);
+        let (login, _): (Option<String>, i64) = redis::pipe()
+            .atomic()
+            .get(&key)
+            .del(&key)
+            .query_async(&mut conn)
+            .await?;
+        Ok(login
+            .map(|login| serde_json::from_str(&login))
+            .transpose()?)
+    }
+
+    async fn store_user_token(
+        &self,
+        kind: UserTokenKind,
+        token: &str,
+        user_id: &UserId,
+        ttl: Duration,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.pool.get().await?;
+
+        conn.set_ex(
+            user_token_key(kind, token),
+            user_id.as_ref().to_string(),
+            ttl_seconds(ttl),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn take_user_token(
+        &self,
+        kind: UserTokenKind,
+        token: &str,
+    ) -> anyhow::Result<Option<UserId>> {
+        let mut conn = self.pool.get().await?;
+
+        let key = user_token_key(kind, token);
+        let (user_id, _): (Option<String>, i64) = redis::pipe()
+            .atomic()
+            .get(&key)
+            .del(&key)
+            .query_async(&mut conn)
+            .await?;
+        Ok(user_id.map(|user_id| user_id.parse()).transpose()?)
+    }
 }
@@ -0,0 +1,58 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secrecy::ExposeSecret;
+
+use crate::configuration::EmailSettings;
+use crate::service::{self, Email};
+
+#[derive(Clone)]
+pub struct SmtpEmailSender {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: Mailbox,
+}
+
+impl SmtpEmailSender {
+    pub fn new(settings: &EmailSettings) -> Result<Self, anyhow::Error> {
+        let builder = if settings.starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)
+                .context("Invalid SMTP host.")?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+        };
+        let mut builder = builder.port(settings.port);
+        if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                password.expose_secret().clone(),
+            ));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            sender: settings.sender.parse().context("Invalid sender address.")?,
+        })
+    }
+}
+
+#[async_trait]
+impl service::EmailSender for SmtpEmailSender {
+    #[tracing::instrument(name = "Send email", skip(self, email), fields(subject = %email.subject))]
+    async fn send(&self, email: Email) -> Result<(), anyhow::Error> {
+        let message = Message::builder()
+            .from(self.sender.clone())
+            .to(email.to.parse().context("Invalid recipient address.")?)
+            .subject(email.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body)
+            .context("Failed build email.")?;
+
+        self.transport
+            .send(message)
+            .await
+            .context("Failed send email.")?;
+        Ok(())
+    }
+}
@@ -2,37 +2,210 @@ use std::sync::Arc;
 
 use axum::{
     middleware,
-    routing::{get, post},
+    routing::{delete, get, post, put},
+    Extension,
 };
 
+use crate::configuration::ClientIpSource;
 use crate::service;
 
-use super::{auth, ws};
+use super::{auth, bot, notification, outgoing_webhook, room, ws};
 
-pub fn get_api_router<A, C>(auth_service: A, chat_service: C) -> axum::Router
+#[allow(clippy::too_many_arguments)]
+pub fn get_api_router<A, C, N, R, B, W, K>(
+    auth_service: A,
+    chat_service: C,
+    notification_service: N,
+    rate_limit_service: R,
+    bot_service: B,
+    outgoing_webhook_service: W,
+    command_service: K,
+    cookies: auth::CookieAuth,
+    client_ip: ClientIpSource,
+) -> axum::Router
 where
     A: service::AuthService + Sync + Send + 'static,
     C: service::ChatService + Sync + Send + 'static,
+    N: service::NotificationService + Sync + Send + 'static,
+    R: service::RateLimitService + Sync + Send + 'static,
+    B: service::BotService + Sync + Send + 'static,
+    W: service::OutgoingWebhookService + Sync + Send + 'static,
+    K: service::CommandService + Sync + Send + 'static,
 {
     let auth_service = Arc::new(auth_service);
     let chat_service = Arc::new(chat_service);
+    let notification_service = Arc::new(notification_service);
+    let rate_limit_service = Arc::new(rate_limit_service);
+    let bot_service = Arc::new(bot_service);
+    let outgoing_webhook_service = Arc::new(outgoing_webhook_service);
 
     let require_authentication_middleware =
         middleware::from_fn_with_state(auth_service.clone(), auth::require_authentication);
 
-    let chat_state = Arc::new(ws::ChatState::new(auth_service.clone(), chat_service));
+    let chat_state = Arc::new(ws::ChatState::new(
+        auth_service.clone(),
+        chat_service,
+        notification_service.clone(),
+        rate_limit_service.clone(),
+        Arc::new(command_service),
+    ));
+    tokio::spawn(ws::forward_user_events(chat_state.clone()));
+
+    let stream_state = Arc::new(ws::StreamState::new(chat_state.clone()));
+
+    let bot_routes = axum::Router::new()
+        .route(
+            "/bot/rooms/:room_id/messages",
+            get(bot::list_messages).post(bot::send_message),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            bot_service.clone(),
+            bot::require_api_key,
+        ))
+        .with_state(chat_state.clone());
+
+    let webhook_routes = axum::Router::new()
+        .route("/hooks/:webhook_id", post(bot::receive_webhook))
+        .route_layer(middleware::from_fn_with_state(
+            bot_service.clone(),
+            bot::require_webhook_token,
+        ))
+        .with_state(chat_state.clone());
+
+    let bot_admin_routes = axum::Router::new()
+        .route("/admin/bots", post(bot::create_bot))
+        .route(
+            "/admin/bots/:bot_id/keys",
+            get(bot::list_api_keys).post(bot::create_api_key),
+        )
+        .route(
+            "/admin/bots/:bot_id/keys/:key_id",
+            delete(bot::revoke_api_key),
+        )
+        .route(
+            "/admin/rooms/:room_id/webhooks",
+            get(bot::list_webhooks).post(bot::create_webhook),
+        )
+        .route(
+            "/admin/rooms/:room_id/webhooks/:webhook_id",
+            delete(bot::revoke_webhook),
+        )
+        .route_layer(require_authentication_middleware.clone())
+        .with_state(bot_service);
+
+    let outgoing_webhook_routes = axum::Router::new()
+        .route(
+            "/rooms/:room_id/outgoing-webhooks",
+            get(outgoing_webhook::list_endpoints).post(outgoing_webhook::create_endpoint),
+        )
+        .route(
+            "/rooms/:room_id/outgoing-webhooks/:endpoint_id",
+            delete(outgoing_webhook::delete_endpoint),
+        )
+        .route(
+            "/rooms/:room_id/dead-letters",
+            get(outgoing_webhook::list_dead_letters),
+        )
+        .route(
+            "/rooms/:room_id/dead-letters/:delivery_id/retry",
+            post(outgoing_webhook::retry_dead_letter),
+        )
+        .route_layer(require_authentication_middleware.clone())
+        .with_state(outgoing_webhook_service);
+
+    let room_routes = axum::Router::new()
+        .route("/rooms/:room_id", get(room::get_room))
+        .route("/rooms/:room_id/topic", put(room::update_topic))
+        .route("/rooms/:room_id/description", put(room::update_description))
+        .route(
+            "/rooms/:room_id/avatar",
+            put(room::update_avatar).delete(room::remove_avatar),
+        )
+        .route(
+            "/rooms/:room_id/admins/:user_id",
+            put(room::grant_room_admin).delete(room::revoke_room_admin),
+        )
+        .route("/attachments/:attachment_id", get(room::get_attachment))
+        .route_layer(require_authentication_middleware.clone())
+        .with_state(chat_state.clone());
 
     let chat_router = axum::Router::new()
+        .route("/ws", get(ws::stream_handler))
         .route("/ws/:room", get(ws::websocket_handler))
         .with_state(chat_state);
 
+    let stream_router = axum::Router::new()
+        .route("/stream/:stream_id/events", post(ws::send_stream_event))
+        .route("/poll", post(ws::open_poll))
+        .route("/poll/:stream_id", get(ws::poll_events))
+        .route_layer(require_authentication_middleware.clone())
+        .route("/sse", get(ws::sse_handler))
+        .with_state(stream_state);
+
+    let notification_routes = axum::Router::new()
+        .route("/notifications", get(notification::list_notifications))
+        .route(
+            "/notifications/read",
+            post(notification::mark_notifications_read),
+        )
+        .route_layer(require_authentication_middleware.clone())
+        .with_state(notification_service);
+
     let auth_routes = axum::Router::new()
         .route("/logout", get(auth::logout))
-        .route("/refresh", get(auth::refresh))
+        .route(
+            "/sessions",
+            get(auth::list_sessions).delete(auth::revoke_all_sessions),
+        )
+        .route("/sessions/:session_id", delete(auth::revoke_session))
+        .route("/ws/ticket", post(auth::issue_ws_ticket))
+        .route("/password", post(auth::change_password))
+        .route("/email/verify/resend", post(auth::resend_verification))
+        .route("/mfa/totp", post(auth::enroll_totp))
+        .route("/mfa/totp/confirm", post(auth::confirm_totp))
+        .route("/mfa/totp/disable", post(auth::disable_totp))
+        .route("/admin/users/:user_id/unlock", post(auth::unlock_account))
+        .route(
+            "/admin/users/:user_id/sessions",
+            delete(auth::revoke_user_sessions),
+        )
         .route_layer(require_authentication_middleware)
-        .route("/login", post(auth::login))
+        .route("/refresh", get(auth::refresh))
+        .route("/login", post(auth::login::<A, R>))
+        .route("/login/mfa", post(auth::login_mfa::<A, R>))
         .route("/signup", post(auth::signup))
+        .route("/oidc/:provider/authorize", get(auth::oidc_authorize))
+        .route("/oidc/:provider/callback", post(auth::oidc_callback))
+        .route("/password/forgot", post(auth::forgot_password::<A, R>))
+        .route("/password/reset", post(auth::reset_password))
+        .route("/email/verify", post(auth::verify_email))
+        .route_layer(middleware::from_fn_with_state(
+            rate_limit_service.clone(),
+            auth::limit_by_ip,
+        ))
+        .layer(Extension(rate_limit_service))
         .with_state(auth_service);
 
-    axum::Router::new().merge(auth_routes).merge(chat_router)
+    axum::Router::new()
+        .merge(auth_routes)
+        .merge(notification_routes)
+        .merge(bot_routes)
+        .merge(webhook_routes)
+        .merge(bot_admin_routes)
+        .merge(outgoing_webhook_routes)
+        .merge(room_routes)
+        .merge(chat_router)
+        .merge(stream_router)
+        .layer(Extension(Arc::new(cookies)))
+        .layer(Extension(client_ip))
+}
+
+/// Routes served outside of `/api` at well-known locations.
+pub fn get_well_known_router<A>(auth_service: A) -> axum::Router
+where
+    A: service::AuthService + Sync + Send + 'static,
+{
+    axum::Router::new()
+        .route("/.well-known/jwks.json", get(auth::jwks))
+        .with_state(Arc::new(auth_service))
 }
@@ -0,0 +1,106 @@
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::{request::Parts, HeaderMap};
+
+use crate::configuration::ClientIpSource;
+use crate::service;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_REAL_IP: &str = "x-real-ip";
+
+/// IP of the client, read from the [`ClientIpSource`] extension. Falls back to the peer
+/// address when the header is missing or malformed.
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = service::Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ConnectInfo(peer) = ConnectInfo::<SocketAddr>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| anyhow!("Missing connection info: {}", e))?;
+        let source = parts
+            .extensions
+            .get::<ClientIpSource>()
+            .copied()
+            .unwrap_or_default();
+        Ok(ClientIp(client_ip(source, peer.ip(), &parts.headers)))
+    }
+}
+
+fn client_ip(source: ClientIpSource, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+    let header = match source {
+        ClientIpSource::Peer => return peer,
+        ClientIpSource::XForwardedFor => headers
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .last()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next()),
+        ClientIpSource::XRealIp => headers.get(X_REAL_IP).and_then(|value| value.to_str().ok()),
+    };
+    header.and_then(|ip| ip.trim().parse().ok()).unwrap_or(peer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    const PROXY: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1));
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn peer_ignores_headers() {
+        let headers = headers(&[(X_FORWARDED_FOR, "203.0.113.7"), (X_REAL_IP, "203.0.113.7")]);
+
+        assert_eq!(client_ip(ClientIpSource::Peer, PROXY, &headers), PROXY);
+    }
+
+    #[test]
+    fn forwarded_for_takes_the_address_appended_by_the_proxy() {
+        let headers = headers(&[
+            (X_FORWARDED_FOR, "198.51.100.1, 198.51.100.2"),
+            (X_FORWARDED_FOR, "192.0.2.9, 203.0.113.7"),
+        ]);
+
+        assert_eq!(
+            client_ip(ClientIpSource::XForwardedFor, PROXY, &headers),
+            IpAddr::from([203, 0, 113, 7])
+        );
+    }
+
+    #[test]
+    fn real_ip_is_read_from_its_header() {
+        let headers = headers(&[(X_REAL_IP, "2001:db8::1")]);
+
+        assert_eq!(
+            client_ip(ClientIpSource::XRealIp, PROXY, &headers),
+            "2001:db8::1".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn missing_or_malformed_header_falls_back_to_the_peer() {
+        assert_eq!(
+            client_ip(ClientIpSource::XForwardedFor, PROXY, &HeaderMap::new()),
+            PROXY
+        );
+        let headers = headers(&[(X_REAL_IP, "unknown")]);
+        assert_eq!(client_ip(ClientIpSource::XRealIp, PROXY, &headers), PROXY);
+    }
+}
@@ -0,0 +1,244 @@
+use axum::http::HeaderMap;
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use secrecy::{ExposeSecret, Secret};
+
+use crate::configuration::{AuthSettings, SameSiteSetting};
+use crate::service;
+
+const ACCESS_COOKIE: &str = "access_token";
+const REFRESH_COOKIE: &str = "refresh_token";
+/// Readable by the client, which echoes it in the [`CSRF_HEADER`].
+const CSRF_COOKIE: &str = "csrf_token";
+const CSRF_HEADER: &str = "x-csrf-token";
+const AUTH_MODE_HEADER: &str = "x-auth-mode";
+
+/// The refresh token is only sent to the route that consumes it.
+const REFRESH_PATH: &str = "/api/refresh";
+
+/// Cookie auth mode: tokens live in HttpOnly cookies and requests authenticated by them
+/// must repeat the CSRF cookie in a header.
+#[derive(Clone)]
+pub struct CookieAuth {
+    enabled: bool,
+    secure: bool,
+    same_site: SameSite,
+    access_max_age: time::Duration,
+    refresh_max_age: time::Duration,
+}
+
+impl CookieAuth {
+    pub fn new(settings: &AuthSettings) -> Self {
+        let cookies = &settings.cookies;
+        Self {
+            enabled: cookies.enabled,
+            secure: cookies.secure,
+            same_site: match cookies.same_site {
+                SameSiteSetting::Strict => SameSite::Strict,
+                SameSiteSetting::Lax => SameSite::Lax,
+            },
+            access_max_age: time::Duration::seconds(settings.access_toked_duration as i64),
+            refresh_max_age: time::Duration::seconds(settings.refresh_toked_duration as i64),
+        }
+    }
+
+    /// Whether the client asked for its tokens in cookies.
+    pub fn requested(&self, headers: &HeaderMap) -> bool {
+        self.enabled
+            && headers
+                .get(AUTH_MODE_HEADER)
+                .and_then(|mode| mode.to_str().ok())
+                .map_or(false, |mode| mode.eq_ignore_ascii_case("cookie"))
+    }
+
+    /// Stores the tokens in cookies, returns the jar and the CSRF token to send along.
+    pub fn set_tokens(
+        &self,
+        jar: CookieJar,
+        access_token: &Secret<String>,
+        refresh_token: &Secret<String>,
+    ) -> (CookieJar, String) {
+        let csrf_token = uuid::Uuid::new_v4().simple().to_string();
+
+        let access = self.cookie(
+            ACCESS_COOKIE,
+            access_token.expose_secret().clone(),
+            "/",
+            self.access_max_age,
+        );
+        let refresh = self.cookie(
+            REFRESH_COOKIE,
+            refresh_token.expose_secret().clone(),
+            REFRESH_PATH,
+            self.refresh_max_age,
+        );
+        let mut csrf = self.cookie(CSRF_COOKIE, csrf_token.clone(), "/", self.refresh_max_age);
+        csrf.set_http_only(false);
+
+        (jar.add(access).add(refresh).add(csrf), csrf_token)
+    }
+
+    pub fn clear(&self, jar: CookieJar) -> CookieJar {
+        jar.remove(Cookie::build(ACCESS_COOKIE, "").path("/").finish())
+            .remove(
+                Cookie::build(REFRESH_COOKIE, "")
+                    .path(REFRESH_PATH)
+                    .finish(),
+            )
+            .remove(Cookie::build(CSRF_COOKIE, "").path("/").finish())
+    }
+
+    /// Access token of a request authenticated by cookie, after checking its CSRF token.
+    pub fn access_token(
+        &self,
+        jar: &CookieJar,
+        headers: &HeaderMap,
+    ) -> Result<Option<String>, service::Error> {
+        self.token(ACCESS_COOKIE, jar, headers)
+    }
+
+    /// Refresh token of a request authenticated by cookie, after checking its CSRF token.
+    pub fn refresh_token(
+        &self,
+        jar: &CookieJar,
+        headers: &HeaderMap,
+    ) -> Result<Option<String>, service::Error> {
+        self.token(REFRESH_COOKIE, jar, headers)
+    }
+
+    /// Access token cookie of an event stream request, which `EventSource` cannot add the
+    /// CSRF header to. Only for requests that read: other origins cannot read the stream.
+    pub fn event_stream_access_token(&self, jar: &CookieJar) -> Option<String> {
+        self.cookie_value(ACCESS_COOKIE, jar)
+    }
+
+    fn token(
+        &self,
+        name: &str,
+        jar: &CookieJar,
+        headers: &HeaderMap,
+    ) -> Result<Option<String>, service::Error> {
+        let Some(token) = self.cookie_value(name, jar) else {
+            return Ok(None);
+        };
+        let expected = jar.get(CSRF_COOKIE).map(|cookie| cookie.value());
+        let presented = headers
+            .get(CSRF_HEADER)
+            .and_then(|value| value.to_str().ok());
+        match (expected, presented) {
+            (Some(expected), Some(presented)) if !expected.is_empty() && expected == presented => {
+                Ok(Some(token))
+            }
+            _ => Err(invalid("Missing or invalid CSRF token.")),
+        }
+    }
+
+    fn cookie_value(&self, name: &str, jar: &CookieJar) -> Option<String> {
+        if !self.enabled {
+            return None;
+        }
+        jar.get(name)
+            .map(|cookie| cookie.value().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn cookie(
+        &self,
+        name: &'static str,
+        value: String,
+        path: &'static str,
+        max_age: time::Duration,
+    ) -> Cookie<'static> {
+        Cookie::build(name, value)
+            .path(path)
+            .max_age(max_age)
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(self.same_site)
+            .finish()
+    }
+}
+
+fn invalid(message: &'static str) -> service::Error {
+    service::Error::InvalidCredentials(anyhow::anyhow!(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn cookie_auth() -> CookieAuth {
+        CookieAuth {
+            enabled: true,
+            secure: true,
+            same_site: SameSite::Strict,
+            access_max_age: time::Duration::minutes(1),
+            refresh_max_age: time::Duration::minutes(10),
+        }
+    }
+
+    fn signed_in(auth: &CookieAuth) -> (CookieJar, String) {
+        auth.set_tokens(
+            CookieJar::new(),
+            &Secret::new("access".to_string()),
+            &Secret::new("refresh".to_string()),
+        )
+    }
+
+    #[test]
+    fn tokens_are_http_only_and_csrf_is_readable() {
+        let auth = cookie_auth();
+        let (jar, _) = signed_in(&auth);
+
+        let access = jar.get(ACCESS_COOKIE).unwrap();
+        assert_eq!(access.http_only(), Some(true));
+        assert_eq!(access.secure(), Some(true));
+        assert_eq!(access.same_site(), Some(SameSite::Strict));
+        assert_eq!(jar.get(CSRF_COOKIE).unwrap().http_only(), Some(false));
+    }
+
+    #[test]
+    fn cookie_token_requires_matching_csrf_header() {
+        let auth = cookie_auth();
+        let (jar, csrf_token) = signed_in(&auth);
+        let mut headers = HeaderMap::new();
+
+        assert!(auth.access_token(&jar, &headers).is_err());
+
+        headers.insert(CSRF_HEADER, HeaderValue::from_static("forged"));
+        assert!(auth.access_token(&jar, &headers).is_err());
+
+        headers.insert(CSRF_HEADER, HeaderValue::from_str(&csrf_token).unwrap());
+        assert_eq!(
+            auth.access_token(&jar, &headers).unwrap().as_deref(),
+            Some("access")
+        );
+    }
+
+    #[test]
+    fn event_stream_token_needs_no_csrf_header() {
+        let auth = cookie_auth();
+        let (jar, _) = signed_in(&auth);
+
+        assert_eq!(
+            auth.event_stream_access_token(&jar).as_deref(),
+            Some("access")
+        );
+        assert_eq!(auth.event_stream_access_token(&CookieJar::new()), None);
+    }
+
+    #[test]
+    fn cookies_are_ignored_when_disabled() {
+        let enabled = cookie_auth();
+        let (jar, _) = signed_in(&enabled);
+        let disabled = CookieAuth {
+            enabled: false,
+            ..cookie_auth()
+        };
+
+        assert_eq!(
+            disabled.access_token(&jar, &HeaderMap::new()).unwrap(),
+            None
+        );
+    }
+}
@@ -2,12 +2,15 @@ use chrono::{DateTime, Utc};
 use secrecy::Secret;
 use shared::domain::{NewUser, User};
 
-use crate::service;
+use crate::service::{self, Session};
 
 #[derive(serde::Deserialize)]
 pub struct LoginRequest {
     pub email: String,
     pub password: Secret<String>,
+    /// Label of the device shown when listing sessions.
+    #[serde(default)]
+    pub device: Option<String>,
 }
 
 impl From<LoginRequest> for service::Credentials {
@@ -19,10 +22,31 @@ impl From<LoginRequest> for service::Credentials {
     }
 }
 
+/// Login waits for the second factor, to be sent with this token to `/login/mfa`.
 #[derive(serde::Serialize)]
-pub struct TokensResponse {
-    pub access_token: String,
-    pub refresh_token: String,
+pub struct MfaChallengeResponse {
+    pub mfa_token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct LoginMfaRequest {
+    pub mfa_token: Secret<String>,
+    /// TOTP or recovery code.
+    pub code: Secret<String>,
+    #[serde(default)]
+    pub device: Option<String>,
+}
+
+/// Issued tokens, in the body or, in cookie mode, as cookies.
+#[derive(serde::Serialize)]
+#[serde(untagged)]
+pub enum TokensResponse {
+    Bearer {
+        access_token: String,
+        refresh_token: String,
+    },
+    /// The tokens were set as cookies, requests repeat this token in `X-CSRF-Token`.
+    Cookie { csrf_token: String },
 }
 
 #[derive(serde::Deserialize)]
@@ -31,6 +55,8 @@ pub struct SignupRequest {
     pub email: String,
     pub password: Secret<String>,
     pub code: String,
+    #[serde(default)]
+    pub device: Option<String>,
 }
 
 impl TryFrom<SignupRequest> for NewUser {
@@ -42,6 +68,7 @@ impl TryFrom<SignupRequest> for NewUser {
             email,
             password,
             code,
+            device: _,
         } = req;
 
         Ok(Self {
@@ -56,8 +83,8 @@ impl TryFrom<SignupRequest> for NewUser {
 #[derive(serde::Serialize)]
 pub struct SignupResponse {
     pub user: UserResponse,
-    pub access_token: String,
-    pub refresh_token: String,
+    #[serde(flatten)]
+    pub tokens: TokensResponse,
 }
 
 #[derive(serde::Serialize)]
@@ -87,3 +114,99 @@ impl From<User> for UserResponse {
         }
     }
 }
+
+#[derive(serde::Serialize)]
+pub struct SessionResponse {
+    pub session_id: uuid::Uuid,
+    pub device: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    /// Whether the request was made with this session's tokens.
+    pub current: bool,
+}
+
+impl SessionResponse {
+    pub fn new(session: Session, current: &uuid::Uuid) -> Self {
+        let Session {
+            family_id,
+            user_id: _,
+            client,
+            created_at,
+            last_used_at,
+        } = session;
+        Self {
+            session_id: family_id,
+            device: client.device_label,
+            ip: client.ip.map(|ip| ip.to_string()),
+            user_agent: client.user_agent,
+            created_at,
+            last_used_at,
+            current: family_id == *current,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct RevokeSessionsResponse {
+    pub revoked: u64,
+}
+
+#[derive(serde::Serialize)]
+pub struct TicketResponse {
+    pub ticket: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: Secret<String>,
+    pub new_password: Secret<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: Secret<String>,
+    pub new_password: Secret<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: Secret<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct TotpEnrollmentResponse {
+    pub secret: String,
+    pub provisioning_uri: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TotpCodeRequest {
+    pub code: Secret<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct RecoveryCodesResponse {
+    /// Shown once, each signs in once instead of a TOTP code.
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct OidcAuthorizeResponse {
+    /// Page of the provider to send the user to.
+    pub authorization_url: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct OidcCallbackRequest {
+    pub code: Secret<String>,
+    pub state: String,
+    #[serde(default)]
+    pub device: Option<String>,
+}
@@ -1,91 +1,565 @@
-use axum::extract::State;
+use anyhow::anyhow;
+use axum::extract::{Path, State};
 use axum::headers::authorization::Bearer;
-use axum::headers::Authorization;
-use axum::http::StatusCode;
+use axum::headers::{Authorization, UserAgent};
+use axum::http::{header, HeaderMap, StatusCode};
 use axum::response::IntoResponse;
 use axum::response::Response;
+use axum::Extension;
 use axum::Json;
 use axum::TypedHeader;
+use axum_extra::extract::CookieJar;
 use secrecy::ExposeSecret;
 use secrecy::Secret;
 use shared::domain::NewUser;
+use std::net::IpAddr;
 use std::sync::Arc;
 
-use crate::service;
+use crate::service::{self, Claims, ClientInfo, LoginOutcome};
 
+use super::client_ip::ClientIp;
+use super::cookies::CookieAuth;
+use super::dto::ChangePasswordRequest;
+use super::dto::ForgotPasswordRequest;
+use super::dto::LoginMfaRequest;
 use super::dto::LoginRequest;
+use super::dto::MfaChallengeResponse;
+use super::dto::OidcAuthorizeResponse;
+use super::dto::OidcCallbackRequest;
+use super::dto::RecoveryCodesResponse;
+use super::dto::ResetPasswordRequest;
+use super::dto::RevokeSessionsResponse;
+use super::dto::SessionResponse;
+use super::dto::TicketResponse;
 use super::dto::TokensResponse;
+use super::dto::TotpCodeRequest;
+use super::dto::TotpEnrollmentResponse;
+use super::dto::VerifyEmailRequest;
 use super::SignupRequest;
 use super::SignupResponse;
 
-#[tracing::instrument(name = "Login user", skip(auth_service, login_request))]
-pub async fn login<A>(
+/// Other services may not see a newly published key for this long.
+const JWKS_CACHE_CONTROL: &str = "public, max-age=300";
+
+#[tracing::instrument(
+    name = "Login user",
+    skip(
+        auth_service,
+        rate_limit_service,
+        cookies,
+        user_agent,
+        headers,
+        jar,
+        login_request
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn login<A, R>(
+    State(auth_service): State<Arc<A>>,
+    Extension(rate_limit_service): Extension<Arc<R>>,
+    Extension(cookies): Extension<Arc<CookieAuth>>,
+    ClientIp(ip): ClientIp,
+    user_agent: Option<TypedHeader<UserAgent>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(mut login_request): Json<LoginRequest>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+    R: service::RateLimitService,
+{
+    rate_limit_service
+        .check_auth_account(&login_request.email)
+        .await?;
+
+    let client = client_info(ip, user_agent, login_request.device.take());
+    let outcome = auth_service
+        .login(service::Credentials::from(login_request), client)
+        .await?;
+    let (asses_token, refresh_token) = match outcome {
+        LoginOutcome::Tokens(tokens) => tokens,
+        LoginOutcome::MfaRequired(mfa_token) => {
+            let response = MfaChallengeResponse {
+                mfa_token: mfa_token.expose_secret().to_string(),
+            };
+            return Ok((StatusCode::OK, Json(response)).into_response());
+        }
+    };
+
+    let cookie_mode = cookies.requested(&headers);
+    let (jar, token_response) =
+        issue_tokens(&cookies, cookie_mode, jar, asses_token, refresh_token);
+    Ok((StatusCode::OK, jar, Json(token_response)).into_response())
+}
+
+/// Second step of a login with two-factor authentication.
+#[tracing::instrument(
+    name = "Login user with second factor",
+    skip(
+        auth_service,
+        rate_limit_service,
+        cookies,
+        user_agent,
+        headers,
+        jar,
+        req
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn login_mfa<A, R>(
     State(auth_service): State<Arc<A>>,
-    Json(login_request): Json<LoginRequest>,
+    Extension(rate_limit_service): Extension<Arc<R>>,
+    Extension(cookies): Extension<Arc<CookieAuth>>,
+    ClientIp(ip): ClientIp,
+    user_agent: Option<TypedHeader<UserAgent>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(mut req): Json<LoginMfaRequest>,
 ) -> Result<Response, service::Error>
 where
     A: service::AuthService,
+    R: service::RateLimitService,
 {
+    // Keyed on the user, a new challenge for the same account shares the bucket.
+    let user_id = auth_service.mfa_challenge_user(req.mfa_token.expose_secret())?;
+    rate_limit_service
+        .check_auth_account(&user_id.as_ref().to_string())
+        .await?;
+
+    let client = client_info(ip, user_agent, req.device.take());
     let (asses_token, refresh_token) = auth_service
-        .login(service::Credentials::from(login_request))
+        .login_mfa(req.mfa_token, req.code.expose_secret(), client)
         .await?;
 
-    let token_response = TokensResponse {
-        access_token: asses_token.expose_secret().to_string(),
-        refresh_token: refresh_token.expose_secret().to_string(),
+    let cookie_mode = cookies.requested(&headers);
+    let (jar, token_response) =
+        issue_tokens(&cookies, cookie_mode, jar, asses_token, refresh_token);
+    Ok((StatusCode::OK, jar, Json(token_response)).into_response())
+}
+
+#[tracing::instrument(name = "Start OIDC login", skip(auth_service))]
+pub async fn oidc_authorize<A>(
+    State(auth_service): State<Arc<A>>,
+    Path(provider): Path<String>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+{
+    let authorization_url = auth_service.oidc_authorize(&provider).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(OidcAuthorizeResponse { authorization_url }),
+    )
+        .into_response())
+}
+
+/// Called by the client page the provider redirected to. The page must check that `state`
+/// is the one it started the login with, so that nobody signs its user in to their account.
+/// Users with a second factor get an MFA challenge to finish at `/login/mfa`.
+#[tracing::instrument(
+    name = "Finish OIDC login",
+    skip(auth_service, cookies, user_agent, headers, jar, req)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn oidc_callback<A>(
+    State(auth_service): State<Arc<A>>,
+    Extension(cookies): Extension<Arc<CookieAuth>>,
+    Path(provider): Path<String>,
+    ClientIp(ip): ClientIp,
+    user_agent: Option<TypedHeader<UserAgent>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(mut req): Json<OidcCallbackRequest>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+{
+    let client = client_info(ip, user_agent, req.device.take());
+    let outcome = auth_service
+        .oidc_login(&provider, req.code.expose_secret(), &req.state, client)
+        .await?;
+    let (asses_token, refresh_token) = match outcome {
+        LoginOutcome::Tokens(tokens) => tokens,
+        LoginOutcome::MfaRequired(mfa_token) => {
+            let response = MfaChallengeResponse {
+                mfa_token: mfa_token.expose_secret().to_string(),
+            };
+            return Ok((StatusCode::OK, Json(response)).into_response());
+        }
     };
-    Ok((StatusCode::OK, Json(token_response)).into_response())
+
+    let cookie_mode = cookies.requested(&headers);
+    let (jar, token_response) =
+        issue_tokens(&cookies, cookie_mode, jar, asses_token, refresh_token);
+    Ok((StatusCode::OK, jar, Json(token_response)).into_response())
 }
 
-#[tracing::instrument(name = "Refresh token", skip(auth_service, bearer))]
+/// Refreshes the token pair presented as bearer token or, in cookie mode, as cookie.
+#[tracing::instrument(
+    name = "Refresh token",
+    skip(auth_service, cookies, user_agent, bearer, headers, jar)
+)]
 pub async fn refresh<A>(
     State(auth_service): State<Arc<A>>,
-    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Extension(cookies): Extension<Arc<CookieAuth>>,
+    ClientIp(ip): ClientIp,
+    user_agent: Option<TypedHeader<UserAgent>>,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    headers: HeaderMap,
+    jar: CookieJar,
 ) -> Result<Response, service::Error>
 where
     A: service::AuthService,
 {
-    let token = Secret::new(bearer.token().to_string());
-    let (asses_token, refresh_token) = auth_service.refresh(token).await?;
-
-    let token_response = TokensResponse {
-        access_token: asses_token.expose_secret().to_string(),
-        refresh_token: refresh_token.expose_secret().to_string(),
+    let (token, cookie_mode) = match bearer {
+        Some(TypedHeader(Authorization(bearer))) => (bearer.token().to_string(), false),
+        None => {
+            let token = cookies
+                .refresh_token(&jar, &headers)?
+                .ok_or_else(|| service::Error::InvalidCredentials(anyhow!("Missing token.")))?;
+            (token, true)
+        }
     };
-    Ok((StatusCode::OK, Json(token_response)).into_response())
+    let client = client_info(ip, user_agent, None);
+    let (asses_token, refresh_token) = auth_service.refresh(Secret::new(token), client).await?;
+
+    let (jar, token_response) =
+        issue_tokens(&cookies, cookie_mode, jar, asses_token, refresh_token);
+    Ok((StatusCode::OK, jar, Json(token_response)).into_response())
 }
 
-#[tracing::instrument(name = "Logout user", skip(auth_service, bearer))]
+#[tracing::instrument(
+    name = "Logout user",
+    skip(auth_service, cookies, bearer, headers, jar)
+)]
 pub async fn logout<A>(
     State(auth_service): State<Arc<A>>,
-    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Extension(cookies): Extension<Arc<CookieAuth>>,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    headers: HeaderMap,
+    jar: CookieJar,
 ) -> Result<Response, service::Error>
 where
     A: service::AuthService,
 {
-    let token = Secret::new(bearer.token().to_string());
-    auth_service.logout(token).await?;
+    let token = match bearer {
+        Some(TypedHeader(Authorization(bearer))) => bearer.token().to_string(),
+        None => cookies
+            .access_token(&jar, &headers)?
+            .ok_or_else(|| service::Error::InvalidCredentials(anyhow!("Missing token.")))?,
+    };
+    auth_service.logout(Secret::new(token)).await?;
 
-    Ok((StatusCode::NO_CONTENT).into_response())
+    Ok((StatusCode::NO_CONTENT, cookies.clear(jar)).into_response())
 }
 
-#[tracing::instrument(name = "Signup user", skip(auth_service, req))]
+#[tracing::instrument(
+    name = "Signup user",
+    skip(auth_service, cookies, user_agent, headers, jar, req)
+)]
 pub async fn signup<A>(
     State(auth_service): State<Arc<A>>,
-    Json(req): Json<SignupRequest>,
+    Extension(cookies): Extension<Arc<CookieAuth>>,
+    ClientIp(ip): ClientIp,
+    user_agent: Option<TypedHeader<UserAgent>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(mut req): Json<SignupRequest>,
 ) -> Result<Response, service::Error>
 where
     A: service::AuthService,
 {
+    let client = client_info(ip, user_agent, req.device.take());
     let new_user = NewUser::try_from(req)?;
 
-    let (user, (asses_token, refresh_token)) = auth_service.signup(new_user).await?;
+    let (user, (asses_token, refresh_token)) = auth_service.signup(new_user, client).await?;
 
+    let cookie_mode = cookies.requested(&headers);
+    let (jar, tokens) = issue_tokens(&cookies, cookie_mode, jar, asses_token, refresh_token);
     let response = SignupResponse {
         user: user.into(),
-        access_token: asses_token.expose_secret().to_string(),
-        refresh_token: refresh_token.expose_secret().to_string(),
+        tokens,
     };
 
+    Ok((StatusCode::CREATED, jar, Json(response)).into_response())
+}
+
+#[tracing::instrument(name = "List sessions", skip(auth_service, claims))]
+pub async fn list_sessions<A>(
+    State(auth_service): State<Arc<A>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+{
+    let current = claims.family_id();
+    let response: Vec<SessionResponse> = auth_service
+        .list_sessions(&claims.user_id())
+        .await?
+        .into_iter()
+        .map(|session| SessionResponse::new(session, &current))
+        .collect();
+
+    Ok((StatusCode::OK, Json(response)).into_response())
+}
+
+#[tracing::instrument(name = "Revoke session", skip(auth_service, claims))]
+pub async fn revoke_session<A>(
+    State(auth_service): State<Arc<A>>,
+    Extension(claims): Extension<Claims>,
+    Path(session_id): Path<uuid::Uuid>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+{
+    auth_service
+        .revoke_session(&claims.user_id(), &session_id)
+        .await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+#[tracing::instrument(name = "Revoke all sessions", skip(auth_service, claims))]
+pub async fn revoke_all_sessions<A>(
+    State(auth_service): State<Arc<A>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+{
+    let revoked = auth_service.revoke_all_sessions(&claims.user_id()).await?;
+
+    Ok((StatusCode::OK, Json(RevokeSessionsResponse { revoked })).into_response())
+}
+
+/// Exchanges the access token of the request for a ticket to open a socket with, so
+/// that tokens never appear in socket URLs.
+#[tracing::instrument(name = "Request socket ticket", skip(auth_service, claims))]
+pub async fn issue_ws_ticket<A>(
+    State(auth_service): State<Arc<A>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+{
+    let ticket = auth_service.issue_ticket(&claims).await?;
+
+    let response = TicketResponse {
+        ticket: ticket.expose_secret().to_string(),
+    };
+    Ok((StatusCode::CREATED, Json(response)).into_response())
+}
+
+/// Every session is signed out, the caller gets the tokens of a new one.
+#[tracing::instrument(
+    name = "Change password",
+    skip(auth_service, cookies, claims, headers, jar, req)
+)]
+pub async fn change_password<A>(
+    State(auth_service): State<Arc<A>>,
+    Extension(cookies): Extension<Arc<CookieAuth>>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(req): Json<ChangePasswordRequest>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+{
+    let (asses_token, refresh_token) = auth_service
+        .change_password(&claims, req.current_password, req.new_password)
+        .await?;
+
+    let cookie_mode = cookies.requested(&headers);
+    let (jar, token_response) =
+        issue_tokens(&cookies, cookie_mode, jar, asses_token, refresh_token);
+    Ok((StatusCode::OK, jar, Json(token_response)).into_response())
+}
+
+/// Accepted whether or not the email belongs to a user, so that it cannot be used to
+/// find out who has an account.
+#[tracing::instrument(name = "Forgot password", skip(auth_service, rate_limit_service, req))]
+pub async fn forgot_password<A, R>(
+    State(auth_service): State<Arc<A>>,
+    Extension(rate_limit_service): Extension<Arc<R>>,
+    Json(req): Json<ForgotPasswordRequest>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+    R: service::RateLimitService,
+{
+    rate_limit_service.check_auth_account(&req.email).await?;
+
+    auth_service.request_password_reset(&req.email).await?;
+
+    Ok(StatusCode::ACCEPTED.into_response())
+}
+
+#[tracing::instrument(name = "Reset password", skip(auth_service, req))]
+pub async fn reset_password<A>(
+    State(auth_service): State<Arc<A>>,
+    Json(req): Json<ResetPasswordRequest>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+{
+    auth_service
+        .reset_password(req.token.expose_secret(), req.new_password)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[tracing::instrument(name = "Verify email", skip(auth_service, req))]
+pub async fn verify_email<A>(
+    State(auth_service): State<Arc<A>>,
+    Json(req): Json<VerifyEmailRequest>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+{
+    auth_service.verify_email(req.token.expose_secret()).await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[tracing::instrument(name = "Resend verification email", skip(auth_service, claims))]
+pub async fn resend_verification<A>(
+    State(auth_service): State<Arc<A>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+{
+    auth_service.resend_verification(&claims.user_id()).await?;
+
+    Ok(StatusCode::ACCEPTED.into_response())
+}
+
+#[tracing::instrument(name = "Enroll TOTP", skip(auth_service, claims))]
+pub async fn enroll_totp<A>(
+    State(auth_service): State<Arc<A>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+{
+    let enrollment = auth_service.enroll_totp(&claims.user_id()).await?;
+
+    let response = TotpEnrollmentResponse {
+        secret: enrollment.secret.expose_secret().to_string(),
+        provisioning_uri: enrollment.provisioning_uri.expose_secret().to_string(),
+    };
     Ok((StatusCode::CREATED, Json(response)).into_response())
 }
+
+#[tracing::instrument(name = "Confirm TOTP", skip(auth_service, claims, req))]
+pub async fn confirm_totp<A>(
+    State(auth_service): State<Arc<A>>,
+    Extension(claims): Extension<Claims>,
+    Json(req): Json<TotpCodeRequest>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+{
+    let recovery_codes = auth_service
+        .confirm_totp(&claims.user_id(), req.code.expose_secret())
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(RecoveryCodesResponse { recovery_codes }),
+    )
+        .into_response())
+}
+
+#[tracing::instrument(name = "Disable TOTP", skip(auth_service, claims, req))]
+pub async fn disable_totp<A>(
+    State(auth_service): State<Arc<A>>,
+    Extension(claims): Extension<Claims>,
+    Json(req): Json<TotpCodeRequest>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+{
+    auth_service
+        .disable_totp(&claims.user_id(), req.code.expose_secret())
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[tracing::instrument(name = "Unlock account", skip(auth_service, claims))]
+pub async fn unlock_account<A>(
+    State(auth_service): State<Arc<A>>,
+    Extension(claims): Extension<Claims>,
+    Path(user_id): Path<uuid::Uuid>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+{
+    auth_service
+        .unlock_account(&claims.user_id(), &user_id.into())
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Signs a user out everywhere, e.g. when banning them.
+#[tracing::instrument(name = "Revoke sessions of user", skip(auth_service, claims))]
+pub async fn revoke_user_sessions<A>(
+    State(auth_service): State<Arc<A>>,
+    Extension(claims): Extension<Claims>,
+    Path(user_id): Path<uuid::Uuid>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService,
+{
+    let revoked = auth_service
+        .revoke_user_sessions(&claims.user_id(), &user_id.into())
+        .await?;
+
+    Ok((StatusCode::OK, Json(RevokeSessionsResponse { revoked })).into_response())
+}
+
+/// Serves the public keys for other services to verify our tokens with.
+pub async fn jwks<A>(State(auth_service): State<Arc<A>>) -> Response
+where
+    A: service::AuthService,
+{
+    let headers = [(header::CACHE_CONTROL, JWKS_CACHE_CONTROL)];
+    (StatusCode::OK, headers, Json(auth_service.jwks())).into_response()
+}
+
+/// Hands the tokens to the client in the body or, in cookie mode, as cookies.
+fn issue_tokens(
+    cookies: &CookieAuth,
+    cookie_mode: bool,
+    jar: CookieJar,
+    access_token: Secret<String>,
+    refresh_token: Secret<String>,
+) -> (CookieJar, TokensResponse) {
+    if cookie_mode {
+        let (jar, csrf_token) = cookies.set_tokens(jar, &access_token, &refresh_token);
+        (jar, TokensResponse::Cookie { csrf_token })
+    } else {
+        let response = TokensResponse::Bearer {
+            access_token: access_token.expose_secret().to_string(),
+            refresh_token: refresh_token.expose_secret().to_string(),
+        };
+        (jar, response)
+    }
+}
+
+fn client_info(
+    ip: IpAddr,
+    user_agent: Option<TypedHeader<UserAgent>>,
+    device_label: Option<String>,
+) -> ClientInfo {
+    ClientInfo {
+        device_label,
+        ip: Some(ip),
+        user_agent: user_agent.map(|TypedHeader(user_agent)| user_agent.as_str().to_string()),
+    }
+}
@@ -1,24 +1,53 @@
+use anyhow::anyhow;
+
 use crate::service;
+
+use super::{ClientIp, CookieAuth};
 use axum::{
     extract::State,
     headers::{authorization::Bearer, Authorization},
     http::Request,
     middleware::Next,
     response::Response,
-    TypedHeader,
+    Extension, TypedHeader,
 };
+use axum_extra::extract::CookieJar;
 use std::sync::Arc;
 
+/// Authenticates by bearer token or, in cookie mode, by the access token cookie and
+/// the CSRF header.
 pub async fn require_authentication<T, A>(
     State(auth_service): State<Arc<A>>,
-    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Extension(cookies): Extension<Arc<CookieAuth>>,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    jar: CookieJar,
     mut request: Request<T>,
     next: Next<T>,
 ) -> Result<Response, service::Error>
 where
     A: service::AuthService,
 {
-    let claims = auth_service.validate_token(bearer.token()).await?;
+    let token = match bearer {
+        Some(TypedHeader(Authorization(bearer))) => bearer.token().to_string(),
+        None => cookies
+            .access_token(&jar, request.headers())?
+            .ok_or_else(|| service::Error::InvalidCredentials(anyhow!("Missing token.")))?,
+    };
+    let claims = auth_service.validate_token(&token).await?;
     request.extensions_mut().insert(claims);
     Ok(next.run(request).await)
 }
+
+/// Throttles requests per client IP.
+pub async fn limit_by_ip<T, R>(
+    State(rate_limit_service): State<Arc<R>>,
+    ClientIp(ip): ClientIp,
+    request: Request<T>,
+    next: Next<T>,
+) -> Result<Response, service::Error>
+where
+    R: service::RateLimitService,
+{
+    rate_limit_service.check_auth_ip(&ip).await?;
+    Ok(next.run(request).await)
+}
@@ -1,7 +1,11 @@
+mod client_ip;
+mod cookies;
 mod dto;
 mod handlers;
 mod middleware;
 
+pub use client_ip::*;
+pub use cookies::*;
 pub use dto::*;
 pub use handlers::*;
 pub use middleware::*;
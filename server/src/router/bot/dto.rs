@@ -0,0 +1,163 @@
+use chrono::{DateTime, Utc};
+use shared::domain::{MessageContent, User};
+
+use crate::service::{self, ApiKey, ApiKeyScope, Webhook};
+
+#[derive(serde::Deserialize)]
+pub struct CreateBotRequest {
+    pub name: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct BotResponse {
+    pub bot_id: uuid::Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<User> for BotResponse {
+    fn from(bot: User) -> Self {
+        Self {
+            bot_id: *bot.user_id.as_ref(),
+            name: bot.name.as_ref().to_owned(),
+            created_at: bot.created_at,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub room_ids: Vec<uuid::Uuid>,
+    #[serde(default)]
+    pub send: bool,
+    #[serde(default)]
+    pub read: bool,
+}
+
+impl From<CreateApiKeyRequest> for ApiKeyScope {
+    fn from(req: CreateApiKeyRequest) -> Self {
+        Self {
+            room_ids: req.room_ids.into_iter().map(Into::into).collect(),
+            send: req.send,
+            read: req.read,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct ApiKeyResponse {
+    pub key_id: uuid::Uuid,
+    pub bot_id: uuid::Uuid,
+    pub prefix: String,
+    pub room_ids: Vec<uuid::Uuid>,
+    pub send: bool,
+    pub read: bool,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(key: ApiKey) -> Self {
+        let ApiKey {
+            key_id,
+            bot_id,
+            prefix,
+            scope,
+            created_at,
+            revoked_at,
+        } = key;
+        Self {
+            key_id,
+            bot_id: *bot_id.as_ref(),
+            prefix,
+            room_ids: scope.room_ids.iter().map(|id| *id.as_ref()).collect(),
+            send: scope.send,
+            read: scope.read,
+            created_at,
+            revoked_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct CreatedApiKeyResponse {
+    #[serde(flatten)]
+    pub key: ApiKeyResponse,
+    /// Shown once, sent as `Authorization: Bearer <api_key>`.
+    pub api_key: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SendMessageRequest {
+    pub content: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct CreateWebhookRequest {
+    /// Bot the webhook posts as.
+    pub bot_id: uuid::Uuid,
+}
+
+#[derive(serde::Serialize)]
+pub struct WebhookResponse {
+    pub webhook_id: uuid::Uuid,
+    pub room_id: uuid::Uuid,
+    pub bot_id: uuid::Uuid,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<Webhook> for WebhookResponse {
+    fn from(webhook: Webhook) -> Self {
+        let Webhook {
+            webhook_id,
+            room_id,
+            bot_id,
+            created_at,
+            revoked_at,
+        } = webhook;
+        Self {
+            webhook_id,
+            room_id: *room_id.as_ref(),
+            bot_id: *bot_id.as_ref(),
+            created_at,
+            revoked_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct CreatedWebhookResponse {
+    #[serde(flatten)]
+    pub webhook: WebhookResponse,
+    /// Path under `/api` that posts to the webhook.
+    pub path: String,
+    /// Shown once, sent as bearer token with each post.
+    pub token: String,
+}
+
+/// Body posted to an incoming webhook. `content` is accepted for `text`, as tools
+/// differ in what they call it.
+#[derive(serde::Deserialize)]
+pub struct IncomingWebhookRequest {
+    #[serde(alias = "content")]
+    pub text: String,
+    /// Summary put before the text, as alerts come with one.
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+impl TryFrom<IncomingWebhookRequest> for MessageContent {
+    type Error = service::Error;
+
+    fn try_from(req: IncomingWebhookRequest) -> Result<Self, Self::Error> {
+        let IncomingWebhookRequest { text, title } = req;
+        let content = match title.filter(|title| !title.trim().is_empty()) {
+            Some(title) => format!("{}: {}", title.trim(), text),
+            None => text,
+        };
+        content
+            .parse()
+            .map_err(|e: shared::domain::Error| service::Error::ValidationError(e.to_string()))
+    }
+}
@@ -0,0 +1,237 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use secrecy::ExposeSecret;
+use shared::domain::{Message, MessageContent, NewMessage, RoomId, UserName};
+use std::sync::Arc;
+
+use crate::router::ws::{self, SharedChatState};
+use crate::service::{self, ApiKey, Claims, Webhook};
+
+use super::dto::{
+    ApiKeyResponse, BotResponse, CreateApiKeyRequest, CreateBotRequest, CreateWebhookRequest,
+    CreatedApiKeyResponse, CreatedWebhookResponse, IncomingWebhookRequest, SendMessageRequest,
+    WebhookResponse,
+};
+
+#[tracing::instrument(name = "Create bot", skip(bot_service, claims, req))]
+pub async fn create_bot<B>(
+    State(bot_service): State<Arc<B>>,
+    Extension(claims): Extension<Claims>,
+    Json(req): Json<CreateBotRequest>,
+) -> Result<Response, service::Error>
+where
+    B: service::BotService,
+{
+    let name: UserName = req
+        .name
+        .parse()
+        .map_err(|e: shared::domain::Error| service::Error::ValidationError(e.to_string()))?;
+
+    let bot = bot_service.create_bot(&claims.user_id(), name).await?;
+
+    Ok((StatusCode::CREATED, Json(BotResponse::from(bot))).into_response())
+}
+
+#[tracing::instrument(name = "Create API key", skip(bot_service, claims, req))]
+pub async fn create_api_key<B>(
+    State(bot_service): State<Arc<B>>,
+    Extension(claims): Extension<Claims>,
+    Path(bot_id): Path<uuid::Uuid>,
+    Json(req): Json<CreateApiKeyRequest>,
+) -> Result<Response, service::Error>
+where
+    B: service::BotService,
+{
+    let (key, api_key) = bot_service
+        .create_api_key(&claims.user_id(), &bot_id.into(), req.into())
+        .await?;
+
+    let response = CreatedApiKeyResponse {
+        key: key.into(),
+        api_key: api_key.expose_secret().to_owned(),
+    };
+    Ok((StatusCode::CREATED, Json(response)).into_response())
+}
+
+pub async fn list_api_keys<B>(
+    State(bot_service): State<Arc<B>>,
+    Extension(claims): Extension<Claims>,
+    Path(bot_id): Path<uuid::Uuid>,
+) -> Result<Response, service::Error>
+where
+    B: service::BotService,
+{
+    let keys = bot_service
+        .list_api_keys(&claims.user_id(), &bot_id.into())
+        .await?;
+
+    let response: Vec<ApiKeyResponse> = keys.into_iter().map(ApiKeyResponse::from).collect();
+    Ok((StatusCode::OK, Json(response)).into_response())
+}
+
+pub async fn revoke_api_key<B>(
+    State(bot_service): State<Arc<B>>,
+    Extension(claims): Extension<Claims>,
+    Path((bot_id, key_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> Result<Response, service::Error>
+where
+    B: service::BotService,
+{
+    bot_service
+        .revoke_api_key(&claims.user_id(), &bot_id.into(), &key_id)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Sends a message as the bot, through the same path as messages from sockets.
+#[tracing::instrument(name = "Send bot message", skip(state, api_key, req))]
+pub async fn send_message<A, C, N, R, K>(
+    State(state): State<SharedChatState<A, C, N, R, K>>,
+    Extension(api_key): Extension<ApiKey>,
+    Path(room_id): Path<uuid::Uuid>,
+    Json(req): Json<SendMessageRequest>,
+) -> Result<Response, service::Error>
+where
+    C: service::ChatService,
+    N: service::NotificationService,
+    R: service::RateLimitService,
+{
+    let room_id: RoomId = room_id.into();
+    api_key.scope.require_send(&room_id)?;
+    let content: MessageContent = req
+        .content
+        .parse()
+        .map_err(|e: shared::domain::Error| service::Error::ValidationError(e.to_string()))?;
+
+    let message = deliver(
+        &state,
+        NewMessage {
+            user_id: api_key.bot_id,
+            room_id,
+            content,
+        },
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(message)).into_response())
+}
+
+#[tracing::instrument(name = "List bot messages", skip(state, api_key))]
+pub async fn list_messages<A, C, N, R, K>(
+    State(state): State<SharedChatState<A, C, N, R, K>>,
+    Extension(api_key): Extension<ApiKey>,
+    Path(room_id): Path<uuid::Uuid>,
+) -> Result<Response, service::Error>
+where
+    C: service::ChatService,
+{
+    let room_id: RoomId = room_id.into();
+    api_key.scope.require_read(&room_id)?;
+
+    let messages = state.chat_service.get_messages(&room_id).await?;
+
+    Ok((StatusCode::OK, Json(messages)).into_response())
+}
+
+#[tracing::instrument(name = "Create webhook", skip(bot_service, claims, req))]
+pub async fn create_webhook<B>(
+    State(bot_service): State<Arc<B>>,
+    Extension(claims): Extension<Claims>,
+    Path(room_id): Path<uuid::Uuid>,
+    Json(req): Json<CreateWebhookRequest>,
+) -> Result<Response, service::Error>
+where
+    B: service::BotService,
+{
+    let (webhook, token) = bot_service
+        .create_webhook(&claims.user_id(), &room_id.into(), &req.bot_id.into())
+        .await?;
+
+    let response = CreatedWebhookResponse {
+        path: format!("/hooks/{}", webhook.webhook_id),
+        token: token.expose_secret().to_owned(),
+        webhook: webhook.into(),
+    };
+    Ok((StatusCode::CREATED, Json(response)).into_response())
+}
+
+pub async fn list_webhooks<B>(
+    State(bot_service): State<Arc<B>>,
+    Extension(claims): Extension<Claims>,
+    Path(room_id): Path<uuid::Uuid>,
+) -> Result<Response, service::Error>
+where
+    B: service::BotService,
+{
+    let webhooks = bot_service
+        .list_webhooks(&claims.user_id(), &room_id.into())
+        .await?;
+
+    let response: Vec<WebhookResponse> = webhooks.into_iter().map(WebhookResponse::from).collect();
+    Ok((StatusCode::OK, Json(response)).into_response())
+}
+
+pub async fn revoke_webhook<B>(
+    State(bot_service): State<Arc<B>>,
+    Extension(claims): Extension<Claims>,
+    Path((room_id, webhook_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> Result<Response, service::Error>
+where
+    B: service::BotService,
+{
+    bot_service
+        .revoke_webhook(&claims.user_id(), &room_id.into(), &webhook_id)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Posts the body of an incoming webhook into its room, as its bot.
+#[tracing::instrument(name = "Receive webhook", skip(state, webhook, req))]
+pub async fn receive_webhook<A, C, N, R, K>(
+    State(state): State<SharedChatState<A, C, N, R, K>>,
+    Extension(webhook): Extension<Webhook>,
+    Json(req): Json<IncomingWebhookRequest>,
+) -> Result<Response, service::Error>
+where
+    C: service::ChatService,
+    N: service::NotificationService,
+    R: service::RateLimitService,
+{
+    let content = MessageContent::try_from(req)?;
+
+    let message = deliver(
+        &state,
+        NewMessage {
+            user_id: webhook.bot_id,
+            room_id: webhook.room_id,
+            content,
+        },
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(message)).into_response())
+}
+
+async fn deliver<A, C, N, R, K>(
+    state: &SharedChatState<A, C, N, R, K>,
+    new_message: NewMessage,
+) -> Result<Message, service::Error>
+where
+    C: service::ChatService,
+    N: service::NotificationService,
+    R: service::RateLimitService,
+{
+    let room_tx = state.get_or_create_room_chanel(&new_message.room_id);
+    ws::deliver_message(
+        state.chat_service.as_ref(),
+        state.notification_service.as_ref(),
+        state.rate_limit_service.as_ref(),
+        &room_tx,
+        new_message,
+    )
+    .await
+}
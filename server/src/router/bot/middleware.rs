@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use axum::{
+    extract::{Path, State},
+    headers::{authorization::Bearer, Authorization},
+    http::Request,
+    middleware::Next,
+    response::Response,
+    TypedHeader,
+};
+
+use crate::service;
+
+/// Authenticates bots by the API key in the bearer token.
+pub async fn require_api_key<T, B>(
+    State(bot_service): State<Arc<B>>,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    mut request: Request<T>,
+    next: Next<T>,
+) -> Result<Response, service::Error>
+where
+    B: service::BotService,
+{
+    let TypedHeader(Authorization(bearer)) =
+        bearer.ok_or_else(|| service::Error::InvalidCredentials(anyhow!("Missing API key.")))?;
+    let api_key = bot_service.authenticate(bearer.token()).await?;
+    request.extensions_mut().insert(api_key);
+    Ok(next.run(request).await)
+}
+
+/// Authenticates posts to an incoming webhook by the token in the bearer token, kept out
+/// of the path as request URIs end up in the logs.
+pub async fn require_webhook_token<T, B>(
+    State(bot_service): State<Arc<B>>,
+    Path(webhook_id): Path<uuid::Uuid>,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    mut request: Request<T>,
+    next: Next<T>,
+) -> Result<Response, service::Error>
+where
+    B: service::BotService,
+{
+    let TypedHeader(Authorization(bearer)) = bearer
+        .ok_or_else(|| service::Error::InvalidCredentials(anyhow!("Missing webhook token.")))?;
+    let webhook = bot_service
+        .authenticate_webhook(&webhook_id, bearer.token())
+        .await?;
+    request.extensions_mut().insert(webhook);
+    Ok(next.run(request).await)
+}
@@ -0,0 +1,7 @@
+mod dto;
+mod handlers;
+mod middleware;
+
+pub use dto::*;
+pub use handlers::*;
+pub use middleware::*;
@@ -1,7 +1,9 @@
-use axum::http::StatusCode;
+use axum::http::{header::RETRY_AFTER, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 
+use shared::domain::event::{ErrorCode, ServerError};
+
 use crate::service;
 
 #[derive(serde::Serialize)]
@@ -11,16 +13,64 @@ pub struct ErrorResponse {
 
 impl IntoResponse for service::Error {
     fn into_response(self) -> Response {
+        let retry_after = self.retry_after_secs();
         let status: StatusCode = match self {
             Self::NotFound(_) => StatusCode::NOT_FOUND,
             Self::ValidationError(_) => StatusCode::BAD_REQUEST,
             Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::InvalidCredentials(_) => StatusCode::FORBIDDEN,
             Self::ConflictError(_) => StatusCode::CONFLICT,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
         };
         let resp = ErrorResponse {
             message: self.to_string(),
         };
-        (status, Json(resp)).into_response()
+        let mut response = (status, Json(resp)).into_response();
+        if let Some(retry_after) = retry_after {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        response
+    }
+}
+
+/// Socket counterpart of `IntoResponse`, sent to the client as `ServerEvent::ErrMessage`.
+impl From<&service::Error> for ServerError {
+    fn from(error: &service::Error) -> Self {
+        match error {
+            service::Error::TooManyRequests(retry_after) => ServerError::rate_limited(*retry_after),
+            service::Error::NotFound(_) => ServerError::new(ErrorCode::NotFound, error.to_string()),
+            service::Error::Forbidden(_) => {
+                ServerError::new(ErrorCode::Forbidden, error.to_string())
+            }
+            _ => ServerError::new(ErrorCode::Other, error.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn fractional_retry_after_is_rounded_up() {
+        let error = service::Error::TooManyRequests(Duration::from_millis(1500));
+        assert_eq!(error.to_string(), "Too many requests, retry in 2 seconds.");
+
+        let response = error.into_response();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "2");
+    }
+
+    #[test]
+    fn retry_after_is_at_least_a_second() {
+        let error = service::Error::TooManyRequests(Duration::from_millis(10));
+
+        assert_eq!(error.retry_after_secs(), Some(1));
     }
 }
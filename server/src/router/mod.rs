@@ -1,4 +1,8 @@
 pub mod api;
 pub mod auth;
+pub mod bot;
 pub mod error;
+pub mod notification;
+pub mod outgoing_webhook;
+pub mod room;
 pub mod ws;
@@ -0,0 +1,51 @@
+use chrono::{DateTime, Utc};
+use shared::domain::Notification;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct NotificationsQuery {
+    #[serde(default)]
+    pub unread: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub struct MarkReadRequest {
+    /// Notifications to mark as read, all unread ones when omitted.
+    pub ids: Option<Vec<uuid::Uuid>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct MarkReadResponse {
+    pub updated: u64,
+}
+
+#[derive(serde::Serialize)]
+pub struct NotificationResponse {
+    pub notification_id: uuid::Uuid,
+    pub sender_id: uuid::Uuid,
+    pub room_id: uuid::Uuid,
+    pub message_id: uuid::Uuid,
+    pub created_at: DateTime<Utc>,
+    pub read_at: Option<DateTime<Utc>>,
+}
+
+impl From<Notification> for NotificationResponse {
+    fn from(notification: Notification) -> Self {
+        let Notification {
+            id,
+            user_id: _,
+            sender_id,
+            room_id,
+            message_id,
+            created_at,
+            read_at,
+        } = notification;
+        Self {
+            notification_id: *id.as_ref(),
+            sender_id: *sender_id.as_ref(),
+            room_id: *room_id.as_ref(),
+            message_id: *message_id.as_ref(),
+            created_at,
+            read_at,
+        }
+    }
+}
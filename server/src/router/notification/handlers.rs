@@ -0,0 +1,54 @@
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use shared::domain::NotificationId;
+use std::sync::Arc;
+
+use crate::service::{self, Claims};
+
+use super::dto::{MarkReadRequest, MarkReadResponse, NotificationResponse, NotificationsQuery};
+
+#[tracing::instrument(name = "List notifications", skip(notification_service, claims))]
+pub async fn list_notifications<N>(
+    State(notification_service): State<Arc<N>>,
+    Extension(claims): Extension<Claims>,
+    Query(query): Query<NotificationsQuery>,
+) -> Result<Response, service::Error>
+where
+    N: service::NotificationService,
+{
+    let notifications = notification_service
+        .list(&claims.user_id(), query.unread)
+        .await?;
+
+    let response: Vec<NotificationResponse> = notifications
+        .into_iter()
+        .map(NotificationResponse::from)
+        .collect();
+    Ok((StatusCode::OK, Json(response)).into_response())
+}
+
+#[tracing::instrument(
+    name = "Mark notifications read",
+    skip(notification_service, claims, req)
+)]
+pub async fn mark_notifications_read<N>(
+    State(notification_service): State<Arc<N>>,
+    Extension(claims): Extension<Claims>,
+    Json(req): Json<MarkReadRequest>,
+) -> Result<Response, service::Error>
+where
+    N: service::NotificationService,
+{
+    let user_id = claims.user_id();
+    let updated = match req.ids {
+        Some(ids) => {
+            let ids: Vec<NotificationId> = ids.into_iter().map(NotificationId::from).collect();
+            notification_service.mark_read(&user_id, &ids).await?
+        }
+        None => notification_service.mark_all_read(&user_id).await?,
+    };
+
+    Ok((StatusCode::OK, Json(MarkReadResponse { updated })).into_response())
+}
@@ -0,0 +1,80 @@
+use chrono::{DateTime, Utc};
+
+use crate::service::{HookEventKind, WebhookDelivery, WebhookEndpoint};
+
+#[derive(serde::Deserialize)]
+pub struct CreateEndpointRequest {
+    pub url: String,
+    pub events: Vec<HookEventKind>,
+}
+
+#[derive(serde::Serialize)]
+pub struct EndpointResponse {
+    pub endpoint_id: uuid::Uuid,
+    pub room_id: uuid::Uuid,
+    pub url: String,
+    pub events: Vec<HookEventKind>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<WebhookEndpoint> for EndpointResponse {
+    fn from(endpoint: WebhookEndpoint) -> Self {
+        let WebhookEndpoint {
+            endpoint_id,
+            room_id,
+            url,
+            events,
+            created_at,
+        } = endpoint;
+        Self {
+            endpoint_id,
+            room_id: *room_id.as_ref(),
+            url,
+            events,
+            created_at,
+        }
+    }
+}
+
+/// Returned once, receivers verify signatures with the secret.
+#[derive(serde::Serialize)]
+pub struct CreatedEndpointResponse {
+    #[serde(flatten)]
+    pub endpoint: EndpointResponse,
+    pub secret: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct DeadLetterResponse {
+    pub delivery_id: uuid::Uuid,
+    pub endpoint_id: uuid::Uuid,
+    pub url: String,
+    pub event: HookEventKind,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<WebhookDelivery> for DeadLetterResponse {
+    fn from(delivery: WebhookDelivery) -> Self {
+        let WebhookDelivery {
+            delivery_id,
+            endpoint_id,
+            url,
+            event,
+            attempts,
+            last_error,
+            created_at,
+            ..
+        } = delivery;
+        Self {
+            delivery_id,
+            endpoint_id,
+            url,
+            event,
+            attempts,
+            last_error,
+            created_at,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use secrecy::ExposeSecret;
+use std::sync::Arc;
+
+use crate::service::{self, Claims};
+
+use super::dto::{
+    CreateEndpointRequest, CreatedEndpointResponse, DeadLetterResponse, EndpointResponse,
+};
+
+#[tracing::instrument(name = "Create outgoing webhook", skip(hook_service, claims, req))]
+pub async fn create_endpoint<W>(
+    State(hook_service): State<Arc<W>>,
+    Extension(claims): Extension<Claims>,
+    Path(room_id): Path<uuid::Uuid>,
+    Json(req): Json<CreateEndpointRequest>,
+) -> Result<Response, service::Error>
+where
+    W: service::OutgoingWebhookService,
+{
+    let (endpoint, secret) = hook_service
+        .create_endpoint(&claims.user_id(), &room_id.into(), req.url, req.events)
+        .await?;
+
+    let response = CreatedEndpointResponse {
+        endpoint: endpoint.into(),
+        secret: secret.expose_secret().to_owned(),
+    };
+    Ok((StatusCode::CREATED, Json(response)).into_response())
+}
+
+pub async fn list_endpoints<W>(
+    State(hook_service): State<Arc<W>>,
+    Extension(claims): Extension<Claims>,
+    Path(room_id): Path<uuid::Uuid>,
+) -> Result<Response, service::Error>
+where
+    W: service::OutgoingWebhookService,
+{
+    let endpoints = hook_service
+        .list_endpoints(&claims.user_id(), &room_id.into())
+        .await?;
+
+    let response: Vec<EndpointResponse> =
+        endpoints.into_iter().map(EndpointResponse::from).collect();
+    Ok((StatusCode::OK, Json(response)).into_response())
+}
+
+pub async fn delete_endpoint<W>(
+    State(hook_service): State<Arc<W>>,
+    Extension(claims): Extension<Claims>,
+    Path((room_id, endpoint_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> Result<Response, service::Error>
+where
+    W: service::OutgoingWebhookService,
+{
+    hook_service
+        .delete_endpoint(&claims.user_id(), &room_id.into(), &endpoint_id)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+pub async fn list_dead_letters<W>(
+    State(hook_service): State<Arc<W>>,
+    Extension(claims): Extension<Claims>,
+    Path(room_id): Path<uuid::Uuid>,
+) -> Result<Response, service::Error>
+where
+    W: service::OutgoingWebhookService,
+{
+    let deliveries = hook_service
+        .list_dead_letters(&claims.user_id(), &room_id.into())
+        .await?;
+
+    let response: Vec<DeadLetterResponse> = deliveries
+        .into_iter()
+        .map(DeadLetterResponse::from)
+        .collect();
+    Ok((StatusCode::OK, Json(response)).into_response())
+}
+
+pub async fn retry_dead_letter<W>(
+    State(hook_service): State<Arc<W>>,
+    Extension(claims): Extension<Claims>,
+    Path((room_id, delivery_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> Result<Response, service::Error>
+where
+    W: service::OutgoingWebhookService,
+{
+    hook_service
+        .retry_dead_letter(&claims.user_id(), &room_id.into(), &delivery_id)
+        .await?;
+
+    Ok(StatusCode::ACCEPTED.into_response())
+}
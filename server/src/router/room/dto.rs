@@ -0,0 +1,37 @@
+use shared::domain::{RoomDescription, RoomTopic};
+
+use crate::service;
+
+/// `null` clears the topic.
+#[derive(serde::Deserialize)]
+pub struct UpdateTopicRequest {
+    pub topic: Option<String>,
+}
+
+impl TryFrom<UpdateTopicRequest> for Option<RoomTopic> {
+    type Error = service::Error;
+
+    fn try_from(req: UpdateTopicRequest) -> Result<Self, Self::Error> {
+        req.topic
+            .map(RoomTopic::try_from)
+            .transpose()
+            .map_err(|e: shared::domain::Error| service::Error::ValidationError(e.to_string()))
+    }
+}
+
+/// `null` clears the description.
+#[derive(serde::Deserialize)]
+pub struct UpdateDescriptionRequest {
+    pub description: Option<String>,
+}
+
+impl TryFrom<UpdateDescriptionRequest> for Option<RoomDescription> {
+    type Error = service::Error;
+
+    fn try_from(req: UpdateDescriptionRequest) -> Result<Self, Self::Error> {
+        req.description
+            .map(RoomDescription::try_from)
+            .transpose()
+            .map_err(|e: shared::domain::Error| service::Error::ValidationError(e.to_string()))
+    }
+}
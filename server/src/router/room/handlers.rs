@@ -0,0 +1,171 @@
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+
+use crate::router::ws::{self, SharedChatState};
+use crate::service::{self, Attachment, Claims, RoomChange};
+
+use super::dto::{UpdateDescriptionRequest, UpdateTopicRequest};
+
+pub async fn get_room<A, C, N, R, K>(
+    State(state): State<SharedChatState<A, C, N, R, K>>,
+    Extension(claims): Extension<Claims>,
+    Path(room_id): Path<uuid::Uuid>,
+) -> Result<Response, service::Error>
+where
+    C: service::ChatService,
+{
+    let room = state
+        .chat_service
+        .get_room(&claims.user_id(), &room_id.into())
+        .await?;
+
+    Ok((StatusCode::OK, Json(room)).into_response())
+}
+
+#[tracing::instrument(name = "Update room topic", skip(state, claims, req))]
+pub async fn update_topic<A, C, N, R, K>(
+    State(state): State<SharedChatState<A, C, N, R, K>>,
+    Extension(claims): Extension<Claims>,
+    Path(room_id): Path<uuid::Uuid>,
+    Json(req): Json<UpdateTopicRequest>,
+) -> Result<Response, service::Error>
+where
+    C: service::ChatService,
+{
+    let change = RoomChange::Topic(req.try_into()?);
+    update(&state, &claims, room_id, change).await
+}
+
+#[tracing::instrument(name = "Update room description", skip(state, claims, req))]
+pub async fn update_description<A, C, N, R, K>(
+    State(state): State<SharedChatState<A, C, N, R, K>>,
+    Extension(claims): Extension<Claims>,
+    Path(room_id): Path<uuid::Uuid>,
+    Json(req): Json<UpdateDescriptionRequest>,
+) -> Result<Response, service::Error>
+where
+    C: service::ChatService,
+{
+    let change = RoomChange::Description(req.try_into()?);
+    update(&state, &claims, room_id, change).await
+}
+
+/// The body is the image itself, typed by its `Content-Type` header.
+#[tracing::instrument(name = "Update room avatar", skip(state, claims, headers, body))]
+pub async fn update_avatar<A, C, N, R, K>(
+    State(state): State<SharedChatState<A, C, N, R, K>>,
+    Extension(claims): Extension<Claims>,
+    Path(room_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, service::Error>
+where
+    C: service::ChatService,
+{
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let avatar = Attachment {
+        content_type,
+        data: body.to_vec(),
+    };
+    update(&state, &claims, room_id, RoomChange::Avatar(Some(avatar))).await
+}
+
+#[tracing::instrument(name = "Remove room avatar", skip(state, claims))]
+pub async fn remove_avatar<A, C, N, R, K>(
+    State(state): State<SharedChatState<A, C, N, R, K>>,
+    Extension(claims): Extension<Claims>,
+    Path(room_id): Path<uuid::Uuid>,
+) -> Result<Response, service::Error>
+where
+    C: service::ChatService,
+{
+    update(&state, &claims, room_id, RoomChange::Avatar(None)).await
+}
+
+pub async fn get_attachment<A, C, N, R, K>(
+    State(state): State<SharedChatState<A, C, N, R, K>>,
+    Extension(claims): Extension<Claims>,
+    Path(attachment_id): Path<uuid::Uuid>,
+) -> Result<Response, service::Error>
+where
+    C: service::ChatService,
+{
+    let attachment = state
+        .chat_service
+        .get_attachment(&claims.user_id(), &attachment_id.into())
+        .await?;
+
+    // Attachments never change, a new upload gets a new id.
+    let headers = [
+        (header::CONTENT_TYPE, attachment.content_type),
+        (
+            header::CACHE_CONTROL,
+            "private, max-age=31536000, immutable".to_string(),
+        ),
+    ];
+    Ok((StatusCode::OK, headers, attachment.data).into_response())
+}
+
+#[tracing::instrument(name = "Grant room admin", skip(state, claims))]
+pub async fn grant_room_admin<A, C, N, R, K>(
+    State(state): State<SharedChatState<A, C, N, R, K>>,
+    Extension(claims): Extension<Claims>,
+    Path((room_id, user_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> Result<Response, service::Error>
+where
+    C: service::ChatService,
+{
+    state
+        .chat_service
+        .set_room_admin(&claims.user_id(), &room_id.into(), &user_id.into(), true)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[tracing::instrument(name = "Revoke room admin", skip(state, claims))]
+pub async fn revoke_room_admin<A, C, N, R, K>(
+    State(state): State<SharedChatState<A, C, N, R, K>>,
+    Extension(claims): Extension<Claims>,
+    Path((room_id, user_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> Result<Response, service::Error>
+where
+    C: service::ChatService,
+{
+    state
+        .chat_service
+        .set_room_admin(&claims.user_id(), &room_id.into(), &user_id.into(), false)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn update<A, C, N, R, K>(
+    state: &SharedChatState<A, C, N, R, K>,
+    claims: &Claims,
+    room_id: uuid::Uuid,
+    change: RoomChange,
+) -> Result<Response, service::Error>
+where
+    C: service::ChatService,
+{
+    let room_id = room_id.into();
+    let room_tx = state.get_or_create_room_chanel(&room_id);
+    let room = ws::update_room(
+        state.chat_service.as_ref(),
+        &room_tx,
+        &claims.user_id(),
+        &room_id,
+        change,
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(room)).into_response())
+}
@@ -0,0 +1,5 @@
+mod dto;
+mod handlers;
+
+pub use dto::*;
+pub use handlers::*;
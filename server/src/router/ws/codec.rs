@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use axum::{
+    extract::ws,
+    http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderMap},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use shared::domain::event::{Tolerant, UnknownEvent};
+
+use crate::service;
+
+const FORMAT_PARAM: &str = "format";
+const JSON_PROTOCOL: &str = "chat.json";
+const BINCODE_PROTOCOL: &str = "chat.bincode";
+
+/// Encoding of events on a socket. JSON goes in text frames, bincode in binary frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WireFormat {
+    #[default]
+    Json,
+    Bincode,
+}
+
+impl WireFormat {
+    /// Picks the format from the `format` query param, falling back to the first
+    /// supported `Sec-WebSocket-Protocol` the client offered, then to JSON.
+    pub fn negotiate(
+        params: &HashMap<String, String>,
+        headers: &HeaderMap,
+    ) -> Result<Self, service::Error> {
+        if let Some(format) = params.get(FORMAT_PARAM) {
+            return format.parse();
+        }
+
+        let offered = headers
+            .get_all(SEC_WEBSOCKET_PROTOCOL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim);
+        for protocol in offered {
+            match protocol {
+                JSON_PROTOCOL => return Ok(Self::Json),
+                BINCODE_PROTOCOL => return Ok(Self::Bincode),
+                _ => continue,
+            }
+        }
+
+        Ok(Self::default())
+    }
+
+    /// Subprotocol to confirm in the handshake response.
+    pub fn protocol(&self) -> &'static str {
+        match self {
+            Self::Json => JSON_PROTOCOL,
+            Self::Bincode => BINCODE_PROTOCOL,
+        }
+    }
+
+    pub fn encode<T: Serialize>(&self, event: &T) -> Result<ws::Message, anyhow::Error> {
+        match self {
+            Self::Json => Ok(ws::Message::Text(
+                serde_json::to_string(event).context("Failed serialize json event.")?,
+            )),
+            Self::Bincode => Ok(ws::Message::Binary(
+                bincode::serialize(event).context("Failed serialize bincode event.")?,
+            )),
+        }
+    }
+
+    /// Decodes an event from a client frame. Control frames, frames of the other
+    /// format and frames that are not valid JSON yield `None`. Events this build does
+    /// not know are returned as `Tolerant::Unknown`.
+    pub fn decode<T: DeserializeOwned>(&self, msg: &ws::Message) -> Option<Tolerant<T>> {
+        match (self, msg) {
+            (Self::Json, ws::Message::Text(text)) => serde_json::from_str(text).ok(),
+            (Self::Bincode, ws::Message::Binary(bytes)) => Some(
+                bincode::deserialize(bytes)
+                    .map(Tolerant::Known)
+                    .unwrap_or(Tolerant::Unknown(UnknownEvent { name: None })),
+            ),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for WireFormat {
+    type Err = service::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "bincode" => Ok(Self::Bincode),
+            other => Err(service::Error::ValidationError(format!(
+                "{} is not a supported format. Use either `json` or `bincode`.",
+                other
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use shared::domain::event::{ClientEvent, JoinResponse, ServerEvent};
+
+    #[test]
+    fn query_param_takes_precedence_over_subprotocol() {
+        let params = HashMap::from([(FORMAT_PARAM.to_string(), "bincode".to_string())]);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static(JSON_PROTOCOL),
+        );
+
+        let format = WireFormat::negotiate(&params, &headers).unwrap();
+
+        assert_eq!(format, WireFormat::Bincode);
+    }
+
+    #[test]
+    fn first_supported_subprotocol_is_selected() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static("chat.v9, chat.bincode, chat.json"),
+        );
+
+        let format = WireFormat::negotiate(&HashMap::new(), &headers).unwrap();
+
+        assert_eq!(format, WireFormat::Bincode);
+    }
+
+    #[test]
+    fn unknown_format_is_rejected() {
+        let params = HashMap::from([(FORMAT_PARAM.to_string(), "xml".to_string())]);
+
+        assert!(WireFormat::negotiate(&params, &HeaderMap::new()).is_err());
+    }
+
+    #[test]
+    fn bincode_events_round_trip_in_binary_frames() {
+        let event = ServerEvent::Join(JoinResponse {
+            user_id: uuid::Uuid::new_v4().into(),
+        });
+
+        let msg = WireFormat::Bincode.encode(&event).unwrap();
+        let decoded = WireFormat::Bincode.decode::<ServerEvent>(&msg).unwrap();
+
+        assert!(matches!(msg, ws::Message::Binary(_)));
+        assert!(matches!(decoded, Tolerant::Known(ServerEvent::Join(_))));
+    }
+
+    #[test]
+    fn frames_of_other_format_are_ignored() {
+        let msg = ws::Message::Text(r#"{"SendMessage":"hello"}"#.to_string());
+
+        assert!(WireFormat::Json.decode::<ClientEvent>(&msg).is_some());
+        assert!(WireFormat::Bincode.decode::<ClientEvent>(&msg).is_none());
+    }
+
+    #[test]
+    fn unknown_events_are_reported_by_name() {
+        let msg = ws::Message::Text(r#"{"Typing":{"until":"2023-10-21T10:00:05Z"}}"#.to_string());
+
+        let decoded = WireFormat::Json.decode::<ClientEvent>(&msg).unwrap();
+
+        assert!(matches!(
+            decoded,
+            Tolerant::Unknown(UnknownEvent { name: Some(name) }) if name == "Typing"
+        ));
+    }
+}
@@ -0,0 +1,10 @@
+#[derive(serde::Serialize)]
+pub struct OpenStreamResponse {
+    pub stream_id: uuid::Uuid,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct PollQuery {
+    /// Seconds to wait for the first event before answering with an empty batch.
+    pub timeout: Option<u64>,
+}
@@ -1,137 +1,312 @@
 use std::{
     collections::HashMap,
-    ops::Deref,
     str::FromStr,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
-use anyhow::Context;
 use async_trait::async_trait;
 use axum::{
     extract::{
         ws::{self, WebSocket},
         Path, Query, State, WebSocketUpgrade,
     },
+    http::HeaderMap,
     response::Response,
 };
-use chrono::Utc;
 use futures::{SinkExt, StreamExt};
 use shared::domain::{
-    event::{ClientEvent, JoinRequest, JoinResponse, ServerEvent, UserJoinResponse},
-    Message, MessageContent, RoomId, UserId,
+    event::{
+        ClientEvent, CommandReply, ErrorCode, Hello, JoinRequest, JoinResponse, ServerError,
+        ServerEvent, Tolerant, UnknownEvent, UserJoinResponse,
+    },
+    Message, MessageContent, NewMessage, Room, RoomId, UserId,
+};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use uuid::Uuid;
+
+use crate::service::{
+    self, ChatService, CommandContext, CommandOutcome, CommandService, NotificationService,
+    Permissions, RateLimitService, RoomChange, SocketTicket,
 };
-use tokio::sync::{broadcast, mpsc};
 
-use crate::service::{self, ChatService};
+use super::{unsupported_event, Negotiation, WireFormat};
+
+const USER_EVENTS_MIN_BACKOFF: Duration = Duration::from_millis(100);
+const USER_EVENTS_MAX_BACKOFF: Duration = Duration::from_secs(30);
 
 #[derive(Clone)]
-pub struct ChatState<A, C> {
+pub struct ChatState<A, C, N, R, K> {
     rooms: Arc<Mutex<HashMap<RoomId, RoomState>>>,
-    auth_service: A,
-    chat_service: C,
+    users: Arc<Mutex<HashMap<UserId, UserState>>>,
+    pub(crate) auth_service: A,
+    pub(crate) chat_service: C,
+    pub(crate) notification_service: N,
+    pub(crate) rate_limit_service: R,
+    pub(crate) command_service: K,
 }
 
+pub type SharedChatState<A, C, N, R, K> = Arc<ChatState<Arc<A>, Arc<C>, Arc<N>, Arc<R>, Arc<K>>>;
+
 struct RoomState {
-    tx: broadcast::Sender<ws::Message>,
+    tx: broadcast::Sender<ServerEvent>,
+}
+
+struct UserState {
+    tx: broadcast::Sender<ServerEvent>,
+    /// Open subscriptions, the state is dropped with the last one.
+    subscriptions: usize,
+}
+
+/// Subscription of one socket or stream to the events of its user.
+pub struct UserSubscription {
+    users: Arc<Mutex<HashMap<UserId, UserState>>>,
+    user_id: UserId,
+    rx: broadcast::Receiver<ServerEvent>,
 }
 
-impl<A, C> ChatState<A, C> {
-    pub fn new(auth_service: A, chat_service: C) -> Self {
+impl UserSubscription {
+    pub async fn recv(&mut self) -> Result<ServerEvent, broadcast::error::RecvError> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for UserSubscription {
+    fn drop(&mut self) {
+        let mut users = self.users.lock().unwrap();
+        if let Some(user) = users.get_mut(&self.user_id) {
+            user.subscriptions -= 1;
+            if user.subscriptions == 0 {
+                users.remove(&self.user_id);
+            }
+        }
+    }
+}
+
+impl<A, C, N, R, K> ChatState<A, C, N, R, K> {
+    pub fn new(
+        auth_service: A,
+        chat_service: C,
+        notification_service: N,
+        rate_limit_service: R,
+        command_service: K,
+    ) -> Self {
         Self {
             rooms: Arc::new(Mutex::new(HashMap::default())),
+            users: Arc::new(Mutex::new(HashMap::default())),
             auth_service,
             chat_service,
+            notification_service,
+            rate_limit_service,
+            command_service,
         }
     }
 }
 
-impl<A, C> ChatState<A, C> {
-    pub fn get_or_create_room_chanel(&self, id: &RoomId) -> broadcast::Sender<ws::Message> {
+impl<A, C, N, R, K> ChatState<A, C, N, R, K> {
+    pub fn get_or_create_room_chanel(&self, id: &RoomId) -> broadcast::Sender<ServerEvent> {
         let mut rooms = self.rooms.lock().unwrap();
         let room = rooms.entry(*id).or_insert(RoomState {
             tx: broadcast::channel(1000).0,
         });
         room.tx.clone()
     }
+
+    pub fn subscribe_user_chanel(&self, id: &UserId) -> UserSubscription {
+        let mut users = self.users.lock().unwrap();
+        let user = users.entry(*id).or_insert_with(|| UserState {
+            tx: broadcast::channel(100).0,
+            subscriptions: 0,
+        });
+        user.subscriptions += 1;
+        UserSubscription {
+            users: self.users.clone(),
+            user_id: *id,
+            rx: user.tx.subscribe(),
+        }
+    }
+
+    pub(super) fn get_user_chanel(&self, id: &UserId) -> Option<broadcast::Sender<ServerEvent>> {
+        let users = self.users.lock().unwrap();
+        users.get(id).map(|user| user.tx.clone())
+    }
+}
+
+/// Forwards events published for a user on any instance to the sockets that user
+/// has open on this one. Resubscribes with backoff when the event bus fails or closes.
+pub async fn forward_user_events<A, C, N, R, K>(state: SharedChatState<A, C, N, R, K>)
+where
+    N: service::NotificationService + Send + Sync + 'static,
+    R: service::RateLimitService + Send + Sync + 'static,
+    K: service::CommandService + Send + Sync + 'static,
+{
+    let mut backoff = USER_EVENTS_MIN_BACKOFF;
+    loop {
+        match state.notification_service.subscribe().await {
+            Ok(mut events) => {
+                while let Some((user_id, event)) = events.next().await {
+                    backoff = USER_EVENTS_MIN_BACKOFF;
+                    if let Some(user_tx) = state.get_user_chanel(&user_id) {
+                        let _ = user_tx.send(event);
+                    }
+                }
+                tracing::warn!("User events stream closed, resubscribing");
+            }
+            Err(e) => tracing::error!("Failed subscribe to user events: {}", e),
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(USER_EVENTS_MAX_BACKOFF);
+    }
 }
 
-pub async fn websocket_handler<A, C>(
+/// Redeems the single-use ticket a socket is opened with, see `POST /api/ws/ticket`.
+pub(super) async fn redeem_handshake_ticket<A>(
+    auth_service: &A,
+    params: &HashMap<String, String>,
+) -> Result<SocketTicket, service::Error>
+where
+    A: service::AuthService,
+{
+    let ticket = params
+        .get("ticket")
+        .ok_or_else(|| anyhow::anyhow!("Missing ticket."))
+        .map_err(service::Error::InvalidCredentials)?;
+    auth_service.redeem_ticket(ticket).await
+}
+
+pub async fn websocket_handler<A, C, N, R, K>(
     ws: WebSocketUpgrade,
-    State(state): State<Arc<ChatState<Arc<A>, Arc<C>>>>,
+    State(state): State<SharedChatState<A, C, N, R, K>>,
     Path(room): Path<String>,
     Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
 ) -> Result<Response, service::Error>
 where
     A: service::AuthService + Send + Sync + 'static,
     C: service::ChatService + Send + Sync + 'static,
+    N: service::NotificationService + Send + Sync + 'static,
+    R: service::RateLimitService + Send + Sync + 'static,
+    K: service::CommandService + Send + Sync + 'static,
 {
-    let token = params
-        .get("token")
-        .ok_or(service::Error::NotFound("room not found".to_string()))?;
-
     let room_id = RoomId::from_str(&room)
         .map_err(|_| service::Error::NotFound("room not found".to_string()))?;
 
-    let claims = state.auth_service.validate_token(token).await?;
+    let format = WireFormat::negotiate(&params, &headers)?;
 
-    let user_id = claims.user_id();
+    let membership = room_membership(&state, room_id, &params).await?;
 
-    // let membership = state
-    //     .chat_service
-    //     .get_membership(&room_id, &user_id)
-    //     .await?
-    //     .ok_or(service::Error::NotFound("room not found".to_string()))?;
+    Ok(ws
+        .protocols([format.protocol()])
+        .on_upgrade(move |socket| websocket(socket, membership, format, state)))
+}
+
+/// Checks that the user of the ticket may join the room and is a member of it.
+async fn room_membership<A, C, N, R, K>(
+    state: &SharedChatState<A, C, N, R, K>,
+    room_id: RoomId,
+    params: &HashMap<String, String>,
+) -> Result<Membership, service::Error>
+where
+    A: service::AuthService,
+    C: service::ChatService,
+{
+    let SocketTicket {
+        user_id,
+        session_id,
+    } = redeem_handshake_ticket(state.auth_service.as_ref(), params).await?;
 
-    let code = uuid::Uuid::new_v4().to_string();
+    let permissions = state.auth_service.permissions(&user_id).await?;
+    permissions.require_join_rooms()?;
 
-    let membership = Membership {
+    let code = state
+        .chat_service
+        .get_membership(&room_id, &user_id)
+        .await?
+        .ok_or(service::Error::NotFound("room not found".to_string()))?;
+
+    Ok(Membership {
         user_id,
+        session_id,
         room_id,
         code,
-    };
-
-    Ok(ws.on_upgrade(|socket| websocket(socket, membership, state)))
+        permissions,
+    })
 }
 
 struct Membership {
     pub user_id: UserId,
+    /// Refresh token family the socket was opened with.
+    pub session_id: Uuid,
     pub room_id: RoomId,
     pub code: String,
+    pub permissions: Permissions,
 }
 
-async fn websocket<A, C>(
+async fn websocket<A, C, N, R, K>(
     stream: WebSocket,
     membership: Membership,
-    state: Arc<ChatState<Arc<A>, Arc<C>>>,
+    format: WireFormat,
+    state: SharedChatState<A, C, N, R, K>,
 ) where
     A: service::AuthService + Send + Sync + 'static,
     C: service::ChatService + Send + Sync + 'static,
+    N: service::NotificationService + Send + Sync + 'static,
+    R: service::RateLimitService + Send + Sync + 'static,
+    K: service::CommandService + Send + Sync + 'static,
 {
     let (mut sender, mut receiver) = stream.split();
 
     let room_tx = state.get_or_create_room_chanel(&membership.room_id);
 
     let mut subscription = room_tx.subscribe();
+    let mut user_subscription = state.subscribe_user_chanel(&membership.user_id);
     let (user_tx, mut rx) = mpsc::channel(100);
+    let protocol = Arc::new(Negotiation::default());
 
     let Membership {
         user_id,
+        session_id,
         room_id,
         code,
+        permissions,
     } = membership;
 
     let event_handler = SocketHandler {
         user_id,
         room_id,
         membership_code: code,
+        permissions,
+        joined: Presence::default(),
         auth_service: state.auth_service.clone(),
         chat_service: state.chat_service.clone(),
+        notification_service: state.notification_service.clone(),
+        rate_limit_service: state.rate_limit_service.clone(),
+        command_service: state.command_service.clone(),
+        protocol: protocol.clone(),
         room_tx,
         user_tx: user_tx.clone(),
     };
 
     let mut subscribe = tokio::spawn(async move {
-        while let Ok(msg) = subscription.recv().await {
+        loop {
+            let msg = tokio::select! {
+                msg = subscription.recv() => msg,
+                msg = user_subscription.recv() => msg,
+            };
+            let msg = match msg {
+                Ok(msg) => msg,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            if !protocol.allows(&msg) || !msg.is_for_session(&session_id) {
+                continue;
+            }
+            if msg
+                .removed_from()
+                .is_some_and(|removed| *removed != room_id)
+            {
+                continue;
+            }
             if user_tx.send(msg).await.is_err() {
                 break;
             }
@@ -140,21 +315,39 @@ async fn websocket<A, C>(
     });
 
     let mut send = tokio::spawn(async move {
-        while let Some(msg) = rx.recv().await {
+        while let Some(event) = rx.recv().await {
+            let msg = match format.encode(&event) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    tracing::error!("Failed serialize event: {}", e);
+                    continue;
+                }
+            };
             if sender.send(msg).await.is_err() {
                 break;
             }
+            // Only removals from the socket's own room get this far.
+            if event.ends_connection() || event.removed_from().is_some() {
+                let _ = sender.send(ws::Message::Close(None)).await;
+                break;
+            }
         }
         tracing::debug!("Close socket from chat send task");
     });
 
     let mut recv = tokio::spawn(async move {
-        while let Some(Ok(ws::Message::Text(text))) = receiver.next().await {
-            if let Ok(event) = serde_json::from_str::<ClientEvent>(&text) {
-                if let Err(e) = event_handler.handle(event).await {
-                    tracing::error!("Failed handel event: {}", e)
-                };
+        while let Some(Ok(msg)) = receiver.next().await {
+            if let ws::Message::Close(_) = msg {
+                break;
             }
+            let result = match format.decode::<ClientEvent>(&msg) {
+                Some(Tolerant::Known(event)) => event_handler.handle(event).await,
+                Some(Tolerant::Unknown(event)) => event_handler.handle(event).await,
+                None => continue,
+            };
+            if let Err(e) = result {
+                tracing::error!("Failed handel event: {}", e)
+            };
         }
         tracing::debug!("Close socket from recv task");
     });
@@ -176,94 +369,497 @@ async fn websocket<A, C>(
 }
 
 #[derive(Clone)]
-pub struct SocketHandler<A, C> {
+pub struct SocketHandler<A, C, N, R, K> {
     pub user_id: UserId,
     pub room_id: RoomId,
     pub membership_code: String,
+    /// Checked when the room was joined.
+    pub permissions: Permissions,
+    pub joined: Presence,
     pub auth_service: Arc<A>,
     pub chat_service: Arc<C>,
-    pub user_tx: mpsc::Sender<ws::Message>,
-    pub room_tx: broadcast::Sender<ws::Message>,
+    pub notification_service: Arc<N>,
+    pub rate_limit_service: Arc<R>,
+    pub command_service: Arc<K>,
+    pub protocol: Arc<Negotiation>,
+    pub user_tx: mpsc::Sender<ServerEvent>,
+    pub room_tx: broadcast::Sender<ServerEvent>,
 }
 
+/// Set while the user is joined to the room through a handler. The room's webhooks hear
+/// of the user leaving once the handler and its clones are gone.
+pub type Presence = Arc<Mutex<Option<oneshot::Sender<()>>>>;
+
 #[async_trait]
 pub trait EventHandler<Ev: ?Sized> {
     async fn handle(&self, event: Ev) -> Result<(), anyhow::Error>;
 }
 
 #[async_trait]
-impl<A, C> EventHandler<JoinRequest> for SocketHandler<A, C>
+impl<A, C, N, R, K> EventHandler<Hello> for SocketHandler<A, C, N, R, K>
 where
-    C: ChatService + Send + Sync,
+    C: Send + Sync,
+    A: Send + Sync,
+    N: Send + Sync,
+    R: Send + Sync,
+    K: Send + Sync,
+{
+    async fn handle(&self, ev: Hello) -> Result<(), anyhow::Error> {
+        let event = match self.protocol.accept(&ev) {
+            Ok(welcome) => ServerEvent::Welcome(welcome),
+            Err(e) => ServerEvent::ErrMessage(e),
+        };
+
+        self.user_tx.send(event).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<A, C, N, R, K> EventHandler<UnknownEvent> for SocketHandler<A, C, N, R, K>
+where
+    C: Send + Sync,
+    A: Send + Sync,
+    N: Send + Sync,
+    R: Send + Sync,
+    K: Send + Sync,
+{
+    async fn handle(&self, ev: UnknownEvent) -> Result<(), anyhow::Error> {
+        tracing::debug!("Received unknown event: {:?}", ev.name);
+
+        self.user_tx
+            .send(ServerEvent::ErrMessage(unsupported_event(&ev)))
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<A, C, N, R, K> EventHandler<JoinRequest> for SocketHandler<A, C, N, R, K>
+where
+    C: ChatService + Send + Sync + 'static,
     A: Send + Sync,
+    N: Send + Sync,
+    R: Send + Sync,
+    K: Send + Sync,
 {
     async fn handle(&self, _ev: JoinRequest) -> Result<(), anyhow::Error> {
         let user_event = ServerEvent::Join(JoinResponse {
             user_id: self.user_id,
         });
 
-        self.user_tx
-            .send(WsMessage::try_from(user_event)?.0)
-            .await?;
+        self.user_tx.send(user_event).await?;
 
         let room_event = ServerEvent::UserJoin(UserJoinResponse {
             user_id: self.user_id,
         });
 
-        self.room_tx.send(WsMessage::try_from(room_event)?.0)?;
+        self.room_tx.send(room_event)?;
+
+        let left = {
+            let mut joined = self.joined.lock().unwrap();
+            if joined.is_some() {
+                return Ok(());
+            }
+            let (sender, left) = oneshot::channel::<()>();
+            *joined = Some(sender);
+            left
+        };
+        self.chat_service
+            .user_joined(&self.room_id, &self.user_id)
+            .await;
+        let chat_service = self.chat_service.clone();
+        let (room_id, user_id) = (self.room_id, self.user_id);
+        tokio::spawn(async move {
+            // Never sent on, the channel closes with the handler.
+            let _ = left.await;
+            chat_service.user_left(&room_id, &user_id).await;
+        });
 
         Ok(())
     }
 }
 
 #[async_trait]
-impl<A, C> EventHandler<MessageContent> for SocketHandler<A, C>
+impl<A, C, N, R, K> EventHandler<MessageContent> for SocketHandler<A, C, N, R, K>
 where
     C: ChatService + Send + Sync,
     A: Send + Sync,
+    N: NotificationService + Send + Sync,
+    R: RateLimitService + Send + Sync,
+    K: CommandService + Send + Sync,
 {
     async fn handle(&self, ev: MessageContent) -> Result<(), anyhow::Error> {
-        let room_event = ServerEvent::ReceivedMessage(Message {
-            id: uuid::Uuid::new_v4().into(),
+        if let Err(e) = self.permissions.require_send_messages() {
+            self.user_tx
+                .send(ServerEvent::ErrMessage(ServerError::from(&e)))
+                .await?;
+            return Ok(());
+        }
+
+        let result = match ev.command() {
+            Some((name, args)) => self.run_command(name, args).await,
+            None => self.send_message(ev).await,
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(service::Error::UnexpectedError(e)) => Err(e),
+            Err(e) => {
+                self.user_tx
+                    .send(ServerEvent::ErrMessage(ServerError::from(&e)))
+                    .await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<A, C, N, R, K> SocketHandler<A, C, N, R, K>
+where
+    C: ChatService + Send + Sync,
+    A: Send + Sync,
+    N: NotificationService + Send + Sync,
+    R: RateLimitService + Send + Sync,
+    K: CommandService + Send + Sync,
+{
+    async fn send_message(&self, content: MessageContent) -> Result<(), service::Error> {
+        let new_message = NewMessage {
             user_id: self.user_id,
             room_id: self.room_id,
-            content: ev,
-            created_at: Utc::now(),
-        });
+            content,
+        };
+        deliver_message(
+            self.chat_service.as_ref(),
+            self.notification_service.as_ref(),
+            self.rate_limit_service.as_ref(),
+            &self.room_tx,
+            new_message,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Runs a slash command in place of sending the message to the room.
+    async fn run_command(&self, name: &str, args: &str) -> Result<(), service::Error> {
+        let context = CommandContext {
+            user_id: self.user_id,
+            room_id: self.room_id,
+        };
+        match self.command_service.execute(&context, name, args).await? {
+            CommandOutcome::Reply(text) => {
+                let reply = ServerEvent::CommandReply(CommandReply {
+                    command: name.to_string(),
+                    text: text.clone(),
+                });
+                let reply = if self.protocol.allows(&reply) {
+                    reply
+                } else {
+                    // Clients without commands still show errors, the reply is not lost.
+                    ServerEvent::ErrMessage(ServerError::new(ErrorCode::Other, text))
+                };
+                self.user_tx
+                    .send(reply)
+                    .await
+                    .map_err(|e| service::Error::UnexpectedError(e.into()))
+            }
+            CommandOutcome::Post(content) => self.send_message(content).await,
+            CommandOutcome::Update(change) => {
+                update_room(
+                    self.chat_service.as_ref(),
+                    &self.room_tx,
+                    &self.user_id,
+                    &self.room_id,
+                    change,
+                )
+                .await?;
+                Ok(())
+            }
+        }
+    }
+}
 
-        self.room_tx.send(WsMessage::try_from(room_event)?.0)?;
+/// Stores a message and delivers it to the room, the send path of sockets and bots alike.
+pub async fn deliver_message<C, N, R>(
+    chat_service: &C,
+    notification_service: &N,
+    rate_limit_service: &R,
+    room_tx: &broadcast::Sender<ServerEvent>,
+    new_message: NewMessage,
+) -> Result<Message, service::Error>
+where
+    C: ChatService,
+    N: NotificationService,
+    R: RateLimitService,
+{
+    rate_limit_service
+        .check_message(&new_message.user_id, &new_message.room_id)
+        .await?;
 
-        Ok(())
+    let message = chat_service.create_message(new_message).await?;
+
+    if let Err(e) = notification_service.notify_mentions(&message).await {
+        tracing::error!("Failed notify mentions: {}", e);
     }
+
+    // Nobody may be listening, the message is in the history either way.
+    let _ = room_tx.send(ServerEvent::ReceivedMessage(message.clone()));
+
+    Ok(message)
+}
+
+/// Applies a change to the room's details and announces it to the room.
+pub async fn update_room<C>(
+    chat_service: &C,
+    room_tx: &broadcast::Sender<ServerEvent>,
+    user_id: &UserId,
+    room_id: &RoomId,
+    change: RoomChange,
+) -> Result<Room, service::Error>
+where
+    C: ChatService,
+{
+    let (room, message) = chat_service.update_room(user_id, room_id, change).await?;
+
+    let _ = room_tx.send(ServerEvent::RoomUpdated(room.clone()));
+    let _ = room_tx.send(ServerEvent::ReceivedMessage(message));
+
+    Ok(room)
 }
 
 #[async_trait]
-impl<A, C> EventHandler<ClientEvent> for SocketHandler<A, C>
+impl<A, C, N, R, K> EventHandler<ClientEvent> for SocketHandler<A, C, N, R, K>
 where
-    C: ChatService + Send + Sync,
+    C: ChatService + Send + Sync + 'static,
     A: Send + Sync,
+    N: NotificationService + Send + Sync,
+    R: RateLimitService + Send + Sync,
+    K: CommandService + Send + Sync,
 {
     async fn handle(&self, ev: ClientEvent) -> Result<(), anyhow::Error> {
         match ev {
+            ClientEvent::Hello(ev) => self.handle(ev).await,
             ClientEvent::Join(ev) => self.handle(ev).await,
             ClientEvent::SendMessage(ev) => self.handle(ev).await,
         }
     }
 }
 
-struct WsMessage(ws::Message);
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::service::{
+        MockAuthService, MockChatService, MockCommandService, MockNotificationService,
+        MockRateLimitService,
+    };
+    use shared::domain::event::{Capability, SessionRevoked, PROTOCOL_VERSION};
+
+    pub(crate) type MockChatState = SharedChatState<
+        MockAuthService,
+        MockChatService,
+        MockNotificationService,
+        MockRateLimitService,
+        MockCommandService,
+    >;
+
+    pub(crate) fn chat_state(
+        auth_service: MockAuthService,
+        chat_service: MockChatService,
+        notification_service: MockNotificationService,
+    ) -> MockChatState {
+        Arc::new(ChatState::new(
+            Arc::new(auth_service),
+            Arc::new(chat_service),
+            Arc::new(notification_service),
+            Arc::new(MockRateLimitService::new()),
+            Arc::new(MockCommandService::new()),
+        ))
+    }
+
+    /// Handler for a member of a room, with the events for the user on the receiver.
+    fn socket_handler(
+        command_service: MockCommandService,
+    ) -> (
+        SocketHandler<
+            MockAuthService,
+            MockChatService,
+            MockNotificationService,
+            MockRateLimitService,
+            MockCommandService,
+        >,
+        mpsc::Receiver<ServerEvent>,
+    ) {
+        let (user_tx, user_rx) = mpsc::channel(10);
+        let handler = SocketHandler {
+            user_id: Uuid::new_v4().into(),
+            room_id: Uuid::new_v4().into(),
+            membership_code: "code".to_string(),
+            permissions: Permissions::ALL,
+            joined: Presence::default(),
+            auth_service: Arc::new(MockAuthService::new()),
+            chat_service: Arc::new(MockChatService::new()),
+            notification_service: Arc::new(MockNotificationService::new()),
+            rate_limit_service: Arc::new(MockRateLimitService::new()),
+            command_service: Arc::new(command_service),
+            protocol: Arc::new(Negotiation::default()),
+            user_tx,
+            room_tx: broadcast::channel(10).0,
+        };
+        (handler, user_rx)
+    }
 
-impl Deref for WsMessage {
-    type Target = ws::Message;
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    fn replying_command_service() -> MockCommandService {
+        let mut command_service = MockCommandService::new();
+        command_service.expect_execute().returning(|_, _, _| {
+            Box::pin(async { Ok(CommandOutcome::Reply("Kicked bob.".to_string())) })
+        });
+        command_service
     }
-}
 
-impl TryFrom<ServerEvent> for WsMessage {
-    type Error = anyhow::Error;
+    fn hello(capabilities: Vec<Capability>) -> Hello {
+        Hello {
+            version: PROTOCOL_VERSION,
+            capabilities,
+        }
+    }
+
+    #[tokio::test]
+    async fn command_replies_are_sent_to_clients_with_commands() {
+        let (handler, mut user_rx) = socket_handler(replying_command_service());
+        handler
+            .protocol
+            .accept(&hello(vec![Capability::Commands]))
+            .unwrap();
+
+        handler
+            .handle("/kick bob".parse::<MessageContent>().unwrap())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            user_rx.recv().await,
+            Some(ServerEvent::CommandReply(CommandReply { command, text }))
+                if command == "kick" && text == "Kicked bob."
+        ));
+    }
+
+    #[tokio::test]
+    async fn command_replies_fall_back_to_errors_for_clients_without_commands() {
+        let (handler, mut user_rx) = socket_handler(replying_command_service());
+        handler.protocol.accept(&hello(Vec::new())).unwrap();
+
+        handler
+            .handle("/kick bob".parse::<MessageContent>().unwrap())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            user_rx.recv().await,
+            Some(ServerEvent::ErrMessage(ServerError { message, code: ErrorCode::Other, .. }))
+                if message == "Kicked bob."
+        ));
+    }
+
+    fn ticket_state(chat_service: MockChatService) -> MockChatState {
+        let mut auth_service = MockAuthService::new();
+        auth_service.expect_redeem_ticket().returning(|_| {
+            Box::pin(async {
+                Ok(SocketTicket {
+                    user_id: Uuid::new_v4().into(),
+                    session_id: Uuid::new_v4(),
+                })
+            })
+        });
+        auth_service
+            .expect_permissions()
+            .returning(|_| Box::pin(async { Ok(Permissions::ALL) }));
+        chat_state(auth_service, chat_service, MockNotificationService::new())
+    }
+
+    fn ticket_params() -> HashMap<String, String> {
+        HashMap::from([("ticket".to_string(), "ticket".to_string())])
+    }
+
+    #[tokio::test]
+    async fn room_sockets_are_refused_to_non_members() {
+        let mut chat_service = MockChatService::new();
+        chat_service
+            .expect_get_membership()
+            .returning(|_, _| Box::pin(async { Ok(None) }));
+        let state = ticket_state(chat_service);
+
+        let result = room_membership(&state, Uuid::new_v4().into(), &ticket_params()).await;
 
-    fn try_from(value: ServerEvent) -> Result<Self, Self::Error> {
-        Ok(Self(serde_json::to_string(&value).map(ws::Message::Text)?))
+        assert!(matches!(result, Err(service::Error::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn room_sockets_of_members_use_their_membership() {
+        let mut chat_service = MockChatService::new();
+        chat_service
+            .expect_get_membership()
+            .returning(|_, _| Box::pin(async { Ok(Some("code".to_string())) }));
+        let state = ticket_state(chat_service);
+        let room_id: RoomId = Uuid::new_v4().into();
+
+        let membership = room_membership(&state, room_id, &ticket_params())
+            .await
+            .unwrap();
+
+        assert_eq!(membership.room_id, room_id);
+        assert_eq!(membership.code, "code");
+    }
+
+    fn revoked() -> ServerEvent {
+        ServerEvent::SessionRevoked(SessionRevoked { session_id: None })
+    }
+
+    #[test]
+    fn user_chanels_are_dropped_with_their_last_subscription() {
+        let state = chat_state(
+            MockAuthService::new(),
+            MockChatService::new(),
+            MockNotificationService::new(),
+        );
+        let user_id: UserId = Uuid::new_v4().into();
+
+        let first = state.subscribe_user_chanel(&user_id);
+        let second = state.subscribe_user_chanel(&user_id);
+        drop(first);
+        assert!(state.get_user_chanel(&user_id).is_some());
+
+        drop(second);
+        assert!(state.get_user_chanel(&user_id).is_none());
+    }
+
+    #[tokio::test]
+    async fn user_events_are_resubscribed_after_failures() {
+        let user_id: UserId = Uuid::new_v4().into();
+        let mut notification_service = MockNotificationService::new();
+        let mut attempts = 0;
+        notification_service.expect_subscribe().returning(move || {
+            attempts += 1;
+            let result = match attempts {
+                1 => Err(service::Error::UnexpectedError(anyhow::anyhow!("down"))),
+                2 => Ok(futures::stream::empty().boxed()),
+                _ => Ok(futures::stream::iter([(user_id, revoked())])
+                    .chain(futures::stream::pending())
+                    .boxed()),
+            };
+            Box::pin(async { result })
+        });
+        let state = chat_state(
+            MockAuthService::new(),
+            MockChatService::new(),
+            notification_service,
+        );
+        let mut subscription = state.subscribe_user_chanel(&user_id);
+
+        let forward = tokio::spawn(forward_user_events(state.clone()));
+        let event = subscription.recv().await.unwrap();
+        forward.abort();
+
+        assert!(event.ends_connection());
     }
 }
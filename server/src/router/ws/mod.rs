@@ -1,3 +1,17 @@
+mod codec;
+mod dto;
 mod handlers;
+mod poll;
+mod protocol;
+mod session;
+mod sse;
+mod stream;
 
+pub use codec::*;
+pub use dto::*;
 pub use handlers::*;
+pub use poll::*;
+pub use protocol::*;
+pub use session::*;
+pub use sse::*;
+pub use stream::*;
@@ -0,0 +1,216 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use shared::domain::event::StreamServerEvent;
+use tokio::{
+    sync::{self, mpsc},
+    time::Instant,
+};
+use uuid::Uuid;
+
+use crate::service::{self, Claims};
+
+use super::{OpenStreamResponse, PollQuery, SharedStreamState, StreamSession};
+
+const DEFAULT_POLL_TIMEOUT: u64 = 25;
+const MAX_POLL_TIMEOUT: u64 = 60;
+const MAX_POLL_BATCH: usize = 100;
+/// A long-polling stream is closed when the client stops polling for this long.
+const POLL_SESSION_TTL: Duration = Duration::from_secs(90);
+
+pub(super) struct PollQueue {
+    events: sync::Mutex<mpsc::Receiver<StreamServerEvent>>,
+    last_poll: Mutex<Instant>,
+}
+
+impl PollQueue {
+    fn new(events: mpsc::Receiver<StreamServerEvent>) -> Self {
+        Self {
+            events: sync::Mutex::new(events),
+            last_poll: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits up to `timeout` for a first event, then takes every event queued so far.
+    async fn next_batch(&self, timeout: Duration) -> Vec<StreamServerEvent> {
+        let mut batch = Vec::new();
+        {
+            let mut events = self.events.lock().await;
+            if let Ok(Some(event)) = tokio::time::timeout(timeout, events.recv()).await {
+                batch.push(event);
+                while batch.len() < MAX_POLL_BATCH {
+                    match events.try_recv() {
+                        Ok(event) => batch.push(event),
+                        Err(_) => break,
+                    }
+                }
+            }
+        }
+        *self.last_poll.lock().unwrap() = Instant::now();
+        batch
+    }
+}
+
+#[tracing::instrument(name = "Open poll stream", skip(state, claims))]
+pub async fn open_poll<A, C, N, R, K>(
+    State(state): State<SharedStreamState<A, C, N, R, K>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService + Send + Sync + 'static,
+    C: service::ChatService + Send + Sync + 'static,
+    N: service::NotificationService + Send + Sync + 'static,
+    R: service::RateLimitService + Send + Sync + 'static,
+    K: service::CommandService + Send + Sync + 'static,
+{
+    let (session, events) =
+        StreamSession::open(claims.user_id(), claims.family_id(), state.chat.clone());
+    let session = Arc::new(session);
+    let stream_id = state.register(session.clone());
+    tokio::spawn(async move { session.subscribe_to_memberships().await });
+
+    let queue = Arc::new(PollQueue::new(events));
+    state.register_poll(stream_id, queue.clone());
+    tokio::spawn(expire_poll(state, stream_id, queue));
+
+    Ok((StatusCode::CREATED, Json(OpenStreamResponse { stream_id })).into_response())
+}
+
+/// Waits for stream events and answers with every event queued so far, or with an
+/// empty batch once the timeout elapses.
+#[tracing::instrument(name = "Poll stream", skip(state, claims))]
+pub async fn poll_events<A, C, N, R, K>(
+    State(state): State<SharedStreamState<A, C, N, R, K>>,
+    Extension(claims): Extension<Claims>,
+    Path(stream_id): Path<Uuid>,
+    Query(query): Query<PollQuery>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService + Send + Sync + 'static,
+    C: service::ChatService + Send + Sync + 'static,
+    N: service::NotificationService + Send + Sync + 'static,
+    R: service::RateLimitService + Send + Sync + 'static,
+    K: service::CommandService + Send + Sync + 'static,
+{
+    state
+        .get(&stream_id, &claims.user_id())
+        .ok_or(service::Error::NotFound("stream not found".to_string()))?;
+    let queue = state
+        .get_poll(&stream_id)
+        .ok_or(service::Error::NotFound("stream not found".to_string()))?;
+
+    let timeout = query
+        .timeout
+        .unwrap_or(DEFAULT_POLL_TIMEOUT)
+        .min(MAX_POLL_TIMEOUT);
+
+    let batch = queue.next_batch(Duration::from_secs(timeout)).await;
+    if batch.iter().any(StreamServerEvent::ends_stream) {
+        state.remove(&stream_id);
+    }
+
+    Ok((StatusCode::OK, Json(batch)).into_response())
+}
+
+async fn expire_poll<A, C, N, R, K>(
+    state: SharedStreamState<A, C, N, R, K>,
+    stream_id: Uuid,
+    queue: Arc<PollQueue>,
+) {
+    loop {
+        tokio::time::sleep(POLL_SESSION_TTL).await;
+        let idle = queue.last_poll.lock().unwrap().elapsed();
+        if idle >= POLL_SESSION_TTL && queue.events.try_lock().is_ok() {
+            break;
+        }
+    }
+    state.remove(&stream_id);
+    tracing::debug!("Close idle poll stream");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::ws::handlers::tests::{chat_state, MockChatState};
+    use crate::router::ws::StreamState;
+    use crate::service::{MockAuthService, MockChatService, MockNotificationService};
+    use shared::domain::{
+        event::{ServerEvent, SessionRevoked},
+        UserId,
+    };
+
+    fn state() -> SharedStreamState<
+        MockAuthService,
+        MockChatService,
+        MockNotificationService,
+        crate::service::MockRateLimitService,
+        crate::service::MockCommandService,
+    > {
+        let chat: MockChatState = chat_state(
+            MockAuthService::new(),
+            MockChatService::new(),
+            MockNotificationService::new(),
+        );
+        Arc::new(StreamState::new(chat))
+    }
+
+    fn revoked() -> ServerEvent {
+        ServerEvent::SessionRevoked(SessionRevoked { session_id: None })
+    }
+
+    #[tokio::test]
+    async fn polls_answer_with_every_queued_event() {
+        let (tx, rx) = mpsc::channel(MAX_POLL_BATCH * 2);
+        let queue = PollQueue::new(rx);
+        for _ in 0..MAX_POLL_BATCH + 1 {
+            tx.send(StreamServerEvent::User(revoked())).await.unwrap();
+        }
+
+        let batch = queue.next_batch(Duration::from_secs(1)).await;
+        assert_eq!(batch.len(), MAX_POLL_BATCH);
+
+        let batch = queue.next_batch(Duration::from_secs(1)).await;
+        assert_eq!(batch.len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn polls_answer_with_an_empty_batch_after_the_timeout() {
+        let (_tx, rx) = mpsc::channel(1);
+        let queue = PollQueue::new(rx);
+        let started = Instant::now();
+
+        let batch = queue.next_batch(Duration::from_secs(25)).await;
+
+        assert!(batch.is_empty());
+        assert_eq!(started.elapsed(), Duration::from_secs(25));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_poll_streams_expire() {
+        let state = state();
+        let user_id: UserId = uuid::Uuid::new_v4().into();
+        let (session, events) =
+            StreamSession::open(user_id, uuid::Uuid::new_v4(), state.chat.clone());
+        let stream_id = state.register(Arc::new(session));
+        let queue = Arc::new(PollQueue::new(events));
+        state.register_poll(stream_id, queue.clone());
+        tokio::spawn(expire_poll(state.clone(), stream_id, queue.clone()));
+
+        tokio::time::sleep(POLL_SESSION_TTL - Duration::from_secs(30)).await;
+        queue.next_batch(Duration::ZERO).await;
+        tokio::time::sleep(Duration::from_secs(40)).await;
+        assert!(state.get(&stream_id, &user_id).is_some());
+
+        tokio::time::sleep(POLL_SESSION_TTL).await;
+        assert!(state.get(&stream_id, &user_id).is_none());
+        assert!(state.get_poll(&stream_id).is_none());
+    }
+}
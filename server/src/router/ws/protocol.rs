@@ -0,0 +1,162 @@
+use std::{collections::BTreeSet, sync::RwLock};
+
+use shared::domain::event::{
+    Capability, ErrorCode, Hello, ServerError, ServerEvent, UnknownEvent, Welcome,
+    MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+
+/// Protocol negotiated with the client of a socket or stream.
+///
+/// Until the client says `Hello` it is treated as a client from before the handshake
+/// existed and receives every event.
+#[derive(Default)]
+pub struct Negotiation {
+    capabilities: RwLock<Option<BTreeSet<Capability>>>,
+}
+
+impl Negotiation {
+    /// Agrees on the lower of both versions and on the capabilities both sides know.
+    pub fn accept(&self, hello: &Hello) -> Result<Welcome, ServerError> {
+        if hello.version < MIN_PROTOCOL_VERSION {
+            return Err(ServerError::new(
+                ErrorCode::UnsupportedVersion,
+                format!(
+                    "Protocol version {} is no longer supported. Use at least version {}.",
+                    hello.version, MIN_PROTOCOL_VERSION
+                ),
+            ));
+        }
+
+        let supported = Capability::supported();
+        let capabilities: BTreeSet<Capability> = hello
+            .capabilities
+            .iter()
+            .filter(|capability| supported.contains(capability))
+            .copied()
+            .collect();
+
+        let welcome = Welcome {
+            version: hello.version.min(PROTOCOL_VERSION),
+            capabilities: capabilities.iter().copied().collect(),
+        };
+        *self.capabilities.write().unwrap() = Some(capabilities);
+
+        Ok(welcome)
+    }
+
+    /// Whether the event can be sent to the client.
+    pub fn allows(&self, event: &ServerEvent) -> bool {
+        let Some(required) = event.required_capability() else {
+            return true;
+        };
+        match self.capabilities.read().unwrap().as_ref() {
+            Some(capabilities) => capabilities.contains(&required),
+            None => true,
+        }
+    }
+}
+
+pub fn unsupported_event(event: &UnknownEvent) -> ServerError {
+    let message = match event.name.as_deref() {
+        Some(name) => format!("Unsupported event `{}`.", name),
+        None => "Unsupported event.".to_string(),
+    };
+    ServerError::new(ErrorCode::UnsupportedEvent, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use shared::domain::{event::JoinResponse, Message, MessageKind, Notification};
+
+    fn notification() -> ServerEvent {
+        ServerEvent::Notification(Notification {
+            id: uuid::Uuid::new_v4().into(),
+            user_id: uuid::Uuid::new_v4().into(),
+            sender_id: uuid::Uuid::new_v4().into(),
+            room_id: uuid::Uuid::new_v4().into(),
+            message_id: uuid::Uuid::new_v4().into(),
+            created_at: Utc::now(),
+            read_at: None,
+        })
+    }
+
+    #[test]
+    fn clients_without_hello_receive_every_event() {
+        let negotiation = Negotiation::default();
+
+        assert!(negotiation.allows(&notification()));
+    }
+
+    #[test]
+    fn newer_clients_are_downgraded_to_known_capabilities() {
+        let negotiation = Negotiation::default();
+
+        let welcome = negotiation
+            .accept(&Hello {
+                version: PROTOCOL_VERSION + 1,
+                capabilities: vec![Capability::Unknown, Capability::Notifications],
+            })
+            .unwrap();
+
+        assert_eq!(welcome.version, PROTOCOL_VERSION);
+        assert_eq!(welcome.capabilities, vec![Capability::Notifications]);
+        assert!(negotiation.allows(&notification()));
+    }
+
+    #[test]
+    fn events_of_capabilities_not_negotiated_are_filtered() {
+        let negotiation = Negotiation::default();
+
+        negotiation
+            .accept(&Hello {
+                version: PROTOCOL_VERSION,
+                capabilities: Vec::new(),
+            })
+            .unwrap();
+
+        assert!(!negotiation.allows(&notification()));
+        assert!(negotiation.allows(&ServerEvent::Join(JoinResponse {
+            user_id: uuid::Uuid::new_v4().into(),
+        })));
+    }
+
+    fn message(kind: MessageKind) -> ServerEvent {
+        ServerEvent::ReceivedMessage(Message {
+            id: uuid::Uuid::new_v4().into(),
+            user_id: uuid::Uuid::new_v4().into(),
+            room_id: uuid::Uuid::new_v4().into(),
+            content: "changed the room topic".parse().unwrap(),
+            created_at: Utc::now(),
+            kind,
+        })
+    }
+
+    #[test]
+    fn system_messages_need_room_updates() {
+        let negotiation = Negotiation::default();
+
+        negotiation
+            .accept(&Hello {
+                version: PROTOCOL_VERSION,
+                capabilities: vec![Capability::Notifications],
+            })
+            .unwrap();
+
+        assert!(!negotiation.allows(&message(MessageKind::System)));
+        assert!(negotiation.allows(&message(MessageKind::User)));
+    }
+
+    #[test]
+    fn versions_below_minimum_are_rejected() {
+        let negotiation = Negotiation::default();
+
+        let result = negotiation.accept(&Hello {
+            version: MIN_PROTOCOL_VERSION - 1,
+            capabilities: Vec::new(),
+        });
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,638 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use shared::domain::{
+    event::{
+        ErrorCode, RoomEvent, ServerError, ServerEvent, StreamClientEvent, StreamServerEvent,
+        UnknownEvent,
+    },
+    RoomId, UserId,
+};
+use tokio::{
+    sync::{self, broadcast, mpsc},
+    task::JoinHandle,
+};
+use uuid::Uuid;
+
+use crate::service;
+
+use super::{
+    unsupported_event, EventHandler, Negotiation, PollQueue, Presence, SharedChatState,
+    SocketHandler,
+};
+
+/// Registry of the streams served over transports that split sending and receiving
+/// into separate requests.
+pub struct StreamState<A, C, N, R, K> {
+    pub(super) chat: SharedChatState<A, C, N, R, K>,
+    sessions: StreamSessions<A, C, N, R, K>,
+    polls: Mutex<HashMap<Uuid, Arc<PollQueue>>>,
+}
+
+pub type SharedStreamState<A, C, N, R, K> = Arc<StreamState<A, C, N, R, K>>;
+
+type SharedStreamSession<A, C, N, R, K> = Arc<StreamSession<A, C, N, R, K>>;
+
+type StreamSessions<A, C, N, R, K> = Mutex<HashMap<Uuid, SharedStreamSession<A, C, N, R, K>>>;
+
+impl<A, C, N, R, K> StreamState<A, C, N, R, K> {
+    pub fn new(chat: SharedChatState<A, C, N, R, K>) -> Self {
+        Self {
+            chat,
+            sessions: Mutex::new(HashMap::default()),
+            polls: Mutex::new(HashMap::default()),
+        }
+    }
+
+    pub(super) fn register(&self, session: SharedStreamSession<A, C, N, R, K>) -> Uuid {
+        let stream_id = Uuid::new_v4();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.insert(stream_id, session);
+        stream_id
+    }
+
+    pub(super) fn get(
+        &self,
+        stream_id: &Uuid,
+        user_id: &UserId,
+    ) -> Option<SharedStreamSession<A, C, N, R, K>> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions
+            .get(stream_id)
+            .filter(|session| session.user_id == *user_id)
+            .cloned()
+    }
+
+    pub(super) fn register_poll(&self, stream_id: Uuid, queue: Arc<PollQueue>) {
+        let mut polls = self.polls.lock().unwrap();
+        polls.insert(stream_id, queue);
+    }
+
+    pub(super) fn get_poll(&self, stream_id: &Uuid) -> Option<Arc<PollQueue>> {
+        let polls = self.polls.lock().unwrap();
+        polls.get(stream_id).cloned()
+    }
+
+    pub(super) fn remove(&self, stream_id: &Uuid) {
+        self.polls.lock().unwrap().remove(stream_id);
+        self.sessions.lock().unwrap().remove(stream_id);
+    }
+}
+
+/// Transport independent part of a per-user stream: the room subscriptions and the
+/// dispatch of client events to the room handlers.
+pub struct StreamSession<A, C, N, R, K> {
+    pub user_id: UserId,
+    rooms: SharedRoomSubscriptions<A, C, N, R, K>,
+    user_forward: JoinHandle<()>,
+}
+
+impl<A, C, N, R, K> Drop for StreamSession<A, C, N, R, K> {
+    fn drop(&mut self) {
+        self.user_forward.abort();
+    }
+}
+
+impl<A, C, N, R, K> StreamSession<A, C, N, R, K>
+where
+    A: service::AuthService + Send + Sync + 'static,
+    C: service::ChatService + Send + Sync + 'static,
+    N: service::NotificationService + Send + Sync + 'static,
+    R: service::RateLimitService + Send + Sync + 'static,
+    K: service::CommandService + Send + Sync + 'static,
+{
+    /// Opens a session for the user, authenticated with the tokens of `session_id`. Events
+    /// for the client are delivered on the returned receiver, which the transport must
+    /// drain until an event that ends the stream.
+    pub fn open(
+        user_id: UserId,
+        session_id: Uuid,
+        state: SharedChatState<A, C, N, R, K>,
+    ) -> (Self, mpsc::Receiver<StreamServerEvent>) {
+        let (out_tx, out_rx) = mpsc::channel(100);
+        let protocol = Arc::new(Negotiation::default());
+
+        let mut user_subscription = state.subscribe_user_chanel(&user_id);
+        let user_tx = out_tx.clone();
+        let user_protocol = protocol.clone();
+        let rooms = Arc::new(sync::Mutex::new(RoomSubscriptions::new(
+            user_id, state, protocol, out_tx,
+        )));
+        let user_rooms = Arc::downgrade(&rooms);
+        let user_forward = tokio::spawn(async move {
+            loop {
+                let event = match user_subscription.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if !user_protocol.allows(&event) || !event.is_for_session(&session_id) {
+                    continue;
+                }
+                let removed_from = event.removed_from().copied();
+                if user_tx.send(StreamServerEvent::User(event)).await.is_err() {
+                    break;
+                }
+                if let (Some(room_id), Some(rooms)) = (removed_from, user_rooms.upgrade()) {
+                    if rooms.lock().await.unsubscribe(room_id).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let session = Self {
+            user_id,
+            rooms,
+            user_forward,
+        };
+        (session, out_rx)
+    }
+
+    pub async fn subscribe_to_memberships(&self) {
+        self.rooms.lock().await.subscribe_to_memberships().await
+    }
+
+    pub async fn handle(&self, event: StreamClientEvent) -> Result<(), anyhow::Error> {
+        self.rooms.lock().await.handle(event).await
+    }
+
+    /// Answers an event this build does not know with an error instead of dropping it.
+    pub async fn reject(&self, event: UnknownEvent) -> Result<(), anyhow::Error> {
+        tracing::debug!("Received unknown stream event: {:?}", event.name);
+        let rooms = self.rooms.lock().await;
+        rooms.send_error(unsupported_event(&event)).await
+    }
+}
+
+type SharedRoomSubscriptions<A, C, N, R, K> = Arc<sync::Mutex<RoomSubscriptions<A, C, N, R, K>>>;
+
+struct RoomSubscriptions<A, C, N, R, K> {
+    user_id: UserId,
+    state: SharedChatState<A, C, N, R, K>,
+    protocol: Arc<Negotiation>,
+    out_tx: mpsc::Sender<StreamServerEvent>,
+    rooms: HashMap<RoomId, RoomSubscription<A, C, N, R, K>>,
+}
+
+struct RoomSubscription<A, C, N, R, K> {
+    handler: SocketHandler<A, C, N, R, K>,
+    forward: JoinHandle<()>,
+}
+
+impl<A, C, N, R, K> Drop for RoomSubscription<A, C, N, R, K> {
+    fn drop(&mut self) {
+        self.forward.abort();
+    }
+}
+
+impl<A, C, N, R, K> RoomSubscriptions<A, C, N, R, K>
+where
+    A: service::AuthService + Send + Sync + 'static,
+    C: service::ChatService + Send + Sync + 'static,
+    N: service::NotificationService + Send + Sync + 'static,
+    R: service::RateLimitService + Send + Sync + 'static,
+    K: service::CommandService + Send + Sync + 'static,
+{
+    fn new(
+        user_id: UserId,
+        state: SharedChatState<A, C, N, R, K>,
+        protocol: Arc<Negotiation>,
+        out_tx: mpsc::Sender<StreamServerEvent>,
+    ) -> Self {
+        Self {
+            user_id,
+            state,
+            protocol,
+            out_tx,
+            rooms: HashMap::default(),
+        }
+    }
+
+    async fn subscribe_to_memberships(&mut self) {
+        let rooms = match self.state.chat_service.get_user_rooms(&self.user_id).await {
+            Ok(rooms) => rooms,
+            Err(e) => {
+                tracing::error!("Failed get user rooms: {}", e);
+                return;
+            }
+        };
+
+        for room in rooms {
+            if let Err(e) = self.subscribe(room.id).await {
+                tracing::error!("Failed subscribe to room: {}", e);
+            }
+        }
+    }
+
+    async fn handle(&mut self, event: StreamClientEvent) -> Result<(), anyhow::Error> {
+        match event {
+            StreamClientEvent::Hello(hello) => match self.protocol.accept(&hello) {
+                Ok(welcome) => {
+                    self.out_tx
+                        .send(StreamServerEvent::Welcome(welcome))
+                        .await?;
+                    Ok(())
+                }
+                Err(e) => self.send_error(e).await,
+            },
+            StreamClientEvent::Subscribe(room_id) => {
+                if let Err(e) = self.subscribe(room_id).await {
+                    self.send_error(ServerError::from(&e)).await?;
+                }
+                Ok(())
+            }
+            StreamClientEvent::Unsubscribe(room_id) => self.unsubscribe(room_id).await,
+            StreamClientEvent::Room(RoomEvent { room_id, event }) => {
+                match self.rooms.get(&room_id) {
+                    Some(room) => room.handler.handle(event).await,
+                    None => {
+                        self.send_error(ServerError::new(
+                            ErrorCode::NotSubscribed,
+                            "Not subscribed to room.",
+                        ))
+                        .await
+                    }
+                }
+            }
+        }
+    }
+
+    async fn subscribe(&mut self, room_id: RoomId) -> Result<(), service::Error> {
+        if self.rooms.contains_key(&room_id) {
+            return Ok(());
+        }
+
+        let code = self
+            .state
+            .chat_service
+            .get_membership(&room_id, &self.user_id)
+            .await?
+            .ok_or(service::Error::NotFound("room not found".to_string()))?;
+        let permissions = self.state.auth_service.permissions(&self.user_id).await?;
+        permissions.require_join_rooms()?;
+
+        let room_tx = self.state.get_or_create_room_chanel(&room_id);
+        let mut subscription = room_tx.subscribe();
+        let (user_tx, mut user_rx) = mpsc::channel(100);
+
+        let handler = SocketHandler {
+            user_id: self.user_id,
+            room_id,
+            membership_code: code,
+            permissions,
+            joined: Presence::default(),
+            auth_service: self.state.auth_service.clone(),
+            chat_service: self.state.chat_service.clone(),
+            notification_service: self.state.notification_service.clone(),
+            rate_limit_service: self.state.rate_limit_service.clone(),
+            command_service: self.state.command_service.clone(),
+            protocol: self.protocol.clone(),
+            user_tx,
+            room_tx,
+        };
+
+        let out_tx = self.out_tx.clone();
+        let protocol = self.protocol.clone();
+        let forward = tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    event = subscription.recv() => match event {
+                        Ok(event) => Some(event),
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => None,
+                    },
+                    event = user_rx.recv() => event,
+                };
+                let Some(event) = event else {
+                    break;
+                };
+                if !protocol.allows(&event) {
+                    continue;
+                }
+                let event = StreamServerEvent::Room(RoomEvent { room_id, event });
+                if out_tx.send(event).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        self.rooms
+            .insert(room_id, RoomSubscription { handler, forward });
+        self.out_tx
+            .send(StreamServerEvent::Subscribed(room_id))
+            .await
+            .map_err(|e| service::Error::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, room_id: RoomId) -> Result<(), anyhow::Error> {
+        if self.rooms.remove(&room_id).is_some() {
+            self.out_tx
+                .send(StreamServerEvent::Unsubscribed(room_id))
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn send_error(&self, error: ServerError) -> Result<(), anyhow::Error> {
+        let event = ServerEvent::ErrMessage(error);
+        self.out_tx.send(StreamServerEvent::User(event)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::router::ws::handlers::tests::{chat_state, MockChatState};
+    use crate::service::{
+        MockAuthService, MockChatService, MockCommandService, MockNotificationService,
+        MockRateLimitService, Permissions,
+    };
+    use shared::domain::event::{
+        ClientEvent, JoinRequest, JoinResponse, RemovedFromRoom, UserJoinResponse,
+    };
+
+    type MockSession = StreamSession<
+        MockAuthService,
+        MockChatService,
+        MockNotificationService,
+        MockRateLimitService,
+        MockCommandService,
+    >;
+
+    fn member_state() -> MockChatState {
+        member_state_with(MockChatService::new())
+    }
+
+    fn member_state_with(mut chat_service: MockChatService) -> MockChatState {
+        let mut auth_service = MockAuthService::new();
+        auth_service
+            .expect_permissions()
+            .returning(|_| Box::pin(async { Ok(Permissions::ALL) }));
+        chat_service
+            .expect_get_membership()
+            .returning(|_, _| Box::pin(async { Ok(Some("code".to_string())) }));
+        chat_state(auth_service, chat_service, MockNotificationService::new())
+    }
+
+    /// Member state expecting one join, the rooms left are reported on the receiver.
+    fn joining_state() -> (MockChatState, mpsc::UnboundedReceiver<RoomId>) {
+        let (left_tx, left_rx) = mpsc::unbounded_channel();
+        let mut chat_service = MockChatService::new();
+        chat_service
+            .expect_user_joined()
+            .times(1)
+            .returning(|_, _| Box::pin(async {}));
+        chat_service
+            .expect_user_left()
+            .returning(move |room_id, _| {
+                let _ = left_tx.send(*room_id);
+                Box::pin(async {})
+            });
+        (member_state_with(chat_service), left_rx)
+    }
+
+    fn join(room_id: RoomId) -> StreamClientEvent {
+        StreamClientEvent::Room(RoomEvent {
+            room_id,
+            event: ClientEvent::Join(JoinRequest {
+                join_at: chrono::Utc::now(),
+            }),
+        })
+    }
+
+    fn open(state: &MockChatState) -> (MockSession, mpsc::Receiver<StreamServerEvent>) {
+        StreamSession::open(Uuid::new_v4().into(), Uuid::new_v4(), state.clone())
+    }
+
+    async fn next(events: &mut mpsc::Receiver<StreamServerEvent>) -> StreamServerEvent {
+        tokio::time::timeout(Duration::from_secs(1), events.recv())
+            .await
+            .expect("no stream event")
+            .expect("stream closed")
+    }
+
+    fn user_join(user_id: UserId) -> ServerEvent {
+        ServerEvent::UserJoin(UserJoinResponse { user_id })
+    }
+
+    #[tokio::test]
+    async fn subscribed_rooms_are_forwarded_tagged_with_their_room() {
+        let state = member_state();
+        let room_id: RoomId = Uuid::new_v4().into();
+        let (session, mut events) = open(&state);
+
+        session
+            .handle(StreamClientEvent::Subscribe(room_id))
+            .await
+            .unwrap();
+        assert!(
+            matches!(next(&mut events).await, StreamServerEvent::Subscribed(id) if id == room_id)
+        );
+
+        state
+            .get_or_create_room_chanel(&room_id)
+            .send(user_join(session.user_id))
+            .unwrap();
+
+        assert!(matches!(
+            next(&mut events).await,
+            StreamServerEvent::Room(RoomEvent { room_id: id, event: ServerEvent::UserJoin(_) })
+                if id == room_id
+        ));
+    }
+
+    #[tokio::test]
+    async fn unsubscribed_rooms_are_no_longer_forwarded() {
+        let state = member_state();
+        let room_id: RoomId = Uuid::new_v4().into();
+        let (session, mut events) = open(&state);
+        session
+            .handle(StreamClientEvent::Subscribe(room_id))
+            .await
+            .unwrap();
+        next(&mut events).await;
+
+        session
+            .handle(StreamClientEvent::Unsubscribe(room_id))
+            .await
+            .unwrap();
+        assert!(
+            matches!(next(&mut events).await, StreamServerEvent::Unsubscribed(id) if id == room_id)
+        );
+
+        let room_tx = state.get_or_create_room_chanel(&room_id);
+        let _ = room_tx.send(user_join(session.user_id));
+        tokio::task::yield_now().await;
+
+        assert!(events.try_recv().is_err());
+        assert_eq!(room_tx.receiver_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn room_events_fan_out_to_every_subscribed_stream() {
+        let state = member_state();
+        let room_id: RoomId = Uuid::new_v4().into();
+        let (alice, mut alice_events) = open(&state);
+        let (bob, mut bob_events) = open(&state);
+        for (session, events) in [(&alice, &mut alice_events), (&bob, &mut bob_events)] {
+            session
+                .handle(StreamClientEvent::Subscribe(room_id))
+                .await
+                .unwrap();
+            next(events).await;
+        }
+
+        state
+            .get_or_create_room_chanel(&room_id)
+            .send(ServerEvent::Join(JoinResponse {
+                user_id: alice.user_id,
+            }))
+            .unwrap();
+
+        for events in [&mut alice_events, &mut bob_events] {
+            assert!(matches!(
+                next(events).await,
+                StreamServerEvent::Room(RoomEvent {
+                    event: ServerEvent::Join(_),
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn rooms_the_user_is_removed_from_are_unsubscribed() {
+        let state = member_state();
+        let room_id: RoomId = Uuid::new_v4().into();
+        let other_room_id: RoomId = Uuid::new_v4().into();
+        let (session, mut events) = open(&state);
+        for id in [room_id, other_room_id] {
+            session
+                .handle(StreamClientEvent::Subscribe(id))
+                .await
+                .unwrap();
+            next(&mut events).await;
+        }
+
+        state
+            .get_user_chanel(&session.user_id)
+            .unwrap()
+            .send(ServerEvent::RemovedFromRoom(RemovedFromRoom { room_id }))
+            .unwrap();
+
+        assert!(matches!(
+            next(&mut events).await,
+            StreamServerEvent::User(ServerEvent::RemovedFromRoom(ev)) if ev.room_id == room_id
+        ));
+        assert!(
+            matches!(next(&mut events).await, StreamServerEvent::Unsubscribed(id) if id == room_id)
+        );
+        assert_eq!(
+            state.get_or_create_room_chanel(&room_id).receiver_count(),
+            0
+        );
+        assert_eq!(
+            state
+                .get_or_create_room_chanel(&other_room_id)
+                .receiver_count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn rooms_of_other_users_cannot_be_subscribed() {
+        let mut chat_service = MockChatService::new();
+        chat_service
+            .expect_get_membership()
+            .returning(|_, _| Box::pin(async { Ok(None) }));
+        let state = chat_state(
+            MockAuthService::new(),
+            chat_service,
+            MockNotificationService::new(),
+        );
+        let (session, mut events) = open(&state);
+
+        session
+            .handle(StreamClientEvent::Subscribe(Uuid::new_v4().into()))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            next(&mut events).await,
+            StreamServerEvent::User(ServerEvent::ErrMessage(ServerError {
+                code: ErrorCode::NotFound,
+                ..
+            }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rooms_left_without_joining_are_not_announced() {
+        let mut chat_service = MockChatService::new();
+        chat_service.expect_user_joined().never();
+        chat_service.expect_user_left().never();
+        let state = member_state_with(chat_service);
+        let room_id: RoomId = Uuid::new_v4().into();
+        let (session, _events) = open(&state);
+
+        session
+            .handle(StreamClientEvent::Subscribe(room_id))
+            .await
+            .unwrap();
+        session
+            .handle(StreamClientEvent::Unsubscribe(room_id))
+            .await
+            .unwrap();
+        drop(session);
+        tokio::task::yield_now().await;
+    }
+
+    #[tokio::test]
+    async fn joined_room_is_announced_left_once_on_unsubscribe() {
+        let (state, mut left) = joining_state();
+        let room_id: RoomId = Uuid::new_v4().into();
+        let (session, _events) = open(&state);
+        session
+            .handle(StreamClientEvent::Subscribe(room_id))
+            .await
+            .unwrap();
+        session.handle(join(room_id)).await.unwrap();
+        session.handle(join(room_id)).await.unwrap();
+
+        session
+            .handle(StreamClientEvent::Unsubscribe(room_id))
+            .await
+            .unwrap();
+
+        let announced = tokio::time::timeout(Duration::from_secs(1), left.recv()).await;
+        assert_eq!(announced.unwrap(), Some(room_id));
+        drop(session);
+        tokio::task::yield_now().await;
+        assert!(left.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn joined_rooms_are_announced_left_when_the_stream_ends() {
+        let (state, mut left) = joining_state();
+        let room_id: RoomId = Uuid::new_v4().into();
+        let (session, _events) = open(&state);
+        session
+            .handle(StreamClientEvent::Subscribe(room_id))
+            .await
+            .unwrap();
+        session.handle(join(room_id)).await.unwrap();
+
+        drop(session);
+
+        let announced = tokio::time::timeout(Duration::from_secs(1), left.recv()).await;
+        assert_eq!(announced.unwrap(), Some(room_id));
+    }
+}
@@ -0,0 +1,183 @@
+use std::{collections::HashMap, convert::Infallible, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Extension, Json,
+};
+use axum_extra::extract::CookieJar;
+use futures::{Stream, StreamExt};
+use shared::domain::event::{StreamClientEvent, StreamServerEvent, Tolerant};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::router::auth::CookieAuth;
+use crate::service::{self, Claims, SocketTicket};
+
+use super::{redeem_handshake_ticket, unsupported_event, SharedStreamState, StreamSession};
+
+/// Serves the per-user stream as Server-Sent Events. The first event, named `connected`,
+/// carries the stream id that client events are posted to.
+///
+/// Opened with a socket ticket or, in cookie mode, the access token cookie. `EventSource`
+/// reconnects to the same URL, after the ticket was used up, so reconnects need the cookie.
+pub async fn sse_handler<A, C, N, R, K>(
+    State(state): State<SharedStreamState<A, C, N, R, K>>,
+    Extension(cookies): Extension<Arc<CookieAuth>>,
+    jar: CookieJar,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService + Send + Sync + 'static,
+    C: service::ChatService + Send + Sync + 'static,
+    N: service::NotificationService + Send + Sync + 'static,
+    R: service::RateLimitService + Send + Sync + 'static,
+    K: service::CommandService + Send + Sync + 'static,
+{
+    let auth_service = state.chat.auth_service.as_ref();
+    let SocketTicket {
+        user_id,
+        session_id,
+    } = match cookies.event_stream_access_token(&jar) {
+        Some(token) if !params.contains_key("ticket") => {
+            let claims = auth_service.validate_token(&token).await?;
+            SocketTicket {
+                user_id: claims.user_id(),
+                session_id: claims.family_id(),
+            }
+        }
+        _ => redeem_handshake_ticket(auth_service, &params).await?,
+    };
+
+    let (session, events) = StreamSession::open(user_id, session_id, state.chat.clone());
+    let session = Arc::new(session);
+    let stream_id = state.register(session.clone());
+    tokio::spawn(async move { session.subscribe_to_memberships().await });
+
+    let stream = sse_events(state, stream_id, events);
+
+    Ok(Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response())
+}
+
+/// The `connected` event followed by the stream events, until one ends the stream or
+/// the client disconnects.
+fn sse_events<A, C, N, R, K>(
+    state: SharedStreamState<A, C, N, R, K>,
+    stream_id: Uuid,
+    events: mpsc::Receiver<StreamServerEvent>,
+) -> impl Stream<Item = Result<Event, Infallible>>
+where
+    A: Send + Sync + 'static,
+    C: Send + Sync + 'static,
+    N: Send + Sync + 'static,
+    R: Send + Sync + 'static,
+    K: Send + Sync + 'static,
+{
+    let guard = SessionGuard { state, stream_id };
+    let connected = Event::default()
+        .event("connected")
+        .data(stream_id.to_string());
+    let events = futures::stream::unfold(Some((events, guard)), |open| async move {
+        let (mut events, guard) = open?;
+        let event = events.recv().await?;
+        let next = if event.ends_stream() {
+            None
+        } else {
+            Some((events, guard))
+        };
+        let event = Event::default().json_data(event).unwrap_or_else(|e| {
+            tracing::error!("Failed serialize event: {}", e);
+            Event::default().comment("dropped")
+        });
+        Some((Ok::<_, Infallible>(event), next))
+    });
+    futures::stream::once(async move { Ok(connected) }).chain(events)
+}
+
+#[tracing::instrument(name = "Send stream event", skip(state, claims, event))]
+pub async fn send_stream_event<A, C, N, R, K>(
+    State(state): State<SharedStreamState<A, C, N, R, K>>,
+    Extension(claims): Extension<Claims>,
+    Path(stream_id): Path<Uuid>,
+    Json(event): Json<Tolerant<StreamClientEvent>>,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService + Send + Sync + 'static,
+    C: service::ChatService + Send + Sync + 'static,
+    N: service::NotificationService + Send + Sync + 'static,
+    R: service::RateLimitService + Send + Sync + 'static,
+    K: service::CommandService + Send + Sync + 'static,
+{
+    let session = state
+        .get(&stream_id, &claims.user_id())
+        .ok_or(service::Error::NotFound("stream not found".to_string()))?;
+
+    let event = match event {
+        Tolerant::Known(event) => event,
+        Tolerant::Unknown(event) => {
+            return Err(service::Error::ValidationError(
+                unsupported_event(&event).message,
+            ))
+        }
+    };
+
+    session.handle(event).await?;
+
+    Ok(StatusCode::ACCEPTED.into_response())
+}
+
+/// Drops the stream from the registry once the client disconnects.
+struct SessionGuard<A, C, N, R, K> {
+    state: SharedStreamState<A, C, N, R, K>,
+    stream_id: Uuid,
+}
+
+impl<A, C, N, R, K> Drop for SessionGuard<A, C, N, R, K> {
+    fn drop(&mut self) {
+        self.state.remove(&self.stream_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::ws::handlers::tests::chat_state;
+    use crate::router::ws::StreamState;
+    use crate::service::{MockAuthService, MockChatService, MockNotificationService};
+    use shared::domain::{
+        event::{ServerEvent, SessionRevoked},
+        UserId,
+    };
+
+    #[tokio::test]
+    async fn sse_streams_end_and_unregister_after_a_closing_event() {
+        let state = Arc::new(StreamState::new(chat_state(
+            MockAuthService::new(),
+            MockChatService::new(),
+            MockNotificationService::new(),
+        )));
+        let user_id: UserId = Uuid::new_v4().into();
+        let (session, events) = StreamSession::open(user_id, Uuid::new_v4(), state.chat.clone());
+        let stream_id = state.register(Arc::new(session));
+        let stream = sse_events(state.clone(), stream_id, events);
+
+        state
+            .chat
+            .get_user_chanel(&user_id)
+            .unwrap()
+            .send(ServerEvent::SessionRevoked(SessionRevoked {
+                session_id: None,
+            }))
+            .unwrap();
+
+        let sent: Vec<_> = stream.collect().await;
+        assert_eq!(sent.len(), 2);
+        assert!(state.get(&stream_id, &user_id).is_none());
+    }
+}
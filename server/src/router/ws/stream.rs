@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{
+        ws::{self, WebSocket},
+        Query, State, WebSocketUpgrade,
+    },
+    http::HeaderMap,
+    response::Response,
+};
+use futures::{SinkExt, StreamExt};
+use shared::domain::{
+    event::{StreamClientEvent, Tolerant},
+    UserId,
+};
+
+use uuid::Uuid;
+
+use crate::service::{self, SocketTicket};
+
+use super::{redeem_handshake_ticket, SharedChatState, StreamSession, WireFormat};
+
+/// Opens a single socket for the user, multiplexing every room they subscribe to.
+pub async fn stream_handler<A, C, N, R, K>(
+    ws: WebSocketUpgrade,
+    State(state): State<SharedChatState<A, C, N, R, K>>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Response, service::Error>
+where
+    A: service::AuthService + Send + Sync + 'static,
+    C: service::ChatService + Send + Sync + 'static,
+    N: service::NotificationService + Send + Sync + 'static,
+    R: service::RateLimitService + Send + Sync + 'static,
+    K: service::CommandService + Send + Sync + 'static,
+{
+    let format = WireFormat::negotiate(&params, &headers)?;
+
+    let SocketTicket {
+        user_id,
+        session_id,
+    } = redeem_handshake_ticket(state.auth_service.as_ref(), &params).await?;
+
+    Ok(ws
+        .protocols([format.protocol()])
+        .on_upgrade(move |socket| stream(socket, user_id, session_id, format, state)))
+}
+
+async fn stream<A, C, N, R, K>(
+    socket: WebSocket,
+    user_id: UserId,
+    session_id: Uuid,
+    format: WireFormat,
+    state: SharedChatState<A, C, N, R, K>,
+) where
+    A: service::AuthService + Send + Sync + 'static,
+    C: service::ChatService + Send + Sync + 'static,
+    N: service::NotificationService + Send + Sync + 'static,
+    R: service::RateLimitService + Send + Sync + 'static,
+    K: service::CommandService + Send + Sync + 'static,
+{
+    let (mut sender, mut receiver) = socket.split();
+    let (session, mut events) = StreamSession::open(user_id, session_id, state);
+
+    let mut send = tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            let msg = match format.encode(&event) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    tracing::error!("Failed serialize event: {}", e);
+                    continue;
+                }
+            };
+            if sender.send(msg).await.is_err() {
+                break;
+            }
+            if event.ends_stream() {
+                let _ = sender.send(ws::Message::Close(None)).await;
+                break;
+            }
+        }
+        tracing::debug!("Close stream from send task");
+    });
+
+    let mut recv = tokio::spawn(async move {
+        session.subscribe_to_memberships().await;
+
+        while let Some(Ok(msg)) = receiver.next().await {
+            if let ws::Message::Close(_) = msg {
+                break;
+            }
+            let result = match format.decode::<StreamClientEvent>(&msg) {
+                Some(Tolerant::Known(event)) => session.handle(event).await,
+                Some(Tolerant::Unknown(event)) => session.reject(event).await,
+                None => continue,
+            };
+            if let Err(e) = result {
+                tracing::error!("Failed handel event: {}", e)
+            };
+        }
+        tracing::debug!("Close stream from recv task");
+    });
+
+    tokio::select! {
+        _ = (&mut send) => recv.abort(),
+        _ = (&mut recv) => send.abort(),
+    }
+}
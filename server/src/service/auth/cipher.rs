@@ -0,0 +1,69 @@
+use anyhow::{anyhow, ensure};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+
+const NONCE_LENGTH: usize = 24;
+
+/// Encrypts secrets stored in the database with a key from configuration.
+#[derive(Clone)]
+pub struct SecretCipher {
+    cipher: XChaCha20Poly1305,
+}
+
+impl SecretCipher {
+    pub fn new(key: &[u8]) -> Result<Self, anyhow::Error> {
+        let cipher = XChaCha20Poly1305::new_from_slice(key)
+            .map_err(|_| anyhow!("Encryption key must be 32 bytes."))?;
+        Ok(Self { cipher })
+    }
+
+    /// Returns the nonce followed by the ciphertext. `context` binds the ciphertext to its
+    /// owner, so that it cannot be moved to another row.
+    pub fn encrypt(&self, plaintext: &[u8], context: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        let mut nonce = [0u8; NONCE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let payload = Payload {
+            msg: plaintext,
+            aad: context,
+        };
+        let ciphertext = self
+            .cipher
+            .encrypt(XNonce::from_slice(&nonce), payload)
+            .map_err(|_| anyhow!("Failed encrypt secret."))?;
+
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    pub fn decrypt(&self, data: &[u8], context: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        ensure!(data.len() > NONCE_LENGTH, "Encrypted secret is too short.");
+        let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: context,
+        };
+        self.cipher
+            .decrypt(XNonce::from_slice(nonce), payload)
+            .map_err(|_| anyhow!("Failed decrypt secret."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_decrypts_only_in_its_context() {
+        let cipher = SecretCipher::new(&[7u8; 32]).unwrap();
+
+        let encrypted = cipher.encrypt(b"secret", b"user-1").unwrap();
+
+        assert_eq!(cipher.decrypt(&encrypted, b"user-1").unwrap(), b"secret");
+        assert!(cipher.decrypt(&encrypted, b"user-2").is_err());
+    }
+
+    #[test]
+    fn short_keys_are_rejected() {
+        assert!(SecretCipher::new(&[7u8; 16]).is_err());
+    }
+}
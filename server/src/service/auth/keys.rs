@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use anyhow::{ensure, Context};
+use base64::engine::general_purpose;
+use base64::Engine;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, OctetKeyPairParameters,
+    OctetKeyPairType, PublicKeyUse,
+};
+use jsonwebtoken::{DecodingKey, EncodingKey};
+
+use super::ALGORITHM;
+
+/// Key id of tokens signed before tokens carried a `kid`.
+pub const LEGACY_KEY_ID: &str = "default";
+
+/// Length of a raw Ed25519 public key, the tail of its DER encoding.
+const ED25519_KEY_LENGTH: usize = 32;
+
+/// Keys tokens are signed with and verified against.
+///
+/// Tokens are signed with a single key and name it in their `kid` header. Keys that no
+/// longer sign keep verifying the tokens signed with them until those expire.
+#[derive(Clone)]
+pub struct KeySet {
+    signing_key_id: String,
+    encoding_key: EncodingKey,
+    decoding_keys: HashMap<String, DecodingKey>,
+    jwks: JwkSet,
+}
+
+impl KeySet {
+    /// `public_keys` are PEM encoded Ed25519 keys by key id and must include the public
+    /// key of the signing key.
+    pub fn new(
+        signing_key_id: &str,
+        signing_key_pem: &[u8],
+        public_keys: &[(String, Vec<u8>)],
+    ) -> Result<Self, anyhow::Error> {
+        let encoding_key =
+            EncodingKey::from_ed_pem(signing_key_pem).context("Invalid private key pem.")?;
+
+        let mut decoding_keys = HashMap::default();
+        let mut jwks = JwkSet { keys: Vec::new() };
+        for (key_id, pem) in public_keys {
+            let decoding_key = DecodingKey::from_ed_pem(pem)
+                .with_context(|| format!("Invalid public key pem of key {}.", key_id))?;
+            ensure!(
+                decoding_keys.insert(key_id.clone(), decoding_key).is_none(),
+                "Duplicate key id {}.",
+                key_id
+            );
+            jwks.keys.push(public_jwk(key_id, pem)?);
+        }
+        ensure!(
+            decoding_keys.contains_key(signing_key_id),
+            "Missing public key of signing key {}.",
+            signing_key_id
+        );
+
+        Ok(Self {
+            signing_key_id: signing_key_id.to_string(),
+            encoding_key,
+            decoding_keys,
+            jwks,
+        })
+    }
+
+    pub fn signing_key(&self) -> (&str, &EncodingKey) {
+        (&self.signing_key_id, &self.encoding_key)
+    }
+
+    /// Key a token with the given `kid` header is verified with.
+    pub fn decoding_key(&self, key_id: Option<&str>) -> Option<&DecodingKey> {
+        self.decoding_keys.get(key_id.unwrap_or(LEGACY_KEY_ID))
+    }
+
+    /// Public keys for other services to verify tokens with.
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+}
+
+fn public_jwk(key_id: &str, pem: &[u8]) -> Result<Jwk, anyhow::Error> {
+    let der = pem_body(pem)?;
+    ensure!(
+        der.len() >= ED25519_KEY_LENGTH,
+        "Public key {} is too short.",
+        key_id
+    );
+    let raw = &der[der.len() - ED25519_KEY_LENGTH..];
+
+    Ok(Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            algorithm: Some(ALGORITHM),
+            key_id: Some(key_id.to_string()),
+            ..CommonParameters::default()
+        },
+        algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x: general_purpose::URL_SAFE_NO_PAD.encode(raw),
+        }),
+    })
+}
+
+/// Decodes the DER body of a PEM, which may be written on a single line.
+fn pem_body(pem: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let pem = std::str::from_utf8(pem).context("Public key pem is not utf-8.")?;
+    let mut body = String::new();
+    for (i, part) in pem.split("-----").enumerate() {
+        // Odd parts are the BEGIN and END labels.
+        if i % 2 == 0 {
+            body.extend(part.chars().filter(|c| !c.is_whitespace()));
+        }
+    }
+    general_purpose::STANDARD
+        .decode(body)
+        .context("Invalid public key pem.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jwt_simple::algorithms::Ed25519KeyPair;
+
+    #[test]
+    fn jwks_holds_raw_public_keys() {
+        let key_pair = Ed25519KeyPair::generate();
+        let public_pem = key_pair.public_key().to_pem();
+
+        let keys = KeySet::new(
+            "current",
+            key_pair.to_pem().as_bytes(),
+            &[("current".to_string(), public_pem.into_bytes())],
+        )
+        .unwrap();
+
+        let jwk = keys.jwks().find("current").unwrap();
+        let AlgorithmParameters::OctetKeyPair(params) = &jwk.algorithm else {
+            panic!("Expected an octet key pair, got {:?}", jwk.algorithm);
+        };
+        assert_eq!(
+            general_purpose::URL_SAFE_NO_PAD.decode(&params.x).unwrap(),
+            key_pair.public_key().to_bytes()
+        );
+    }
+
+    #[test]
+    fn signing_key_must_be_verifiable() {
+        let key_pair = Ed25519KeyPair::generate();
+        let other = Ed25519KeyPair::generate();
+
+        let keys = KeySet::new(
+            "current",
+            key_pair.to_pem().as_bytes(),
+            &[("old".to_string(), other.public_key().to_pem().into_bytes())],
+        );
+
+        assert!(keys.is_err());
+    }
+}
@@ -1,7 +1,15 @@
+mod cipher;
+mod keys;
+mod oidc;
 mod password;
+mod revocation;
 mod service;
 mod tokens;
+mod totp;
 
+pub use cipher::*;
+pub use keys::*;
+pub use oidc::*;
 pub use password::*;
 pub use service::*;
 pub use tokens::*;
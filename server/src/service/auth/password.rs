@@ -1,3 +1,6 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
 use anyhow::Context;
 use argon2::{
     password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
@@ -6,8 +9,12 @@ use argon2::{
 use secrecy::{ExposeSecret, Secret};
 use tokio::task::JoinHandle;
 
+use crate::configuration::PasswordSettings;
 use crate::service;
 
+/// Names and emails shorter than this are not looked for in passwords.
+const MIN_PERSONAL_LENGTH: usize = 3;
+
 #[tracing::instrument(
     name = "Validate credentials",
     skip(expected_password_hash, password_candidate)
@@ -28,18 +35,105 @@ pub fn verify_password_hash(
         .map_err(service::Error::InvalidCredentials)
 }
 
-pub fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+pub fn compute_password_hash(
+    password: Secret<String>,
+    params: Params,
+) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
-    )
-    .hash_password(password.expose_secret().as_bytes(), &salt)?
-    .to_string();
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
     Ok(Secret::new(password_hash))
 }
 
+/// Whether the hash was computed with other parameters than `params`, so that it should
+/// be replaced while the password is known.
+pub fn needs_rehash(password_hash: &Secret<String>, params: &Params) -> bool {
+    let Ok(hash) = PasswordHash::new(password_hash.expose_secret()) else {
+        return false;
+    };
+    if hash.algorithm != Algorithm::Argon2id.ident() || hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+    match Params::try_from(&hash) {
+        Ok(current) => {
+            current.m_cost() != params.m_cost()
+                || current.t_cost() != params.t_cost()
+                || current.p_cost() != params.p_cost()
+        }
+        Err(_) => true,
+    }
+}
+
+/// Rules new passwords have to follow.
+#[derive(Clone, Default)]
+pub struct PasswordPolicy {
+    min_length: usize,
+    /// Lowercase passwords known from breaches.
+    breached: Arc<HashSet<String>>,
+}
+
+impl PasswordPolicy {
+    pub fn new(min_length: usize, breached: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            min_length,
+            breached: Arc::new(breached.into_iter().map(|p| p.to_lowercase()).collect()),
+        }
+    }
+
+    /// Reads the breached password list, one password per line.
+    pub fn load(settings: &PasswordSettings) -> Result<Self, anyhow::Error> {
+        let breached = match &settings.breached_list_path {
+            Some(path) => std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read breached passwords {:?}.", path))?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+            None => Vec::new(),
+        };
+        Ok(Self::new(settings.min_length, breached))
+    }
+
+    /// `personal` are the name and email of the user, which the password may not contain.
+    pub fn check(
+        &self,
+        password: &Secret<String>,
+        personal: &[&str],
+    ) -> Result<(), service::Error> {
+        let password = password.expose_secret().to_lowercase();
+        if password.chars().count() < self.min_length {
+            return Err(service::Error::ValidationError(format!(
+                "Password must be at least {} characters.",
+                self.min_length
+            )));
+        }
+        if self.breached.contains(&password) {
+            return Err(service::Error::ValidationError(
+                "Password appears in a data breach, choose another one.".to_string(),
+            ));
+        }
+
+        let mut personal: Vec<String> = personal.iter().map(|s| s.to_lowercase()).collect();
+        let local_parts: Vec<String> = personal
+            .iter()
+            .filter_map(|s| s.split_once('@').map(|(local, _)| local.to_string()))
+            .collect();
+        personal.extend(local_parts);
+        if personal
+            .iter()
+            .any(|s| s.chars().count() >= MIN_PERSONAL_LENGTH && password.contains(s.as_str()))
+        {
+            return Err(service::Error::ValidationError(
+                "Password must not contain your name or email.".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
 pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
 where
     F: FnOnce() -> R + Send + 'static,
@@ -48,3 +142,39 @@ where
     let current_span = tracing::Span::current();
     tokio::task::spawn_blocking(move || current_span.in_scope(f))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(m_cost: u32) -> Params {
+        Params::new(m_cost, 1, 1, None).unwrap()
+    }
+
+    #[test]
+    fn hash_with_other_parameters_needs_rehash() {
+        let hash = compute_password_hash(Secret::new("secret".into()), params(1024)).unwrap();
+
+        assert!(!needs_rehash(&hash, &params(1024)));
+        assert!(needs_rehash(&hash, &params(2048)));
+    }
+
+    #[test]
+    fn short_breached_and_personal_passwords_are_rejected() {
+        let policy = PasswordPolicy::new(8, ["Password123".to_string()]);
+        let personal = ["alice", "alice.smith@example.com"];
+
+        let rejected = ["short", "password123", "my-AliCe-pass", "x-alice.smith-x"];
+        for password in rejected {
+            let result = policy.check(&Secret::new(password.into()), &personal);
+            assert!(
+                matches!(result, Err(service::Error::ValidationError(_))),
+                "{} was accepted",
+                password
+            );
+        }
+        assert!(policy
+            .check(&Secret::new("correct horse battery".into()), &personal)
+            .is_ok());
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
+use crate::configuration::RevocationSettings;
+
+/// Remembers for a short while whether access tokens were revoked, so that not every
+/// request needs a lookup.
+pub struct RevocationCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<Uuid, (bool, Instant)>>,
+}
+
+impl RevocationCache {
+    pub fn new(settings: &RevocationSettings) -> Self {
+        Self {
+            ttl: Duration::from_millis(settings.cache_ttl_ms),
+            capacity: settings.cache_capacity,
+            entries: Mutex::new(HashMap::default()),
+        }
+    }
+
+    pub fn get(&self, token_id: &Uuid) -> Option<bool> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(token_id)
+            .filter(|(_, cached_at)| cached_at.elapsed() < self.ttl)
+            .map(|(revoked, _)| *revoked)
+    }
+
+    pub fn insert(&self, token_id: Uuid, revoked: bool) {
+        if self.capacity == 0 || self.ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity {
+            entries.retain(|_, (_, cached_at)| cached_at.elapsed() < self.ttl);
+        }
+        if entries.len() >= self.capacity {
+            entries.clear();
+        }
+        entries.insert(token_id, (revoked, Instant::now()));
+    }
+
+    /// Forgets every entry, for revocations that cannot be told apart by token id.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(cache_ttl_ms: u64, cache_capacity: usize) -> RevocationCache {
+        RevocationCache::new(&RevocationSettings {
+            enabled: true,
+            cache_ttl_ms,
+            cache_capacity,
+        })
+    }
+
+    #[test]
+    fn entries_expire_after_ttl() {
+        let cache = cache(10, 10);
+        let token_id = Uuid::new_v4();
+        cache.insert(token_id, false);
+        assert_eq!(cache.get(&token_id), Some(false));
+
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(cache.get(&token_id), None);
+    }
+
+    #[test]
+    fn full_cache_makes_room_for_new_entries() {
+        let cache = cache(60_000, 2);
+        let token_ids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        for token_id in token_ids {
+            cache.insert(token_id, true);
+        }
+
+        assert_eq!(cache.get(&token_ids[2]), Some(true));
+        assert!(cache.entries.lock().unwrap().len() <= 2);
+    }
+
+    #[test]
+    fn zero_ttl_disables_caching() {
+        let cache = cache(0, 10);
+        let token_id = Uuid::new_v4();
+        cache.insert(token_id, true);
+
+        assert_eq!(cache.get(&token_id), None);
+    }
+}
@@ -1,54 +1,163 @@
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
 use anyhow::Context;
 
+use argon2::Params;
 use async_trait::async_trait;
+use base64::engine::general_purpose;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::jwk::JwkSet;
+use rand::RngCore;
 use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use shared::domain::event::{ServerEvent, SessionRevoked};
 use shared::domain::{NewUser, User, UserCode, UserEmail, UserId, UserName};
 use uuid::Uuid;
 
-use crate::configuration::AuthSettings;
-use crate::service::Error;
+use crate::configuration::{AuthSettings, EmailVerificationSettings, LockoutSettings};
+use crate::service::{Email, EmailSender, Error, UserEventBus};
 
+use super::cipher::SecretCipher;
+use super::oidc::{pkce_challenge, AuthorizationRequest, ExternalIdentity, OidcClient, OidcLogin};
+use super::revocation::RevocationCache;
+use super::totp;
 use super::{compute_password_hash, decode_token, encode_token, spawn_blocking_with_tracing};
-use super::{verify_password_hash, Claims};
+use super::{needs_rehash, verify_password_hash, PasswordPolicy};
+use super::{Claims, KeySet, TokenScope, TokenType};
 
 pub struct Credentials {
     pub email: String,
     pub password: Secret<String>,
 }
 
+/// Where a session was signed in from, shown when listing sessions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub device_label: Option<String>,
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
 type Tokens = (Secret<String>, Secret<String>);
 
+/// Result of checking the password, the tokens wait for the second factor when it is enabled.
+#[derive(Debug)]
+pub enum LoginOutcome {
+    Tokens(Tokens),
+    /// Short-lived token to pass to `login_mfa` with the code.
+    MfaRequired(Secret<String>),
+}
+
+/// Secret of a TOTP enrollment, to add to an authenticator app before confirming it.
+#[derive(Debug)]
+pub struct TotpEnrollment {
+    pub secret: Secret<String>,
+    /// `otpauth://` URI to show as a QR code.
+    pub provisioning_uri: Secret<String>,
+}
+
+/// Target of the structured events security monitoring is fed from.
+pub(crate) const SECURITY_AUDIT: &str = "security_audit";
+
+/// Random bytes in socket tickets and password reset tokens.
+const RANDOM_TOKEN_LENGTH: usize = 32;
+
 #[derive(Clone)]
-pub struct AuthServiceImp<CredRepo, TokenRepo>
+pub struct AuthServiceImp<CredRepo, TokenRepo, Attempts, EventBus, Mailer, Oidc>
 where
     CredRepo: CredentialsRepository,
     TokenRepo: TokenRepository,
+    Attempts: LoginAttemptRepository,
+    EventBus: UserEventBus,
+    Mailer: EmailSender,
+    Oidc: OidcClient,
 {
     credentials_repo: CredRepo,
     tokens_repo: TokenRepo,
-    encoding_key: jsonwebtoken::EncodingKey,
-    decoding_key: jsonwebtoken::DecodingKey,
+    login_attempts: Attempts,
+    event_bus: EventBus,
+    /// Shared with the tasks password reset emails are sent from.
+    email_sender: Arc<Mailer>,
+    oidc_client: Oidc,
+    keys: KeySet,
+    token_scope: TokenScope,
     access_toked_duration: u64,
     refresh_toked_duration: u64,
+    ws_ticket_duration: u64,
+    password_reset_duration: u64,
+    password_reset_url: String,
+    email_verification: EmailVerificationSettings,
+    totp_cipher: SecretCipher,
+    mfa_issuer: String,
+    mfa_challenge_duration: u64,
+    lockout: LockoutSettings,
+    password_params: Params,
+    password_policy: PasswordPolicy,
+    /// Checked against for unknown emails, so that they take as long as known ones.
+    dummy_password_hash: Secret<String>,
+    oidc_providers: HashSet<String>,
+    oidc_login_duration: u64,
+    /// Set when access tokens are checked against revocations.
+    revocation_cache: Option<Arc<RevocationCache>>,
 }
 
-impl<CredRepo, TokenRepo> AuthServiceImp<CredRepo, TokenRepo>
+impl<CredRepo, TokenRepo, Attempts, EventBus, Mailer, Oidc>
+    AuthServiceImp<CredRepo, TokenRepo, Attempts, EventBus, Mailer, Oidc>
 where
     CredRepo: CredentialsRepository,
     TokenRepo: TokenRepository,
+    Attempts: LoginAttemptRepository,
+    EventBus: UserEventBus,
+    Mailer: EmailSender,
+    Oidc: OidcClient,
 {
     pub fn build(
         config: &AuthSettings,
         credentials_repo: CredRepo,
         tokens_repo: TokenRepo,
+        login_attempts: Attempts,
+        event_bus: EventBus,
+        email_sender: Mailer,
+        oidc_client: Oidc,
     ) -> Result<Self, anyhow::Error> {
+        let password_params = config.password.argon2.params()?;
+        let dummy_password_hash =
+            compute_password_hash(Secret::new(random_token()), password_params.clone())?;
         Ok(Self {
             credentials_repo,
             tokens_repo,
-            encoding_key: config.encoding_key()?,
-            decoding_key: config.decoding_key()?,
+            login_attempts,
+            event_bus,
+            email_sender: Arc::new(email_sender),
+            oidc_client,
+            keys: config.key_set()?,
+            token_scope: TokenScope {
+                issuer: config.issuer.clone(),
+                audience: config.audience.clone(),
+            },
             access_toked_duration: config.access_toked_duration,
             refresh_toked_duration: config.refresh_toked_duration,
+            ws_ticket_duration: config.ws_ticket_duration,
+            password_reset_duration: config.password_reset_duration,
+            password_reset_url: config.password_reset_url.clone(),
+            email_verification: config.email_verification.clone(),
+            totp_cipher: config.mfa.cipher()?,
+            mfa_issuer: config.mfa.issuer.clone(),
+            mfa_challenge_duration: config.mfa.challenge_duration,
+            lockout: config.lockout.clone(),
+            password_params,
+            password_policy: PasswordPolicy::load(&config.password)?,
+            dummy_password_hash,
+            oidc_providers: config.oidc.providers.keys().cloned().collect(),
+            oidc_login_duration: config.oidc.login_duration,
+            revocation_cache: config
+                .revocation
+                .enabled
+                .then(|| Arc::new(RevocationCache::new(&config.revocation))),
         })
     }
 }
@@ -63,6 +172,8 @@ pub trait CredentialsRepository {
 
     async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, anyhow::Error>;
 
+    async fn get_user(&self, user_id: &UserId) -> Result<Option<User>, anyhow::Error>;
+
     async fn signup(
         &self,
         user_name: &UserName,
@@ -70,40 +181,426 @@ pub trait CredentialsRepository {
         user_password_hash: Secret<String>,
         code: &UserCode,
     ) -> Result<User, anyhow::Error>;
+
+    async fn get_password_hash(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<Secret<String>>, anyhow::Error>;
+
+    async fn update_password(
+        &self,
+        user_id: &UserId,
+        password_hash: Secret<String>,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Email of the user and whether it was verified.
+    async fn get_email_status(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<(String, bool)>, anyhow::Error>;
+
+    async fn verify_email(&self, user_id: &UserId) -> Result<(), anyhow::Error>;
+
+    async fn is_admin(&self, user_id: &UserId) -> Result<bool, anyhow::Error>;
+
+    async fn get_totp(&self, user_id: &UserId) -> Result<Option<TotpRecord>, anyhow::Error>;
+
+    /// Starts an enrollment, replacing an unconfirmed one. Returns false if TOTP is
+    /// enabled already.
+    async fn store_totp(&self, user_id: &UserId, secret: &[u8]) -> Result<bool, anyhow::Error>;
+
+    /// Enables TOTP with the recovery codes, replacing older ones. `step` is the time step
+    /// of the code that confirmed it.
+    async fn confirm_totp(
+        &self,
+        user_id: &UserId,
+        step: u64,
+        recovery_code_hashes: &[String],
+    ) -> Result<(), anyhow::Error>;
+
+    /// Records that the code of `step` was used. Returns false if it or a later one was
+    /// used already.
+    async fn use_totp_step(&self, user_id: &UserId, step: u64) -> Result<bool, anyhow::Error>;
+
+    /// Returns false if the recovery code does not exist or was used already.
+    async fn use_recovery_code(
+        &self,
+        user_id: &UserId,
+        code_hash: &str,
+    ) -> Result<bool, anyhow::Error>;
+
+    async fn delete_totp(&self, user_id: &UserId) -> Result<(), anyhow::Error>;
+
+    /// User the identity at a provider is linked to.
+    async fn get_identity_user(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<UserId>, anyhow::Error>;
+
+    async fn link_identity(
+        &self,
+        user_id: &UserId,
+        identity: &ExternalIdentity,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Signs up a user linked to the identity, with the email verified if the provider
+    /// verified it.
+    async fn create_external_user(
+        &self,
+        name: &UserName,
+        email: &UserEmail,
+        password_hash: Secret<String>,
+        code: &UserCode,
+        identity: &ExternalIdentity,
+    ) -> Result<User, anyhow::Error>;
+}
+
+/// TOTP secret of a user, encrypted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TotpRecord {
+    pub secret: Vec<u8>,
+    /// Unconfirmed secrets are enrollments in progress and not asked for at login.
+    pub confirmed: bool,
+}
+
+/// Outcome of presenting a refresh token for rotation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    /// The token was the current one of its family and has been replaced.
+    Rotated,
+    /// The token was already rotated out, someone else holds the family.
+    Reused,
+    /// The family expired or was revoked.
+    Unknown,
+}
+
+/// Whom a socket ticket was issued to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SocketTicket {
+    pub user_id: UserId,
+    /// Refresh token family of the access token the ticket was exchanged for.
+    pub session_id: Uuid,
+}
+
+/// What a user may do, narrowed for accounts that did not verify their email.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct Permissions {
+    pub join_rooms: bool,
+    pub send_messages: bool,
+}
+
+impl Permissions {
+    pub const ALL: Self = Self {
+        join_rooms: true,
+        send_messages: true,
+    };
+
+    pub fn require_join_rooms(&self) -> Result<(), Error> {
+        self.join_rooms
+            .then_some(())
+            .ok_or_else(|| Error::Forbidden("Verify your email to join rooms.".to_string()))
+    }
+
+    pub fn require_send_messages(&self) -> Result<(), Error> {
+        self.send_messages
+            .then_some(())
+            .ok_or_else(|| Error::Forbidden("Verify your email to send messages.".to_string()))
+    }
 }
 
+/// Single-use tokens mailed to a user, valid for one purpose only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserTokenKind {
+    PasswordReset,
+    EmailVerification,
+}
+
+/// Refresh token family as seen by its owner, one per signed in device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub family_id: Uuid,
+    pub user_id: UserId,
+    pub client: ClientInfo,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+}
+
+/// Refresh tokens are tracked in families: every login starts one, every refresh
+/// replaces the current token of the family with a new one. A family expires `ttl`
+/// after it was last created or rotated.
 #[async_trait]
 #[cfg_attr(test, mockall::automock)]
 pub trait TokenRepository {
-    async fn create(&self, token_id: &Uuid, user_id: &UserId) -> anyhow::Result<()>;
-    async fn exist(&self, token_id: &Uuid, user_id: &UserId) -> anyhow::Result<Option<UserId>>;
-    async fn delete(&self, token_id: &Uuid, user_id: &UserId) -> anyhow::Result<()>;
+    /// Starts a new family with `token_id` as its current token.
+    async fn create(
+        &self,
+        family_id: &Uuid,
+        token_id: &Uuid,
+        user_id: &UserId,
+        ttl: Duration,
+        client: &ClientInfo,
+    ) -> anyhow::Result<()>;
+
+    /// Replaces `token_id` with `new_token_id` if it is the current token of the family,
+    /// recording `client` as the last one to use it.
+    async fn rotate(
+        &self,
+        family_id: &Uuid,
+        token_id: &Uuid,
+        new_token_id: &Uuid,
+        user_id: &UserId,
+        ttl: Duration,
+        client: &ClientInfo,
+    ) -> anyhow::Result<Rotation>;
+
+    async fn get(&self, family_id: &Uuid, user_id: &UserId) -> anyhow::Result<Option<Session>>;
+
+    async fn list(&self, user_id: &UserId) -> anyhow::Result<Vec<Session>>;
+
+    /// Revokes the family, returns its current token id if it still existed.
+    async fn revoke_family(
+        &self,
+        family_id: &Uuid,
+        user_id: &UserId,
+    ) -> anyhow::Result<Option<Uuid>>;
+
+    /// Revokes every family of the user, returns how many were revoked.
+    async fn revoke_all(&self, user_id: &UserId) -> anyhow::Result<u64>;
+
+    /// Rejects the access token `token_id` for `ttl`, the longest it can still be valid.
+    async fn revoke_access(&self, token_id: &Uuid, ttl: Duration) -> anyhow::Result<()>;
+
+    /// Rejects every access token of the user issued up to now.
+    async fn revoke_user_access(&self, user_id: &UserId, ttl: Duration) -> anyhow::Result<()>;
+
+    async fn is_access_revoked(
+        &self,
+        token_id: &Uuid,
+        user_id: &UserId,
+        issued_at: u64,
+    ) -> anyhow::Result<bool>;
+
+    async fn store_ticket(
+        &self,
+        ticket: &str,
+        owner: &SocketTicket,
+        ttl: Duration,
+    ) -> anyhow::Result<()>;
+
+    /// Removes the ticket, returns whom it was issued to unless it expired or was used.
+    async fn take_ticket(&self, ticket: &str) -> anyhow::Result<Option<SocketTicket>>;
+
+    async fn store_user_token(
+        &self,
+        kind: UserTokenKind,
+        token: &str,
+        user_id: &UserId,
+        ttl: Duration,
+    ) -> anyhow::Result<()>;
+
+    /// Removes the token, returns whom it was issued to unless it expired or was used.
+    async fn take_user_token(
+        &self,
+        kind: UserTokenKind,
+        token: &str,
+    ) -> anyhow::Result<Option<UserId>>;
+
+    /// Keeps an OIDC login in progress under its `state` until the provider redirects back.
+    async fn store_oidc_login(
+        &self,
+        state: &str,
+        login: &OidcLogin,
+        ttl: Duration,
+    ) -> anyhow::Result<()>;
+
+    /// Removes the login so that its state is used once.
+    async fn take_oidc_login(&self, state: &str) -> anyhow::Result<Option<OidcLogin>>;
+}
+
+/// Failed logins and the locks they lead to, stored under keys naming the account or IP.
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait LoginAttemptRepository {
+    /// Counts a failure under `key`, forgetting them `window` after the last one.
+    /// Returns the failures so far.
+    async fn record_failure(&self, key: &str, window: Duration) -> anyhow::Result<u64>;
+
+    async fn lock(&self, key: &str, duration: Duration) -> anyhow::Result<()>;
+
+    /// Time until `key` is unlocked, if locked.
+    async fn locked_for(&self, key: &str) -> anyhow::Result<Option<Duration>>;
+
+    /// Forgets the failures and lifts the lock.
+    async fn clear(&self, key: &str) -> anyhow::Result<()>;
 }
 
 #[async_trait]
 #[cfg_attr(test, mockall::automock)]
 pub trait AuthService {
     async fn validate_token(&self, token: &str) -> Result<Claims, Error>;
-    async fn signup(&self, new_user: NewUser) -> Result<(User, Tokens), Error>;
-    async fn login(&self, credentials: Credentials) -> Result<Tokens, Error>;
+    async fn signup(&self, new_user: NewUser, client: ClientInfo) -> Result<(User, Tokens), Error>;
+    async fn login(
+        &self,
+        credentials: Credentials,
+        client: ClientInfo,
+    ) -> Result<LoginOutcome, Error>;
+    /// User an MFA challenge was issued to, for throttling the codes tried with it.
+    fn mfa_challenge_user(&self, mfa_token: &str) -> Result<UserId, Error>;
+    /// Completes a login with the TOTP or a recovery code.
+    async fn login_mfa(
+        &self,
+        mfa_token: Secret<String>,
+        code: &str,
+        client: ClientInfo,
+    ) -> Result<Tokens, Error>;
+    /// Starts a login at an OpenID Connect provider, returns the page to send the user to.
+    async fn oidc_authorize(&self, provider: &str) -> Result<String, Error>;
+    /// Finishes a login with the code the provider redirected back with, signing up users
+    /// the first time they come. Users with a second factor still have to enter it.
+    async fn oidc_login(
+        &self,
+        provider: &str,
+        code: &str,
+        state: &str,
+        client: ClientInfo,
+    ) -> Result<LoginOutcome, Error>;
     async fn logout(&self, access_token: Secret<String>) -> Result<(), Error>;
-    async fn refresh(&self, refresh_token: Secret<String>) -> Result<Tokens, Error>;
+    async fn refresh(
+        &self,
+        refresh_token: Secret<String>,
+        client: ClientInfo,
+    ) -> Result<Tokens, Error>;
+    async fn list_sessions(&self, user_id: &UserId) -> Result<Vec<Session>, Error>;
+    async fn revoke_session(&self, user_id: &UserId, session_id: &Uuid) -> Result<(), Error>;
+    async fn revoke_all_sessions(&self, user_id: &UserId) -> Result<u64, Error>;
+    /// Public keys tokens can be verified with.
+    fn jwks(&self) -> JwkSet;
+    /// Exchanges a validated access token for a short-lived, single-use socket ticket.
+    async fn issue_ticket(&self, claims: &Claims) -> Result<Secret<String>, Error>;
+    async fn redeem_ticket(&self, ticket: &str) -> Result<SocketTicket, Error>;
+
+    /// Changes the password of the signed in user and signs out every session, including
+    /// their access tokens. The caller continues in a new session with the returned tokens.
+    async fn change_password(
+        &self,
+        claims: &Claims,
+        current_password: Secret<String>,
+        new_password: Secret<String>,
+    ) -> Result<Tokens, Error>;
+    /// Emails a reset link, unless no user has the email, which is not reported.
+    async fn request_password_reset(&self, email: &str) -> Result<(), Error>;
+    /// Sets the password with a reset token and signs out every session.
+    async fn reset_password(&self, token: &str, new_password: Secret<String>) -> Result<(), Error>;
+
+    async fn verify_email(&self, token: &str) -> Result<(), Error>;
+    /// Emails a new verification link, unless the email is verified already.
+    async fn resend_verification(&self, user_id: &UserId) -> Result<(), Error>;
+    async fn permissions(&self, user_id: &UserId) -> Result<Permissions, Error>;
+
+    /// Starts enabling TOTP, replacing an unconfirmed enrollment.
+    async fn enroll_totp(&self, user_id: &UserId) -> Result<TotpEnrollment, Error>;
+    /// Enables TOTP with a code of the enrollment, returns the recovery codes.
+    async fn confirm_totp(&self, user_id: &UserId, code: &str) -> Result<Vec<String>, Error>;
+    async fn disable_totp(&self, user_id: &UserId, code: &str) -> Result<(), Error>;
+
+    /// Lifts the lock and forgets the failed logins of the user's account. Admins only.
+    async fn unlock_account(&self, admin_id: &UserId, user_id: &UserId) -> Result<(), Error>;
+    /// Signs the user out of every session, access tokens included, e.g. when banning
+    /// them. Admins only. Returns how many sessions were revoked.
+    async fn revoke_user_sessions(&self, admin_id: &UserId, user_id: &UserId)
+        -> Result<u64, Error>;
 }
 
 #[async_trait]
-impl<CredRepo, TokenRepo> AuthService for AuthServiceImp<CredRepo, TokenRepo>
+impl<CredRepo, TokenRepo, Attempts, EventBus, Mailer, Oidc> AuthService
+    for AuthServiceImp<CredRepo, TokenRepo, Attempts, EventBus, Mailer, Oidc>
 where
     CredRepo: CredentialsRepository + Send + Sync,
     TokenRepo: TokenRepository + Send + Sync,
+    Attempts: LoginAttemptRepository + Send + Sync,
+    EventBus: UserEventBus + Send + Sync,
+    Mailer: EmailSender + Send + Sync + 'static,
+    Oidc: OidcClient + Send + Sync,
 {
-    #[tracing::instrument(name = "Login User", skip(self, credentials))]
-    async fn login(&self, credentials: Credentials) -> Result<Tokens, Error> {
-        let user_id = self.validate_credentials(credentials).await?;
-        Ok(self.create_token_pair(&user_id).await?)
+    #[tracing::instrument(name = "Login User", skip(self, credentials, client))]
+    async fn login(
+        &self,
+        credentials: Credentials,
+        client: ClientInfo,
+    ) -> Result<LoginOutcome, Error> {
+        let account_key = account_attempt_key(&credentials.email);
+        let ip_key = client.ip.map(|ip| format!("ip:{}", ip));
+        let keys: Vec<&str> = [Some(account_key.as_str()), ip_key.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        self.check_lockout(&keys).await?;
+
+        let user_id = match self.validate_credentials(credentials).await {
+            Ok(user_id) => user_id,
+            Err(Error::InvalidCredentials(e)) => {
+                self.record_login_failure(&account_key, ip_key.as_deref())
+                    .await;
+                return Err(Error::InvalidCredentials(e));
+            }
+            Err(e) => return Err(e),
+        };
+
+        self.finish_login(&user_id, &account_key, &client).await
+    }
+
+    fn mfa_challenge_user(&self, mfa_token: &str) -> Result<UserId, Error> {
+        let claims = decode_token(
+            mfa_token,
+            TokenType::MfaChallenge,
+            &self.token_scope,
+            &self.keys,
+        )
+        .context("Invalid token.")
+        .map_err(Error::InvalidCredentials)?;
+        Ok(claims.user_id())
     }
 
-    #[tracing::instrument(name = "Signup User", skip(self, new_user))]
-    async fn signup(&self, new_user: NewUser) -> Result<(User, Tokens), Error> {
+    #[tracing::instrument(
+        name = "Login User with second factor",
+        skip(self, mfa_token, code, client)
+    )]
+    async fn login_mfa(
+        &self,
+        mfa_token: Secret<String>,
+        code: &str,
+        client: ClientInfo,
+    ) -> Result<Tokens, Error> {
+        let user_id = self.mfa_challenge_user(mfa_token.expose_secret())?;
+        let (email, _) = self
+            .credentials_repo
+            .get_email_status(&user_id)
+            .await?
+            .ok_or_else(|| Error::InvalidCredentials(anyhow::anyhow!("Unknown user.")))?;
+        let account_key = account_attempt_key(&email);
+        let ip_key = client.ip.map(|ip| format!("ip:{}", ip));
+        let keys: Vec<&str> = [Some(account_key.as_str()), ip_key.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        self.check_lockout(&keys).await?;
+
+        match self.check_second_factor(&user_id, code).await {
+            Ok(()) => self.clear_login_failures(&account_key).await,
+            Err(Error::InvalidCredentials(e)) => {
+                self.record_login_failure(&account_key, ip_key.as_deref())
+                    .await;
+                return Err(Error::InvalidCredentials(e));
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(self.create_token_pair(&user_id, &client).await?)
+    }
+
+    #[tracing::instrument(name = "Signup User", skip(self, new_user, client))]
+    async fn signup(&self, new_user: NewUser, client: ClientInfo) -> Result<(User, Tokens), Error> {
         let NewUser {
             name,
             email,
@@ -119,124 +616,2774 @@ where
         {
             return Err(Error::ConflictError("User already exist".to_string()));
         }
+        self.password_policy
+            .check(&password, &[name.as_ref(), email.as_ref()])?;
 
-        let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
-            .await
-            .map_err(|e| Error::UnexpectedError(e.into()))??;
+        let password_hash = self.hash_password(password).await?;
         // TODO:invalidate old refresh token
         let user = self
             .credentials_repo
             .signup(&name, &email, password_hash, &code)
             .await?;
 
-        let user_id = user.user_id.clone();
-        Ok((user, (self.create_token_pair(&user_id).await?)))
+        let user_id = user.user_id;
+        // The user can ask for another link, signing up must not depend on the mail server.
+        if let Err(e) = self.send_verification(&user_id, user.email.as_ref()).await {
+            tracing::error!("Failed send verification email: {}", e);
+        }
+
+        Ok((user, (self.create_token_pair(&user_id, &client).await?)))
     }
 
-    #[tracing::instrument(name = "Login User", skip(self, access_token))]
-    async fn logout(&self, access_token: Secret<String>) -> Result<(), Error> {
-        let claims = decode_token(access_token.expose_secret(), &self.decoding_key)
-            .context("Invalid token.")
-            .map_err(Error::InvalidCredentials)?;
+    #[tracing::instrument(name = "Start OIDC login", skip(self))]
+    async fn oidc_authorize(&self, provider: &str) -> Result<String, Error> {
+        if !self.oidc_providers.contains(provider) {
+            return Err(Error::NotFound("provider not found".to_string()));
+        }
 
+        let state = random_token();
+        let login = OidcLogin {
+            provider: provider.to_string(),
+            code_verifier: random_token(),
+            nonce: random_token(),
+        };
         self.tokens_repo
-            .delete(&claims.token_id(), &claims.user_id())
+            .store_oidc_login(
+                &state,
+                &login,
+                Duration::from_secs(self.oidc_login_duration),
+            )
             .await
-            .context("Failed delete refresh token.")?;
+            .context("Failed store OIDC login.")?;
+
+        let request = AuthorizationRequest {
+            state,
+            nonce: login.nonce,
+            code_challenge: pkce_challenge(&login.code_verifier),
+        };
+        Ok(self
+            .oidc_client
+            .authorization_url(provider, &request)
+            .await?)
+    }
+
+    #[tracing::instrument(name = "Finish OIDC login", skip(self, code, state, client))]
+    async fn oidc_login(
+        &self,
+        provider: &str,
+        code: &str,
+        state: &str,
+        client: ClientInfo,
+    ) -> Result<LoginOutcome, Error> {
+        let ip_key = client.ip.map(|ip| format!("ip:{}", ip));
+        let ip_keys: Vec<&str> = ip_key.as_deref().into_iter().collect();
+        self.check_lockout(&ip_keys).await?;
+
+        let user_id = match self.oidc_user(provider, code, state).await {
+            Ok(user_id) => user_id,
+            Err(Error::InvalidCredentials(e)) => {
+                if let Some(ip_key) = &ip_key {
+                    self.record_failure(ip_key, self.lockout.ip_threshold).await;
+                }
+                return Err(Error::InvalidCredentials(e));
+            }
+            Err(e) => return Err(e),
+        };
+        let (email, _) = self
+            .credentials_repo
+            .get_email_status(&user_id)
+            .await?
+            .ok_or_else(|| Error::InvalidCredentials(anyhow::anyhow!("Unknown user.")))?;
+        let account_key = account_attempt_key(&email);
+        self.check_lockout(&[account_key.as_str()]).await?;
+
+        self.finish_login(&user_id, &account_key, &client).await
+    }
+
+    #[tracing::instrument(name = "Login User", skip(self, access_token))]
+    async fn logout(&self, access_token: Secret<String>) -> Result<(), Error> {
+        let claims = decode_token(
+            access_token.expose_secret(),
+            TokenType::Access,
+            &self.token_scope,
+            &self.keys,
+        )
+        .context("Invalid token.")
+        .map_err(Error::InvalidCredentials)?;
+
+        let revoked = self
+            .revoke_family(&claims.user_id(), &claims.family_id())
+            .await?;
+        // The token may be from before the last refresh of its family.
+        if revoked != Some(claims.token_id()) {
+            self.revoke_access(&claims.token_id()).await?;
+        }
 
         Ok(())
     }
 
-    #[tracing::instrument(name = "Login User", skip(self, refresh_token))]
-    async fn refresh(&self, refresh_token: Secret<String>) -> Result<Tokens, Error> {
-        let claims = decode_token(refresh_token.expose_secret(), &self.decoding_key)
-            .context("Invalid token.")
-            .map_err(Error::InvalidCredentials)?;
+    #[tracing::instrument(name = "Login User", skip(self, refresh_token, client))]
+    async fn refresh(
+        &self,
+        refresh_token: Secret<String>,
+        client: ClientInfo,
+    ) -> Result<Tokens, Error> {
+        let claims = decode_token(
+            refresh_token.expose_secret(),
+            TokenType::Refresh,
+            &self.token_scope,
+            &self.keys,
+        )
+        .context("Invalid token.")
+        .map_err(Error::InvalidCredentials)?;
 
-        let user_id = self
+        let user_id = claims.user_id();
+        let family_id = claims.family_id();
+        let token_id = uuid::Uuid::new_v4();
+
+        let rotation = self
             .tokens_repo
-            .exist(&claims.token_id(), &claims.user_id())
-            .await
-            .context("Unknown refresh token.")?
-            .ok_or_else(|| anyhow::anyhow!("Unknown refresh token."))
-            .map_err(Error::InvalidCredentials)?;
-        // TODO:add rotation
-        self.tokens_repo
-            .delete(&claims.token_id(), &claims.user_id())
+            .rotate(
+                &family_id,
+                &claims.token_id(),
+                &token_id,
+                &user_id,
+                Duration::from_secs(self.refresh_toked_duration),
+                &client,
+            )
             .await
-            .context("Failed delete refresh token.")?;
+            .context("Failed rotate refresh token.")?;
 
-        Ok(self.create_token_pair(&user_id).await?)
+        match rotation {
+            Rotation::Rotated => Ok(self.encode_token_pair(&user_id, token_id, family_id)?),
+            Rotation::Reused => {
+                tracing::warn!("Refresh token reused, revoking family {}", family_id);
+                self.revoke_family(&user_id, &family_id).await?;
+                Err(Error::InvalidCredentials(anyhow::anyhow!(
+                    "Refresh token already used."
+                )))
+            }
+            Rotation::Unknown => Err(Error::InvalidCredentials(anyhow::anyhow!(
+                "Unknown refresh token."
+            ))),
+        }
     }
 
     async fn validate_token(&self, token: &str) -> Result<Claims, Error> {
-        let claims = decode_token(token, &self.decoding_key)
+        let claims = decode_token(token, TokenType::Access, &self.token_scope, &self.keys)
             .context("Invalid token")
             .map_err(Error::InvalidCredentials)?;
+
+        if let Some(cache) = &self.revocation_cache {
+            let token_id = claims.token_id();
+            let revoked = match cache.get(&token_id) {
+                Some(revoked) => revoked,
+                None => {
+                    let revoked = self
+                        .tokens_repo
+                        .is_access_revoked(&token_id, &claims.user_id(), claims.issued_at())
+                        .await
+                        .context("Failed check token revocation.")?;
+                    cache.insert(token_id, revoked);
+                    revoked
+                }
+            };
+            if revoked {
+                return Err(Error::InvalidCredentials(anyhow::anyhow!(
+                    "Token was revoked."
+                )));
+            }
+        }
+
         Ok(claims)
     }
-}
 
-impl<CredRepo, TokenRepo> AuthServiceImp<CredRepo, TokenRepo>
-where
-    CredRepo: CredentialsRepository + Send + Sync,
-    TokenRepo: TokenRepository + Send + Sync,
-{
-    #[tracing::instrument(name = "Validate credentials", skip(self, credentials))]
-    async fn validate_credentials(&self, credentials: Credentials) -> Result<UserId, Error> {
-        let mut user_id = None;
-        //dummy load
-        let mut expected_password_hash = Secret::new(
-            "$argon2id$v=19$m=15000,t=2,p=1$\
-        gZiV/M1gPc22ElAH/Jh1Hw$\
-        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-                .to_string(),
-        );
+    fn jwks(&self) -> JwkSet {
+        self.keys.jwks().clone()
+    }
 
-        if let Some((stored_user_id, stored_password_hash)) = self
+    #[tracing::instrument(name = "Issue socket ticket", skip(self, claims))]
+    async fn issue_ticket(&self, claims: &Claims) -> Result<Secret<String>, Error> {
+        let ticket = random_token();
+
+        let owner = SocketTicket {
+            user_id: claims.user_id(),
+            session_id: claims.family_id(),
+        };
+        self.tokens_repo
+            .store_ticket(
+                &ticket,
+                &owner,
+                Duration::from_secs(self.ws_ticket_duration),
+            )
+            .await
+            .context("Failed store socket ticket.")?;
+
+        Ok(Secret::new(ticket))
+    }
+
+    #[tracing::instrument(name = "Redeem socket ticket", skip(self, ticket))]
+    async fn redeem_ticket(&self, ticket: &str) -> Result<SocketTicket, Error> {
+        self.tokens_repo
+            .take_ticket(ticket)
+            .await
+            .context("Failed take socket ticket.")?
+            .ok_or_else(|| Error::InvalidCredentials(anyhow::anyhow!("Invalid ticket.")))
+    }
+
+    #[tracing::instrument(
+        name = "Change password",
+        skip(self, claims, current_password, new_password)
+    )]
+    async fn change_password(
+        &self,
+        claims: &Claims,
+        current_password: Secret<String>,
+        new_password: Secret<String>,
+    ) -> Result<Tokens, Error> {
+        let user_id = claims.user_id();
+        let expected_password_hash = self
             .credentials_repo
-            .get_credential(&credentials.email)
+            .get_password_hash(&user_id)
             .await?
-        {
-            user_id = Some(stored_user_id);
-            expected_password_hash = stored_password_hash;
-        }
-
+            .ok_or_else(|| Error::InvalidCredentials(anyhow::anyhow!("Unknown user.")))?;
         spawn_blocking_with_tracing(move || {
-            verify_password_hash(expected_password_hash, credentials.password)
+            verify_password_hash(expected_password_hash, current_password)
         })
         .await
         .context("Failed to spawn blocking task.")??;
+        self.check_password_policy(&user_id, &new_password).await?;
 
-        Ok(user_id
-            .ok_or_else(|| anyhow::anyhow!("Unknown username."))
-            .map_err(Error::InvalidCredentials)?
-            .into())
+        let password_hash = self.hash_password(new_password).await?;
+        self.credentials_repo
+            .update_password(&user_id, password_hash)
+            .await?;
+
+        // The new session of the caller keeps the device of the one it replaces.
+        let client = self
+            .tokens_repo
+            .get(&claims.family_id(), &user_id)
+            .await?
+            .map(|session| session.client)
+            .unwrap_or_default();
+        self.revoke_all_sessions(&user_id).await?;
+
+        Ok(self.create_token_pair(&user_id, &client).await?)
     }
 
-    async fn create_token_pair(&self, user_id: &UserId) -> Result<Tokens, anyhow::Error> {
-        let token_id = uuid::Uuid::new_v4();
+    #[tracing::instrument(name = "Request password reset", skip(self, email))]
+    async fn request_password_reset(&self, email: &str) -> Result<(), Error> {
+        let Some(user) = self.credentials_repo.get_user_by_email(email).await? else {
+            tracing::info!("Password reset requested for unknown email");
+            return Ok(());
+        };
+
+        let token = random_token();
         self.tokens_repo
-            .create(&token_id, &user_id)
+            .store_user_token(
+                UserTokenKind::PasswordReset,
+                &token,
+                &user.user_id,
+                Duration::from_secs(self.password_reset_duration),
+            )
             .await
-            .context("Failed to store refresh token.")?;
+            .context("Failed store password reset token.")?;
 
-        let access_token = encode_token(
-            user_id,
-            token_id,
-            &self.encoding_key,
-            self.access_toked_duration,
-        )
-        .context("Failed encode access token.")?;
+        let email = Email {
+            to: user.email.as_ref().to_string(),
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Open {}?token={} to choose a new password. The link expires in {} minutes.\n\n\
+                If you did not ask to reset your password, ignore this email.",
+                self.password_reset_url,
+                token,
+                self.password_reset_duration / 60
+            ),
+        };
+        // Sent in the background, failures or the time sending takes would tell which
+        // emails have an account.
+        let email_sender = self.email_sender.clone();
+        tokio::spawn(async move {
+            if let Err(e) = email_sender.send(email).await {
+                tracing::error!("Failed send password reset email: {:?}", e);
+            }
+        });
+        Ok(())
+    }
 
-        let refresh_token = encode_token(
-            user_id,
-            token_id,
-            &self.encoding_key,
-            self.refresh_toked_duration,
-        )
-        .context("Failed encode refresh token.")?;
+    #[tracing::instrument(name = "Reset password", skip(self, token, new_password))]
+    async fn reset_password(&self, token: &str, new_password: Secret<String>) -> Result<(), Error> {
+        // Checked before the token is used up, the name and email once its user is known.
+        self.password_policy.check(&new_password, &[])?;
+        let user_id = self
+            .tokens_repo
+            .take_user_token(UserTokenKind::PasswordReset, token)
+            .await
+            .context("Failed take password reset token.")?
+            .ok_or_else(|| {
+                Error::InvalidCredentials(anyhow::anyhow!("Invalid or expired reset token."))
+            })?;
+        self.check_password_policy(&user_id, &new_password).await?;
 
-        Ok((access_token, refresh_token))
+        let password_hash = self.hash_password(new_password).await?;
+        self.credentials_repo
+            .update_password(&user_id, password_hash)
+            .await?;
+
+        self.revoke_all_sessions(&user_id).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Verify email", skip(self, token))]
+    async fn verify_email(&self, token: &str) -> Result<(), Error> {
+        let user_id = self
+            .tokens_repo
+            .take_user_token(UserTokenKind::EmailVerification, token)
+            .await
+            .context("Failed take email verification token.")?
+            .ok_or_else(|| {
+                Error::InvalidCredentials(anyhow::anyhow!("Invalid or expired verification token."))
+            })?;
+
+        self.credentials_repo.verify_email(&user_id).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Resend verification email", skip(self))]
+    async fn resend_verification(&self, user_id: &UserId) -> Result<(), Error> {
+        let (email, verified) = self
+            .credentials_repo
+            .get_email_status(user_id)
+            .await?
+            .ok_or(Error::NotFound("user not found".to_string()))?;
+        if verified {
+            return Err(Error::ConflictError("Email already verified".to_string()));
+        }
+
+        self.send_verification(user_id, &email).await
+    }
+
+    async fn permissions(&self, user_id: &UserId) -> Result<Permissions, Error> {
+        let unverified = self.email_verification.unverified;
+        if unverified == Permissions::ALL {
+            return Ok(Permissions::ALL);
+        }
+
+        let (_, verified) = self
+            .credentials_repo
+            .get_email_status(user_id)
+            .await?
+            .ok_or_else(|| Error::InvalidCredentials(anyhow::anyhow!("Unknown user.")))?;
+        Ok(if verified {
+            Permissions::ALL
+        } else {
+            unverified
+        })
+    }
+
+    #[tracing::instrument(name = "Enroll TOTP", skip(self))]
+    async fn enroll_totp(&self, user_id: &UserId) -> Result<TotpEnrollment, Error> {
+        let (email, _) = self
+            .credentials_repo
+            .get_email_status(user_id)
+            .await?
+            .ok_or(Error::NotFound("user not found".to_string()))?;
+
+        let secret = totp::generate_secret();
+        let encrypted = self
+            .totp_cipher
+            .encrypt(&secret, user_id.as_ref().as_bytes())?;
+        if !self
+            .credentials_repo
+            .store_totp(user_id, &encrypted)
+            .await?
+        {
+            return Err(Error::ConflictError("TOTP already enabled".to_string()));
+        }
+
+        Ok(TotpEnrollment {
+            secret: Secret::new(totp::encode_secret(&secret)),
+            provisioning_uri: Secret::new(totp::provisioning_uri(
+                &self.mfa_issuer,
+                &email,
+                &secret,
+            )),
+        })
+    }
+
+    #[tracing::instrument(name = "Confirm TOTP", skip(self, code))]
+    async fn confirm_totp(&self, user_id: &UserId, code: &str) -> Result<Vec<String>, Error> {
+        let totp = self
+            .credentials_repo
+            .get_totp(user_id)
+            .await?
+            .ok_or(Error::NotFound("TOTP enrollment not found".to_string()))?;
+        if totp.confirmed {
+            return Err(Error::ConflictError("TOTP already enabled".to_string()));
+        }
+
+        let step = self
+            .verify_totp(user_id, &totp.secret, code)?
+            .ok_or_else(|| Error::InvalidCredentials(anyhow::anyhow!("Invalid code.")))?;
+
+        let recovery_codes = totp::generate_recovery_codes();
+        let hashes: Vec<String> = recovery_codes
+            .iter()
+            .map(|code| totp::hash_recovery_code(code))
+            .collect();
+        self.credentials_repo
+            .confirm_totp(user_id, step, &hashes)
+            .await?;
+
+        Ok(recovery_codes)
+    }
+
+    #[tracing::instrument(name = "Disable TOTP", skip(self, code))]
+    async fn disable_totp(&self, user_id: &UserId, code: &str) -> Result<(), Error> {
+        self.check_second_factor(user_id, code).await?;
+        self.credentials_repo.delete_totp(user_id).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Unlock account", skip(self))]
+    async fn unlock_account(&self, admin_id: &UserId, user_id: &UserId) -> Result<(), Error> {
+        if !self.credentials_repo.is_admin(admin_id).await? {
+            return Err(Error::Forbidden("Admins only".to_string()));
+        }
+        let (email, _) = self
+            .credentials_repo
+            .get_email_status(user_id)
+            .await?
+            .ok_or(Error::NotFound("user not found".to_string()))?;
+
+        self.login_attempts
+            .clear(&account_attempt_key(&email))
+            .await
+            .context("Failed clear failed logins.")?;
+
+        tracing::info!(
+            target: SECURITY_AUDIT,
+            event = "account_unlocked",
+            user_id = %user_id.as_ref(),
+            admin_id = %admin_id.as_ref(),
+            "Account unlocked"
+        );
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Revoke sessions of user", skip(self))]
+    async fn revoke_user_sessions(
+        &self,
+        admin_id: &UserId,
+        user_id: &UserId,
+    ) -> Result<u64, Error> {
+        if !self.credentials_repo.is_admin(admin_id).await? {
+            return Err(Error::Forbidden("Admins only".to_string()));
+        }
+        self.credentials_repo
+            .get_user(user_id)
+            .await?
+            .ok_or(Error::NotFound("user not found".to_string()))?;
+
+        let revoked = self.revoke_all_sessions(user_id).await?;
+
+        tracing::info!(
+            target: SECURITY_AUDIT,
+            event = "sessions_revoked",
+            user_id = %user_id.as_ref(),
+            admin_id = %admin_id.as_ref(),
+            "Sessions of user revoked"
+        );
+        Ok(revoked)
+    }
+
+    async fn list_sessions(&self, user_id: &UserId) -> Result<Vec<Session>, Error> {
+        self.tokens_repo
+            .list(user_id)
+            .await
+            .map_err(Error::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Revoke session", skip(self))]
+    async fn revoke_session(&self, user_id: &UserId, session_id: &Uuid) -> Result<(), Error> {
+        self.tokens_repo
+            .get(session_id, user_id)
+            .await?
+            .ok_or(Error::NotFound("session not found".to_string()))?;
+
+        self.revoke_family(user_id, session_id).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Revoke all sessions", skip(self))]
+    async fn revoke_all_sessions(&self, user_id: &UserId) -> Result<u64, Error> {
+        let revoked = self
+            .tokens_repo
+            .revoke_all(user_id)
+            .await
+            .context("Failed revoke refresh token families.")?;
+        self.revoke_user_access(user_id).await?;
+
+        self.publish_revoked(user_id, None).await;
+
+        Ok(revoked)
+    }
+}
+
+impl<CredRepo, TokenRepo, Attempts, EventBus, Mailer, Oidc>
+    AuthServiceImp<CredRepo, TokenRepo, Attempts, EventBus, Mailer, Oidc>
+where
+    CredRepo: CredentialsRepository + Send + Sync,
+    TokenRepo: TokenRepository + Send + Sync,
+    Attempts: LoginAttemptRepository + Send + Sync,
+    EventBus: UserEventBus + Send + Sync,
+    Mailer: EmailSender + Send + Sync,
+    Oidc: OidcClient + Send + Sync,
+{
+    /// Revokes the refresh token family and closes the connections opened with its tokens.
+    /// Returns the id of the current token pair of the family, whose access token is
+    /// revoked too.
+    async fn revoke_family(
+        &self,
+        user_id: &UserId,
+        family_id: &Uuid,
+    ) -> Result<Option<Uuid>, Error> {
+        let current = self
+            .tokens_repo
+            .revoke_family(family_id, user_id)
+            .await
+            .context("Failed revoke refresh token family.")?;
+        if let Some(token_id) = current {
+            self.revoke_access(&token_id).await?;
+        }
+
+        self.publish_revoked(user_id, Some(*family_id)).await;
+
+        Ok(current)
+    }
+
+    /// Rejects the access token issued together with the refresh token `token_id`.
+    async fn revoke_access(&self, token_id: &Uuid) -> Result<(), Error> {
+        self.tokens_repo
+            .revoke_access(token_id, Duration::from_secs(self.access_toked_duration))
+            .await
+            .context("Failed revoke access token.")?;
+        if let Some(cache) = &self.revocation_cache {
+            cache.insert(*token_id, true);
+        }
+        Ok(())
+    }
+
+    /// Rejects every access token issued to the user so far, for password changes and bans.
+    async fn revoke_user_access(&self, user_id: &UserId) -> Result<(), Error> {
+        self.tokens_repo
+            .revoke_user_access(user_id, Duration::from_secs(self.access_toked_duration))
+            .await
+            .context("Failed revoke access tokens of user.")?;
+        if let Some(cache) = &self.revocation_cache {
+            cache.clear();
+        }
+        Ok(())
+    }
+
+    async fn send_verification(&self, user_id: &UserId, email: &str) -> Result<(), Error> {
+        let token = random_token();
+        self.tokens_repo
+            .store_user_token(
+                UserTokenKind::EmailVerification,
+                &token,
+                user_id,
+                Duration::from_secs(self.email_verification.token_duration),
+            )
+            .await
+            .context("Failed store email verification token.")?;
+
+        let email = Email {
+            to: email.to_string(),
+            subject: "Verify your email".to_string(),
+            body: format!(
+                "Open {}?token={} to verify your email address.\n\n\
+                If you did not sign up, ignore this email.",
+                self.email_verification.verify_url, token
+            ),
+        };
+        self.email_sender.send(email).await?;
+        Ok(())
+    }
+
+    /// Rejects the login while any of the keys is locked. Storage failures let the login
+    /// through, like the rate limiter.
+    async fn check_lockout(&self, keys: &[&str]) -> Result<(), Error> {
+        let mut locked_for = None;
+        for key in keys {
+            match self.login_attempts.locked_for(key).await {
+                Ok(remaining) => locked_for = locked_for.max(remaining),
+                Err(e) => tracing::error!("Failed check login lock: {}", e),
+            }
+        }
+        match locked_for {
+            Some(remaining) => Err(Error::TooManyRequests(remaining)),
+            None => Ok(()),
+        }
+    }
+
+    async fn record_login_failure(&self, account_key: &str, ip_key: Option<&str>) {
+        self.record_failure(account_key, self.lockout.account_threshold)
+            .await;
+        if let Some(ip_key) = ip_key {
+            self.record_failure(ip_key, self.lockout.ip_threshold).await;
+        }
+    }
+
+    async fn clear_login_failures(&self, account_key: &str) {
+        if let Err(e) = self.login_attempts.clear(account_key).await {
+            tracing::error!("Failed clear failed logins: {}", e);
+        }
+    }
+
+    /// Delays the next login under `key` once past the free attempts, and locks it out at
+    /// `threshold` failures.
+    async fn record_failure(&self, key: &str, threshold: u64) {
+        let window = Duration::from_secs(self.lockout.failure_window);
+        let failures = match self.login_attempts.record_failure(key, window).await {
+            Ok(failures) => failures,
+            Err(e) => {
+                tracing::error!("Failed record failed login: {}", e);
+                return;
+            }
+        };
+
+        let lock = if failures >= threshold {
+            tracing::warn!(
+                target: SECURITY_AUDIT,
+                event = "login_locked",
+                key,
+                failures,
+                duration_secs = self.lockout.lockout_duration,
+                "Locked after repeated failed logins"
+            );
+            Duration::from_secs(self.lockout.lockout_duration)
+        } else if failures > self.lockout.free_attempts {
+            let doublings = (failures - self.lockout.free_attempts - 1).min(32) as u32;
+            let delay_ms = self
+                .lockout
+                .base_delay_ms
+                .saturating_mul(2u64.pow(doublings))
+                .min(self.lockout.max_delay_ms);
+            Duration::from_millis(delay_ms)
+        } else {
+            return;
+        };
+
+        if let Err(e) = self.login_attempts.lock(key, lock).await {
+            tracing::error!("Failed lock login: {}", e);
+        }
+    }
+
+    /// Accepts a TOTP code once, or an unused recovery code.
+    async fn check_second_factor(&self, user_id: &UserId, code: &str) -> Result<(), Error> {
+        let invalid = || Error::InvalidCredentials(anyhow::anyhow!("Invalid code."));
+        let totp = self
+            .credentials_repo
+            .get_totp(user_id)
+            .await?
+            .filter(|totp| totp.confirmed)
+            .ok_or_else(invalid)?;
+
+        let accepted = if totp::is_totp_code(code) {
+            match self.verify_totp(user_id, &totp.secret, code)? {
+                Some(step) => self.credentials_repo.use_totp_step(user_id, step).await?,
+                None => false,
+            }
+        } else {
+            self.credentials_repo
+                .use_recovery_code(user_id, &totp::hash_recovery_code(code))
+                .await?
+        };
+
+        if accepted {
+            Ok(())
+        } else {
+            Err(invalid())
+        }
+    }
+
+    /// Time step of `code` for the encrypted secret.
+    fn verify_totp(
+        &self,
+        user_id: &UserId,
+        encrypted_secret: &[u8],
+        code: &str,
+    ) -> Result<Option<u64>, Error> {
+        let secret = self
+            .totp_cipher
+            .decrypt(encrypted_secret, user_id.as_ref().as_bytes())?;
+        let now = jsonwebtoken::get_current_timestamp();
+        Ok(totp::verify(&secret, code, now))
+    }
+
+    async fn publish_revoked(&self, user_id: &UserId, session_id: Option<Uuid>) {
+        let event = ServerEvent::SessionRevoked(SessionRevoked { session_id });
+        if let Err(e) = self.event_bus.publish(user_id, &event).await {
+            tracing::error!("Failed publish session revocation: {}", e);
+        }
+    }
+
+    #[tracing::instrument(name = "Validate credentials", skip(self, credentials))]
+    async fn validate_credentials(&self, credentials: Credentials) -> Result<UserId, Error> {
+        let mut user_id = None;
+        let mut expected_password_hash = self.dummy_password_hash.clone();
+
+        if let Some((stored_user_id, stored_password_hash)) = self
+            .credentials_repo
+            .get_credential(&credentials.email)
+            .await?
+        {
+            user_id = Some(stored_user_id);
+            expected_password_hash = stored_password_hash;
+        }
+
+        let params = self.password_params.clone();
+        let rehashed = spawn_blocking_with_tracing(move || {
+            let outdated = needs_rehash(&expected_password_hash, &params);
+            verify_password_hash(expected_password_hash, credentials.password.clone())?;
+            if !outdated {
+                return Ok(None);
+            }
+            Ok::<_, Error>(Some(compute_password_hash(credentials.password, params)?))
+        })
+        .await
+        .context("Failed to spawn blocking task.")??;
+
+        let user_id: UserId = user_id
+            .ok_or_else(|| anyhow::anyhow!("Unknown username."))
+            .map_err(Error::InvalidCredentials)?
+            .into();
+        if let Some(password_hash) = rehashed {
+            if let Err(e) = self
+                .credentials_repo
+                .update_password(&user_id, password_hash)
+                .await
+            {
+                tracing::error!("Failed rehash password: {}", e);
+            }
+        }
+        Ok(user_id)
+    }
+
+    /// Ends a login whose first factor was accepted. Users with a second factor get a
+    /// challenge for it, their failed logins are forgotten once the code was accepted.
+    async fn finish_login(
+        &self,
+        user_id: &UserId,
+        account_key: &str,
+        client: &ClientInfo,
+    ) -> Result<LoginOutcome, Error> {
+        let totp = self.credentials_repo.get_totp(user_id).await?;
+        if totp.is_some_and(|totp| totp.confirmed) {
+            let challenge = encode_token(
+                user_id,
+                Uuid::new_v4(),
+                Uuid::new_v4(),
+                TokenType::MfaChallenge,
+                &self.token_scope,
+                &self.keys,
+                self.mfa_challenge_duration,
+            )
+            .context("Failed encode MFA challenge.")?;
+            return Ok(LoginOutcome::MfaRequired(challenge));
+        }
+        self.clear_login_failures(account_key).await;
+
+        Ok(LoginOutcome::Tokens(
+            self.create_token_pair(user_id, client).await?,
+        ))
+    }
+
+    /// User of the identity the provider vouched for with `code`.
+    async fn oidc_user(&self, provider: &str, code: &str, state: &str) -> Result<UserId, Error> {
+        let login = self
+            .tokens_repo
+            .take_oidc_login(state)
+            .await
+            .context("Failed take OIDC login.")?
+            .filter(|login| login.provider == provider)
+            .ok_or_else(|| Error::InvalidCredentials(anyhow::anyhow!("Unknown login state.")))?;
+
+        let identity = self
+            .oidc_client
+            .exchange_code(provider, code, &login.code_verifier, &login.nonce)
+            .await
+            .map_err(Error::InvalidCredentials)?;
+        self.external_user(identity).await
+    }
+
+    /// User linked to the identity. Links a user with the same email if the provider
+    /// verified it, or signs up a new one.
+    async fn external_user(&self, identity: ExternalIdentity) -> Result<UserId, Error> {
+        if let Some(user_id) = self
+            .credentials_repo
+            .get_identity_user(&identity.provider, &identity.subject)
+            .await?
+        {
+            return Ok(user_id);
+        }
+
+        let email = identity.email.clone().ok_or_else(|| {
+            Error::ValidationError("The provider did not share an email address.".to_string())
+        })?;
+        if let Some(user) = self.credentials_repo.get_user_by_email(&email).await? {
+            // Otherwise anyone able to claim the address at the provider takes the account.
+            if !identity.email_verified {
+                return Err(Error::ConflictError(
+                    "An account with this email exists, sign in with its password.".to_string(),
+                ));
+            }
+            // Anyone can sign up with an address they do not own, the password of such an
+            // account must not keep working once the owner of the address is let in.
+            let (_, verified) = self
+                .credentials_repo
+                .get_email_status(&user.user_id)
+                .await?
+                .ok_or(Error::NotFound("user not found".to_string()))?;
+            if !verified {
+                return Err(Error::ConflictError(
+                    "An account with this email exists but its email is not verified, \
+                    reset its password to claim it."
+                        .to_string(),
+                ));
+            }
+            self.credentials_repo
+                .link_identity(&user.user_id, &identity)
+                .await?;
+            return Ok(user.user_id);
+        }
+
+        let email: UserEmail = email
+            .parse()
+            .map_err(|e: shared::domain::Error| Error::ValidationError(e.to_string()))?;
+        let local_part = email.as_ref().split('@').next().unwrap_or_default();
+        let name: UserName = identity
+            .name
+            .as_deref()
+            .and_then(|name| name.parse().ok())
+            .or_else(|| local_part.parse().ok())
+            .ok_or_else(|| Error::ValidationError("No valid user name.".to_string()))?;
+        let code: UserCode = random_token().parse().context("Invalid user code.")?;
+        // Nobody knows it, a password reset sets one.
+        let password_hash = self.hash_password(Secret::new(random_token())).await?;
+
+        let user = self
+            .credentials_repo
+            .create_external_user(&name, &email, password_hash, &code, &identity)
+            .await?;
+        Ok(user.user_id)
+    }
+
+    async fn check_password_policy(
+        &self,
+        user_id: &UserId,
+        password: &Secret<String>,
+    ) -> Result<(), Error> {
+        let user = self
+            .credentials_repo
+            .get_user(user_id)
+            .await?
+            .ok_or(Error::NotFound("user not found".to_string()))?;
+        self.password_policy
+            .check(password, &[user.name.as_ref(), user.email.as_ref()])
+    }
+
+    async fn hash_password(&self, password: Secret<String>) -> Result<Secret<String>, Error> {
+        let params = self.password_params.clone();
+        Ok(
+            spawn_blocking_with_tracing(move || compute_password_hash(password, params))
+                .await
+                .map_err(|e| Error::UnexpectedError(e.into()))??,
+        )
+    }
+
+    /// Issues a token pair starting a new refresh token family.
+    async fn create_token_pair(
+        &self,
+        user_id: &UserId,
+        client: &ClientInfo,
+    ) -> Result<Tokens, anyhow::Error> {
+        let family_id = uuid::Uuid::new_v4();
+        let token_id = uuid::Uuid::new_v4();
+        self.tokens_repo
+            .create(
+                &family_id,
+                &token_id,
+                user_id,
+                Duration::from_secs(self.refresh_toked_duration),
+                client,
+            )
+            .await
+            .context("Failed to store refresh token.")?;
+
+        self.encode_token_pair(user_id, token_id, family_id)
+    }
+
+    fn encode_token_pair(
+        &self,
+        user_id: &UserId,
+        token_id: Uuid,
+        family_id: Uuid,
+    ) -> Result<Tokens, anyhow::Error> {
+        let access_token = encode_token(
+            user_id,
+            token_id,
+            family_id,
+            TokenType::Access,
+            &self.token_scope,
+            &self.keys,
+            self.access_toked_duration,
+        )
+        .context("Failed encode access token.")?;
+
+        let refresh_token = encode_token(
+            user_id,
+            token_id,
+            family_id,
+            TokenType::Refresh,
+            &self.token_scope,
+            &self.keys,
+            self.refresh_toked_duration,
+        )
+        .context("Failed encode refresh token.")?;
+
+        Ok((access_token, refresh_token))
+    }
+}
+
+/// Failed logins are counted by the email tried, so that unknown emails lock like known ones.
+fn account_attempt_key(email: &str) -> String {
+    format!("account:{}", email.trim().to_lowercase())
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; RANDOM_TOKEN_LENGTH];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::{
+        Argon2Settings, CookieSettings, MfaSettings, OidcProviderSettings, OidcSettings,
+        PasswordSettings, RevocationSettings,
+    };
+    use crate::service::auth::oidc::MockOidcClient;
+    use base64::{engine::general_purpose, Engine};
+    use mockall::predicate::{always, eq};
+
+    use crate::service::email::MockEmailSender;
+    use crate::service::notification::MockUserEventBus;
+
+    type TestService = AuthServiceImp<
+        MockCredentialsRepository,
+        MockTokenRepository,
+        MockLoginAttemptRepository,
+        MockUserEventBus,
+        MockEmailSender,
+        MockOidcClient,
+    >;
+
+    const TOTP_KEY: [u8; 32] = [7; 32];
+
+    fn settings() -> AuthSettings {
+        let key_pair = jwt_simple::algorithms::Ed25519KeyPair::generate();
+        AuthSettings {
+            eddsa_private_key_pem: Secret::new(
+                general_purpose::STANDARD.encode(key_pair.to_pem().as_bytes()),
+            ),
+            eddsa_public_key_pem: general_purpose::STANDARD
+                .encode(key_pair.public_key().to_pem().as_bytes()),
+            key_id: "current".to_string(),
+            verification_keys: Vec::new(),
+            access_toked_duration: 60,
+            refresh_toked_duration: 600,
+            ws_ticket_duration: 30,
+            password_reset_duration: 900,
+            password_reset_url: "http://localhost/reset".to_string(),
+            email_verification: EmailVerificationSettings {
+                token_duration: 3600,
+                verify_url: "http://localhost/verify".to_string(),
+                unverified: Permissions {
+                    join_rooms: true,
+                    send_messages: false,
+                },
+            },
+            mfa: MfaSettings {
+                encryption_key: Secret::new(general_purpose::STANDARD.encode(TOTP_KEY)),
+                issuer: "Chat".to_string(),
+                challenge_duration: 300,
+            },
+            lockout: LockoutSettings {
+                failure_window: 900,
+                free_attempts: 3,
+                base_delay_ms: 1000,
+                max_delay_ms: 30_000,
+                account_threshold: 10,
+                ip_threshold: 100,
+                lockout_duration: 900,
+            },
+            password: PasswordSettings {
+                argon2: Argon2Settings {
+                    memory_kib: 1024,
+                    iterations: 1,
+                    parallelism: 1,
+                },
+                min_length: 8,
+                breached_list_path: None,
+            },
+            oidc: OidcSettings {
+                login_duration: 600,
+                providers: [(
+                    "corp".to_string(),
+                    OidcProviderSettings {
+                        issuer_url: "http://localhost/idp".to_string(),
+                        client_id: "chat".to_string(),
+                        client_secret: None,
+                        redirect_url: "http://localhost/oidc".to_string(),
+                        scopes: vec!["openid".to_string()],
+                        id_token_algorithms: vec![jsonwebtoken::Algorithm::RS256],
+                    },
+                )]
+                .into(),
+            },
+            issuer: "chat".to_string(),
+            audience: "chat-api".to_string(),
+            revocation: RevocationSettings {
+                enabled: true,
+                cache_ttl_ms: 60_000,
+                cache_capacity: 100,
+            },
+            cookies: CookieSettings::default(),
+        }
+    }
+
+    fn password_hash(password: &str) -> Secret<String> {
+        let params = settings().password.argon2.params().unwrap();
+        compute_password_hash(Secret::new(password.into()), params).unwrap()
+    }
+
+    fn service(tokens_repo: MockTokenRepository) -> TestService {
+        service_with_events(tokens_repo, MockUserEventBus::new())
+    }
+
+    fn service_with_events(
+        tokens_repo: MockTokenRepository,
+        event_bus: MockUserEventBus,
+    ) -> TestService {
+        AuthServiceImp::build(
+            &settings(),
+            MockCredentialsRepository::new(),
+            tokens_repo,
+            no_failed_logins(),
+            event_bus,
+            MockEmailSender::new(),
+            MockOidcClient::new(),
+        )
+        .unwrap()
+    }
+
+    fn service_with_credentials(
+        credentials_repo: MockCredentialsRepository,
+        tokens_repo: MockTokenRepository,
+        event_bus: MockUserEventBus,
+        email_sender: MockEmailSender,
+    ) -> TestService {
+        AuthServiceImp::build(
+            &settings(),
+            credentials_repo,
+            tokens_repo,
+            no_failed_logins(),
+            event_bus,
+            email_sender,
+            MockOidcClient::new(),
+        )
+        .unwrap()
+    }
+
+    fn service_with_attempts(
+        credentials_repo: MockCredentialsRepository,
+        login_attempts: MockLoginAttemptRepository,
+    ) -> TestService {
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_create()
+            .returning(|_, _, _, _, _| Box::pin(async { Ok(()) }));
+        AuthServiceImp::build(
+            &settings(),
+            credentials_repo,
+            tokens_repo,
+            login_attempts,
+            MockUserEventBus::new(),
+            MockEmailSender::new(),
+            MockOidcClient::new(),
+        )
+        .unwrap()
+    }
+
+    fn service_with_oidc(
+        credentials_repo: MockCredentialsRepository,
+        tokens_repo: MockTokenRepository,
+        oidc_client: MockOidcClient,
+    ) -> TestService {
+        AuthServiceImp::build(
+            &settings(),
+            credentials_repo,
+            tokens_repo,
+            no_failed_logins(),
+            MockUserEventBus::new(),
+            MockEmailSender::new(),
+            oidc_client,
+        )
+        .unwrap()
+    }
+
+    fn no_failed_logins() -> MockLoginAttemptRepository {
+        let mut login_attempts = MockLoginAttemptRepository::new();
+        login_attempts
+            .expect_locked_for()
+            .returning(|_| Box::pin(async { Ok(None) }));
+        login_attempts
+            .expect_clear()
+            .returning(|_| Box::pin(async { Ok(()) }));
+        login_attempts
+    }
+
+    fn user(user_id: UserId) -> User {
+        User {
+            user_id,
+            name: "user1".parse().unwrap(),
+            email: "user1@example.com".parse().unwrap(),
+            code: "1234".parse().unwrap(),
+            created_at: Utc::now(),
+        }
+    }
+
+    fn session(user_id: UserId, family_id: Uuid) -> Session {
+        Session {
+            family_id,
+            user_id,
+            client: ClientInfo::default(),
+            created_at: Utc::now(),
+            last_used_at: Utc::now(),
+        }
+    }
+
+    /// Expects a single revocation event for `session_id` sent to the user.
+    fn expect_revoked(user_id: UserId, session_id: Option<Uuid>) -> MockUserEventBus {
+        let mut event_bus = MockUserEventBus::new();
+        event_bus
+            .expect_publish()
+            .withf(move |user, event| {
+                *user == user_id
+                    && matches!(event, ServerEvent::SessionRevoked(ev) if ev.session_id == session_id)
+            })
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        event_bus
+    }
+
+    /// Issues a refresh token of a family, the way login does.
+    fn refresh_token(
+        service: &TestService,
+        user_id: &UserId,
+        family_id: Uuid,
+    ) -> (Uuid, Secret<String>) {
+        let token_id = Uuid::new_v4();
+        let (_, refresh_token) = service
+            .encode_token_pair(user_id, token_id, family_id)
+            .unwrap();
+        (token_id, refresh_token)
+    }
+
+    #[tokio::test]
+    async fn refresh_rotates_token_within_family() {
+        let user_id = UserId::from(Uuid::new_v4());
+        let family_id = Uuid::new_v4();
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_rotate()
+            .withf(move |family, _, _, user, ttl, client| {
+                *family == family_id
+                    && *user == user_id
+                    && ttl.as_secs() == 600
+                    && client.user_agent.as_deref() == Some("tests")
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _| Box::pin(async { Ok(Rotation::Rotated) }));
+        tokens_repo.expect_revoke_family().never();
+        let service = service(tokens_repo);
+        let (token_id, token) = refresh_token(&service, &user_id, family_id);
+
+        let client = ClientInfo {
+            user_agent: Some("tests".to_string()),
+            ..ClientInfo::default()
+        };
+        let (_, new_refresh_token) = service.refresh(token, client).await.unwrap();
+
+        let claims = decode_token(
+            new_refresh_token.expose_secret(),
+            TokenType::Refresh,
+            &service.token_scope,
+            &service.keys,
+        )
+        .unwrap();
+        assert_eq!(claims.family_id(), family_id);
+        assert_ne!(claims.token_id(), token_id);
+    }
+
+    #[tokio::test]
+    async fn replayed_refresh_token_revokes_family() {
+        let user_id = UserId::from(Uuid::new_v4());
+        let family_id = Uuid::new_v4();
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_rotate()
+            .returning(|_, _, _, _, _, _| Box::pin(async { Ok(Rotation::Reused) }));
+        tokens_repo
+            .expect_revoke_family()
+            .with(eq(family_id), eq(user_id))
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(None) }));
+        let service = service_with_events(tokens_repo, expect_revoked(user_id, Some(family_id)));
+        let (_, token) = refresh_token(&service, &user_id, family_id);
+
+        let result = service.refresh(token, ClientInfo::default()).await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn refresh_token_of_revoked_family_is_rejected() {
+        let user_id = UserId::from(Uuid::new_v4());
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_rotate()
+            .returning(|_, _, _, _, _, _| Box::pin(async { Ok(Rotation::Unknown) }));
+        tokens_repo.expect_revoke_family().never();
+        let service = service(tokens_repo);
+        let (_, token) = refresh_token(&service, &user_id, Uuid::new_v4());
+
+        let result = service.refresh(token, ClientInfo::default()).await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn access_token_is_not_accepted_for_refresh() {
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo.expect_rotate().never();
+        let service = service(tokens_repo);
+        let (access_token, _) = service
+            .encode_token_pair(&Uuid::new_v4().into(), Uuid::new_v4(), Uuid::new_v4())
+            .unwrap();
+
+        let result = service.refresh(access_token, ClientInfo::default()).await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn refresh_token_is_not_accepted_as_access_token() {
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo.expect_is_access_revoked().never();
+        let service = service(tokens_repo);
+        let (_, refresh_token) = service
+            .encode_token_pair(&Uuid::new_v4().into(), Uuid::new_v4(), Uuid::new_v4())
+            .unwrap();
+
+        let result = service.validate_token(refresh_token.expose_secret()).await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn logout_revokes_family_and_access_token() {
+        let user_id = UserId::from(Uuid::new_v4());
+        let family_id = Uuid::new_v4();
+        let token_id = Uuid::new_v4();
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_revoke_family()
+            .with(eq(family_id), eq(user_id))
+            .times(1)
+            .returning(move |_, _| {
+                let result = Ok(Some(token_id));
+                Box::pin(async { result })
+            });
+        tokens_repo
+            .expect_revoke_access()
+            .withf(move |id, ttl| *id == token_id && ttl.as_secs() == 60)
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        let service = service_with_events(tokens_repo, expect_revoked(user_id, Some(family_id)));
+        let (access_token, _) = service
+            .encode_token_pair(&user_id, token_id, family_id)
+            .unwrap();
+
+        service.logout(access_token).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn logout_with_stale_access_token_revokes_it_too() {
+        let user_id = UserId::from(Uuid::new_v4());
+        let family_id = Uuid::new_v4();
+        let stale_token_id = Uuid::new_v4();
+        let current_token_id = Uuid::new_v4();
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_revoke_family()
+            .with(eq(family_id), eq(user_id))
+            .times(1)
+            .returning(move |_, _| {
+                let result = Ok(Some(current_token_id));
+                Box::pin(async { result })
+            });
+        for token_id in [current_token_id, stale_token_id] {
+            tokens_repo
+                .expect_revoke_access()
+                .withf(move |id, _| *id == token_id)
+                .times(1)
+                .returning(|_, _| Box::pin(async { Ok(()) }));
+        }
+        let service = service_with_events(tokens_repo, expect_revoked(user_id, Some(family_id)));
+        let (access_token, _) = service
+            .encode_token_pair(&user_id, stale_token_id, family_id)
+            .unwrap();
+
+        service.logout(access_token).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn revoking_unknown_session_is_not_found() {
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_get()
+            .returning(|_, _| Box::pin(async { Ok(None) }));
+        tokens_repo.expect_revoke_family().never();
+        let service = service(tokens_repo);
+
+        let result = service
+            .revoke_session(&Uuid::new_v4().into(), &Uuid::new_v4())
+            .await;
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn revoking_all_sessions_closes_every_connection() {
+        let user_id = UserId::from(Uuid::new_v4());
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_revoke_all()
+            .with(eq(user_id))
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(3) }));
+        tokens_repo
+            .expect_revoke_user_access()
+            .with(eq(user_id), eq(Duration::from_secs(60)))
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        let service = service_with_events(tokens_repo, expect_revoked(user_id, None));
+
+        let revoked = service.revoke_all_sessions(&user_id).await.unwrap();
+
+        assert_eq!(revoked, 3);
+    }
+
+    #[tokio::test]
+    async fn revoked_access_token_is_rejected() {
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_is_access_revoked()
+            .returning(|_, _, _| Box::pin(async { Ok(true) }));
+        let service = service(tokens_repo);
+        let (access_token, _) = service
+            .encode_token_pair(&Uuid::new_v4().into(), Uuid::new_v4(), Uuid::new_v4())
+            .unwrap();
+
+        let result = service.validate_token(access_token.expose_secret()).await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn revocation_lookups_are_cached() {
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_is_access_revoked()
+            .times(1)
+            .returning(|_, _, _| Box::pin(async { Ok(false) }));
+        let service = service(tokens_repo);
+        let (access_token, _) = service
+            .encode_token_pair(&Uuid::new_v4().into(), Uuid::new_v4(), Uuid::new_v4())
+            .unwrap();
+
+        for _ in 0..2 {
+            service
+                .validate_token(access_token.expose_secret())
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn ticket_is_issued_to_the_session_of_the_token() {
+        let user_id = UserId::from(Uuid::new_v4());
+        let family_id = Uuid::new_v4();
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_is_access_revoked()
+            .returning(|_, _, _| Box::pin(async { Ok(false) }));
+        tokens_repo
+            .expect_store_ticket()
+            .withf(move |ticket, owner, ttl| {
+                !ticket.is_empty()
+                    && owner.user_id == user_id
+                    && owner.session_id == family_id
+                    && *ttl == Duration::from_secs(30)
+            })
+            .times(1)
+            .returning(|_, _, _| Box::pin(async { Ok(()) }));
+        let service = service(tokens_repo);
+        let (access_token, _) = service
+            .encode_token_pair(&user_id, Uuid::new_v4(), family_id)
+            .unwrap();
+        let claims = service
+            .validate_token(access_token.expose_secret())
+            .await
+            .unwrap();
+
+        let ticket = service.issue_ticket(&claims).await.unwrap();
+
+        assert_ne!(ticket.expose_secret(), access_token.expose_secret());
+    }
+
+    #[tokio::test]
+    async fn unknown_ticket_is_rejected() {
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_take_ticket()
+            .returning(|_| Box::pin(async { Ok(None) }));
+        let service = service(tokens_repo);
+
+        let result = service.redeem_ticket("used").await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn wrong_current_password_is_rejected() {
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo.expect_get_password_hash().returning(|_| {
+            let result = Ok(Some(password_hash("secret")));
+            Box::pin(async { result })
+        });
+        credentials_repo.expect_update_password().never();
+        let service = service_with_credentials(
+            credentials_repo,
+            MockTokenRepository::new(),
+            MockUserEventBus::new(),
+            MockEmailSender::new(),
+        );
+        let (access_token, _) = service
+            .encode_token_pair(&Uuid::new_v4().into(), Uuid::new_v4(), Uuid::new_v4())
+            .unwrap();
+        let claims = decode_token(
+            access_token.expose_secret(),
+            TokenType::Access,
+            &service.token_scope,
+            &service.keys,
+        )
+        .unwrap();
+
+        let result = service
+            .change_password(
+                &claims,
+                Secret::new("guess".into()),
+                Secret::new("new secret".into()),
+            )
+            .await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn password_change_revokes_every_session_and_starts_a_new_one() {
+        let user_id = UserId::from(Uuid::new_v4());
+        let current = Uuid::new_v4();
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo.expect_get_password_hash().returning(|_| {
+            let result = Ok(Some(password_hash("secret")));
+            Box::pin(async { result })
+        });
+        credentials_repo.expect_get_user().returning(move |_| {
+            let result = Ok(Some(user(user_id)));
+            Box::pin(async { result })
+        });
+        credentials_repo
+            .expect_update_password()
+            .withf(move |user, _| *user == user_id)
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_get()
+            .with(eq(current), eq(user_id))
+            .returning(move |_, _| {
+                let mut session = session(user_id, current);
+                session.client.device_label = Some("laptop".to_string());
+                let result = Ok(Some(session));
+                Box::pin(async { result })
+            });
+        tokens_repo
+            .expect_revoke_all()
+            .with(eq(user_id))
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(2) }));
+        tokens_repo
+            .expect_revoke_user_access()
+            .withf(move |user, _| *user == user_id)
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        tokens_repo
+            .expect_create()
+            .withf(move |family, _, user, _, client| {
+                *family != current
+                    && *user == user_id
+                    && client.device_label.as_deref() == Some("laptop")
+            })
+            .times(1)
+            .returning(|_, _, _, _, _| Box::pin(async { Ok(()) }));
+        let service = service_with_credentials(
+            credentials_repo,
+            tokens_repo,
+            expect_revoked(user_id, None),
+            MockEmailSender::new(),
+        );
+        let (access_token, _) = service
+            .encode_token_pair(&user_id, Uuid::new_v4(), current)
+            .unwrap();
+        let claims = decode_token(
+            access_token.expose_secret(),
+            TokenType::Access,
+            &service.token_scope,
+            &service.keys,
+        )
+        .unwrap();
+
+        let (access_token, _) = service
+            .change_password(
+                &claims,
+                Secret::new("secret".into()),
+                Secret::new("new secret".into()),
+            )
+            .await
+            .unwrap();
+
+        let claims = decode_token(
+            access_token.expose_secret(),
+            TokenType::Access,
+            &service.token_scope,
+            &service.keys,
+        )
+        .unwrap();
+        assert_eq!(claims.user_id(), user_id);
+        assert_ne!(claims.family_id(), current);
+    }
+
+    #[tokio::test]
+    async fn reset_of_unknown_email_sends_nothing() {
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo
+            .expect_get_user_by_email()
+            .returning(|_| Box::pin(async { Ok(None) }));
+        let mut email_sender = MockEmailSender::new();
+        email_sender.expect_send().never();
+        let service = service_with_credentials(
+            credentials_repo,
+            MockTokenRepository::new(),
+            MockUserEventBus::new(),
+            email_sender,
+        );
+
+        service
+            .request_password_reset("nobody@example.com")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_reset_email_is_not_reported() {
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo.expect_get_user_by_email().returning(|_| {
+            let result = Ok(Some(User {
+                user_id: Uuid::new_v4().into(),
+                name: "user1".parse().unwrap(),
+                email: "user1@example.com".parse().unwrap(),
+                code: "1234".parse().unwrap(),
+                created_at: Utc::now(),
+            }));
+            Box::pin(async { result })
+        });
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_store_user_token()
+            .returning(|_, _, _, _| Box::pin(async { Ok(()) }));
+        let (attempted, mut attempts) = tokio::sync::mpsc::unbounded_channel();
+        let mut email_sender = MockEmailSender::new();
+        email_sender.expect_send().times(1).returning(move |_| {
+            attempted.send(()).unwrap();
+            Box::pin(async { Err(anyhow::anyhow!("connection refused")) })
+        });
+        let service = service_with_credentials(
+            credentials_repo,
+            tokens_repo,
+            MockUserEventBus::new(),
+            email_sender,
+        );
+
+        let result = service.request_password_reset("user1@example.com").await;
+
+        assert!(result.is_ok());
+        attempts.recv().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reset_email_carries_the_stored_token() {
+        let user_id = UserId::from(Uuid::new_v4());
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo
+            .expect_get_user_by_email()
+            .returning(move |_| {
+                let result = Ok(Some(User {
+                    user_id,
+                    name: "user1".parse().unwrap(),
+                    email: "user1@example.com".parse().unwrap(),
+                    code: "1234".parse().unwrap(),
+                    created_at: Utc::now(),
+                }));
+                Box::pin(async { result })
+            });
+        let stored = Arc::new(std::sync::Mutex::new(String::new()));
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_store_user_token()
+            .withf(move |kind, _, user, ttl| {
+                *kind == UserTokenKind::PasswordReset
+                    && *user == user_id
+                    && *ttl == Duration::from_secs(900)
+            })
+            .times(1)
+            .returning({
+                let stored = stored.clone();
+                move |_, token, _, _| {
+                    *stored.lock().unwrap() = token.to_string();
+                    Box::pin(async { Ok(()) })
+                }
+            });
+        let (sent, mut outbox) = tokio::sync::mpsc::unbounded_channel();
+        let mut email_sender = MockEmailSender::new();
+        email_sender.expect_send().times(1).returning(move |email| {
+            sent.send(email).unwrap();
+            Box::pin(async { Ok(()) })
+        });
+        let service = service_with_credentials(
+            credentials_repo,
+            tokens_repo,
+            MockUserEventBus::new(),
+            email_sender,
+        );
+
+        service
+            .request_password_reset("user1@example.com")
+            .await
+            .unwrap();
+
+        let email = outbox.recv().await.unwrap();
+        assert_eq!(email.to, "user1@example.com");
+        let link = format!("http://localhost/reset?token={}", stored.lock().unwrap());
+        assert!(email.body.contains(&link));
+    }
+
+    #[tokio::test]
+    async fn password_reset_revokes_every_session() {
+        let user_id = UserId::from(Uuid::new_v4());
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo.expect_get_user().returning(move |_| {
+            let result = Ok(Some(user(user_id)));
+            Box::pin(async { result })
+        });
+        credentials_repo
+            .expect_update_password()
+            .withf(move |user, _| *user == user_id)
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_take_user_token()
+            .with(eq(UserTokenKind::PasswordReset), eq("reset"))
+            .returning(move |_, _| {
+                let result = Ok(Some(user_id));
+                Box::pin(async { result })
+            });
+        tokens_repo
+            .expect_revoke_all()
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(2) }));
+        tokens_repo
+            .expect_revoke_user_access()
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        let service = service_with_credentials(
+            credentials_repo,
+            tokens_repo,
+            expect_revoked(user_id, None),
+            MockEmailSender::new(),
+        );
+
+        service
+            .reset_password("reset", Secret::new("new secret".into()))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn used_reset_token_is_rejected() {
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_take_user_token()
+            .returning(|_, _| Box::pin(async { Ok(None) }));
+        let service = service(tokens_repo);
+
+        let result = service
+            .reset_password("used", Secret::new("new secret".into()))
+            .await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    fn email_status(verified: bool) -> MockCredentialsRepository {
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo
+            .expect_get_email_status()
+            .returning(move |_| {
+                let result = Ok(Some(("user1@example.com".to_string(), verified)));
+                Box::pin(async { result })
+            });
+        credentials_repo
+    }
+
+    #[tokio::test]
+    async fn unverified_user_gets_policy_permissions() {
+        let service = service_with_credentials(
+            email_status(false),
+            MockTokenRepository::new(),
+            MockUserEventBus::new(),
+            MockEmailSender::new(),
+        );
+
+        let permissions = service.permissions(&Uuid::new_v4().into()).await.unwrap();
+
+        assert!(permissions.require_join_rooms().is_ok());
+        assert!(matches!(
+            permissions.require_send_messages(),
+            Err(Error::Forbidden(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn verified_user_gets_all_permissions() {
+        let service = service_with_credentials(
+            email_status(true),
+            MockTokenRepository::new(),
+            MockUserEventBus::new(),
+            MockEmailSender::new(),
+        );
+
+        let permissions = service.permissions(&Uuid::new_v4().into()).await.unwrap();
+
+        assert_eq!(permissions, Permissions::ALL);
+    }
+
+    #[tokio::test]
+    async fn verification_token_marks_email_verified() {
+        let user_id = UserId::from(Uuid::new_v4());
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo
+            .expect_verify_email()
+            .with(eq(user_id))
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(()) }));
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_take_user_token()
+            .with(eq(UserTokenKind::EmailVerification), eq("verify"))
+            .returning(move |_, _| {
+                let result = Ok(Some(user_id));
+                Box::pin(async { result })
+            });
+        let service = service_with_credentials(
+            credentials_repo,
+            tokens_repo,
+            MockUserEventBus::new(),
+            MockEmailSender::new(),
+        );
+
+        service.verify_email("verify").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn verified_email_is_not_sent_another_link() {
+        let mut email_sender = MockEmailSender::new();
+        email_sender.expect_send().never();
+        let service = service_with_credentials(
+            email_status(true),
+            MockTokenRepository::new(),
+            MockUserEventBus::new(),
+            email_sender,
+        );
+
+        let result = service.resend_verification(&Uuid::new_v4().into()).await;
+
+        assert!(matches!(result, Err(Error::ConflictError(_))));
+    }
+
+    #[tokio::test]
+    async fn signup_succeeds_when_verification_email_fails() {
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo
+            .expect_get_user_by_email()
+            .returning(|_| Box::pin(async { Ok(None) }));
+        credentials_repo
+            .expect_signup()
+            .returning(|name, email, _, code| {
+                let result = Ok(User {
+                    user_id: Uuid::new_v4().into(),
+                    name: name.clone(),
+                    email: email.as_ref().parse().unwrap(),
+                    code: code.as_ref().parse().unwrap(),
+                    created_at: Utc::now(),
+                });
+                Box::pin(async { result })
+            });
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_store_user_token()
+            .withf(|kind, _, _, _| *kind == UserTokenKind::EmailVerification)
+            .times(1)
+            .returning(|_, _, _, _| Box::pin(async { Ok(()) }));
+        tokens_repo
+            .expect_create()
+            .returning(|_, _, _, _, _| Box::pin(async { Ok(()) }));
+        let mut email_sender = MockEmailSender::new();
+        email_sender
+            .expect_send()
+            .times(1)
+            .returning(|_| Box::pin(async { Err(anyhow::anyhow!("mail server down")) }));
+        let service = service_with_credentials(
+            credentials_repo,
+            tokens_repo,
+            MockUserEventBus::new(),
+            email_sender,
+        );
+        let new_user = NewUser {
+            name: "user1".parse().unwrap(),
+            email: "user1@example.com".parse().unwrap(),
+            code: "1234".parse().unwrap(),
+            password: Secret::new("correct horse battery".to_string()),
+        };
+
+        let result = service.signup(new_user, ClientInfo::default()).await;
+
+        assert!(result.is_ok());
+    }
+
+    const TOTP_SECRET: &[u8] = b"12345678901234567890";
+
+    /// Repository of a user with a password and confirmed TOTP.
+    fn totp_credentials(user_id: UserId) -> MockCredentialsRepository {
+        let secret = SecretCipher::new(&TOTP_KEY)
+            .unwrap()
+            .encrypt(TOTP_SECRET, user_id.as_ref().as_bytes())
+            .unwrap();
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo
+            .expect_get_credential()
+            .returning(move |_| {
+                let result = Ok(Some((*user_id.as_ref(), password_hash("secret"))));
+                Box::pin(async { result })
+            });
+        credentials_repo.expect_get_totp().returning(move |_| {
+            let result = Ok(Some(TotpRecord {
+                secret: secret.clone(),
+                confirmed: true,
+            }));
+            Box::pin(async { result })
+        });
+        credentials_repo
+            .expect_get_email_status()
+            .returning(|_| Box::pin(async { Ok(Some(("user1@example.com".to_string(), true))) }));
+        credentials_repo
+    }
+
+    fn current_code() -> (String, u64) {
+        let step = jsonwebtoken::get_current_timestamp() / 30;
+        (format!("{:06}", totp::code_at(TOTP_SECRET, step)), step)
+    }
+
+    async fn mfa_token(service: &TestService) -> Secret<String> {
+        let credentials = Credentials {
+            email: "user1@example.com".to_string(),
+            password: Secret::new("secret".into()),
+        };
+        match service
+            .login(credentials, ClientInfo::default())
+            .await
+            .unwrap()
+        {
+            LoginOutcome::MfaRequired(mfa_token) => mfa_token,
+            LoginOutcome::Tokens(_) => panic!("login skipped the second factor"),
+        }
+    }
+
+    #[tokio::test]
+    async fn login_with_totp_waits_for_the_code() {
+        let user_id: UserId = Uuid::new_v4().into();
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo.expect_create().never();
+        let service = service_with_credentials(
+            totp_credentials(user_id),
+            tokens_repo,
+            MockUserEventBus::new(),
+            MockEmailSender::new(),
+        );
+
+        let mfa_token = mfa_token(&service).await;
+
+        let result = service.validate_token(mfa_token.expose_secret()).await;
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn mfa_challenge_names_its_user() {
+        let user_id: UserId = Uuid::new_v4().into();
+        let service = service_with_credentials(
+            totp_credentials(user_id),
+            MockTokenRepository::new(),
+            MockUserEventBus::new(),
+            MockEmailSender::new(),
+        );
+        let mfa_token = mfa_token(&service).await;
+        let (access_token, _) = service
+            .encode_token_pair(&user_id, Uuid::new_v4(), Uuid::new_v4())
+            .unwrap();
+
+        assert_eq!(
+            service
+                .mfa_challenge_user(mfa_token.expose_secret())
+                .unwrap(),
+            user_id
+        );
+        assert!(matches!(
+            service.mfa_challenge_user(access_token.expose_secret()),
+            Err(Error::InvalidCredentials(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn current_code_completes_login() {
+        let user_id: UserId = Uuid::new_v4().into();
+        let (code, step) = current_code();
+        let mut credentials_repo = totp_credentials(user_id);
+        credentials_repo
+            .expect_use_totp_step()
+            .with(eq(user_id), eq(step))
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(true) }));
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_create()
+            .times(1)
+            .returning(|_, _, _, _, _| Box::pin(async { Ok(()) }));
+        let service = service_with_credentials(
+            credentials_repo,
+            tokens_repo,
+            MockUserEventBus::new(),
+            MockEmailSender::new(),
+        );
+        let mfa_token = mfa_token(&service).await;
+
+        let (access_token, _) = service
+            .login_mfa(mfa_token, &code, ClientInfo::default())
+            .await
+            .unwrap();
+
+        let claims = decode_token(
+            access_token.expose_secret(),
+            TokenType::Access,
+            &service.token_scope,
+            &service.keys,
+        )
+        .unwrap();
+        assert_eq!(claims.user_id(), user_id);
+    }
+
+    #[tokio::test]
+    async fn used_code_is_rejected_and_counted_as_failed_login() {
+        let user_id: UserId = Uuid::new_v4().into();
+        let (code, _) = current_code();
+        let mut credentials_repo = totp_credentials(user_id);
+        credentials_repo
+            .expect_use_totp_step()
+            .returning(|_, _| Box::pin(async { Ok(false) }));
+        let mut login_attempts = MockLoginAttemptRepository::new();
+        login_attempts
+            .expect_locked_for()
+            .returning(|_| Box::pin(async { Ok(None) }));
+        login_attempts.expect_clear().never();
+        login_attempts
+            .expect_record_failure()
+            .with(eq("account:user1@example.com"), always())
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(1) }));
+        login_attempts
+            .expect_record_failure()
+            .with(eq("ip:10.0.0.1"), always())
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(1) }));
+        let service = service_with_attempts(credentials_repo, login_attempts);
+        let mfa_token = mfa_token(&service).await;
+        let (_, client) = login_as("secret");
+
+        let result = service.login_mfa(mfa_token, &code, client).await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn locked_account_is_rejected_before_checking_the_code() {
+        let user_id: UserId = Uuid::new_v4().into();
+        let mut credentials_repo = totp_credentials(user_id);
+        credentials_repo.expect_use_recovery_code().never();
+        let mut login_attempts = MockLoginAttemptRepository::new();
+        login_attempts
+            .expect_locked_for()
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(None) }));
+        login_attempts
+            .expect_locked_for()
+            .returning(|_| Box::pin(async { Ok(Some(Duration::from_secs(120))) }));
+        let service = service_with_attempts(credentials_repo, login_attempts);
+        let mfa_token = mfa_token(&service).await;
+
+        let result = service
+            .login_mfa(mfa_token, "ABCDE-12345", ClientInfo::default())
+            .await;
+
+        assert!(matches!(result, Err(Error::TooManyRequests(_))));
+    }
+
+    #[tokio::test]
+    async fn accepted_code_forgets_failures_of_the_account() {
+        let user_id: UserId = Uuid::new_v4().into();
+        let mut credentials_repo = totp_credentials(user_id);
+        credentials_repo
+            .expect_use_recovery_code()
+            .returning(|_, _| Box::pin(async { Ok(true) }));
+        let mut login_attempts = MockLoginAttemptRepository::new();
+        login_attempts
+            .expect_locked_for()
+            .returning(|_| Box::pin(async { Ok(None) }));
+        login_attempts.expect_record_failure().never();
+        login_attempts
+            .expect_clear()
+            .with(eq("account:user1@example.com"))
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(()) }));
+        let service = service_with_attempts(credentials_repo, login_attempts);
+        let mfa_token = mfa_token(&service).await;
+
+        let result = service
+            .login_mfa(mfa_token, "ABCDE-12345", ClientInfo::default())
+            .await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn recovery_code_completes_login() {
+        let user_id: UserId = Uuid::new_v4().into();
+        let mut credentials_repo = totp_credentials(user_id);
+        credentials_repo.expect_use_totp_step().never();
+        credentials_repo
+            .expect_use_recovery_code()
+            .with(eq(user_id), eq(totp::hash_recovery_code("abcde-12345")))
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(true) }));
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_create()
+            .times(1)
+            .returning(|_, _, _, _, _| Box::pin(async { Ok(()) }));
+        let service = service_with_credentials(
+            credentials_repo,
+            tokens_repo,
+            MockUserEventBus::new(),
+            MockEmailSender::new(),
+        );
+        let mfa_token = mfa_token(&service).await;
+
+        let result = service
+            .login_mfa(mfa_token, "ABCDE-12345", ClientInfo::default())
+            .await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn confirmation_stores_hashes_of_recovery_codes() {
+        let user_id: UserId = Uuid::new_v4().into();
+        let (code, step) = current_code();
+        let secret = SecretCipher::new(&TOTP_KEY)
+            .unwrap()
+            .encrypt(TOTP_SECRET, user_id.as_ref().as_bytes())
+            .unwrap();
+        let stored = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo.expect_get_totp().returning(move |_| {
+            let result = Ok(Some(TotpRecord {
+                secret: secret.clone(),
+                confirmed: false,
+            }));
+            Box::pin(async { result })
+        });
+        let stored_hashes = stored.clone();
+        credentials_repo
+            .expect_confirm_totp()
+            .withf(move |id, used_step, _| *id == user_id && *used_step == step)
+            .times(1)
+            .returning(move |_, _, hashes| {
+                *stored_hashes.lock().unwrap() = hashes.to_vec();
+                Box::pin(async { Ok(()) })
+            });
+        let service = service_with_credentials(
+            credentials_repo,
+            MockTokenRepository::new(),
+            MockUserEventBus::new(),
+            MockEmailSender::new(),
+        );
+
+        let recovery_codes = service.confirm_totp(&user_id, &code).await.unwrap();
+
+        let hashes: Vec<String> = recovery_codes
+            .iter()
+            .map(|code| totp::hash_recovery_code(code))
+            .collect();
+        assert_eq!(recovery_codes.len(), 10);
+        assert_eq!(*stored.lock().unwrap(), hashes);
+    }
+
+    fn password_credentials(user_id: UserId) -> MockCredentialsRepository {
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo
+            .expect_get_credential()
+            .returning(move |_| {
+                let result = Ok(Some((*user_id.as_ref(), password_hash("secret"))));
+                Box::pin(async { result })
+            });
+        credentials_repo
+            .expect_get_totp()
+            .returning(|_| Box::pin(async { Ok(None) }));
+        credentials_repo
+    }
+
+    fn login_as(password: &str) -> (Credentials, ClientInfo) {
+        let credentials = Credentials {
+            email: "User1@Example.com".to_string(),
+            password: Secret::new(password.into()),
+        };
+        let client = ClientInfo {
+            ip: Some([10, 0, 0, 1].into()),
+            ..ClientInfo::default()
+        };
+        (credentials, client)
+    }
+
+    #[tokio::test]
+    async fn locked_account_is_rejected_before_checking_password() {
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo.expect_get_credential().never();
+        let mut login_attempts = MockLoginAttemptRepository::new();
+        login_attempts
+            .expect_locked_for()
+            .with(eq("account:user1@example.com"))
+            .returning(|_| Box::pin(async { Ok(Some(Duration::from_secs(120))) }));
+        login_attempts
+            .expect_locked_for()
+            .with(eq("ip:10.0.0.1"))
+            .returning(|_| Box::pin(async { Ok(None) }));
+        let service = service_with_attempts(credentials_repo, login_attempts);
+        let (credentials, client) = login_as("secret");
+
+        let result = service.login(credentials, client).await;
+
+        assert!(matches!(result, Err(Error::TooManyRequests(d)) if d.as_secs() == 120));
+    }
+
+    #[tokio::test]
+    async fn failures_past_free_attempts_delay_the_next_login() {
+        let mut login_attempts = MockLoginAttemptRepository::new();
+        login_attempts
+            .expect_locked_for()
+            .returning(|_| Box::pin(async { Ok(None) }));
+        login_attempts
+            .expect_record_failure()
+            .with(eq("account:user1@example.com"), always())
+            .returning(|_, _| Box::pin(async { Ok(6) }));
+        login_attempts
+            .expect_record_failure()
+            .with(eq("ip:10.0.0.1"), always())
+            .returning(|_, _| Box::pin(async { Ok(1) }));
+        login_attempts
+            .expect_lock()
+            .with(eq("account:user1@example.com"), eq(Duration::from_secs(4)))
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        let service =
+            service_with_attempts(password_credentials(Uuid::new_v4().into()), login_attempts);
+        let (credentials, client) = login_as("guess");
+
+        let result = service.login(credentials, client).await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn threshold_of_failures_locks_the_account_out() {
+        let mut login_attempts = MockLoginAttemptRepository::new();
+        login_attempts
+            .expect_locked_for()
+            .returning(|_| Box::pin(async { Ok(None) }));
+        login_attempts
+            .expect_record_failure()
+            .with(eq("account:user1@example.com"), always())
+            .returning(|_, _| Box::pin(async { Ok(10) }));
+        login_attempts
+            .expect_record_failure()
+            .with(eq("ip:10.0.0.1"), always())
+            .returning(|_, _| Box::pin(async { Ok(1) }));
+        login_attempts
+            .expect_lock()
+            .with(
+                eq("account:user1@example.com"),
+                eq(Duration::from_secs(900)),
+            )
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        let service =
+            service_with_attempts(password_credentials(Uuid::new_v4().into()), login_attempts);
+        let (credentials, client) = login_as("guess");
+
+        let result = service.login(credentials, client).await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn successful_login_forgets_failures_of_the_account() {
+        let mut login_attempts = MockLoginAttemptRepository::new();
+        login_attempts
+            .expect_locked_for()
+            .returning(|_| Box::pin(async { Ok(None) }));
+        login_attempts.expect_record_failure().never();
+        login_attempts
+            .expect_clear()
+            .with(eq("account:user1@example.com"))
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(()) }));
+        let service =
+            service_with_attempts(password_credentials(Uuid::new_v4().into()), login_attempts);
+        let (credentials, client) = login_as("secret");
+
+        let result = service.login(credentials, client).await;
+
+        assert!(matches!(result, Ok(LoginOutcome::Tokens(_))));
+    }
+
+    #[tokio::test]
+    async fn only_admins_unlock_accounts() {
+        let admin_id: UserId = Uuid::new_v4().into();
+        let user_id: UserId = Uuid::new_v4().into();
+        let mut credentials_repo = email_status(true);
+        credentials_repo.expect_is_admin().returning(move |id| {
+            let result = Ok(*id == admin_id);
+            Box::pin(async { result })
+        });
+        let mut login_attempts = MockLoginAttemptRepository::new();
+        login_attempts
+            .expect_clear()
+            .with(eq("account:user1@example.com"))
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(()) }));
+        let service = service_with_attempts(credentials_repo, login_attempts);
+
+        let result = service.unlock_account(&user_id, &user_id).await;
+        assert!(matches!(result, Err(Error::Forbidden(_))));
+
+        service.unlock_account(&admin_id, &user_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_admins_revoke_sessions_of_users() {
+        let admin_id: UserId = Uuid::new_v4().into();
+        let user_id: UserId = Uuid::new_v4().into();
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo.expect_is_admin().returning(move |id| {
+            let result = Ok(*id == admin_id);
+            Box::pin(async { result })
+        });
+        credentials_repo.expect_get_user().returning(move |_| {
+            let result = Ok(Some(user(user_id)));
+            Box::pin(async { result })
+        });
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_revoke_all()
+            .with(eq(user_id))
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(3) }));
+        tokens_repo
+            .expect_revoke_user_access()
+            .withf(move |user, _| *user == user_id)
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        let service = service_with_credentials(
+            credentials_repo,
+            tokens_repo,
+            expect_revoked(user_id, None),
+            MockEmailSender::new(),
+        );
+
+        let result = service.revoke_user_sessions(&user_id, &user_id).await;
+        assert!(matches!(result, Err(Error::Forbidden(_))));
+
+        let revoked = service
+            .revoke_user_sessions(&admin_id, &user_id)
+            .await
+            .unwrap();
+        assert_eq!(revoked, 3);
+    }
+
+    #[tokio::test]
+    async fn weak_password_does_not_use_up_reset_token() {
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo.expect_take_user_token().never();
+        let service = service(tokens_repo);
+
+        let result = service
+            .reset_password("reset", Secret::new("short".into()))
+            .await;
+
+        assert!(matches!(result, Err(Error::ValidationError(_))));
+    }
+
+    #[tokio::test]
+    async fn password_containing_the_name_is_rejected_on_reset() {
+        let user_id = UserId::from(Uuid::new_v4());
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo.expect_get_user().returning(move |_| {
+            let result = Ok(Some(user(user_id)));
+            Box::pin(async { result })
+        });
+        credentials_repo.expect_update_password().never();
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo.expect_take_user_token().returning(move |_, _| {
+            let result = Ok(Some(user_id));
+            Box::pin(async { result })
+        });
+        let service = service_with_credentials(
+            credentials_repo,
+            tokens_repo,
+            MockUserEventBus::new(),
+            MockEmailSender::new(),
+        );
+
+        let result = service
+            .reset_password("reset", Secret::new("User1-is-me!".into()))
+            .await;
+
+        assert!(matches!(result, Err(Error::ValidationError(_))));
+    }
+
+    #[tokio::test]
+    async fn outdated_hash_is_replaced_on_login() {
+        let user_id: UserId = Uuid::new_v4().into();
+        let outdated = compute_password_hash(
+            Secret::new("secret".into()),
+            Params::new(2048, 1, 1, None).unwrap(),
+        )
+        .unwrap();
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo
+            .expect_get_credential()
+            .returning(move |_| {
+                let result = Ok(Some((*user_id.as_ref(), outdated.clone())));
+                Box::pin(async { result })
+            });
+        credentials_repo
+            .expect_get_totp()
+            .returning(|_| Box::pin(async { Ok(None) }));
+        let params = settings().password.argon2.params().unwrap();
+        credentials_repo
+            .expect_update_password()
+            .withf(move |id, hash| *id == user_id && !needs_rehash(hash, &params))
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        let service = service_with_attempts(credentials_repo, no_failed_logins());
+        let (credentials, client) = login_as("secret");
+
+        let result = service.login(credentials, client).await;
+
+        assert!(matches!(result, Ok(LoginOutcome::Tokens(_))));
+    }
+
+    fn identity(email_verified: bool) -> ExternalIdentity {
+        ExternalIdentity {
+            provider: "corp".to_string(),
+            subject: "external-1".to_string(),
+            email: Some("user1@example.com".to_string()),
+            email_verified,
+            name: Some("User One".to_string()),
+        }
+    }
+
+    /// Tokens repository holding a login started at `provider`.
+    fn pending_oidc_login(provider: &str) -> MockTokenRepository {
+        let login = OidcLogin {
+            provider: provider.to_string(),
+            code_verifier: "verifier".to_string(),
+            nonce: "nonce".to_string(),
+        };
+        let mut tokens_repo = MockTokenRepository::new();
+        tokens_repo
+            .expect_take_oidc_login()
+            .with(eq("state"))
+            .returning(move |_| {
+                let result = Ok(Some(login.clone()));
+                Box::pin(async { result })
+            });
+        tokens_repo
+            .expect_create()
+            .returning(|_, _, _, _, _| Box::pin(async { Ok(()) }));
+        tokens_repo
+    }
+
+    fn oidc_client(identity: ExternalIdentity) -> MockOidcClient {
+        let mut oidc_client = MockOidcClient::new();
+        oidc_client
+            .expect_exchange_code()
+            .with(eq("corp"), eq("code"), eq("verifier"), eq("nonce"))
+            .returning(move |_, _, _, _| {
+                let result = Ok(identity.clone());
+                Box::pin(async { result })
+            });
+        oidc_client
+    }
+
+    #[tokio::test]
+    async fn authorization_carries_challenge_of_stored_verifier() {
+        let mut tokens_repo = MockTokenRepository::new();
+        let stored = Arc::new(std::sync::Mutex::new(None));
+        let stored_login = stored.clone();
+        tokens_repo
+            .expect_store_oidc_login()
+            .times(1)
+            .returning(move |state, login, _| {
+                *stored_login.lock().unwrap() = Some((state.to_string(), login.clone()));
+                Box::pin(async { Ok(()) })
+            });
+        let mut oidc_client = MockOidcClient::new();
+        oidc_client
+            .expect_authorization_url()
+            .returning(|_, request| {
+                let result = Ok(format!("{:?}", request));
+                Box::pin(async { result })
+            });
+        let service = service_with_oidc(MockCredentialsRepository::new(), tokens_repo, oidc_client);
+
+        let url = service.oidc_authorize("corp").await.unwrap();
+
+        let (state, login) = stored.lock().unwrap().clone().unwrap();
+        let request = AuthorizationRequest {
+            state,
+            nonce: login.nonce,
+            code_challenge: pkce_challenge(&login.code_verifier),
+        };
+        assert_eq!(url, format!("{:?}", request));
+        assert_eq!(login.provider, "corp");
+    }
+
+    #[tokio::test]
+    async fn unknown_provider_is_not_found() {
+        let service = service(MockTokenRepository::new());
+
+        let result = service.oidc_authorize("other").await;
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn state_of_another_provider_is_rejected() {
+        let mut oidc_client = MockOidcClient::new();
+        oidc_client.expect_exchange_code().never();
+        let service = service_with_oidc(
+            MockCredentialsRepository::new(),
+            pending_oidc_login("other"),
+            oidc_client,
+        );
+
+        let result = service
+            .oidc_login("corp", "code", "state", ClientInfo::default())
+            .await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn verified_email_links_existing_user() {
+        let user_id: UserId = Uuid::new_v4().into();
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo
+            .expect_get_identity_user()
+            .returning(|_, _| Box::pin(async { Ok(None) }));
+        credentials_repo
+            .expect_get_user_by_email()
+            .returning(move |_| {
+                let result = Ok(Some(user(user_id)));
+                Box::pin(async { result })
+            });
+        credentials_repo
+            .expect_get_email_status()
+            .returning(|_| Box::pin(async { Ok(Some(("user1@example.com".to_string(), true))) }));
+        credentials_repo
+            .expect_get_totp()
+            .returning(|_| Box::pin(async { Ok(None) }));
+        credentials_repo
+            .expect_link_identity()
+            .with(eq(user_id), eq(identity(true)))
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        let service = service_with_oidc(
+            credentials_repo,
+            pending_oidc_login("corp"),
+            oidc_client(identity(true)),
+        );
+
+        let outcome = service
+            .oidc_login("corp", "code", "state", ClientInfo::default())
+            .await
+            .unwrap();
+
+        let LoginOutcome::Tokens((access_token, _)) = outcome else {
+            panic!("login asked for a second factor");
+        };
+
+        let claims = decode_token(
+            access_token.expose_secret(),
+            TokenType::Access,
+            &service.token_scope,
+            &service.keys,
+        )
+        .unwrap();
+        assert_eq!(claims.user_id(), user_id);
+    }
+
+    #[tokio::test]
+    async fn unverified_email_does_not_take_over_existing_user() {
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo
+            .expect_get_identity_user()
+            .returning(|_, _| Box::pin(async { Ok(None) }));
+        credentials_repo.expect_get_user_by_email().returning(|_| {
+            let result = Ok(Some(user(Uuid::new_v4().into())));
+            Box::pin(async { result })
+        });
+        credentials_repo.expect_link_identity().never();
+        let service = service_with_oidc(
+            credentials_repo,
+            pending_oidc_login("corp"),
+            oidc_client(identity(false)),
+        );
+
+        let result = service
+            .oidc_login("corp", "code", "state", ClientInfo::default())
+            .await;
+
+        assert!(matches!(result, Err(Error::ConflictError(_))));
+    }
+
+    #[tokio::test]
+    async fn new_identity_signs_up_user() {
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo
+            .expect_get_identity_user()
+            .returning(|_, _| Box::pin(async { Ok(None) }));
+        credentials_repo
+            .expect_get_user_by_email()
+            .returning(|_| Box::pin(async { Ok(None) }));
+        credentials_repo
+            .expect_create_external_user()
+            .withf(|name, email, _, _, identity| {
+                name.as_ref() == "User One"
+                    && email.as_ref() == "user1@example.com"
+                    && identity.subject == "external-1"
+            })
+            .times(1)
+            .returning(|_, _, _, _, _| {
+                let result = Ok(user(Uuid::new_v4().into()));
+                Box::pin(async { result })
+            });
+        credentials_repo
+            .expect_get_email_status()
+            .returning(|_| Box::pin(async { Ok(Some(("user1@example.com".to_string(), true))) }));
+        credentials_repo
+            .expect_get_totp()
+            .returning(|_| Box::pin(async { Ok(None) }));
+        let service = service_with_oidc(
+            credentials_repo,
+            pending_oidc_login("corp"),
+            oidc_client(identity(true)),
+        );
+
+        let result = service
+            .oidc_login("corp", "code", "state", ClientInfo::default())
+            .await;
+
+        assert!(matches!(result, Ok(LoginOutcome::Tokens(_))));
+    }
+
+    #[tokio::test]
+    async fn unverified_local_account_is_not_linked() {
+        let mut credentials_repo = MockCredentialsRepository::new();
+        credentials_repo
+            .expect_get_identity_user()
+            .returning(|_, _| Box::pin(async { Ok(None) }));
+        credentials_repo.expect_get_user_by_email().returning(|_| {
+            let result = Ok(Some(user(Uuid::new_v4().into())));
+            Box::pin(async { result })
+        });
+        credentials_repo
+            .expect_get_email_status()
+            .returning(|_| Box::pin(async { Ok(Some(("user1@example.com".to_string(), false))) }));
+        credentials_repo.expect_link_identity().never();
+        let service = service_with_oidc(
+            credentials_repo,
+            pending_oidc_login("corp"),
+            oidc_client(identity(true)),
+        );
+
+        let result = service
+            .oidc_login("corp", "code", "state", ClientInfo::default())
+            .await;
+
+        assert!(matches!(result, Err(Error::ConflictError(_))));
+    }
+
+    #[tokio::test]
+    async fn oidc_login_of_user_with_totp_asks_for_the_code() {
+        let user_id: UserId = Uuid::new_v4().into();
+        let mut credentials_repo = totp_credentials(user_id);
+        credentials_repo
+            .expect_get_identity_user()
+            .returning(move |_, _| {
+                let result = Ok(Some(user_id));
+                Box::pin(async { result })
+            });
+        let service = service_with_oidc(
+            credentials_repo,
+            pending_oidc_login("corp"),
+            oidc_client(identity(true)),
+        );
+
+        let result = service
+            .oidc_login("corp", "code", "state", ClientInfo::default())
+            .await;
+
+        assert!(matches!(result, Ok(LoginOutcome::MfaRequired(_))));
+    }
+
+    #[tokio::test]
+    async fn rejected_oidc_login_counts_against_the_ip() {
+        let mut login_attempts = MockLoginAttemptRepository::new();
+        login_attempts
+            .expect_locked_for()
+            .with(eq("ip:10.0.0.1"))
+            .returning(|_| Box::pin(async { Ok(None) }));
+        login_attempts
+            .expect_record_failure()
+            .with(eq("ip:10.0.0.1"), always())
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(1) }));
+        let mut oidc_client = MockOidcClient::new();
+        oidc_client
+            .expect_exchange_code()
+            .returning(|_, _, _, _| Box::pin(async { Err(anyhow::anyhow!("invalid_grant")) }));
+        let service = AuthServiceImp::build(
+            &settings(),
+            MockCredentialsRepository::new(),
+            pending_oidc_login("corp"),
+            login_attempts,
+            MockUserEventBus::new(),
+            MockEmailSender::new(),
+            oidc_client,
+        )
+        .unwrap();
+        let (_, client) = login_as("secret");
+
+        let result = service.oidc_login("corp", "code", "state", client).await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn locked_account_cannot_sign_in_with_oidc() {
+        let user_id: UserId = Uuid::new_v4().into();
+        let mut credentials_repo = email_status(true);
+        credentials_repo
+            .expect_get_identity_user()
+            .returning(move |_, _| {
+                let result = Ok(Some(user_id));
+                Box::pin(async { result })
+            });
+        credentials_repo.expect_get_totp().never();
+        let mut login_attempts = MockLoginAttemptRepository::new();
+        login_attempts
+            .expect_locked_for()
+            .with(eq("account:user1@example.com"))
+            .returning(|_| Box::pin(async { Ok(Some(Duration::from_secs(120))) }));
+        let service = AuthServiceImp::build(
+            &settings(),
+            credentials_repo,
+            pending_oidc_login("corp"),
+            login_attempts,
+            MockUserEventBus::new(),
+            MockEmailSender::new(),
+            oidc_client(identity(true)),
+        )
+        .unwrap();
+
+        let result = service
+            .oidc_login("corp", "code", "state", ClientInfo::default())
+            .await;
+
+        assert!(matches!(result, Err(Error::TooManyRequests(_))));
     }
 }
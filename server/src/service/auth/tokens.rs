@@ -1,15 +1,46 @@
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use anyhow::{ensure, Context};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, Header, Validation};
 use secrecy::Secret;
 use serde::{Deserialize, Serialize};
 use shared::domain::UserId;
 use uuid::Uuid;
 
+use super::KeySet;
+
 pub const ALGORITHM: Algorithm = Algorithm::EdDSA;
 
+/// Kind of a token. Each endpoint accepts exactly one kind.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    Access,
+    Refresh,
+    /// Proves the password of a login that still needs its second factor.
+    #[serde(rename = "mfa_challenge")]
+    MfaChallenge,
+}
+
+/// Issuer and audience tokens are signed with and checked against.
+#[derive(Debug, Clone)]
+pub struct TokenScope {
+    pub issuer: String,
+    pub audience: String,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Claims {
     sub: Uuid,
+    typ: TokenType,
+    iss: String,
+    aud: String,
     token_id: Uuid,
+    /// Refresh token family the token was issued in. Tokens issued before families
+    /// existed decode with a nil family, which is never stored.
+    #[serde(default)]
+    family_id: Uuid,
+    /// Issue time, tokens from before `iat` existed decode as issued at the epoch.
+    #[serde(default)]
+    iat: u64,
     exp: u64,
 }
 
@@ -20,29 +51,70 @@ impl Claims {
     pub fn token_id(&self) -> Uuid {
         self.token_id
     }
+    pub fn family_id(&self) -> Uuid {
+        self.family_id
+    }
+    pub fn token_type(&self) -> TokenType {
+        self.typ
+    }
+    pub fn issued_at(&self) -> u64 {
+        self.iat
+    }
 }
 
 pub fn encode_token(
     user_id: &UserId,
     token_id: Uuid,
-    jwt_key: &EncodingKey,
+    family_id: Uuid,
+    typ: TokenType,
+    scope: &TokenScope,
+    keys: &KeySet,
     sec: u64,
 ) -> Result<Secret<String>, anyhow::Error> {
+    let now = jsonwebtoken::get_current_timestamp();
     let claims = Claims {
         sub: *user_id.as_ref(),
+        typ,
+        iss: scope.issuer.clone(),
+        aud: scope.audience.clone(),
         token_id,
-        exp: jsonwebtoken::get_current_timestamp() + sec,
+        family_id,
+        iat: now,
+        exp: now + sec,
     };
+    let (key_id, jwt_key) = keys.signing_key();
     let header = Header {
         alg: ALGORITHM,
+        kid: Some(key_id.to_string()),
         ..Default::default()
     };
     Ok(Secret::new(encode(&header, &claims, jwt_key)?))
 }
 
-pub fn decode_token(token: &str, jwt_key: &DecodingKey) -> Result<Claims, anyhow::Error> {
-    let validation = Validation::new(ALGORITHM);
+/// Decodes a token of the given kind, issued by and for `scope`, with the key named in
+/// its `kid` header.
+pub fn decode_token(
+    token: &str,
+    typ: TokenType,
+    scope: &TokenScope,
+    keys: &KeySet,
+) -> Result<Claims, anyhow::Error> {
+    let header = decode_header(token)?;
+    let jwt_key = keys
+        .decoding_key(header.kid.as_deref())
+        .with_context(|| format!("Unknown key id {:?}.", header.kid))?;
+
+    let mut validation = Validation::new(ALGORITHM);
+    validation.set_issuer(&[&scope.issuer]);
+    validation.set_audience(&[&scope.audience]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
     let decoded = decode::<Claims>(token, jwt_key, &validation)?;
+    ensure!(
+        decoded.claims.typ == typ,
+        "Expected {:?} token, got {:?}.",
+        typ,
+        decoded.claims.typ
+    );
     Ok(decoded.claims)
 }
 
@@ -56,22 +128,149 @@ mod tests {
     fn encode_decode_token() {
         let sub = Uuid::new_v4();
         let token_id = Uuid::new_v4();
-        let (encoding_key, decoding_key) = generate_keys();
+        let family_id = Uuid::new_v4();
+        let keys = generate_keys("current");
 
-        let token = encode_token(&sub.into(), token_id, &encoding_key, 20).unwrap();
-        let decoded = decode_token(token.expose_secret(), &decoding_key);
+        let token = encode_token(
+            &sub.into(),
+            token_id,
+            family_id,
+            TokenType::Access,
+            &scope(),
+            &keys,
+            20,
+        )
+        .unwrap();
+        let decoded = decode_token(token.expose_secret(), TokenType::Access, &scope(), &keys);
 
         let decoded = decoded.expect("Failed to decode token");
         assert_eq!(decoded.sub, sub);
+        assert_eq!(decoded.family_id, family_id);
+    }
+
+    #[test]
+    fn token_of_other_type_is_rejected() {
+        let keys = generate_keys("current");
+
+        let token = encode_token(
+            &Uuid::new_v4().into(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            TokenType::Refresh,
+            &scope(),
+            &keys,
+            20,
+        )
+        .unwrap();
+        let decoded = decode_token(token.expose_secret(), TokenType::Access, &scope(), &keys);
+
+        assert!(decoded.is_err());
+    }
+
+    #[test]
+    fn token_for_other_audience_is_rejected() {
+        let keys = generate_keys("current");
+        let other = TokenScope {
+            audience: "other-api".to_string(),
+            ..scope()
+        };
+
+        let token = encode_token(
+            &Uuid::new_v4().into(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            TokenType::Access,
+            &other,
+            &keys,
+            20,
+        )
+        .unwrap();
+        let decoded = decode_token(token.expose_secret(), TokenType::Access, &scope(), &keys);
+
+        assert!(decoded.is_err());
+    }
+
+    #[test]
+    fn token_signed_with_retired_key_is_accepted() {
+        let retired = jwt_simple::algorithms::Ed25519KeyPair::generate();
+        let current = jwt_simple::algorithms::Ed25519KeyPair::generate();
+        let old_keys = KeySet::new(
+            "retired",
+            retired.to_pem().as_bytes(),
+            &[(
+                "retired".to_string(),
+                retired.public_key().to_pem().into_bytes(),
+            )],
+        )
+        .unwrap();
+        let keys = KeySet::new(
+            "current",
+            current.to_pem().as_bytes(),
+            &[
+                (
+                    "current".to_string(),
+                    current.public_key().to_pem().into_bytes(),
+                ),
+                (
+                    "retired".to_string(),
+                    retired.public_key().to_pem().into_bytes(),
+                ),
+            ],
+        )
+        .unwrap();
+
+        let token = encode_token(
+            &Uuid::new_v4().into(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            TokenType::Access,
+            &scope(),
+            &old_keys,
+            20,
+        )
+        .unwrap();
+        let decoded = decode_token(token.expose_secret(), TokenType::Access, &scope(), &keys);
+
+        assert!(decoded.is_ok());
+    }
+
+    #[test]
+    fn token_signed_with_unknown_key_is_rejected() {
+        let keys = generate_keys("current");
+        let other = generate_keys("retired");
+
+        let token = encode_token(
+            &Uuid::new_v4().into(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            TokenType::Access,
+            &scope(),
+            &other,
+            20,
+        )
+        .unwrap();
+        let decoded = decode_token(token.expose_secret(), TokenType::Access, &scope(), &keys);
+
+        assert!(decoded.is_err());
+    }
+
+    fn scope() -> TokenScope {
+        TokenScope {
+            issuer: "chat".to_string(),
+            audience: "chat-api".to_string(),
+        }
     }
 
-    fn generate_keys() -> (EncodingKey, DecodingKey) {
+    fn generate_keys(key_id: &str) -> KeySet {
         let key_pair = jwt_simple::algorithms::Ed25519KeyPair::generate();
-        dbg!(&key_pair.to_pem().to_string());
-        dbg!(&key_pair.public_key().to_pem().to_string());
-        let encoding_key = EncodingKey::from_ed_pem(key_pair.to_pem().as_bytes()).unwrap();
-        let decoding_key =
-            DecodingKey::from_ed_pem(key_pair.public_key().to_pem().as_bytes()).unwrap();
-        (encoding_key, decoding_key)
+        KeySet::new(
+            key_id,
+            key_pair.to_pem().as_bytes(),
+            &[(
+                key_id.to_string(),
+                key_pair.public_key().to_pem().into_bytes(),
+            )],
+        )
+        .unwrap()
     }
 }
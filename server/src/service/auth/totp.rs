@@ -0,0 +1,152 @@
+//! RFC 6238 time-based one-time passwords, as generated by authenticator apps.
+
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng, RngCore};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+/// Seconds a code is valid for.
+const STEP: u64 = 30;
+const DIGITS: u32 = 6;
+/// Steps around the current one whose codes are accepted, for clock drift.
+const SKEW: u64 = 1;
+/// 160 bits, the length RFC 4226 recommends.
+const SECRET_LENGTH: usize = 20;
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; SECRET_LENGTH];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+/// Base32 form of the secret, for entering it by hand.
+pub fn encode_secret(secret: &[u8]) -> String {
+    BASE32_NOPAD.encode(secret)
+}
+
+/// `otpauth://` URI that authenticator apps read from a QR code.
+pub fn provisioning_uri(issuer: &str, account: &str, secret: &[u8]) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP}",
+        issuer = percent_encode(issuer),
+        account = percent_encode(account),
+        secret = encode_secret(secret),
+    )
+}
+
+/// Time step of `code` if it is valid at `now`, in seconds since the epoch.
+pub fn verify(secret: &[u8], code: &str, now: u64) -> Option<u64> {
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let current = now / STEP;
+    (current.saturating_sub(SKEW)..=current + SKEW).find(|step| code_at(secret, *step) == code)
+}
+
+/// Whether `code` has the shape of a TOTP code rather than of a recovery code.
+pub fn is_totp_code(code: &str) -> bool {
+    code.len() == DIGITS as usize && code.bytes().all(|b| b.is_ascii_digit())
+}
+
+pub fn code_at(secret: &[u8], step: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 10u32.pow(DIGITS)
+}
+
+/// Single-use codes that sign in when the authenticator is lost.
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = (&mut rng)
+                .sample_iter(&Alphanumeric)
+                .take(RECOVERY_CODE_LENGTH)
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// Recovery codes are random enough that a plain hash keeps them safe at rest.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    data_encoding::HEXLOWER.encode(&Sha256::digest(normalized.as_bytes()))
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(b).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Secret of the SHA-1 test vectors in RFC 6238, appendix B.
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn codes_match_rfc_test_vectors() {
+        assert_eq!(verify(RFC_SECRET, "287082", 59), Some(1));
+        assert_eq!(verify(RFC_SECRET, "081804", 1111111109), Some(37037036));
+        assert_eq!(verify(RFC_SECRET, "005924", 1234567890), Some(41152263));
+    }
+
+    #[test]
+    fn codes_of_neighbouring_steps_only_are_accepted() {
+        let now = 1234567890;
+        let code = format!("{:06}", code_at(RFC_SECRET, now / STEP - 1));
+        let stale = format!("{:06}", code_at(RFC_SECRET, now / STEP - 2));
+
+        assert!(verify(RFC_SECRET, &code, now).is_some());
+        assert!(verify(RFC_SECRET, &stale, now).is_none());
+    }
+
+    #[test]
+    fn provisioning_uri_encodes_labels() {
+        let uri = provisioning_uri("My Chat", "user1@example.com", RFC_SECRET);
+
+        assert_eq!(
+            uri,
+            "otpauth://totp/My%20Chat:user1%40example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+            &issuer=My%20Chat&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+
+    #[test]
+    fn recovery_code_hash_ignores_formatting() {
+        let code = &generate_recovery_codes()[0];
+
+        assert_eq!(
+            hash_recovery_code(code),
+            hash_recovery_code(&code.replace('-', "").to_uppercase())
+        );
+        assert!(!is_totp_code(code));
+    }
+}
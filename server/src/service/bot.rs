@@ -0,0 +1,880 @@
+use anyhow::{anyhow, Context};
+use argon2::Params;
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::configuration::Argon2Settings;
+use crate::service::{
+    announce, compute_password_hash, spawn_blocking_with_tracing, Error, HookEvent,
+    OutgoingWebhookService, SECURITY_AUDIT,
+};
+use shared::domain::{RoomId, User, UserCode, UserEmail, UserId, UserName};
+
+/// Start of every API key, tells them apart from JWTs and makes leaked keys easy to spot.
+pub const API_KEY_PREFIX: &str = "chat_bot_";
+
+/// Random bytes in an API key.
+const API_KEY_LENGTH: usize = 32;
+
+/// Characters of a key kept in clear, to tell the keys of a bot apart.
+const API_KEY_DISPLAY_LENGTH: usize = API_KEY_PREFIX.len() + 6;
+
+/// Domain of the placeholder emails bots are created with, reserved to never resolve.
+const BOT_EMAIL_DOMAIN: &str = "bots.invalid";
+
+#[derive(Clone)]
+pub struct BotServiceImp<BotRepo, Hooks> {
+    bot_repo: BotRepo,
+    hooks: Hooks,
+    password_params: Params,
+}
+
+impl<BotRepo, Hooks> BotServiceImp<BotRepo, Hooks>
+where
+    BotRepo: BotRepository,
+    Hooks: OutgoingWebhookService,
+{
+    pub fn new(
+        password: &Argon2Settings,
+        bot_repo: BotRepo,
+        hooks: Hooks,
+    ) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            bot_repo,
+            hooks,
+            password_params: password.params()?,
+        })
+    }
+}
+
+/// What requests made with an API key may do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyScope {
+    pub room_ids: Vec<RoomId>,
+    pub send: bool,
+    pub read: bool,
+}
+
+impl ApiKeyScope {
+    pub fn require_send(&self, room_id: &RoomId) -> Result<(), Error> {
+        self.require(self.send, room_id, "send messages")
+    }
+
+    pub fn require_read(&self, room_id: &RoomId) -> Result<(), Error> {
+        self.require(self.read, room_id, "read messages")
+    }
+
+    fn require(&self, allowed: bool, room_id: &RoomId, action: &str) -> Result<(), Error> {
+        if allowed && self.room_ids.contains(room_id) {
+            Ok(())
+        } else {
+            Err(Error::Forbidden(format!(
+                "API key may not {} in this room.",
+                action
+            )))
+        }
+    }
+}
+
+/// API key as stored, only its hash is kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub key_id: Uuid,
+    pub bot_id: UserId,
+    /// Start of the key, kept in clear.
+    pub prefix: String,
+    pub scope: ApiKeyScope,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// URL a service posts to, to send messages as a bot into one room.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    pub webhook_id: Uuid,
+    pub room_id: RoomId,
+    pub bot_id: UserId,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait BotRepository {
+    async fn is_admin(&self, user_id: &UserId) -> Result<bool, anyhow::Error>;
+
+    async fn is_bot(&self, user_id: &UserId) -> Result<bool, anyhow::Error>;
+
+    async fn create_bot(
+        &self,
+        name: &UserName,
+        email: &UserEmail,
+        password_hash: Secret<String>,
+        code: &UserCode,
+    ) -> Result<User, anyhow::Error>;
+
+    /// The bot becomes a member of the rooms of the scope, to post like anyone else.
+    /// Returns the key and the rooms the bot was not a member of yet.
+    async fn create_api_key(
+        &self,
+        bot_id: &UserId,
+        key_hash: &str,
+        prefix: &str,
+        scope: &ApiKeyScope,
+    ) -> Result<(ApiKey, Vec<RoomId>), anyhow::Error>;
+
+    /// Rooms of `room_ids` that do not exist.
+    async fn missing_rooms(&self, room_ids: &[RoomId]) -> Result<Vec<RoomId>, anyhow::Error>;
+
+    /// Key with the hash, revoked ones included.
+    async fn get_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, anyhow::Error>;
+
+    async fn list_api_keys(&self, bot_id: &UserId) -> Result<Vec<ApiKey>, anyhow::Error>;
+
+    /// Returns whether an active key of the bot was revoked.
+    async fn revoke_api_key(&self, bot_id: &UserId, key_id: &Uuid) -> Result<bool, anyhow::Error>;
+
+    /// The bot becomes a member of the room, to post like anyone else. Returns the webhook
+    /// and whether the bot was not a member of the room yet.
+    async fn create_webhook(
+        &self,
+        room_id: &RoomId,
+        bot_id: &UserId,
+        token_hash: &str,
+    ) -> Result<(Webhook, bool), anyhow::Error>;
+
+    /// Webhook with the id and token hash, revoked ones included.
+    async fn get_webhook(
+        &self,
+        webhook_id: &Uuid,
+        token_hash: &str,
+    ) -> Result<Option<Webhook>, anyhow::Error>;
+
+    async fn list_webhooks(&self, room_id: &RoomId) -> Result<Vec<Webhook>, anyhow::Error>;
+
+    /// Returns whether an active webhook of the room was revoked.
+    async fn revoke_webhook(
+        &self,
+        room_id: &RoomId,
+        webhook_id: &Uuid,
+    ) -> Result<bool, anyhow::Error>;
+}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait BotService {
+    /// Creates a user that cannot log in and acts through API keys. Admins only.
+    async fn create_bot(&self, admin_id: &UserId, name: UserName) -> Result<User, Error>;
+
+    /// Issues a key for the bot. The key is only ever returned here. Admins only.
+    async fn create_api_key(
+        &self,
+        admin_id: &UserId,
+        bot_id: &UserId,
+        scope: ApiKeyScope,
+    ) -> Result<(ApiKey, Secret<String>), Error>;
+
+    async fn list_api_keys(&self, admin_id: &UserId, bot_id: &UserId)
+        -> Result<Vec<ApiKey>, Error>;
+
+    async fn revoke_api_key(
+        &self,
+        admin_id: &UserId,
+        bot_id: &UserId,
+        key_id: &Uuid,
+    ) -> Result<(), Error>;
+
+    /// Finds the active key a request was made with.
+    async fn authenticate(&self, key: &str) -> Result<ApiKey, Error>;
+
+    /// Adds a webhook posting as the bot into the room. The token is only ever returned
+    /// here. Admins only.
+    async fn create_webhook(
+        &self,
+        admin_id: &UserId,
+        room_id: &RoomId,
+        bot_id: &UserId,
+    ) -> Result<(Webhook, Secret<String>), Error>;
+
+    async fn list_webhooks(
+        &self,
+        admin_id: &UserId,
+        room_id: &RoomId,
+    ) -> Result<Vec<Webhook>, Error>;
+
+    async fn revoke_webhook(
+        &self,
+        admin_id: &UserId,
+        room_id: &RoomId,
+        webhook_id: &Uuid,
+    ) -> Result<(), Error>;
+
+    /// Finds the active webhook a request was posted to.
+    async fn authenticate_webhook(&self, webhook_id: &Uuid, token: &str) -> Result<Webhook, Error>;
+}
+
+impl<BotRepo, Hooks> BotServiceImp<BotRepo, Hooks>
+where
+    BotRepo: BotRepository + Send + Sync,
+{
+    async fn require_admin(&self, user_id: &UserId) -> Result<(), Error> {
+        if self.bot_repo.is_admin(user_id).await? {
+            Ok(())
+        } else {
+            Err(Error::Forbidden("Admins only".to_string()))
+        }
+    }
+
+    async fn require_bot(&self, bot_id: &UserId) -> Result<(), Error> {
+        if self.bot_repo.is_bot(bot_id).await? {
+            Ok(())
+        } else {
+            Err(Error::NotFound("bot not found".to_string()))
+        }
+    }
+}
+
+#[async_trait]
+impl<BotRepo, Hooks> BotService for BotServiceImp<BotRepo, Hooks>
+where
+    BotRepo: BotRepository + Send + Sync,
+    Hooks: OutgoingWebhookService + Send + Sync,
+{
+    #[tracing::instrument(name = "Create bot", skip(self))]
+    async fn create_bot(&self, admin_id: &UserId, name: UserName) -> Result<User, Error> {
+        self.require_admin(admin_id).await?;
+
+        let email: UserEmail = format!("{}@{}", Uuid::new_v4(), BOT_EMAIL_DOMAIN)
+            .parse()
+            .context("Invalid bot email.")?;
+        let code: UserCode = random_key().parse().context("Invalid user code.")?;
+        // Nobody knows the password, bots only authenticate with their keys.
+        let password = Secret::new(random_key());
+        let params = self.password_params.clone();
+        let password_hash =
+            spawn_blocking_with_tracing(move || compute_password_hash(password, params))
+                .await
+                .map_err(|e| Error::UnexpectedError(e.into()))??;
+
+        let bot = self
+            .bot_repo
+            .create_bot(&name, &email, password_hash, &code)
+            .await?;
+
+        tracing::info!(
+            target: SECURITY_AUDIT,
+            event = "bot_created",
+            bot_id = %bot.user_id.as_ref(),
+            admin_id = %admin_id.as_ref(),
+            "Bot created"
+        );
+        Ok(bot)
+    }
+
+    #[tracing::instrument(name = "Create API key", skip(self))]
+    async fn create_api_key(
+        &self,
+        admin_id: &UserId,
+        bot_id: &UserId,
+        scope: ApiKeyScope,
+    ) -> Result<(ApiKey, Secret<String>), Error> {
+        self.require_admin(admin_id).await?;
+        self.require_bot(bot_id).await?;
+        if scope.room_ids.is_empty() || !(scope.send || scope.read) {
+            return Err(Error::ValidationError(
+                "API key must allow sending or reading in at least one room.".to_string(),
+            ));
+        }
+        let missing = self.bot_repo.missing_rooms(&scope.room_ids).await?;
+        if let Some(room_id) = missing.first() {
+            return Err(Error::ValidationError(format!(
+                "Room {} does not exist.",
+                room_id.as_ref()
+            )));
+        }
+
+        let key = format!("{}{}", API_KEY_PREFIX, random_key());
+        let (api_key, joined) = self
+            .bot_repo
+            .create_api_key(
+                bot_id,
+                &hash_secret(&key),
+                &key[..API_KEY_DISPLAY_LENGTH],
+                &scope,
+            )
+            .await?;
+
+        tracing::info!(
+            target: SECURITY_AUDIT,
+            event = "api_key_created",
+            key_id = %api_key.key_id,
+            bot_id = %bot_id.as_ref(),
+            admin_id = %admin_id.as_ref(),
+            "API key created"
+        );
+        for room_id in &joined {
+            announce(&self.hooks, room_id, HookEvent::MemberJoined(*bot_id)).await;
+        }
+        Ok((api_key, Secret::new(key)))
+    }
+
+    async fn list_api_keys(
+        &self,
+        admin_id: &UserId,
+        bot_id: &UserId,
+    ) -> Result<Vec<ApiKey>, Error> {
+        self.require_admin(admin_id).await?;
+        self.require_bot(bot_id).await?;
+        self.bot_repo
+            .list_api_keys(bot_id)
+            .await
+            .map_err(Error::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Revoke API key", skip(self))]
+    async fn revoke_api_key(
+        &self,
+        admin_id: &UserId,
+        bot_id: &UserId,
+        key_id: &Uuid,
+    ) -> Result<(), Error> {
+        self.require_admin(admin_id).await?;
+        if !self.bot_repo.revoke_api_key(bot_id, key_id).await? {
+            return Err(Error::NotFound("API key not found".to_string()));
+        }
+
+        tracing::info!(
+            target: SECURITY_AUDIT,
+            event = "api_key_revoked",
+            key_id = %key_id,
+            bot_id = %bot_id.as_ref(),
+            admin_id = %admin_id.as_ref(),
+            "API key revoked"
+        );
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Authenticate API key", skip(self, key))]
+    async fn authenticate(&self, key: &str) -> Result<ApiKey, Error> {
+        if !key.starts_with(API_KEY_PREFIX) {
+            return Err(Error::InvalidCredentials(anyhow!("Not an API key.")));
+        }
+
+        let api_key = self
+            .bot_repo
+            .get_api_key(&hash_secret(key))
+            .await?
+            .ok_or_else(|| Error::InvalidCredentials(anyhow!("Unknown API key.")))?;
+        if api_key.revoked_at.is_some() {
+            tracing::warn!(
+                target: SECURITY_AUDIT,
+                event = "revoked_api_key_used",
+                key_id = %api_key.key_id,
+                bot_id = %api_key.bot_id.as_ref(),
+                "Revoked API key used"
+            );
+            return Err(Error::InvalidCredentials(anyhow!("API key was revoked.")));
+        }
+        Ok(api_key)
+    }
+
+    #[tracing::instrument(name = "Create webhook", skip(self))]
+    async fn create_webhook(
+        &self,
+        admin_id: &UserId,
+        room_id: &RoomId,
+        bot_id: &UserId,
+    ) -> Result<(Webhook, Secret<String>), Error> {
+        self.require_admin(admin_id).await?;
+        self.require_bot(bot_id).await?;
+        if !self.bot_repo.missing_rooms(&[*room_id]).await?.is_empty() {
+            return Err(Error::NotFound("Room not found.".to_string()));
+        }
+
+        let token = random_key();
+        let (webhook, joined) = self
+            .bot_repo
+            .create_webhook(room_id, bot_id, &hash_secret(&token))
+            .await?;
+
+        tracing::info!(
+            target: SECURITY_AUDIT,
+            event = "webhook_created",
+            webhook_id = %webhook.webhook_id,
+            room_id = %room_id.as_ref(),
+            bot_id = %bot_id.as_ref(),
+            admin_id = %admin_id.as_ref(),
+            "Webhook created"
+        );
+        if joined {
+            announce(&self.hooks, room_id, HookEvent::MemberJoined(*bot_id)).await;
+        }
+        Ok((webhook, Secret::new(token)))
+    }
+
+    async fn list_webhooks(
+        &self,
+        admin_id: &UserId,
+        room_id: &RoomId,
+    ) -> Result<Vec<Webhook>, Error> {
+        self.require_admin(admin_id).await?;
+        self.bot_repo
+            .list_webhooks(room_id)
+            .await
+            .map_err(Error::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Revoke webhook", skip(self))]
+    async fn revoke_webhook(
+        &self,
+        admin_id: &UserId,
+        room_id: &RoomId,
+        webhook_id: &Uuid,
+    ) -> Result<(), Error> {
+        self.require_admin(admin_id).await?;
+        if !self.bot_repo.revoke_webhook(room_id, webhook_id).await? {
+            return Err(Error::NotFound("webhook not found".to_string()));
+        }
+
+        tracing::info!(
+            target: SECURITY_AUDIT,
+            event = "webhook_revoked",
+            webhook_id = %webhook_id,
+            room_id = %room_id.as_ref(),
+            admin_id = %admin_id.as_ref(),
+            "Webhook revoked"
+        );
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Authenticate webhook", skip(self, token))]
+    async fn authenticate_webhook(&self, webhook_id: &Uuid, token: &str) -> Result<Webhook, Error> {
+        let webhook = self
+            .bot_repo
+            .get_webhook(webhook_id, &hash_secret(token))
+            .await?
+            .ok_or_else(|| Error::InvalidCredentials(anyhow!("Unknown webhook.")))?;
+        if webhook.revoked_at.is_some() {
+            tracing::warn!(
+                target: SECURITY_AUDIT,
+                event = "revoked_webhook_used",
+                webhook_id = %webhook.webhook_id,
+                room_id = %webhook.room_id.as_ref(),
+                "Revoked webhook used"
+            );
+            return Err(Error::InvalidCredentials(anyhow!("Webhook was revoked.")));
+        }
+        Ok(webhook)
+    }
+}
+
+/// Keys and webhook tokens are random enough that a plain hash keeps them safe at rest.
+fn hash_secret(secret: &str) -> String {
+    data_encoding::HEXLOWER.encode(&Sha256::digest(secret.as_bytes()))
+}
+
+fn random_key() -> String {
+    let mut bytes = [0u8; API_KEY_LENGTH];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::outgoing_webhook::MockOutgoingWebhookService;
+    use mockall::predicate::eq;
+    use secrecy::ExposeSecret;
+    use std::sync::{Arc, Mutex};
+
+    fn ids() -> (UserId, UserId, RoomId) {
+        (
+            Uuid::new_v4().into(),
+            Uuid::new_v4().into(),
+            Uuid::new_v4().into(),
+        )
+    }
+
+    fn api_key(bot_id: UserId, room_id: RoomId) -> ApiKey {
+        ApiKey {
+            key_id: Uuid::new_v4(),
+            bot_id,
+            prefix: "chat_bot_abcdef".to_string(),
+            scope: ApiKeyScope {
+                room_ids: vec![room_id],
+                send: true,
+                read: false,
+            },
+            created_at: Utc::now(),
+            revoked_at: None,
+        }
+    }
+
+    fn service(
+        repo: MockBotRepository,
+    ) -> BotServiceImp<MockBotRepository, MockOutgoingWebhookService> {
+        let mut hooks = MockOutgoingWebhookService::new();
+        hooks
+            .expect_publish()
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        service_with_hooks(repo, hooks)
+    }
+
+    fn service_with_hooks(
+        repo: MockBotRepository,
+        hooks: MockOutgoingWebhookService,
+    ) -> BotServiceImp<MockBotRepository, MockOutgoingWebhookService> {
+        let password = Argon2Settings {
+            memory_kib: 1024,
+            iterations: 1,
+            parallelism: 1,
+        };
+        BotServiceImp::new(&password, repo, hooks).unwrap()
+    }
+
+    fn admin_repo(admin_id: UserId, bot_id: UserId) -> MockBotRepository {
+        let mut repo = MockBotRepository::new();
+        repo.expect_is_admin().returning(move |id| {
+            let result = Ok(*id == admin_id);
+            Box::pin(async { result })
+        });
+        repo.expect_is_bot().returning(move |id| {
+            let result = Ok(*id == bot_id);
+            Box::pin(async { result })
+        });
+        repo
+    }
+
+    #[tokio::test]
+    async fn issued_key_is_stored_hashed_and_authenticates() {
+        let (admin_id, bot_id, room_id) = ids();
+        let stored = Arc::new(Mutex::new(None));
+        let mut repo = admin_repo(admin_id, bot_id);
+        repo.expect_missing_rooms()
+            .returning(|_| Box::pin(async { Ok(Vec::new()) }));
+        let store = stored.clone();
+        repo.expect_create_api_key()
+            .returning(move |bot_id, key_hash, prefix, scope| {
+                let mut key = api_key(*bot_id, scope.room_ids[0]);
+                key.prefix = prefix.to_string();
+                *store.lock().unwrap() = Some((key_hash.to_string(), key.clone()));
+                let result = Ok((key, scope.room_ids.clone()));
+                Box::pin(async { result })
+            });
+        let lookup = stored.clone();
+        repo.expect_get_api_key().returning(move |key_hash| {
+            let result = Ok(lookup
+                .lock()
+                .unwrap()
+                .clone()
+                .filter(|(hash, _)| hash == key_hash)
+                .map(|(_, key)| key));
+            Box::pin(async { result })
+        });
+        let service = service(repo);
+        let scope = ApiKeyScope {
+            room_ids: vec![room_id],
+            send: true,
+            read: false,
+        };
+
+        let (created, key) = service
+            .create_api_key(&admin_id, &bot_id, scope)
+            .await
+            .unwrap();
+
+        let key = key.expose_secret();
+        assert!(key.starts_with(&created.prefix));
+        assert_ne!(stored.lock().unwrap().as_ref().unwrap().0, *key);
+        let authenticated = service.authenticate(key).await.unwrap();
+        assert_eq!(authenticated, created);
+        assert!(service.authenticate("chat_bot_guess").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn revoked_key_is_rejected() {
+        let (_, bot_id, room_id) = ids();
+        let mut repo = MockBotRepository::new();
+        repo.expect_get_api_key().returning(move |_| {
+            let mut key = api_key(bot_id, room_id);
+            key.revoked_at = Some(Utc::now());
+            let result = Ok(Some(key));
+            Box::pin(async { result })
+        });
+        let service = service(repo);
+
+        let result = service.authenticate("chat_bot_revoked").await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn tokens_without_key_prefix_are_not_looked_up() {
+        let mut repo = MockBotRepository::new();
+        repo.expect_get_api_key().never();
+        let service = service(repo);
+
+        let result = service.authenticate("eyJhbGciOiJFZERTQSJ9.e30.sig").await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn only_admins_manage_keys() {
+        let (admin_id, bot_id, room_id) = ids();
+        let mut repo = admin_repo(admin_id, bot_id);
+        repo.expect_create_api_key().never();
+        repo.expect_revoke_api_key().never();
+        let service = service(repo);
+        let scope = ApiKeyScope {
+            room_ids: vec![room_id],
+            send: true,
+            read: true,
+        };
+
+        let created = service.create_api_key(&bot_id, &bot_id, scope).await;
+        let revoked = service
+            .revoke_api_key(&bot_id, &bot_id, &Uuid::new_v4())
+            .await;
+
+        assert!(matches!(created, Err(Error::Forbidden(_))));
+        assert!(matches!(revoked, Err(Error::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn keys_are_only_issued_to_bots() {
+        let (admin_id, bot_id, room_id) = ids();
+        let mut repo = admin_repo(admin_id, bot_id);
+        repo.expect_create_api_key().never();
+        let service = service(repo);
+        let scope = ApiKeyScope {
+            room_ids: vec![room_id],
+            send: true,
+            read: true,
+        };
+
+        let result = service.create_api_key(&admin_id, &admin_id, scope).await;
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn keys_for_missing_rooms_are_rejected() {
+        let (admin_id, bot_id, room_id) = ids();
+        let mut repo = admin_repo(admin_id, bot_id);
+        repo.expect_missing_rooms()
+            .with(eq(vec![room_id]))
+            .returning(|room_ids| {
+                let result = Ok(room_ids.to_vec());
+                Box::pin(async { result })
+            });
+        repo.expect_create_api_key().never();
+        let service = service(repo);
+        let scope = ApiKeyScope {
+            room_ids: vec![room_id],
+            send: true,
+            read: true,
+        };
+
+        let result = service.create_api_key(&admin_id, &bot_id, scope).await;
+
+        assert!(matches!(result, Err(Error::ValidationError(_))));
+    }
+
+    #[tokio::test]
+    async fn webhooks_for_missing_rooms_are_not_found() {
+        let (admin_id, bot_id, room_id) = ids();
+        let mut repo = admin_repo(admin_id, bot_id);
+        repo.expect_missing_rooms()
+            .with(eq(vec![room_id]))
+            .returning(|room_ids| {
+                let result = Ok(room_ids.to_vec());
+                Box::pin(async { result })
+            });
+        repo.expect_create_webhook().never();
+        let service = service(repo);
+
+        let result = service.create_webhook(&admin_id, &room_id, &bot_id).await;
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn bots_are_announced_only_to_rooms_they_joined() {
+        let (admin_id, bot_id, room_id) = ids();
+        let member_of: RoomId = Uuid::new_v4().into();
+        let mut repo = admin_repo(admin_id, bot_id);
+        repo.expect_missing_rooms()
+            .returning(|_| Box::pin(async { Ok(Vec::new()) }));
+        repo.expect_create_api_key()
+            .returning(move |bot_id, _, _, _| {
+                let result = Ok((api_key(*bot_id, room_id), vec![room_id]));
+                Box::pin(async { result })
+            });
+        let mut hooks = MockOutgoingWebhookService::new();
+        hooks
+            .expect_publish()
+            .withf(move |joined, event| {
+                *joined == room_id
+                    && matches!(event, HookEvent::MemberJoined(user_id) if *user_id == bot_id)
+            })
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        let service = service_with_hooks(repo, hooks);
+        let scope = ApiKeyScope {
+            room_ids: vec![room_id, member_of],
+            send: true,
+            read: false,
+        };
+
+        service
+            .create_api_key(&admin_id, &bot_id, scope)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn bot_password_is_hashed_with_configured_params() {
+        let (admin_id, bot_id, _) = ids();
+        let mut repo = admin_repo(admin_id, bot_id);
+        repo.expect_create_bot()
+            .withf(|_, _, password_hash, _| {
+                password_hash.expose_secret().contains("$m=1024,t=1,p=1$")
+            })
+            .times(1)
+            .returning(move |name, email, _, code| {
+                let result = Ok(User {
+                    user_id: bot_id,
+                    name: name.clone(),
+                    email: email.as_ref().parse().unwrap(),
+                    code: code.as_ref().parse().unwrap(),
+                    created_at: Utc::now(),
+                });
+                Box::pin(async { result })
+            });
+        let service = service(repo);
+
+        let result = service.create_bot(&admin_id, "ci".parse().unwrap()).await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn unknown_key_is_not_revoked() {
+        let (admin_id, bot_id, _) = ids();
+        let key_id = Uuid::new_v4();
+        let mut repo = admin_repo(admin_id, bot_id);
+        repo.expect_revoke_api_key()
+            .with(eq(bot_id), eq(key_id))
+            .returning(|_, _| Box::pin(async { Ok(false) }));
+        let service = service(repo);
+
+        let result = service.revoke_api_key(&admin_id, &bot_id, &key_id).await;
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn webhook_token_is_stored_hashed_and_authenticates() {
+        let (admin_id, bot_id, room_id) = ids();
+        let stored = Arc::new(Mutex::new(None));
+        let mut repo = admin_repo(admin_id, bot_id);
+        repo.expect_missing_rooms()
+            .returning(|_| Box::pin(async { Ok(Vec::new()) }));
+        let store = stored.clone();
+        repo.expect_create_webhook()
+            .returning(move |room_id, bot_id, token_hash| {
+                let webhook = Webhook {
+                    webhook_id: Uuid::new_v4(),
+                    room_id: *room_id,
+                    bot_id: *bot_id,
+                    created_at: Utc::now(),
+                    revoked_at: None,
+                };
+                *store.lock().unwrap() = Some((token_hash.to_string(), webhook.clone()));
+                let result = Ok((webhook, true));
+                Box::pin(async { result })
+            });
+        let lookup = stored.clone();
+        repo.expect_get_webhook()
+            .returning(move |webhook_id, token_hash| {
+                let result = Ok(lookup
+                    .lock()
+                    .unwrap()
+                    .clone()
+                    .filter(|(hash, webhook)| {
+                        hash == token_hash && webhook.webhook_id == *webhook_id
+                    })
+                    .map(|(_, webhook)| webhook));
+                Box::pin(async { result })
+            });
+        let service = service(repo);
+
+        let (created, token) = service
+            .create_webhook(&admin_id, &room_id, &bot_id)
+            .await
+            .unwrap();
+
+        let token = token.expose_secret();
+        assert_ne!(stored.lock().unwrap().as_ref().unwrap().0, *token);
+        let webhook = service
+            .authenticate_webhook(&created.webhook_id, token)
+            .await
+            .unwrap();
+        assert_eq!(webhook, created);
+        let other_webhook = service.authenticate_webhook(&Uuid::new_v4(), token).await;
+        assert!(matches!(other_webhook, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn revoked_webhook_is_rejected() {
+        let (_, bot_id, room_id) = ids();
+        let mut repo = MockBotRepository::new();
+        repo.expect_get_webhook().returning(move |webhook_id, _| {
+            let result = Ok(Some(Webhook {
+                webhook_id: *webhook_id,
+                room_id,
+                bot_id,
+                created_at: Utc::now(),
+                revoked_at: Some(Utc::now()),
+            }));
+            Box::pin(async { result })
+        });
+        let service = service(repo);
+
+        let result = service.authenticate_webhook(&Uuid::new_v4(), "token").await;
+
+        assert!(matches!(result, Err(Error::InvalidCredentials(_))));
+    }
+
+    #[tokio::test]
+    async fn only_admins_manage_webhooks() {
+        let (admin_id, bot_id, room_id) = ids();
+        let mut repo = admin_repo(admin_id, bot_id);
+        repo.expect_create_webhook().never();
+        repo.expect_list_webhooks().never();
+        let service = service(repo);
+
+        let created = service.create_webhook(&bot_id, &room_id, &bot_id).await;
+        let listed = service.list_webhooks(&bot_id, &room_id).await;
+
+        assert!(matches!(created, Err(Error::Forbidden(_))));
+        assert!(matches!(listed, Err(Error::Forbidden(_))));
+    }
+
+    #[test]
+    fn scope_limits_rooms_and_actions() {
+        let (_, bot_id, room_id) = ids();
+        let other_room: RoomId = Uuid::new_v4().into();
+        let scope = api_key(bot_id, room_id).scope;
+
+        assert!(scope.require_send(&room_id).is_ok());
+        assert!(scope.require_send(&other_room).is_err());
+        assert!(scope.require_read(&room_id).is_err());
+    }
+}
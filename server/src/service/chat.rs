@@ -1,19 +1,64 @@
+use std::str::FromStr;
+
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::service::{announce, Error, HookEvent, OutgoingWebhookService};
+use shared::domain::{
+    AttachmentId, Message, MessageContent, NewMessage, Room, RoomDescription, RoomId, RoomTopic,
+    User, UserId,
+};
+
+const MESSAGES_PAGE_SIZE: i64 = 100;
+const MAX_AVATAR_SIZE: usize = 512 * 1024;
+const AVATAR_CONTENT_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// An uploaded file, served as is with its content type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
 
-use crate::service::Error;
-use shared::domain::{RoomId, User, UserId};
+/// A change to the details of a room, `None` clears the detail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoomChange {
+    Topic(Option<RoomTopic>),
+    Description(Option<RoomDescription>),
+    Avatar(Option<Attachment>),
+}
+
+impl RoomChange {
+    /// Text of the system message recording the change, after the name of its author.
+    fn summary(&self) -> &'static str {
+        match self {
+            Self::Topic(Some(_)) => "changed the room topic",
+            Self::Topic(None) => "cleared the room topic",
+            Self::Description(Some(_)) => "changed the room description",
+            Self::Description(None) => "cleared the room description",
+            Self::Avatar(Some(_)) => "changed the room avatar",
+            Self::Avatar(None) => "removed the room avatar",
+        }
+    }
+}
 
 #[derive(Clone)]
-pub struct ChatServiceImp<ChatRepo> {
+pub struct ChatServiceImp<ChatRepo, Hooks> {
     chat_repo: ChatRepo,
+    hooks: Hooks,
 }
 
-impl<ChatRepo> ChatServiceImp<ChatRepo>
+impl<ChatRepo, Hooks> ChatServiceImp<ChatRepo, Hooks>
 where
     ChatRepo: ChatRepository,
+    Hooks: OutgoingWebhookService,
 {
-    pub fn new(chat_repo: ChatRepo) -> Self {
-        Self { chat_repo }
+    pub fn new(chat_repo: ChatRepo, hooks: Hooks) -> Self {
+        Self { chat_repo, hooks }
+    }
+
+    async fn publish(&self, room_id: &RoomId, event: HookEvent) {
+        announce(&self.hooks, room_id, event).await;
     }
 }
 
@@ -27,6 +72,56 @@ pub trait ChatRepository {
         room_id: &RoomId,
         user_id: &UserId,
     ) -> Result<Option<String>, anyhow::Error>;
+
+    async fn create_message(&self, new_message: &NewMessage) -> Result<Message, anyhow::Error>;
+
+    /// End of the member's mute, `None` when they are not muted.
+    async fn muted_until(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+    ) -> Result<Option<DateTime<Utc>>, anyhow::Error>;
+
+    /// The latest `limit` messages of the room, oldest first.
+    async fn get_messages(
+        &self,
+        room_id: &RoomId,
+        limit: i64,
+    ) -> Result<Vec<Message>, anyhow::Error>;
+
+    async fn get_user_rooms(&self, user_id: &UserId) -> Result<Vec<Room>, anyhow::Error>;
+
+    async fn get_room(&self, room_id: &RoomId) -> Result<Option<Room>, anyhow::Error>;
+
+    /// Whether the user is an admin of the room or of the whole server.
+    async fn can_manage_room(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+    ) -> Result<bool, anyhow::Error>;
+
+    /// Applies the change and stores `record` as a system message, atomically. `None`
+    /// when the room does not exist.
+    async fn update_room(
+        &self,
+        change: &RoomChange,
+        record: &NewMessage,
+    ) -> Result<Option<(Room, Message)>, anyhow::Error>;
+
+    /// Only attachments of rooms the user is a member of.
+    async fn get_attachment(
+        &self,
+        attachment_id: &AttachmentId,
+        user_id: &UserId,
+    ) -> Result<Option<Attachment>, anyhow::Error>;
+
+    /// Returns whether the user is a member of the room.
+    async fn set_room_admin(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        is_admin: bool,
+    ) -> Result<bool, anyhow::Error>;
 }
 
 #[async_trait]
@@ -38,12 +133,43 @@ pub trait ChatService {
         room_id: &RoomId,
         user_id: &UserId,
     ) -> Result<Option<String>, Error>;
+    async fn create_message(&self, new_message: NewMessage) -> Result<Message, Error>;
+    async fn get_messages(&self, room_id: &RoomId) -> Result<Vec<Message>, Error>;
+    async fn get_user_rooms(&self, user_id: &UserId) -> Result<Vec<Room>, Error>;
+    /// Members only.
+    async fn get_room(&self, user_id: &UserId, room_id: &RoomId) -> Result<Room, Error>;
+    /// Room admins only. Returns the updated room and the system message recording the change.
+    async fn update_room(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+        change: RoomChange,
+    ) -> Result<(Room, Message), Error>;
+    /// Members of a room using the attachment only.
+    async fn get_attachment(
+        &self,
+        user_id: &UserId,
+        attachment_id: &AttachmentId,
+    ) -> Result<Attachment, Error>;
+    /// Makes a member an admin of the room, or a regular member again. Room admins only.
+    async fn set_room_admin(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+        member_id: &UserId,
+        is_admin: bool,
+    ) -> Result<(), Error>;
+    /// Announces the user opening the room to its webhooks. Membership itself is unchanged.
+    async fn user_joined(&self, room_id: &RoomId, user_id: &UserId);
+    /// Announces to the room's webhooks that a user who opened the room closed it again.
+    async fn user_left(&self, room_id: &RoomId, user_id: &UserId);
 }
 
 #[async_trait]
-impl<ChatRepo> ChatService for ChatServiceImp<ChatRepo>
+impl<ChatRepo, Hooks> ChatService for ChatServiceImp<ChatRepo, Hooks>
 where
     ChatRepo: ChatRepository + Send + Sync,
+    Hooks: OutgoingWebhookService + Send + Sync,
 {
     async fn get_users(&self, chat_id: &RoomId) -> Result<Vec<User>, Error> {
         self.chat_repo
@@ -62,4 +188,385 @@ where
             .await
             .map_err(Error::UnexpectedError)
     }
+
+    async fn create_message(&self, new_message: NewMessage) -> Result<Message, Error> {
+        let membership = self
+            .chat_repo
+            .get_membership(&new_message.room_id, &new_message.user_id)
+            .await?;
+        if membership.is_none() {
+            return Err(Error::Forbidden(
+                "You are not a member of this room.".to_string(),
+            ));
+        }
+        let muted_until = self
+            .chat_repo
+            .muted_until(&new_message.room_id, &new_message.user_id)
+            .await?;
+        if let Some(until) = muted_until.filter(|until| *until > Utc::now()) {
+            return Err(Error::Forbidden(format!(
+                "You are muted in this room until {}.",
+                until.format("%H:%M UTC")
+            )));
+        }
+
+        let message = self
+            .chat_repo
+            .create_message(&new_message)
+            .await
+            .map_err(Error::UnexpectedError)?;
+
+        self.publish(&message.room_id, HookEvent::MessageCreated(message.clone()))
+            .await;
+        Ok(message)
+    }
+
+    async fn get_messages(&self, room_id: &RoomId) -> Result<Vec<Message>, Error> {
+        self.chat_repo
+            .get_messages(room_id, MESSAGES_PAGE_SIZE)
+            .await
+            .map_err(Error::UnexpectedError)
+    }
+
+    async fn get_user_rooms(&self, user_id: &UserId) -> Result<Vec<Room>, Error> {
+        self.chat_repo
+            .get_user_rooms(user_id)
+            .await
+            .map_err(Error::UnexpectedError)
+    }
+
+    async fn get_room(&self, user_id: &UserId, room_id: &RoomId) -> Result<Room, Error> {
+        let not_found = || Error::NotFound("Room not found.".to_string());
+        self.chat_repo
+            .get_membership(room_id, user_id)
+            .await?
+            .ok_or_else(not_found)?;
+        self.chat_repo
+            .get_room(room_id)
+            .await?
+            .ok_or_else(not_found)
+    }
+
+    async fn update_room(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+        change: RoomChange,
+    ) -> Result<(Room, Message), Error> {
+        if !self.chat_repo.can_manage_room(user_id, room_id).await? {
+            return Err(Error::Forbidden("Room admins only".to_string()));
+        }
+        if let RoomChange::Avatar(Some(avatar)) = &change {
+            validate_avatar(avatar)?;
+        }
+
+        let record = NewMessage {
+            user_id: *user_id,
+            room_id: *room_id,
+            content: MessageContent::from_str(change.summary())
+                .map_err(|e| Error::UnexpectedError(e.into()))?,
+        };
+        self.chat_repo
+            .update_room(&change, &record)
+            .await?
+            .ok_or_else(|| Error::NotFound("Room not found.".to_string()))
+    }
+
+    async fn get_attachment(
+        &self,
+        user_id: &UserId,
+        attachment_id: &AttachmentId,
+    ) -> Result<Attachment, Error> {
+        self.chat_repo
+            .get_attachment(attachment_id, user_id)
+            .await?
+            .ok_or_else(|| Error::NotFound("Attachment not found.".to_string()))
+    }
+
+    async fn set_room_admin(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+        member_id: &UserId,
+        is_admin: bool,
+    ) -> Result<(), Error> {
+        if !self.chat_repo.can_manage_room(user_id, room_id).await? {
+            return Err(Error::Forbidden("Room admins only".to_string()));
+        }
+        if !self
+            .chat_repo
+            .set_room_admin(room_id, member_id, is_admin)
+            .await?
+        {
+            return Err(Error::NotFound("Member not found.".to_string()));
+        }
+        tracing::info!(
+            room_id = %room_id.as_ref(),
+            member_id = %member_id.as_ref(),
+            is_admin,
+            "Room admin changed"
+        );
+        Ok(())
+    }
+
+    async fn user_joined(&self, room_id: &RoomId, user_id: &UserId) {
+        self.publish(room_id, HookEvent::PresenceJoined(*user_id))
+            .await;
+    }
+
+    async fn user_left(&self, room_id: &RoomId, user_id: &UserId) {
+        self.publish(room_id, HookEvent::PresenceLeft(*user_id))
+            .await;
+    }
+}
+
+fn validate_avatar(avatar: &Attachment) -> Result<(), Error> {
+    if !AVATAR_CONTENT_TYPES.contains(&avatar.content_type.as_str()) {
+        return Err(Error::ValidationError(format!(
+            "Avatars must be one of {}.",
+            AVATAR_CONTENT_TYPES.join(", ")
+        )));
+    }
+    if avatar.data.is_empty() || avatar.data.len() > MAX_AVATAR_SIZE {
+        return Err(Error::ValidationError(format!(
+            "Avatars must be at most {} KiB.",
+            MAX_AVATAR_SIZE / 1024
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::outgoing_webhook::MockOutgoingWebhookService;
+    use mockall::predicate::{always, eq};
+    use shared::domain::{MessageKind, RoomCode, RoomName};
+    use uuid::Uuid;
+
+    fn new_message() -> NewMessage {
+        NewMessage {
+            user_id: Uuid::new_v4().into(),
+            room_id: Uuid::new_v4().into(),
+            content: "hi".parse().unwrap(),
+        }
+    }
+
+    fn member_repo() -> MockChatRepository {
+        let mut repo = MockChatRepository::new();
+        repo.expect_get_membership()
+            .returning(|_, _| Box::pin(async { Ok(Some("code".to_string())) }));
+        repo
+    }
+
+    #[tokio::test]
+    async fn non_members_cannot_send_messages() {
+        let mut repo = MockChatRepository::new();
+        repo.expect_get_membership()
+            .returning(|_, _| Box::pin(async { Ok(None) }));
+        repo.expect_create_message().never();
+        let service = ChatServiceImp::new(repo, MockOutgoingWebhookService::new());
+
+        let result = service.create_message(new_message()).await;
+
+        assert!(matches!(result, Err(Error::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn muted_members_cannot_send_messages() {
+        let mut repo = member_repo();
+        repo.expect_muted_until().returning(|_, _| {
+            Box::pin(async { Ok(Some(Utc::now() + chrono::Duration::minutes(5))) })
+        });
+        repo.expect_create_message().never();
+        let service = ChatServiceImp::new(repo, MockOutgoingWebhookService::new());
+
+        let result = service.create_message(new_message()).await;
+
+        assert!(matches!(result, Err(Error::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn expired_mutes_are_ignored_and_messages_published() {
+        let mut repo = member_repo();
+        repo.expect_muted_until().returning(|_, _| {
+            Box::pin(async { Ok(Some(Utc::now() - chrono::Duration::minutes(5))) })
+        });
+        repo.expect_create_message().returning(|new_message| {
+            let message = Message {
+                id: Uuid::new_v4().into(),
+                user_id: new_message.user_id,
+                room_id: new_message.room_id,
+                content: new_message.content.clone(),
+                created_at: Utc::now(),
+                kind: MessageKind::User,
+            };
+            Box::pin(async { Ok(message) })
+        });
+        let mut hooks = MockOutgoingWebhookService::new();
+        hooks
+            .expect_publish()
+            .withf(|_, event| matches!(event, HookEvent::MessageCreated(_)))
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        let service = ChatServiceImp::new(repo, hooks);
+
+        service.create_message(new_message()).await.unwrap();
+    }
+
+    fn avatar(content_type: &str, size: usize) -> RoomChange {
+        RoomChange::Avatar(Some(Attachment {
+            content_type: content_type.to_string(),
+            data: vec![0; size],
+        }))
+    }
+
+    #[tokio::test]
+    async fn only_room_admins_update_rooms() {
+        let mut repo = MockChatRepository::new();
+        repo.expect_can_manage_room()
+            .returning(|_, _| Box::pin(async { Ok(false) }));
+        repo.expect_update_room().never();
+        let service = ChatServiceImp::new(repo, MockOutgoingWebhookService::new());
+
+        let result = service
+            .update_room(
+                &Uuid::new_v4().into(),
+                &Uuid::new_v4().into(),
+                RoomChange::Topic(None),
+            )
+            .await;
+
+        assert!(matches!(result, Err(Error::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn avatars_must_be_small_images() {
+        let mut repo = MockChatRepository::new();
+        repo.expect_can_manage_room()
+            .returning(|_, _| Box::pin(async { Ok(true) }));
+        repo.expect_update_room().never();
+        let service = ChatServiceImp::new(repo, MockOutgoingWebhookService::new());
+
+        for change in [
+            avatar("text/html", 10),
+            avatar("image/png", 0),
+            avatar("image/png", MAX_AVATAR_SIZE + 1),
+        ] {
+            let result = service
+                .update_room(&Uuid::new_v4().into(), &Uuid::new_v4().into(), change)
+                .await;
+
+            assert!(matches!(result, Err(Error::ValidationError(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn room_changes_are_recorded_as_system_messages() {
+        let user_id: UserId = Uuid::new_v4().into();
+        let room_id: RoomId = Uuid::new_v4().into();
+        let mut repo = MockChatRepository::new();
+        repo.expect_can_manage_room()
+            .returning(|_, _| Box::pin(async { Ok(true) }));
+        repo.expect_update_room()
+            .withf(move |change, record| {
+                *change == RoomChange::Topic(None)
+                    && record.user_id == user_id
+                    && record.room_id == room_id
+                    && record.content.as_ref() == "cleared the room topic"
+            })
+            .times(1)
+            .returning(|_, record| {
+                let room = Room {
+                    id: record.room_id,
+                    name: RoomName::from_str("general").unwrap(),
+                    code: RoomCode::from_str("code").unwrap(),
+                    topic: None,
+                    description: None,
+                    avatar: None,
+                    created_at: Utc::now(),
+                    created_by: None,
+                };
+                let message = Message {
+                    id: Uuid::new_v4().into(),
+                    user_id: record.user_id,
+                    room_id: record.room_id,
+                    content: record.content.clone(),
+                    created_at: Utc::now(),
+                    kind: MessageKind::System,
+                };
+                Box::pin(async { Ok(Some((room, message))) })
+            });
+        let service = ChatServiceImp::new(repo, MockOutgoingWebhookService::new());
+
+        let (_, message) = service
+            .update_room(&user_id, &room_id, RoomChange::Topic(None))
+            .await
+            .unwrap();
+
+        assert_eq!(message.kind, MessageKind::System);
+    }
+
+    #[tokio::test]
+    async fn updating_missing_rooms_is_not_found() {
+        let mut repo = MockChatRepository::new();
+        repo.expect_can_manage_room()
+            .returning(|_, _| Box::pin(async { Ok(true) }));
+        repo.expect_update_room()
+            .returning(|_, _| Box::pin(async { Ok(None) }));
+        let service = ChatServiceImp::new(repo, MockOutgoingWebhookService::new());
+
+        let result = service
+            .update_room(
+                &Uuid::new_v4().into(),
+                &Uuid::new_v4().into(),
+                RoomChange::Topic(None),
+            )
+            .await;
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn only_room_admins_grant_room_admin() {
+        let mut repo = MockChatRepository::new();
+        repo.expect_can_manage_room()
+            .returning(|_, _| Box::pin(async { Ok(false) }));
+        repo.expect_set_room_admin().never();
+        let service = ChatServiceImp::new(repo, MockOutgoingWebhookService::new());
+
+        let result = service
+            .set_room_admin(
+                &Uuid::new_v4().into(),
+                &Uuid::new_v4().into(),
+                &Uuid::new_v4().into(),
+                true,
+            )
+            .await;
+
+        assert!(matches!(result, Err(Error::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn room_admin_is_granted_to_members_only() {
+        let mut repo = MockChatRepository::new();
+        repo.expect_can_manage_room()
+            .returning(|_, _| Box::pin(async { Ok(true) }));
+        repo.expect_set_room_admin()
+            .with(always(), always(), eq(true))
+            .times(1)
+            .returning(|_, _, _| Box::pin(async { Ok(false) }));
+        let service = ChatServiceImp::new(repo, MockOutgoingWebhookService::new());
+
+        let result = service
+            .set_room_admin(
+                &Uuid::new_v4().into(),
+                &Uuid::new_v4().into(),
+                &Uuid::new_v4().into(),
+                true,
+            )
+            .await;
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+    }
 }
@@ -0,0 +1,578 @@
+use std::{str::FromStr, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use shared::domain::event::{RemovedFromRoom, ServerEvent};
+use shared::domain::{MessageContent, RoomTopic, User};
+
+use crate::service::{
+    announce, Error, HookEvent, OutgoingWebhookService, RoomChange, UserEventBus,
+};
+
+use super::registry::{CommandContext, CommandHandler, CommandOutcome, CommandPermission};
+use super::service::CommandRepository;
+
+const DEFAULT_MUTE_MINUTES: i64 = 10;
+/// A week.
+const MAX_MUTE_MINUTES: i64 = 7 * 24 * 60;
+
+/// `/me waves` posts `* alice waves`.
+pub(super) struct Me<Repo> {
+    command_repo: Arc<Repo>,
+}
+
+pub(super) struct Topic;
+
+pub(super) struct Invite<Repo, Hooks> {
+    command_repo: Arc<Repo>,
+    hooks: Arc<Hooks>,
+}
+
+pub(super) struct Kick<Repo, EventBus, Hooks> {
+    command_repo: Arc<Repo>,
+    event_bus: Arc<EventBus>,
+    hooks: Arc<Hooks>,
+}
+
+pub(super) struct Mute<Repo> {
+    command_repo: Arc<Repo>,
+}
+
+impl<Repo> Me<Repo> {
+    pub(super) fn new(command_repo: Arc<Repo>) -> Self {
+        Self { command_repo }
+    }
+}
+
+impl<Repo, Hooks> Invite<Repo, Hooks> {
+    pub(super) fn new(command_repo: Arc<Repo>, hooks: Arc<Hooks>) -> Self {
+        Self {
+            command_repo,
+            hooks,
+        }
+    }
+}
+
+impl<Repo, EventBus, Hooks> Kick<Repo, EventBus, Hooks> {
+    pub(super) fn new(
+        command_repo: Arc<Repo>,
+        event_bus: Arc<EventBus>,
+        hooks: Arc<Hooks>,
+    ) -> Self {
+        Self {
+            command_repo,
+            event_bus,
+            hooks,
+        }
+    }
+}
+
+impl<Repo> Mute<Repo> {
+    pub(super) fn new(command_repo: Arc<Repo>) -> Self {
+        Self { command_repo }
+    }
+}
+
+fn usage(usage: &str) -> Error {
+    Error::ValidationError(format!("Usage: {}", usage))
+}
+
+/// Splits the `@name` or `name` the arguments start with from the rest.
+async fn target_user<'a, Repo>(command_repo: &Repo, args: &'a str) -> Result<(User, &'a str), Error>
+where
+    Repo: CommandRepository,
+{
+    let (name, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let name = name.strip_prefix('@').unwrap_or(name);
+    let not_found = || Error::NotFound(format!("No user named {}.", name));
+    let name = name.parse().map_err(|_| not_found())?;
+    let user = command_repo
+        .get_user_by_name(&name)
+        .await?
+        .ok_or_else(not_found)?;
+    Ok((user, rest.trim()))
+}
+
+#[async_trait]
+impl<Repo> CommandHandler for Me<Repo>
+where
+    Repo: CommandRepository + Send + Sync,
+{
+    fn usage(&self) -> &str {
+        "/me <action>"
+    }
+
+    fn description(&self) -> &str {
+        "Describe what you are doing."
+    }
+
+    fn permission(&self) -> CommandPermission {
+        CommandPermission::Member
+    }
+
+    async fn execute(&self, context: &CommandContext, args: &str) -> Result<CommandOutcome, Error> {
+        if args.is_empty() {
+            return Err(usage(self.usage()));
+        }
+        let user = self
+            .command_repo
+            .get_user(&context.user_id)
+            .await?
+            .ok_or_else(|| Error::NotFound("user not found".to_string()))?;
+
+        let content: MessageContent = format!("* {} {}", user.name.as_ref(), args)
+            .parse()
+            .map_err(|e: shared::domain::Error| Error::ValidationError(e.to_string()))?;
+        Ok(CommandOutcome::Post(content))
+    }
+}
+
+#[async_trait]
+impl CommandHandler for Topic {
+    fn usage(&self) -> &str {
+        "/topic [text]"
+    }
+
+    fn description(&self) -> &str {
+        "Set the topic of the room, or clear it without text."
+    }
+
+    fn permission(&self) -> CommandPermission {
+        CommandPermission::RoomAdmin
+    }
+
+    async fn execute(
+        &self,
+        _context: &CommandContext,
+        args: &str,
+    ) -> Result<CommandOutcome, Error> {
+        let topic = match args {
+            "" => None,
+            args => Some(
+                RoomTopic::from_str(args)
+                    .map_err(|e: shared::domain::Error| Error::ValidationError(e.to_string()))?,
+            ),
+        };
+        Ok(CommandOutcome::Update(RoomChange::Topic(topic)))
+    }
+}
+
+#[async_trait]
+impl<Repo, Hooks> CommandHandler for Invite<Repo, Hooks>
+where
+    Repo: CommandRepository + Send + Sync,
+    Hooks: OutgoingWebhookService + Send + Sync,
+{
+    fn usage(&self) -> &str {
+        "/invite <user>"
+    }
+
+    fn description(&self) -> &str {
+        "Add a user to the room."
+    }
+
+    fn permission(&self) -> CommandPermission {
+        CommandPermission::Member
+    }
+
+    async fn execute(&self, context: &CommandContext, args: &str) -> Result<CommandOutcome, Error> {
+        if args.is_empty() {
+            return Err(usage(self.usage()));
+        }
+        let (user, _) = target_user(self.command_repo.as_ref(), args).await?;
+
+        let added = self
+            .command_repo
+            .add_member(&context.room_id, &user.user_id)
+            .await?;
+        if !added {
+            return Ok(CommandOutcome::Reply(format!(
+                "{} is already in the room.",
+                user.name.as_ref()
+            )));
+        }
+        announce(
+            self.hooks.as_ref(),
+            &context.room_id,
+            HookEvent::MemberJoined(user.user_id),
+        )
+        .await;
+        Ok(CommandOutcome::Reply(format!(
+            "Invited {}.",
+            user.name.as_ref()
+        )))
+    }
+}
+
+#[async_trait]
+impl<Repo, EventBus, Hooks> CommandHandler for Kick<Repo, EventBus, Hooks>
+where
+    Repo: CommandRepository + Send + Sync,
+    EventBus: UserEventBus + Send + Sync,
+    Hooks: OutgoingWebhookService + Send + Sync,
+{
+    fn usage(&self) -> &str {
+        "/kick <user>"
+    }
+
+    fn description(&self) -> &str {
+        "Remove a user from the room."
+    }
+
+    fn permission(&self) -> CommandPermission {
+        CommandPermission::RoomAdmin
+    }
+
+    async fn execute(&self, context: &CommandContext, args: &str) -> Result<CommandOutcome, Error> {
+        if args.is_empty() {
+            return Err(usage(self.usage()));
+        }
+        let (user, _) = target_user(self.command_repo.as_ref(), args).await?;
+        if user.user_id == context.user_id {
+            return Err(Error::ValidationError(
+                "You cannot kick yourself.".to_string(),
+            ));
+        }
+
+        if !self
+            .command_repo
+            .remove_member(&context.room_id, &user.user_id)
+            .await?
+        {
+            return Err(Error::NotFound(format!(
+                "{} is not in the room.",
+                user.name.as_ref()
+            )));
+        }
+        tracing::info!(kicked = %user.user_id.as_ref(), "User kicked from room");
+        announce(
+            self.hooks.as_ref(),
+            &context.room_id,
+            HookEvent::MemberLeft(user.user_id),
+        )
+        .await;
+
+        // Their open connections to the room end, the membership is already gone.
+        let event = ServerEvent::RemovedFromRoom(RemovedFromRoom {
+            room_id: context.room_id,
+        });
+        if let Err(e) = self.event_bus.publish(&user.user_id, &event).await {
+            tracing::error!("Failed publish removal from room: {}", e);
+        }
+        Ok(CommandOutcome::Reply(format!(
+            "Kicked {}.",
+            user.name.as_ref()
+        )))
+    }
+}
+
+#[async_trait]
+impl<Repo> CommandHandler for Mute<Repo>
+where
+    Repo: CommandRepository + Send + Sync,
+{
+    fn usage(&self) -> &str {
+        "/mute <user> [minutes]"
+    }
+
+    fn description(&self) -> &str {
+        "Stop a user from sending messages for a while, 0 minutes unmutes."
+    }
+
+    fn permission(&self) -> CommandPermission {
+        CommandPermission::RoomAdmin
+    }
+
+    async fn execute(&self, context: &CommandContext, args: &str) -> Result<CommandOutcome, Error> {
+        if args.is_empty() {
+            return Err(usage(self.usage()));
+        }
+        let (user, rest) = target_user(self.command_repo.as_ref(), args).await?;
+        let minutes = match rest {
+            "" => DEFAULT_MUTE_MINUTES,
+            rest => rest
+                .parse()
+                .ok()
+                .filter(|minutes| (0..=MAX_MUTE_MINUTES).contains(minutes))
+                .ok_or_else(|| {
+                    Error::ValidationError(format!("Mute for 0 to {} minutes.", MAX_MUTE_MINUTES))
+                })?,
+        };
+
+        let until = (minutes > 0).then(|| Utc::now() + chrono::Duration::minutes(minutes));
+        if !self
+            .command_repo
+            .mute_member(&context.room_id, &user.user_id, until)
+            .await?
+        {
+            return Err(Error::NotFound(format!(
+                "{} is not in the room.",
+                user.name.as_ref()
+            )));
+        }
+
+        let reply = match until {
+            Some(_) => format!("Muted {} for {} minutes.", user.name.as_ref(), minutes),
+            None => format!("Unmuted {}.", user.name.as_ref()),
+        };
+        Ok(CommandOutcome::Reply(reply))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::command::service::MockCommandRepository;
+    use crate::service::notification::MockUserEventBus;
+    use crate::service::outgoing_webhook::MockOutgoingWebhookService;
+    use shared::domain::UserId;
+    use uuid::Uuid;
+
+    fn context() -> CommandContext {
+        CommandContext {
+            user_id: Uuid::new_v4().into(),
+            room_id: Uuid::new_v4().into(),
+        }
+    }
+
+    fn user(name: &str, user_id: UserId) -> User {
+        User {
+            user_id,
+            name: name.parse().unwrap(),
+            email: format!("{}@example.com", name).parse().unwrap(),
+            code: format!("{}-code", name).parse().unwrap(),
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Repository knowing a single user.
+    fn repo_with_user(name: &'static str, user_id: UserId) -> MockCommandRepository {
+        let mut repo = MockCommandRepository::new();
+        repo.expect_get_user_by_name().returning(move |wanted| {
+            let result = Ok((wanted.as_ref() == name).then(|| user(name, user_id)));
+            Box::pin(async { result })
+        });
+        repo
+    }
+
+    #[tokio::test]
+    async fn me_posts_action_of_user() {
+        let mut repo = MockCommandRepository::new();
+        repo.expect_get_user().returning(|user_id| {
+            let result = Ok(Some(user("alice", *user_id)));
+            Box::pin(async { result })
+        });
+
+        let outcome = Me::new(Arc::new(repo))
+            .execute(&context(), "waves")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            outcome,
+            CommandOutcome::Post("* alice waves".parse().unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn mute_defaults_to_ten_minutes() {
+        let bob_id: UserId = Uuid::new_v4().into();
+        let mut repo = repo_with_user("bob", bob_id);
+        repo.expect_mute_member()
+            .withf(move |_, user_id, until| {
+                let left = until.unwrap() - Utc::now();
+                *user_id == bob_id
+                    && left > chrono::Duration::minutes(DEFAULT_MUTE_MINUTES - 1)
+                    && left <= chrono::Duration::minutes(DEFAULT_MUTE_MINUTES)
+            })
+            .times(1)
+            .returning(|_, _, _| Box::pin(async { Ok(true) }));
+
+        let outcome = Mute::new(Arc::new(repo))
+            .execute(&context(), "@bob")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            outcome,
+            CommandOutcome::Reply("Muted bob for 10 minutes.".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn mute_for_zero_minutes_unmutes() {
+        let mut repo = repo_with_user("bob", Uuid::new_v4().into());
+        repo.expect_mute_member()
+            .withf(|_, _, until| until.is_none())
+            .times(1)
+            .returning(|_, _, _| Box::pin(async { Ok(true) }));
+
+        let outcome = Mute::new(Arc::new(repo))
+            .execute(&context(), "bob 0")
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, CommandOutcome::Reply("Unmuted bob.".to_string()));
+    }
+
+    #[tokio::test]
+    async fn mute_rejects_invalid_durations() {
+        let mut repo = repo_with_user("bob", Uuid::new_v4().into());
+        repo.expect_mute_member().never();
+        let mute = Mute::new(Arc::new(repo));
+
+        for args in ["bob soon", "bob -5", "bob 100000"] {
+            let result = mute.execute(&context(), args).await;
+            assert!(matches!(result, Err(Error::ValidationError(_))), "{}", args);
+        }
+    }
+
+    #[tokio::test]
+    async fn users_cannot_kick_themselves() {
+        let context = context();
+        let mut repo = repo_with_user("alice", context.user_id);
+        repo.expect_remove_member().never();
+
+        let mut event_bus = MockUserEventBus::new();
+        event_bus.expect_publish().never();
+
+        let mut hooks = MockOutgoingWebhookService::new();
+        hooks.expect_publish().never();
+
+        let result = Kick::new(Arc::new(repo), Arc::new(event_bus), Arc::new(hooks))
+            .execute(&context, "alice")
+            .await;
+
+        assert!(matches!(result, Err(Error::ValidationError(_))));
+    }
+
+    #[tokio::test]
+    async fn kicked_users_are_removed_from_their_connections_to_the_room() {
+        let context = context();
+        let bob_id: UserId = Uuid::new_v4().into();
+        let mut repo = repo_with_user("bob", bob_id);
+        repo.expect_remove_member()
+            .withf(move |room_id, user_id| *room_id == context.room_id && *user_id == bob_id)
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(true) }));
+        let mut event_bus = MockUserEventBus::new();
+        event_bus
+            .expect_publish()
+            .withf(move |user_id, event| {
+                *user_id == bob_id && event.removed_from() == Some(&context.room_id)
+            })
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+        let mut hooks = MockOutgoingWebhookService::new();
+        hooks
+            .expect_publish()
+            .withf(move |room_id, event| {
+                *room_id == context.room_id
+                    && matches!(event, HookEvent::MemberLeft(user_id) if *user_id == bob_id)
+            })
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+
+        let outcome = Kick::new(Arc::new(repo), Arc::new(event_bus), Arc::new(hooks))
+            .execute(&context, "@bob")
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, CommandOutcome::Reply("Kicked bob.".to_string()));
+    }
+
+    #[tokio::test]
+    async fn kicking_non_members_publishes_nothing() {
+        let mut repo = repo_with_user("bob", Uuid::new_v4().into());
+        repo.expect_remove_member()
+            .returning(|_, _| Box::pin(async { Ok(false) }));
+        let mut event_bus = MockUserEventBus::new();
+        event_bus.expect_publish().never();
+        let mut hooks = MockOutgoingWebhookService::new();
+        hooks.expect_publish().never();
+
+        let result = Kick::new(Arc::new(repo), Arc::new(event_bus), Arc::new(hooks))
+            .execute(&context(), "bob")
+            .await;
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn inviting_unknown_user_is_not_found() {
+        let mut repo = repo_with_user("bob", Uuid::new_v4().into());
+        repo.expect_add_member().never();
+
+        let result = Invite::new(Arc::new(repo), Arc::new(MockOutgoingWebhookService::new()))
+            .execute(&context(), "@carol")
+            .await;
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn invited_users_are_announced_to_the_room_webhooks() {
+        let context = context();
+        let bob_id: UserId = Uuid::new_v4().into();
+        let mut repo = repo_with_user("bob", bob_id);
+        repo.expect_add_member()
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(true) }));
+        let mut hooks = MockOutgoingWebhookService::new();
+        hooks
+            .expect_publish()
+            .withf(move |room_id, event| {
+                *room_id == context.room_id
+                    && matches!(event, HookEvent::MemberJoined(user_id) if *user_id == bob_id)
+            })
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+
+        let outcome = Invite::new(Arc::new(repo), Arc::new(hooks))
+            .execute(&context, "@bob")
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, CommandOutcome::Reply("Invited bob.".to_string()));
+    }
+
+    #[tokio::test]
+    async fn inviting_members_announces_nothing() {
+        let mut repo = repo_with_user("bob", Uuid::new_v4().into());
+        repo.expect_add_member()
+            .returning(|_, _| Box::pin(async { Ok(false) }));
+        let mut hooks = MockOutgoingWebhookService::new();
+        hooks.expect_publish().never();
+
+        let outcome = Invite::new(Arc::new(repo), Arc::new(hooks))
+            .execute(&context(), "bob")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            outcome,
+            CommandOutcome::Reply("bob is already in the room.".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn topic_without_text_is_cleared() {
+        let outcome = Topic.execute(&context(), "").await.unwrap();
+
+        assert_eq!(outcome, CommandOutcome::Update(RoomChange::Topic(None)));
+    }
+
+    #[tokio::test]
+    async fn topic_is_set_from_the_arguments() {
+        let outcome = Topic
+            .execute(&context(), "Release on Friday")
+            .await
+            .unwrap();
+
+        let topic = "Release on Friday".parse().unwrap();
+        assert_eq!(
+            outcome,
+            CommandOutcome::Update(RoomChange::Topic(Some(topic)))
+        );
+    }
+}
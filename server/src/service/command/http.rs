@@ -0,0 +1,222 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use shared::domain::{MessageContent, RoomId, UserId};
+
+use crate::configuration::HttpCommandSettings;
+use crate::service::outgoing_webhook::sign;
+use crate::service::{Error, WebhookRequest, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+
+use super::registry::{CommandContext, CommandHandler, CommandOutcome, CommandPermission};
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait CommandClient {
+    /// Posts the request and returns the body of the 2xx answer.
+    async fn call(&self, request: &WebhookRequest) -> Result<String, anyhow::Error>;
+}
+
+/// Body posted to the integration.
+#[derive(Serialize)]
+struct Invocation<'a> {
+    command: &'a str,
+    args: &'a str,
+    user_id: &'a UserId,
+    room_id: &'a RoomId,
+}
+
+/// Body the integration answers with.
+#[derive(Deserialize)]
+struct Answer {
+    text: String,
+    /// Post the text to the room as the user instead of replying to them only.
+    #[serde(default)]
+    in_room: bool,
+}
+
+/// Command answered by an integration over HTTP.
+pub(super) struct HttpCommand<Client> {
+    name: String,
+    usage: String,
+    description: String,
+    url: String,
+    secret: Secret<String>,
+    permission: CommandPermission,
+    client: Arc<Client>,
+}
+
+impl<Client> HttpCommand<Client> {
+    pub(super) fn new(name: &str, settings: &HttpCommandSettings, client: Arc<Client>) -> Self {
+        let permission = if settings.admin_only {
+            CommandPermission::RoomAdmin
+        } else {
+            CommandPermission::Member
+        };
+        Self {
+            name: name.to_string(),
+            usage: format!("/{}", name),
+            description: settings.description.clone(),
+            url: settings.url.clone(),
+            secret: settings.secret.clone(),
+            permission,
+            client,
+        }
+    }
+
+    fn request(&self, context: &CommandContext, args: &str) -> Result<WebhookRequest, Error> {
+        let body = serde_json::to_string(&Invocation {
+            command: &self.name,
+            args,
+            user_id: &context.user_id,
+            room_id: &context.room_id,
+        })
+        .map_err(|e| Error::UnexpectedError(e.into()))?;
+        let timestamp = Utc::now().timestamp();
+        let signature = sign(self.secret.expose_secret().as_bytes(), timestamp, &body);
+
+        Ok(WebhookRequest {
+            url: self.url.clone(),
+            headers: vec![
+                (TIMESTAMP_HEADER, timestamp.to_string()),
+                (SIGNATURE_HEADER, format!("sha256={}", signature)),
+            ],
+            body,
+        })
+    }
+}
+
+#[async_trait]
+impl<Client> CommandHandler for HttpCommand<Client>
+where
+    Client: CommandClient + Send + Sync,
+{
+    fn usage(&self) -> &str {
+        &self.usage
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn permission(&self) -> CommandPermission {
+        self.permission
+    }
+
+    async fn execute(&self, context: &CommandContext, args: &str) -> Result<CommandOutcome, Error> {
+        let request = self.request(context, args)?;
+        let answer = self
+            .client
+            .call(&request)
+            .await
+            .and_then(|body| Ok(serde_json::from_str::<Answer>(&body)?));
+        let answer = match answer {
+            Ok(answer) => answer,
+            Err(e) => {
+                tracing::warn!(command = %self.name, "Command integration failed: {:#}", e);
+                return Ok(CommandOutcome::Reply(format!(
+                    "/{} is unavailable, try again later.",
+                    self.name
+                )));
+            }
+        };
+
+        if !answer.in_room {
+            return Ok(CommandOutcome::Reply(answer.text));
+        }
+        let content: MessageContent = answer
+            .text
+            .parse()
+            .map_err(|e: shared::domain::Error| Error::ValidationError(e.to_string()))?;
+        Ok(CommandOutcome::Post(content))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use uuid::Uuid;
+
+    fn command(client: MockCommandClient) -> HttpCommand<MockCommandClient> {
+        let settings = HttpCommandSettings {
+            url: "https://example.com/commands/deploy".to_string(),
+            description: "Deploy the app.".to_string(),
+            admin_only: true,
+            secret: Secret::new("secret".to_string()),
+        };
+        HttpCommand::new("deploy", &settings, Arc::new(client))
+    }
+
+    fn context() -> CommandContext {
+        CommandContext {
+            user_id: Uuid::new_v4().into(),
+            room_id: Uuid::new_v4().into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn invocation_is_signed_with_command_secret() {
+        let mut client = MockCommandClient::new();
+        client
+            .expect_call()
+            .withf(|request| {
+                let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+                let timestamp: i64 = request.headers[0].1.parse().unwrap();
+                body["command"] == "deploy"
+                    && body["args"] == "prod"
+                    && request.headers[1].1
+                        == format!("sha256={}", sign(b"secret", timestamp, &request.body))
+            })
+            .times(1)
+            .returning(|_| {
+                let result = Ok(r#"{"text": "Deploying."}"#.to_string());
+                Box::pin(async { result })
+            });
+
+        let outcome = command(client).execute(&context(), "prod").await.unwrap();
+
+        assert_eq!(outcome, CommandOutcome::Reply("Deploying.".to_string()));
+    }
+
+    #[tokio::test]
+    async fn answers_for_the_room_are_posted() {
+        let mut client = MockCommandClient::new();
+        client.expect_call().returning(|_| {
+            let result = Ok(r#"{"text": "Deployed v2.", "in_room": true}"#.to_string());
+            Box::pin(async { result })
+        });
+
+        let outcome = command(client).execute(&context(), "").await.unwrap();
+
+        assert_eq!(
+            outcome,
+            CommandOutcome::Post("Deployed v2.".parse().unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn failing_integration_is_reported_to_user() {
+        let mut client = MockCommandClient::new();
+        client
+            .expect_call()
+            .returning(|_| Box::pin(async { Err(anyhow!("502 Bad Gateway")) }));
+
+        let outcome = command(client).execute(&context(), "").await.unwrap();
+
+        assert_eq!(
+            outcome,
+            CommandOutcome::Reply("/deploy is unavailable, try again later.".to_string())
+        );
+    }
+
+    #[test]
+    fn admin_only_commands_require_room_admins() {
+        assert_eq!(
+            command(MockCommandClient::new()).permission(),
+            CommandPermission::RoomAdmin
+        );
+    }
+}
@@ -0,0 +1,8 @@
+mod builtin;
+mod http;
+mod registry;
+mod service;
+
+pub use http::CommandClient;
+pub use registry::*;
+pub use service::*;
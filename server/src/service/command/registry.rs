@@ -0,0 +1,79 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use async_trait::async_trait;
+use shared::domain::{MessageContent, RoomId, UserId};
+
+use crate::service::{Error, RoomChange};
+
+/// Who ran a command, and in which room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandContext {
+    pub user_id: UserId,
+    pub room_id: RoomId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandOutcome {
+    /// Shown to the user who ran the command only.
+    Reply(String),
+    /// Sent to the room as a message of the user who ran the command.
+    Post(MessageContent),
+    /// Applied to the room's details, the change is announced to the room.
+    Update(RoomChange),
+}
+
+/// Who may run a command, checked before its handler runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandPermission {
+    Member,
+    RoomAdmin,
+}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait CommandHandler: Send + Sync {
+    /// Shown by `/help`, e.g. `/kick <user>`.
+    fn usage(&self) -> &str;
+
+    fn description(&self) -> &str;
+
+    fn permission(&self) -> CommandPermission;
+
+    /// Runs the command with the text following its name.
+    async fn execute(&self, context: &CommandContext, args: &str) -> Result<CommandOutcome, Error>;
+}
+
+/// Command handlers by name, without the leading `/`.
+#[derive(Clone, Default)]
+pub struct CommandRegistry {
+    handlers: BTreeMap<String, Arc<dyn CommandHandler>>,
+}
+
+impl CommandRegistry {
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        handler: Arc<dyn CommandHandler>,
+    ) -> Result<(), Error> {
+        let name = name.into();
+        if self.handlers.contains_key(&name) {
+            return Err(Error::ConflictError(format!(
+                "Command /{} is already registered.",
+                name
+            )));
+        }
+        self.handlers.insert(name, handler);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn CommandHandler>> {
+        self.handlers.get(name)
+    }
+
+    /// Handlers in order of their names.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Arc<dyn CommandHandler>)> {
+        self.handlers
+            .iter()
+            .map(|(name, handler)| (name.as_str(), handler))
+    }
+}
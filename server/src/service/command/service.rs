@@ -0,0 +1,326 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use shared::domain::{RoomId, User, UserId, UserName};
+
+use crate::configuration::CommandSettings;
+use crate::service::{Error, OutgoingWebhookService, UserEventBus};
+
+use super::builtin::{Invite, Kick, Me, Mute, Topic};
+use super::http::{CommandClient, HttpCommand};
+use super::registry::{
+    CommandContext, CommandHandler, CommandOutcome, CommandPermission, CommandRegistry,
+};
+
+const HELP: &str = "help";
+
+#[derive(Clone)]
+pub struct CommandServiceImp<CommandRepo> {
+    command_repo: Arc<CommandRepo>,
+    registry: CommandRegistry,
+}
+
+impl<CommandRepo> CommandServiceImp<CommandRepo>
+where
+    CommandRepo: CommandRepository + Send + Sync + 'static,
+{
+    /// Service with the built-in commands, `/kick` tells the kicked user's connections
+    /// on `event_bus`. `/invite` and `/kick` announce the membership change to the room's
+    /// webhooks through `hooks`.
+    pub fn new<EventBus, Hooks>(
+        command_repo: CommandRepo,
+        event_bus: EventBus,
+        hooks: Hooks,
+    ) -> Self
+    where
+        EventBus: UserEventBus + Send + Sync + 'static,
+        Hooks: OutgoingWebhookService + Send + Sync + 'static,
+    {
+        let command_repo = Arc::new(command_repo);
+        let event_bus = Arc::new(event_bus);
+        let hooks = Arc::new(hooks);
+        let mut registry = CommandRegistry::default();
+        let builtins: [(&str, Arc<dyn CommandHandler>); 5] = [
+            ("me", Arc::new(Me::new(command_repo.clone()))),
+            ("topic", Arc::new(Topic)),
+            (
+                "invite",
+                Arc::new(Invite::new(command_repo.clone(), hooks.clone())),
+            ),
+            (
+                "kick",
+                Arc::new(Kick::new(command_repo.clone(), event_bus, hooks)),
+            ),
+            ("mute", Arc::new(Mute::new(command_repo.clone()))),
+        ];
+        for (name, handler) in builtins {
+            registry
+                .register(name, handler)
+                .expect("built-in command names are distinct");
+        }
+
+        Self {
+            command_repo,
+            registry,
+        }
+    }
+
+    /// Service with the built-in commands and the HTTP commands of the settings.
+    pub fn build<EventBus, Hooks, Client>(
+        settings: &CommandSettings,
+        command_repo: CommandRepo,
+        event_bus: EventBus,
+        hooks: Hooks,
+        client: Client,
+    ) -> Result<Self, Error>
+    where
+        EventBus: UserEventBus + Send + Sync + 'static,
+        Hooks: OutgoingWebhookService + Send + Sync + 'static,
+        Client: CommandClient + Send + Sync + 'static,
+    {
+        let mut service = Self::new(command_repo, event_bus, hooks);
+        let client = Arc::new(client);
+        for (name, command) in &settings.http {
+            let handler = HttpCommand::new(name, command, client.clone());
+            service.register(name, Arc::new(handler))?;
+        }
+        Ok(service)
+    }
+
+    /// Adds a command, names must be unique.
+    pub fn register(&mut self, name: &str, handler: Arc<dyn CommandHandler>) -> Result<(), Error> {
+        if name == HELP {
+            return Err(Error::ConflictError(format!(
+                "Command /{} is already registered.",
+                name
+            )));
+        }
+        self.registry.register(name, handler)
+    }
+
+    async fn allows(
+        &self,
+        context: &CommandContext,
+        permission: CommandPermission,
+    ) -> Result<bool, Error> {
+        match permission {
+            CommandPermission::Member => Ok(true),
+            CommandPermission::RoomAdmin => Ok(self
+                .command_repo
+                .can_manage_room(&context.user_id, &context.room_id)
+                .await?),
+        }
+    }
+
+    /// Lists the commands the user may run in the room.
+    async fn help(&self, context: &CommandContext) -> Result<CommandOutcome, Error> {
+        let is_admin = self.allows(context, CommandPermission::RoomAdmin).await?;
+        let mut lines = vec![format!("/{} - List the commands you can run.", HELP)];
+        for (_, handler) in self.registry.iter() {
+            if handler.permission() == CommandPermission::RoomAdmin && !is_admin {
+                continue;
+            }
+            lines.push(format!("{} - {}", handler.usage(), handler.description()));
+        }
+        Ok(CommandOutcome::Reply(lines.join("\n")))
+    }
+}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait CommandRepository {
+    /// Whether the user is an admin of the room or of the whole server.
+    async fn can_manage_room(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+    ) -> Result<bool, anyhow::Error>;
+
+    async fn get_user(&self, user_id: &UserId) -> Result<Option<User>, anyhow::Error>;
+
+    async fn get_user_by_name(&self, name: &UserName) -> Result<Option<User>, anyhow::Error>;
+
+    /// Returns whether the user was not a member yet.
+    async fn add_member(&self, room_id: &RoomId, user_id: &UserId) -> Result<bool, anyhow::Error>;
+
+    /// Returns whether the user was a member.
+    async fn remove_member(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+    ) -> Result<bool, anyhow::Error>;
+
+    /// Mutes the member until `until`, unmutes them without. Returns whether the user
+    /// is a member.
+    async fn mute_member(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<bool, anyhow::Error>;
+}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait CommandService {
+    /// Runs `/name args` for the user, after checking they may.
+    async fn execute(
+        &self,
+        context: &CommandContext,
+        name: &str,
+        args: &str,
+    ) -> Result<CommandOutcome, Error>;
+}
+
+#[async_trait]
+impl<CommandRepo> CommandService for CommandServiceImp<CommandRepo>
+where
+    CommandRepo: CommandRepository + Send + Sync + 'static,
+{
+    #[tracing::instrument(name = "Run command", skip(self, args))]
+    async fn execute(
+        &self,
+        context: &CommandContext,
+        name: &str,
+        args: &str,
+    ) -> Result<CommandOutcome, Error> {
+        if name == HELP {
+            return self.help(context).await;
+        }
+
+        let handler = self
+            .registry
+            .get(name)
+            .ok_or_else(|| Error::NotFound(format!("Unknown command /{}, see /{}.", name, HELP)))?;
+        if !self.allows(context, handler.permission()).await? {
+            return Err(Error::Forbidden(format!(
+                "/{} is for room admins only.",
+                name
+            )));
+        }
+
+        handler.execute(context, args).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::command::registry::MockCommandHandler;
+    use crate::service::notification::MockUserEventBus;
+    use crate::service::outgoing_webhook::MockOutgoingWebhookService;
+    use uuid::Uuid;
+
+    fn context() -> CommandContext {
+        CommandContext {
+            user_id: Uuid::new_v4().into(),
+            room_id: Uuid::new_v4().into(),
+        }
+    }
+
+    fn repo(is_admin: bool) -> MockCommandRepository {
+        let mut repo = MockCommandRepository::new();
+        repo.expect_can_manage_room()
+            .returning(move |_, _| Box::pin(async move { Ok(is_admin) }));
+        repo
+    }
+
+    fn handler(permission: CommandPermission) -> MockCommandHandler {
+        let mut handler = MockCommandHandler::new();
+        handler.expect_usage().return_const("/deploy".to_string());
+        handler
+            .expect_description()
+            .return_const("Deploy the app.".to_string());
+        handler.expect_permission().return_const(permission);
+        handler
+    }
+
+    fn service(repo: MockCommandRepository) -> CommandServiceImp<MockCommandRepository> {
+        CommandServiceImp::new(
+            repo,
+            MockUserEventBus::new(),
+            MockOutgoingWebhookService::new(),
+        )
+    }
+
+    #[tokio::test]
+    async fn unknown_commands_are_not_found() {
+        let service = service(repo(true));
+
+        let result = service.execute(&context(), "nope", "").await;
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn admin_commands_are_refused_to_members() {
+        let mut service = service(repo(false));
+        let mut handler = handler(CommandPermission::RoomAdmin);
+        handler.expect_execute().never();
+        service.register("deploy", Arc::new(handler)).unwrap();
+
+        let result = service.execute(&context(), "deploy", "").await;
+
+        assert!(matches!(result, Err(Error::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn registered_commands_get_their_arguments() {
+        let mut service = service(repo(false));
+        let mut handler = handler(CommandPermission::Member);
+        handler
+            .expect_execute()
+            .withf(|_, args| args == "prod now")
+            .times(1)
+            .returning(|_, _| {
+                Box::pin(async { Ok(CommandOutcome::Reply("Deploying.".to_string())) })
+            });
+        service.register("deploy", Arc::new(handler)).unwrap();
+
+        let outcome = service
+            .execute(&context(), "deploy", "prod now")
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, CommandOutcome::Reply("Deploying.".to_string()));
+    }
+
+    #[test]
+    fn commands_cannot_be_registered_twice() {
+        let mut service = service(repo(false));
+
+        for name in ["help", "kick"] {
+            let result = service.register(name, Arc::new(handler(CommandPermission::Member)));
+            assert!(matches!(result, Err(Error::ConflictError(_))), "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn help_lists_only_commands_the_user_may_run() {
+        let service = service(repo(false));
+
+        let CommandOutcome::Reply(help) = service.execute(&context(), "help", "").await.unwrap()
+        else {
+            panic!("help posted to the room");
+        };
+
+        assert!(help.contains("/me <action>"));
+        assert!(help.contains("/invite <user>"));
+        assert!(!help.contains("/kick"));
+        assert!(!help.contains("/mute"));
+    }
+
+    #[tokio::test]
+    async fn help_lists_admin_commands_to_admins() {
+        let service = service(repo(true));
+
+        let CommandOutcome::Reply(help) = service.execute(&context(), "help", "").await.unwrap()
+        else {
+            panic!("help posted to the room");
+        };
+
+        assert!(help.contains("/kick <user>"));
+        assert!(help.contains("/topic [text]"));
+    }
+}
@@ -0,0 +1,15 @@
+use async_trait::async_trait;
+
+/// Plain text email to a single recipient.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait EmailSender {
+    async fn send(&self, email: Email) -> Result<(), anyhow::Error>;
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("{0}")]
@@ -10,4 +12,23 @@ pub enum Error {
     InvalidCredentials(#[source] anyhow::Error),
     #[error("{0}")]
     ConflictError(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("Too many requests, retry in {} seconds.", whole_seconds(.0))]
+    TooManyRequests(Duration),
+}
+
+impl Error {
+    /// Seconds to wait before retrying a request refused with `TooManyRequests`.
+    pub fn retry_after_secs(&self) -> Option<u64> {
+        match self {
+            Self::TooManyRequests(retry_after) => Some(whole_seconds(retry_after)),
+            _ => None,
+        }
+    }
+}
+
+/// Rounded up, clients waiting the seconds told are never refused again for being early.
+fn whole_seconds(duration: &Duration) -> u64 {
+    ((duration.as_millis() + 999) / 1000).max(1) as u64
 }
@@ -1,6 +1,12 @@
 mod auth;
+mod bot;
 mod chat;
+mod command;
+mod email;
 mod error;
+mod notification;
+mod outgoing_webhook;
+mod rate_limit;
 
 pub use error::Error;
 
@@ -9,14 +15,95 @@ pub use auth::decode_token;
 pub use auth::encode_token;
 pub use auth::spawn_blocking_with_tracing;
 pub use auth::Claims;
+pub use auth::KeySet;
+pub use auth::SecretCipher;
+pub use auth::TokenScope;
+pub use auth::TokenType;
 pub use auth::ALGORITHM;
+pub use auth::LEGACY_KEY_ID;
+
+pub(crate) use auth::SECURITY_AUDIT;
 
 pub use auth::AuthService;
 pub use auth::AuthServiceImp;
+pub use auth::AuthorizationRequest;
+pub use auth::ClientInfo;
 pub use auth::Credentials;
 pub use auth::CredentialsRepository;
+pub use auth::ExternalIdentity;
+pub use auth::LoginAttemptRepository;
+pub use auth::LoginOutcome;
+pub use auth::OidcClient;
+pub use auth::OidcLogin;
+pub use auth::Permissions;
+pub use auth::Rotation;
+pub use auth::Session;
+pub use auth::SocketTicket;
 pub use auth::TokenRepository;
+pub use auth::TotpEnrollment;
+pub use auth::TotpRecord;
+pub use auth::UserTokenKind;
+
+pub use bot::ApiKey;
+pub use bot::ApiKeyScope;
+pub use bot::BotRepository;
+pub use bot::BotService;
+pub use bot::BotServiceImp;
+pub use bot::Webhook;
+pub use bot::API_KEY_PREFIX;
 
+pub use chat::Attachment;
 pub use chat::ChatRepository;
 pub use chat::ChatService;
 pub use chat::ChatServiceImp;
+pub use chat::RoomChange;
+
+pub use command::CommandClient;
+pub use command::CommandContext;
+pub use command::CommandHandler;
+pub use command::CommandOutcome;
+pub use command::CommandPermission;
+pub use command::CommandRegistry;
+pub use command::CommandRepository;
+pub use command::CommandService;
+pub use command::CommandServiceImp;
+
+pub use email::Email;
+pub use email::EmailSender;
+
+pub use notification::NotificationRepository;
+pub use notification::NotificationService;
+pub use notification::NotificationServiceImp;
+pub use notification::UserEventBus;
+
+pub use outgoing_webhook::announce;
+pub use outgoing_webhook::deliver_outgoing_webhooks;
+pub use outgoing_webhook::ClaimedDelivery;
+pub use outgoing_webhook::HookEvent;
+pub use outgoing_webhook::HookEventKind;
+pub use outgoing_webhook::OutgoingWebhookRepository;
+pub use outgoing_webhook::OutgoingWebhookService;
+pub use outgoing_webhook::OutgoingWebhookServiceImp;
+pub use outgoing_webhook::WebhookDelivery;
+pub use outgoing_webhook::WebhookEndpoint;
+pub use outgoing_webhook::WebhookRequest;
+pub use outgoing_webhook::WebhookSender;
+pub use outgoing_webhook::DELIVERY_HEADER;
+pub use outgoing_webhook::EVENT_HEADER;
+pub use outgoing_webhook::SIGNATURE_HEADER;
+pub use outgoing_webhook::TIMESTAMP_HEADER;
+
+pub use rate_limit::RateLimitRepository;
+pub use rate_limit::RateLimitService;
+pub use rate_limit::RateLimitServiceImp;
+
+#[cfg(test)]
+pub use auth::MockAuthService;
+#[cfg(test)]
+pub use chat::MockChatService;
+#[cfg(test)]
+pub use command::MockCommandService;
+#[cfg(test)]
+pub use notification::MockNotificationService;
+#[cfg(test)]
+pub use rate_limit::MockRateLimitService;
@@ -0,0 +1,202 @@
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+
+use crate::service::Error;
+use shared::domain::{event::ServerEvent, Message, Notification, NotificationId, UserId};
+
+const NOTIFICATIONS_PAGE_SIZE: i64 = 100;
+
+#[derive(Clone)]
+pub struct NotificationServiceImp<NotificationRepo, EventBus> {
+    notification_repo: NotificationRepo,
+    event_bus: EventBus,
+}
+
+impl<NotificationRepo, EventBus> NotificationServiceImp<NotificationRepo, EventBus>
+where
+    NotificationRepo: NotificationRepository,
+    EventBus: UserEventBus,
+{
+    pub fn new(notification_repo: NotificationRepo, event_bus: EventBus) -> Self {
+        Self {
+            notification_repo,
+            event_bus,
+        }
+    }
+}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait NotificationRepository {
+    /// Stores a notification for every member of the message room whose name is in `names`,
+    /// except the sender.
+    async fn create_for_mentions(
+        &self,
+        message: &Message,
+        names: &[String],
+    ) -> Result<Vec<Notification>, anyhow::Error>;
+
+    async fn list(
+        &self,
+        user_id: &UserId,
+        unread_only: bool,
+        limit: i64,
+    ) -> Result<Vec<Notification>, anyhow::Error>;
+
+    async fn mark_read(
+        &self,
+        user_id: &UserId,
+        ids: &[NotificationId],
+    ) -> Result<u64, anyhow::Error>;
+
+    async fn mark_all_read(&self, user_id: &UserId) -> Result<u64, anyhow::Error>;
+}
+
+/// Delivers events to every socket a user has open, on any instance.
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait UserEventBus {
+    async fn publish(&self, user_id: &UserId, event: &ServerEvent) -> Result<(), anyhow::Error>;
+
+    async fn subscribe(&self) -> Result<BoxStream<'static, (UserId, ServerEvent)>, anyhow::Error>;
+}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait NotificationService {
+    async fn notify_mentions(&self, message: &Message) -> Result<Vec<Notification>, Error>;
+    async fn list(&self, user_id: &UserId, unread_only: bool) -> Result<Vec<Notification>, Error>;
+    async fn mark_read(&self, user_id: &UserId, ids: &[NotificationId]) -> Result<u64, Error>;
+    async fn mark_all_read(&self, user_id: &UserId) -> Result<u64, Error>;
+    async fn subscribe(&self) -> Result<BoxStream<'static, (UserId, ServerEvent)>, Error>;
+}
+
+#[async_trait]
+impl<NotificationRepo, EventBus> NotificationService
+    for NotificationServiceImp<NotificationRepo, EventBus>
+where
+    NotificationRepo: NotificationRepository + Send + Sync,
+    EventBus: UserEventBus + Send + Sync,
+{
+    #[tracing::instrument(name = "Notify mentions", skip(self, message))]
+    async fn notify_mentions(&self, message: &Message) -> Result<Vec<Notification>, Error> {
+        let names: Vec<String> = message
+            .content
+            .mentions()
+            .into_iter()
+            .map(str::to_owned)
+            .collect();
+
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let notifications = self
+            .notification_repo
+            .create_for_mentions(message, &names)
+            .await?;
+
+        for notification in notifications.iter() {
+            let event = ServerEvent::Notification(notification.clone());
+            if let Err(e) = self.event_bus.publish(&notification.user_id, &event).await {
+                tracing::error!("Failed publish notification: {}", e);
+            }
+        }
+
+        Ok(notifications)
+    }
+
+    async fn list(&self, user_id: &UserId, unread_only: bool) -> Result<Vec<Notification>, Error> {
+        self.notification_repo
+            .list(user_id, unread_only, NOTIFICATIONS_PAGE_SIZE)
+            .await
+            .map_err(Error::UnexpectedError)
+    }
+
+    async fn mark_read(&self, user_id: &UserId, ids: &[NotificationId]) -> Result<u64, Error> {
+        self.notification_repo
+            .mark_read(user_id, ids)
+            .await
+            .map_err(Error::UnexpectedError)
+    }
+
+    async fn mark_all_read(&self, user_id: &UserId) -> Result<u64, Error> {
+        self.notification_repo
+            .mark_all_read(user_id)
+            .await
+            .map_err(Error::UnexpectedError)
+    }
+
+    async fn subscribe(&self) -> Result<BoxStream<'static, (UserId, ServerEvent)>, Error> {
+        self.event_bus
+            .subscribe()
+            .await
+            .map_err(Error::UnexpectedError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use mockall::predicate::eq;
+    use shared::domain::MessageKind;
+
+    fn message(content: &str) -> Message {
+        Message {
+            id: uuid::Uuid::new_v4().into(),
+            user_id: uuid::Uuid::new_v4().into(),
+            room_id: uuid::Uuid::new_v4().into(),
+            content: content.parse().unwrap(),
+            created_at: Utc::now(),
+            kind: MessageKind::User,
+        }
+    }
+
+    fn notification(user_id: UserId, message: &Message) -> Notification {
+        Notification {
+            id: uuid::Uuid::new_v4().into(),
+            user_id,
+            sender_id: message.user_id,
+            room_id: message.room_id,
+            message_id: message.id,
+            created_at: Utc::now(),
+            read_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn message_without_mentions_does_not_touch_storage() {
+        let mut repo = MockNotificationRepository::new();
+        repo.expect_create_for_mentions().never();
+        let mut bus = MockUserEventBus::new();
+        bus.expect_publish().never();
+
+        let service = NotificationServiceImp::new(repo, bus);
+        let notifications = service.notify_mentions(&message("hello")).await.unwrap();
+
+        assert!(notifications.is_empty());
+    }
+
+    #[tokio::test]
+    async fn mentioned_users_are_notified_on_their_channel() {
+        let message = message("hi @alice and @bob");
+        let alice = UserId::from(uuid::Uuid::new_v4());
+        let stored = vec![notification(alice, &message)];
+
+        let mut repo = MockNotificationRepository::new();
+        repo.expect_create_for_mentions()
+            .withf(|_, names| names == ["alice".to_string(), "bob".to_string()])
+            .return_once(move |_, _| Box::pin(async move { Ok(stored) }));
+        let mut bus = MockUserEventBus::new();
+        bus.expect_publish()
+            .with(eq(alice), mockall::predicate::always())
+            .times(1)
+            .returning(|_, _| Box::pin(async { Ok(()) }));
+
+        let service = NotificationServiceImp::new(repo, bus);
+        let notifications = service.notify_mentions(&message).await.unwrap();
+
+        assert_eq!(notifications.len(), 1);
+    }
+}
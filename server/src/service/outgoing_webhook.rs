@@ -0,0 +1,824 @@
+use std::{str::FromStr, time::Duration};
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use reqwest::Url;
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::configuration::WebhookSettings;
+use crate::service::{Error, SecretCipher};
+use shared::domain::{Message, RoomId, UserId};
+
+/// Random bytes in a signing secret.
+const SIGNING_SECRET_LENGTH: usize = 32;
+
+pub const EVENT_HEADER: &str = "X-Chat-Event";
+pub const DELIVERY_HEADER: &str = "X-Chat-Delivery";
+pub const TIMESTAMP_HEADER: &str = "X-Chat-Timestamp";
+/// `sha256=` and the hex HMAC-SHA256 of `<timestamp>.<body>` keyed with the signing secret.
+pub const SIGNATURE_HEADER: &str = "X-Chat-Signature";
+
+/// Room events endpoints can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HookEventKind {
+    #[serde(rename = "message.created")]
+    MessageCreated,
+    /// A user became a member of the room, invited or as a bot.
+    #[serde(rename = "member.joined")]
+    MemberJoined,
+    /// A member was removed from the room.
+    #[serde(rename = "member.left")]
+    MemberLeft,
+    /// A member opened the room, over a socket or a stream.
+    #[serde(rename = "presence.joined")]
+    PresenceJoined,
+    /// A member who opened the room closed it again.
+    #[serde(rename = "presence.left")]
+    PresenceLeft,
+}
+
+impl HookEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MessageCreated => "message.created",
+            Self::MemberJoined => "member.joined",
+            Self::MemberLeft => "member.left",
+            Self::PresenceJoined => "presence.joined",
+            Self::PresenceLeft => "presence.left",
+        }
+    }
+}
+
+impl FromStr for HookEventKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "message.created" => Ok(Self::MessageCreated),
+            "member.joined" => Ok(Self::MemberJoined),
+            "member.left" => Ok(Self::MemberLeft),
+            "presence.joined" => Ok(Self::PresenceJoined),
+            "presence.left" => Ok(Self::PresenceLeft),
+            _ => Err(anyhow!("Unknown webhook event {}.", s)),
+        }
+    }
+}
+
+/// Something that happened in a room, as sent to its endpoints.
+#[derive(Debug, Clone)]
+pub enum HookEvent {
+    MessageCreated(Message),
+    MemberJoined(UserId),
+    MemberLeft(UserId),
+    PresenceJoined(UserId),
+    PresenceLeft(UserId),
+}
+
+impl HookEvent {
+    pub fn kind(&self) -> HookEventKind {
+        match self {
+            Self::MessageCreated(_) => HookEventKind::MessageCreated,
+            Self::MemberJoined(_) => HookEventKind::MemberJoined,
+            Self::MemberLeft(_) => HookEventKind::MemberLeft,
+            Self::PresenceJoined(_) => HookEventKind::PresenceJoined,
+            Self::PresenceLeft(_) => HookEventKind::PresenceLeft,
+        }
+    }
+
+    fn body(&self, room_id: &RoomId) -> Result<String, anyhow::Error> {
+        let data = match self {
+            Self::MessageCreated(message) => serde_json::to_value(message)?,
+            Self::MemberJoined(user_id)
+            | Self::MemberLeft(user_id)
+            | Self::PresenceJoined(user_id)
+            | Self::PresenceLeft(user_id) => {
+                serde_json::json!({ "user_id": user_id })
+            }
+        };
+        let body = serde_json::json!({
+            "event": self.kind(),
+            "room_id": room_id,
+            "occurred_at": Utc::now(),
+            "data": data,
+        });
+        Ok(body.to_string())
+    }
+}
+
+/// HTTP endpoint a room admin registered for events of the room.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookEndpoint {
+    pub endpoint_id: Uuid,
+    pub room_id: RoomId,
+    pub url: String,
+    pub events: Vec<HookEventKind>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One event queued for one endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookDelivery {
+    pub delivery_id: Uuid,
+    pub endpoint_id: Uuid,
+    pub url: String,
+    pub event: HookEventKind,
+    pub body: String,
+    /// Failed attempts so far.
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Delivery taken from the queue, with the encrypted secret to sign it with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClaimedDelivery {
+    pub delivery: WebhookDelivery,
+    pub encrypted_secret: Vec<u8>,
+}
+
+/// Signed request for an endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookRequest {
+    pub url: String,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: String,
+}
+
+#[derive(Clone)]
+pub struct OutgoingWebhookServiceImp<HookRepo, Sender> {
+    hook_repo: HookRepo,
+    sender: Sender,
+    cipher: SecretCipher,
+    settings: WebhookSettings,
+}
+
+impl<HookRepo, Sender> OutgoingWebhookServiceImp<HookRepo, Sender>
+where
+    HookRepo: OutgoingWebhookRepository,
+    Sender: WebhookSender,
+{
+    pub fn new(
+        settings: &WebhookSettings,
+        hook_repo: HookRepo,
+        sender: Sender,
+    ) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            hook_repo,
+            sender,
+            cipher: settings.cipher()?,
+            settings: settings.clone(),
+        })
+    }
+}
+
+/// Durable queue of deliveries. A delivery is due from its `next_attempt_at`, claiming it
+/// moves that time past the lease so that other workers skip it meanwhile.
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait OutgoingWebhookRepository {
+    /// Whether the user is an admin of the room or of the whole server.
+    async fn can_manage_room(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+    ) -> Result<bool, anyhow::Error>;
+
+    async fn create_endpoint(
+        &self,
+        endpoint_id: &Uuid,
+        room_id: &RoomId,
+        url: &str,
+        events: &[HookEventKind],
+        encrypted_secret: &[u8],
+    ) -> Result<WebhookEndpoint, anyhow::Error>;
+
+    async fn list_endpoints(&self, room_id: &RoomId)
+        -> Result<Vec<WebhookEndpoint>, anyhow::Error>;
+
+    /// Returns whether the endpoint existed. Its queued deliveries go with it.
+    async fn delete_endpoint(
+        &self,
+        room_id: &RoomId,
+        endpoint_id: &Uuid,
+    ) -> Result<bool, anyhow::Error>;
+
+    /// Queues the body for every endpoint of the room subscribed to the event, returns
+    /// how many.
+    async fn enqueue(
+        &self,
+        room_id: &RoomId,
+        event: HookEventKind,
+        body: &str,
+    ) -> Result<u64, anyhow::Error>;
+
+    /// Takes up to `limit` due deliveries for `lease`.
+    async fn claim_due(
+        &self,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<ClaimedDelivery>, anyhow::Error>;
+
+    async fn mark_delivered(&self, delivery_id: &Uuid) -> Result<(), anyhow::Error>;
+
+    /// Counts a failed attempt. The delivery is retried at `retry_at`, or moved to the
+    /// dead letters without one.
+    async fn mark_failed(
+        &self,
+        delivery_id: &Uuid,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), anyhow::Error>;
+
+    async fn list_dead(&self, room_id: &RoomId) -> Result<Vec<WebhookDelivery>, anyhow::Error>;
+
+    /// Queues a dead letter of the room again, with fresh attempts. Returns whether it
+    /// was found.
+    async fn retry_dead(&self, room_id: &RoomId, delivery_id: &Uuid)
+        -> Result<bool, anyhow::Error>;
+}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait WebhookSender {
+    /// Fails for URLs the sender refuses to post to, as they point into the network of
+    /// the server.
+    async fn check_url(&self, url: &str) -> Result<(), anyhow::Error>;
+
+    /// Posts the request. Anything but a 2xx answer is an error.
+    async fn send(&self, request: &WebhookRequest) -> Result<(), anyhow::Error>;
+}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait OutgoingWebhookService {
+    /// Registers an endpoint for events of the room. The signing secret is only ever
+    /// returned here. Room admins only.
+    async fn create_endpoint(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+        url: String,
+        events: Vec<HookEventKind>,
+    ) -> Result<(WebhookEndpoint, Secret<String>), Error>;
+
+    async fn list_endpoints(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+    ) -> Result<Vec<WebhookEndpoint>, Error>;
+
+    async fn delete_endpoint(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+        endpoint_id: &Uuid,
+    ) -> Result<(), Error>;
+
+    /// Deliveries of the room that ran out of attempts.
+    async fn list_dead_letters(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+    ) -> Result<Vec<WebhookDelivery>, Error>;
+
+    async fn retry_dead_letter(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+        delivery_id: &Uuid,
+    ) -> Result<(), Error>;
+
+    /// Queues the event for the endpoints of the room, it is sent in the background.
+    async fn publish(&self, room_id: &RoomId, event: HookEvent) -> Result<(), Error>;
+
+    /// Sends the deliveries that are due. Returns how many were attempted.
+    async fn deliver_due(&self) -> Result<usize, Error>;
+}
+
+impl<HookRepo, Sender> OutgoingWebhookServiceImp<HookRepo, Sender>
+where
+    HookRepo: OutgoingWebhookRepository + Send + Sync,
+    Sender: WebhookSender + Send + Sync,
+{
+    async fn require_room_admin(&self, user_id: &UserId, room_id: &RoomId) -> Result<(), Error> {
+        if self.hook_repo.can_manage_room(user_id, room_id).await? {
+            Ok(())
+        } else {
+            Err(Error::Forbidden("Room admins only".to_string()))
+        }
+    }
+
+    async fn deliver(&self, claimed: ClaimedDelivery) -> Result<(), anyhow::Error> {
+        let ClaimedDelivery {
+            delivery,
+            encrypted_secret,
+        } = claimed;
+
+        let result = match self
+            .cipher
+            .decrypt(&encrypted_secret, delivery.endpoint_id.as_bytes())
+        {
+            Ok(secret) => {
+                let request = signed_request(&delivery, &secret, Utc::now().timestamp());
+                self.sender.send(&request).await
+            }
+            Err(e) => Err(e),
+        };
+
+        // Room admins see the error, the causes stay in the logs as they may tell about
+        // the network of the server.
+        let error = match result {
+            Ok(()) => return self.hook_repo.mark_delivered(&delivery.delivery_id).await,
+            Err(e) => {
+                tracing::warn!(
+                    delivery_id = %delivery.delivery_id,
+                    endpoint_id = %delivery.endpoint_id,
+                    "Webhook delivery failed: {:#}",
+                    e
+                );
+                e.to_string()
+            }
+        };
+
+        let attempts = delivery.attempts + 1;
+        let retry_at = (attempts < self.settings.max_attempts).then(|| {
+            Utc::now() + chrono::Duration::seconds(self.backoff(attempts).as_secs() as i64)
+        });
+        if retry_at.is_none() {
+            tracing::warn!(
+                delivery_id = %delivery.delivery_id,
+                endpoint_id = %delivery.endpoint_id,
+                "Webhook delivery dead-lettered after {} attempts: {}",
+                attempts,
+                error
+            );
+        }
+        self.hook_repo
+            .mark_failed(&delivery.delivery_id, &error, retry_at)
+            .await
+    }
+
+    /// Wait before the retry following the `attempts`th failure.
+    fn backoff(&self, attempts: i32) -> Duration {
+        let exponent = attempts.saturating_sub(1).clamp(0, 31) as u32;
+        let secs = self
+            .settings
+            .base_backoff_secs
+            .saturating_mul(2u64.saturating_pow(exponent));
+        Duration::from_secs(secs.min(self.settings.max_backoff_secs))
+    }
+}
+
+#[async_trait]
+impl<HookRepo, Sender> OutgoingWebhookService for OutgoingWebhookServiceImp<HookRepo, Sender>
+where
+    HookRepo: OutgoingWebhookRepository + Send + Sync,
+    Sender: WebhookSender + Send + Sync,
+{
+    #[tracing::instrument(name = "Create webhook endpoint", skip(self))]
+    async fn create_endpoint(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+        url: String,
+        events: Vec<HookEventKind>,
+    ) -> Result<(WebhookEndpoint, Secret<String>), Error> {
+        self.require_room_admin(user_id, room_id).await?;
+        let is_http = Url::parse(&url)
+            .map(|url| matches!(url.scheme(), "http" | "https"))
+            .unwrap_or(false);
+        if !is_http {
+            return Err(Error::ValidationError(format!(
+                "{} is not a valid HTTP URL.",
+                url
+            )));
+        }
+        if events.is_empty() {
+            return Err(Error::ValidationError(
+                "Subscribe to at least one event.".to_string(),
+            ));
+        }
+        if let Err(e) = self.sender.check_url(&url).await {
+            return Err(Error::ValidationError(format!(
+                "{} is not an allowed webhook URL: {}",
+                url, e
+            )));
+        }
+
+        let endpoint_id = Uuid::new_v4();
+        let mut bytes = [0u8; SIGNING_SECRET_LENGTH];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let secret = general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+        let encrypted_secret = self
+            .cipher
+            .encrypt(secret.as_bytes(), endpoint_id.as_bytes())?;
+
+        let endpoint = self
+            .hook_repo
+            .create_endpoint(&endpoint_id, room_id, &url, &events, &encrypted_secret)
+            .await?;
+        Ok((endpoint, Secret::new(secret)))
+    }
+
+    async fn list_endpoints(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+    ) -> Result<Vec<WebhookEndpoint>, Error> {
+        self.require_room_admin(user_id, room_id).await?;
+        self.hook_repo
+            .list_endpoints(room_id)
+            .await
+            .map_err(Error::UnexpectedError)
+    }
+
+    async fn delete_endpoint(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+        endpoint_id: &Uuid,
+    ) -> Result<(), Error> {
+        self.require_room_admin(user_id, room_id).await?;
+        if !self.hook_repo.delete_endpoint(room_id, endpoint_id).await? {
+            return Err(Error::NotFound("webhook endpoint not found".to_string()));
+        }
+        Ok(())
+    }
+
+    async fn list_dead_letters(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        self.require_room_admin(user_id, room_id).await?;
+        self.hook_repo
+            .list_dead(room_id)
+            .await
+            .map_err(Error::UnexpectedError)
+    }
+
+    async fn retry_dead_letter(
+        &self,
+        user_id: &UserId,
+        room_id: &RoomId,
+        delivery_id: &Uuid,
+    ) -> Result<(), Error> {
+        self.require_room_admin(user_id, room_id).await?;
+        if !self.hook_repo.retry_dead(room_id, delivery_id).await? {
+            return Err(Error::NotFound("dead letter not found".to_string()));
+        }
+        Ok(())
+    }
+
+    async fn publish(&self, room_id: &RoomId, event: HookEvent) -> Result<(), Error> {
+        let body = event
+            .body(room_id)
+            .context("Failed serialize webhook event.")?;
+        self.hook_repo
+            .enqueue(room_id, event.kind(), &body)
+            .await
+            .map_err(Error::UnexpectedError)?;
+        Ok(())
+    }
+
+    async fn deliver_due(&self) -> Result<usize, Error> {
+        let timeout = Duration::from_millis(self.settings.timeout_ms);
+        let claimed = self
+            .hook_repo
+            .claim_due(self.settings.batch_size, timeout * 2)
+            .await?;
+        let count = claimed.len();
+
+        let results =
+            futures::future::join_all(claimed.into_iter().map(|claimed| self.deliver(claimed)))
+                .await;
+        for result in results {
+            if let Err(e) = result {
+                tracing::error!("Failed record webhook delivery: {}", e);
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// Room events reach webhooks on a best-effort basis, they never fail what caused them.
+pub async fn announce<Hooks>(hooks: &Hooks, room_id: &RoomId, event: HookEvent)
+where
+    Hooks: OutgoingWebhookService,
+{
+    if let Err(e) = hooks.publish(room_id, event).await {
+        tracing::error!("Failed queue webhook event: {}", e);
+    }
+}
+
+/// Sends queued deliveries for as long as the server runs.
+pub async fn deliver_outgoing_webhooks<S>(service: S, poll_interval: Duration)
+where
+    S: OutgoingWebhookService,
+{
+    loop {
+        match service.deliver_due().await {
+            // More may be due already.
+            Ok(count) if count > 0 => continue,
+            Ok(_) => {}
+            Err(e) => tracing::error!("Failed deliver webhooks: {}", e),
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
+
+fn signed_request(delivery: &WebhookDelivery, secret: &[u8], timestamp: i64) -> WebhookRequest {
+    let signature = sign(secret, timestamp, &delivery.body);
+    WebhookRequest {
+        url: delivery.url.clone(),
+        headers: vec![
+            (EVENT_HEADER, delivery.event.as_str().to_string()),
+            (DELIVERY_HEADER, delivery.delivery_id.to_string()),
+            (TIMESTAMP_HEADER, timestamp.to_string()),
+            (SIGNATURE_HEADER, format!("sha256={}", signature)),
+        ],
+        body: delivery.body.clone(),
+    }
+}
+
+/// The timestamp is signed along, so that receivers can reject replays of old deliveries.
+pub(crate) fn sign(secret: &[u8], timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC can take key of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    data_encoding::HEXLOWER.encode(&mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockall::predicate::{always, eq};
+    use secrecy::ExposeSecret;
+
+    const KEY: [u8; 32] = [9; 32];
+
+    fn settings() -> WebhookSettings {
+        WebhookSettings {
+            encryption_key: Secret::new(general_purpose::STANDARD.encode(KEY)),
+            poll_interval_ms: 1000,
+            batch_size: 10,
+            timeout_ms: 1000,
+            max_attempts: 3,
+            base_backoff_secs: 10,
+            max_backoff_secs: 25,
+            allow_private_addresses: false,
+        }
+    }
+
+    fn service(
+        repo: MockOutgoingWebhookRepository,
+        sender: MockWebhookSender,
+    ) -> OutgoingWebhookServiceImp<MockOutgoingWebhookRepository, MockWebhookSender> {
+        OutgoingWebhookServiceImp::new(&settings(), repo, sender).unwrap()
+    }
+
+    fn claimed(attempts: i32) -> ClaimedDelivery {
+        let endpoint_id = Uuid::new_v4();
+        let encrypted_secret = SecretCipher::new(&KEY)
+            .unwrap()
+            .encrypt(b"secret", endpoint_id.as_bytes())
+            .unwrap();
+        ClaimedDelivery {
+            delivery: WebhookDelivery {
+                delivery_id: Uuid::new_v4(),
+                endpoint_id,
+                url: "http://localhost/hook".to_string(),
+                event: HookEventKind::MemberJoined,
+                body: "{}".to_string(),
+                attempts,
+                last_error: None,
+                created_at: Utc::now(),
+            },
+            encrypted_secret,
+        }
+    }
+
+    fn claim(delivery: ClaimedDelivery) -> MockOutgoingWebhookRepository {
+        let mut repo = MockOutgoingWebhookRepository::new();
+        repo.expect_claim_due().times(1).return_once(move |_, _| {
+            let result = Ok(vec![delivery]);
+            Box::pin(async { result })
+        });
+        repo
+    }
+
+    fn failing_sender() -> MockWebhookSender {
+        let mut sender = MockWebhookSender::new();
+        sender
+            .expect_send()
+            .returning(|_| Box::pin(async { Err(anyhow!("500 Internal Server Error")) }));
+        sender
+    }
+
+    #[test]
+    fn signature_covers_timestamp_and_body() {
+        let signature = sign(b"secret", 1700000000, "{}");
+
+        assert_eq!(
+            signature,
+            "b8569b78799ff9e3cbff0fc2d63a33a2b57f3282abd07c37ae5e8e7d79a5f163"
+        );
+        assert_ne!(signature, sign(b"secret", 1700000001, "{}"));
+    }
+
+    #[tokio::test]
+    async fn delivered_request_is_signed_with_endpoint_secret() {
+        let claimed = claimed(0);
+        let delivery_id = claimed.delivery.delivery_id;
+        let mut repo = claim(claimed);
+        repo.expect_mark_delivered()
+            .with(eq(delivery_id))
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(()) }));
+        let mut sender = MockWebhookSender::new();
+        sender
+            .expect_send()
+            .withf(|request| {
+                let timestamp: i64 = request.headers[2].1.parse().unwrap();
+                request.headers[3].1 == format!("sha256={}", sign(b"secret", timestamp, "{}"))
+            })
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        let count = service(repo, sender).deliver_due().await.unwrap();
+
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn failed_delivery_is_retried_with_backoff() {
+        let claimed = claimed(1);
+        let mut repo = claim(claimed);
+        repo.expect_mark_failed()
+            .withf(|_, error, retry_at| {
+                let wait = retry_at.unwrap() - Utc::now();
+                error.contains("500") && wait > chrono::Duration::seconds(15)
+            })
+            .times(1)
+            .returning(|_, _, _| Box::pin(async { Ok(()) }));
+
+        service(repo, failing_sender()).deliver_due().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn delivery_out_of_attempts_is_dead_lettered() {
+        let claimed = claimed(2);
+        let mut repo = claim(claimed);
+        repo.expect_mark_failed()
+            .with(always(), always(), eq(None))
+            .times(1)
+            .returning(|_, _, _| Box::pin(async { Ok(()) }));
+
+        service(repo, failing_sender()).deliver_due().await.unwrap();
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_maximum() {
+        let service = service(
+            MockOutgoingWebhookRepository::new(),
+            MockWebhookSender::new(),
+        );
+
+        assert_eq!(service.backoff(1), Duration::from_secs(10));
+        assert_eq!(service.backoff(2), Duration::from_secs(20));
+        assert_eq!(service.backoff(3), Duration::from_secs(25));
+        assert_eq!(service.backoff(100), Duration::from_secs(25));
+    }
+
+    #[tokio::test]
+    async fn published_event_is_queued_with_its_kind() {
+        let room_id: RoomId = Uuid::new_v4().into();
+        let user_id: UserId = Uuid::new_v4().into();
+        let mut repo = MockOutgoingWebhookRepository::new();
+        repo.expect_enqueue()
+            .withf(move |room, event, body| {
+                let body: serde_json::Value = serde_json::from_str(body).unwrap();
+                *room == room_id
+                    && *event == HookEventKind::MemberJoined
+                    && body["event"] == "member.joined"
+                    && body["data"]["user_id"] == user_id.as_ref().to_string()
+            })
+            .times(1)
+            .returning(|_, _, _| Box::pin(async { Ok(1) }));
+
+        service(repo, MockWebhookSender::new())
+            .publish(&room_id, HookEvent::MemberJoined(user_id))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_room_admins_register_endpoints() {
+        let mut repo = MockOutgoingWebhookRepository::new();
+        repo.expect_can_manage_room()
+            .returning(|_, _| Box::pin(async { Ok(false) }));
+        repo.expect_create_endpoint().never();
+
+        let result = service(repo, MockWebhookSender::new())
+            .create_endpoint(
+                &Uuid::new_v4().into(),
+                &Uuid::new_v4().into(),
+                "https://example.com/hook".to_string(),
+                vec![HookEventKind::MessageCreated],
+            )
+            .await;
+
+        assert!(matches!(result, Err(Error::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn endpoint_secret_is_stored_encrypted() {
+        let mut repo = MockOutgoingWebhookRepository::new();
+        repo.expect_can_manage_room()
+            .returning(|_, _| Box::pin(async { Ok(true) }));
+        repo.expect_create_endpoint().returning(
+            |endpoint_id, room_id, url, events, encrypted_secret| {
+                assert_ne!(encrypted_secret, b"");
+                let result = Ok(WebhookEndpoint {
+                    endpoint_id: *endpoint_id,
+                    room_id: *room_id,
+                    url: url.to_string(),
+                    events: events.to_vec(),
+                    created_at: Utc::now(),
+                });
+                Box::pin(async { result })
+            },
+        );
+        let mut sender = MockWebhookSender::new();
+        sender
+            .expect_check_url()
+            .returning(|_| Box::pin(async { Ok(()) }));
+        let service = service(repo, sender);
+
+        let (endpoint, secret) = service
+            .create_endpoint(
+                &Uuid::new_v4().into(),
+                &Uuid::new_v4().into(),
+                "https://example.com/hook".to_string(),
+                vec![HookEventKind::MessageCreated],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(endpoint.url, "https://example.com/hook");
+        assert!(!secret.expose_secret().is_empty());
+    }
+
+    #[tokio::test]
+    async fn endpoint_refused_by_sender_is_not_registered() {
+        let mut repo = MockOutgoingWebhookRepository::new();
+        repo.expect_can_manage_room()
+            .returning(|_, _| Box::pin(async { Ok(true) }));
+        repo.expect_create_endpoint().never();
+        let mut sender = MockWebhookSender::new();
+        sender
+            .expect_check_url()
+            .with(eq("http://169.254.169.254/latest"))
+            .times(1)
+            .returning(|_| {
+                Box::pin(async { Err(anyhow!("169.254.169.254 is not a public address.")) })
+            });
+
+        let result = service(repo, sender)
+            .create_endpoint(
+                &Uuid::new_v4().into(),
+                &Uuid::new_v4().into(),
+                "http://169.254.169.254/latest".to_string(),
+                vec![HookEventKind::MessageCreated],
+            )
+            .await;
+
+        assert!(matches!(result, Err(Error::ValidationError(_))));
+    }
+
+    #[tokio::test]
+    async fn failed_delivery_records_only_the_error_without_causes() {
+        let claimed = claimed(0);
+        let mut repo = claim(claimed);
+        repo.expect_mark_failed()
+            .withf(|_, error, _| error == "Failed to reach webhook endpoint.")
+            .times(1)
+            .returning(|_, _, _| Box::pin(async { Ok(()) }));
+        let mut sender = MockWebhookSender::new();
+        sender.expect_send().returning(|_| {
+            let result = Err(anyhow!("Connection refused (10.0.0.5:8080)"))
+                .context("Failed to reach webhook endpoint.");
+            Box::pin(async { result })
+        });
+
+        service(repo, sender).deliver_due().await.unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+use std::{net::IpAddr, time::Duration};
+
+use async_trait::async_trait;
+
+use crate::configuration::{RateLimitSettings, TokenBucketSettings};
+use crate::service::Error;
+use shared::domain::{RoomId, UserId};
+
+#[derive(Clone)]
+pub struct RateLimitServiceImp<LimitRepo> {
+    limit_repo: LimitRepo,
+    settings: RateLimitSettings,
+}
+
+impl<LimitRepo> RateLimitServiceImp<LimitRepo>
+where
+    LimitRepo: RateLimitRepository,
+{
+    pub fn new(settings: &RateLimitSettings, limit_repo: LimitRepo) -> Self {
+        Self {
+            limit_repo,
+            settings: settings.clone(),
+        }
+    }
+}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait RateLimitRepository {
+    /// Takes a token from the bucket stored under `key`. When the bucket is empty returns
+    /// how long until the next token is available.
+    async fn acquire(
+        &self,
+        key: &str,
+        bucket: &TokenBucketSettings,
+    ) -> Result<Option<Duration>, anyhow::Error>;
+}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait RateLimitService {
+    async fn check_message(&self, user_id: &UserId, room_id: &RoomId) -> Result<(), Error>;
+    async fn check_auth_ip(&self, ip: &IpAddr) -> Result<(), Error>;
+    async fn check_auth_account(&self, account: &str) -> Result<(), Error>;
+}
+
+impl<LimitRepo> RateLimitServiceImp<LimitRepo>
+where
+    LimitRepo: RateLimitRepository + Send + Sync,
+{
+    /// Rejects the request when the bucket is empty. Storage failures let the request
+    /// through, an unavailable limiter must not take the whole service down.
+    async fn check(&self, key: &str, bucket: &TokenBucketSettings) -> Result<(), Error> {
+        match self.limit_repo.acquire(key, bucket).await {
+            Ok(None) => Ok(()),
+            Ok(Some(retry_after)) => Err(Error::TooManyRequests(retry_after)),
+            Err(e) => {
+                tracing::error!("Failed acquire rate limit token: {}", e);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<LimitRepo> RateLimitService for RateLimitServiceImp<LimitRepo>
+where
+    LimitRepo: RateLimitRepository + Send + Sync,
+{
+    async fn check_message(&self, user_id: &UserId, room_id: &RoomId) -> Result<(), Error> {
+        let key = format!("message:{}:{}", user_id.as_ref(), room_id.as_ref());
+        self.check(&key, &self.settings.message).await
+    }
+
+    async fn check_auth_ip(&self, ip: &IpAddr) -> Result<(), Error> {
+        let key = format!("auth_ip:{}", ip);
+        self.check(&key, &self.settings.auth_ip).await
+    }
+
+    async fn check_auth_account(&self, account: &str) -> Result<(), Error> {
+        let key = format!("auth_account:{}", account.trim().to_lowercase());
+        self.check(&key, &self.settings.auth_account).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockall::predicate::{always, eq};
+
+    fn settings() -> RateLimitSettings {
+        let bucket = TokenBucketSettings {
+            capacity: 1,
+            refill_per_second: 1.0,
+        };
+        RateLimitSettings {
+            message: bucket,
+            auth_ip: bucket,
+            auth_account: bucket,
+            client_ip: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_bucket_is_too_many_requests() {
+        let mut repo = MockRateLimitRepository::new();
+        repo.expect_acquire()
+            .returning(|_, _| Box::pin(async { Ok(Some(Duration::from_millis(1500))) }));
+
+        let service = RateLimitServiceImp::new(&settings(), repo);
+        let result = service.check_auth_ip(&IpAddr::from([127, 0, 0, 1])).await;
+
+        assert!(matches!(result, Err(Error::TooManyRequests(d)) if d.as_millis() == 1500));
+    }
+
+    #[tokio::test]
+    async fn accounts_share_a_bucket_regardless_of_case() {
+        let mut repo = MockRateLimitRepository::new();
+        repo.expect_acquire()
+            .with(eq("auth_account:alice@example.com"), always())
+            .times(2)
+            .returning(|_, _| Box::pin(async { Ok(None) }));
+
+        let service = RateLimitServiceImp::new(&settings(), repo);
+
+        service
+            .check_auth_account("Alice@Example.com")
+            .await
+            .unwrap();
+        service
+            .check_auth_account("alice@example.com ")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn storage_failure_lets_requests_through() {
+        let mut repo = MockRateLimitRepository::new();
+        repo.expect_acquire()
+            .returning(|_, _| Box::pin(async { Err(anyhow::anyhow!("connection refused")) }));
+
+        let service = RateLimitServiceImp::new(&settings(), repo);
+        let result = service
+            .check_message(&uuid::Uuid::new_v4().into(), &uuid::Uuid::new_v4().into())
+            .await;
+
+        assert!(result.is_ok());
+    }
+}
@@ -1,14 +1,29 @@
 use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
 use tower_http::trace::TraceLayer;
 
 use crate::{
     configuration::Settings,
     repository::{
-        postgres::{get_connection_pool, ChatAdapter, CredentialsAdapter},
-        redis::{get_redis_pool, TokenAdapter},
+        http::{HttpCommandClient, HttpWebhookSender},
+        oidc::HttpOidcClient,
+        postgres::{
+            get_connection_pool, BotAdapter, ChatAdapter, CommandAdapter, CredentialsAdapter,
+            NotificationAdapter, OutgoingWebhookAdapter,
+        },
+        redis::{
+            get_redis_pool, LoginAttemptAdapter, RateLimitAdapter, TokenAdapter, UserEventAdapter,
+        },
+        smtp::SmtpEmailSender,
+    },
+    router::{
+        api::{get_api_router, get_well_known_router},
+        auth::CookieAuth,
+    },
+    service::{
+        deliver_outgoing_webhooks, AuthServiceImp, BotServiceImp, ChatServiceImp,
+        CommandServiceImp, NotificationServiceImp, OutgoingWebhookServiceImp, RateLimitServiceImp,
     },
-    router::api::get_api_router,
-    service::{AuthServiceImp, ChatServiceImp},
 };
 
 pub struct Application {
@@ -29,12 +44,67 @@ impl Application {
 
         let cred_repo = CredentialsAdapter::new(connection_pool.clone());
         let chat_repo = ChatAdapter::new(connection_pool.clone());
-        let token_repo = TokenAdapter::new(redis_pool);
+        let notification_repo = NotificationAdapter::new(connection_pool.clone());
+        let bot_repo = BotAdapter::new(connection_pool.clone());
+        let outgoing_webhook_repo = OutgoingWebhookAdapter::new(connection_pool.clone());
+        let command_repo = CommandAdapter::new(connection_pool.clone());
+        let token_repo = TokenAdapter::new(redis_pool.clone());
+        let login_attempts = LoginAttemptAdapter::new(redis_pool.clone());
+        let user_events = UserEventAdapter::new(redis_pool.clone());
+        let rate_limit_repo = RateLimitAdapter::new(redis_pool);
+        let email_sender = SmtpEmailSender::new(&configuration.email)?;
 
-        let chat_service = ChatServiceImp::new(chat_repo);
-        let auth_service = AuthServiceImp::build(&configuration.auth, cred_repo, token_repo)?;
+        let outgoing_webhook_service = OutgoingWebhookServiceImp::new(
+            &configuration.webhooks,
+            outgoing_webhook_repo,
+            HttpWebhookSender::new(&configuration.webhooks)?,
+        )?;
+        tokio::spawn(deliver_outgoing_webhooks(
+            outgoing_webhook_service.clone(),
+            std::time::Duration::from_millis(configuration.webhooks.poll_interval_ms),
+        ));
+        let chat_service = ChatServiceImp::new(chat_repo, outgoing_webhook_service.clone());
+        let bot_service = BotServiceImp::new(
+            &configuration.auth.password.argon2,
+            bot_repo,
+            outgoing_webhook_service.clone(),
+        )?;
+        let command_service = CommandServiceImp::build(
+            &configuration.commands,
+            command_repo,
+            user_events.clone(),
+            outgoing_webhook_service.clone(),
+            HttpCommandClient::new(&configuration.commands)?,
+        )?;
+        let notification_service =
+            NotificationServiceImp::new(notification_repo, user_events.clone());
+        let auth_service = AuthServiceImp::build(
+            &configuration.auth,
+            cred_repo,
+            token_repo,
+            login_attempts,
+            user_events,
+            email_sender,
+            HttpOidcClient::new(&configuration.auth.oidc),
+        )?;
+        let rate_limit_service =
+            RateLimitServiceImp::new(&configuration.rate_limit, rate_limit_repo);
         let router = axum::Router::new()
-            .nest("/api", get_api_router(auth_service, chat_service))
+            .merge(get_well_known_router(auth_service.clone()))
+            .nest(
+                "/api",
+                get_api_router(
+                    auth_service,
+                    chat_service,
+                    notification_service,
+                    rate_limit_service,
+                    bot_service,
+                    outgoing_webhook_service,
+                    command_service,
+                    CookieAuth::new(&configuration.auth),
+                    configuration.rate_limit.client_ip,
+                ),
+            )
             .layer(TraceLayer::new_for_http());
 
         Ok(Self { listener, router })
@@ -43,7 +113,10 @@ impl Application {
     pub async fn run_until_stopped(self) -> std::io::Result<()> {
         axum::Server::from_tcp(self.listener)
             .map_err(|err| Error::new(ErrorKind::Other, format!("listen error:{}", err)))?
-            .serve(self.router.into_make_service())
+            .serve(
+                self.router
+                    .into_make_service_with_connect_info::<SocketAddr>(),
+            )
             .await
             .map_err(|err| Error::new(ErrorKind::Other, format!("serve error:{}", err)))
     }
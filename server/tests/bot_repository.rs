@@ -0,0 +1,175 @@
+use secrecy::Secret;
+use server::repository::postgres::{BotAdapter, ChatAdapter};
+use server::service::{ApiKeyScope, BotRepository, ChatRepository};
+use shared::domain::{MessageKind, NewMessage, RoomId, User, UserId};
+use sqlx::PgPool;
+
+const USER_1: &str = "2a58d48e-91c2-47e7-9c65-f653c4d4932f";
+const ROOM_ALFA: &str = "6c76b96f-87bf-4909-8ef7-3c9f4f8312e9";
+const ROOM_BETA: &str = "d52cbfb4-03b3-4c87-9fbf-651232a218b8";
+
+async fn create_bot(repo: &BotAdapter) -> User {
+    repo.create_bot(
+        &"ci".parse().unwrap(),
+        &"ci@bots.invalid".parse().unwrap(),
+        Secret::new("hash".to_string()),
+        &"ci-code".parse().unwrap(),
+    )
+    .await
+    .unwrap()
+}
+
+#[sqlx::test(fixtures("users"))]
+async fn bots_are_told_apart_from_users(pool: PgPool) {
+    let repo = BotAdapter::new(pool);
+    let user_1: UserId = USER_1.parse().unwrap();
+
+    let bot = create_bot(&repo).await;
+
+    assert!(repo.is_bot(&bot.user_id).await.unwrap());
+    assert!(!repo.is_bot(&user_1).await.unwrap());
+}
+
+#[sqlx::test(fixtures("users", "roms"))]
+async fn api_key_is_found_by_hash_until_revoked(pool: PgPool) {
+    let repo = BotAdapter::new(pool);
+    let bot = create_bot(&repo).await;
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+    let scope = ApiKeyScope {
+        room_ids: vec![room_alfa],
+        send: true,
+        read: false,
+    };
+
+    let (key, _) = repo
+        .create_api_key(&bot.user_id, "hash-1", "chat_bot_abcdef", &scope)
+        .await
+        .unwrap();
+
+    let found = repo.get_api_key("hash-1").await.unwrap().unwrap();
+    assert_eq!(found, key);
+    assert_eq!(found.scope, scope);
+    assert!(repo.get_api_key("hash-2").await.unwrap().is_none());
+
+    assert!(repo
+        .revoke_api_key(&bot.user_id, &key.key_id)
+        .await
+        .unwrap());
+    assert!(!repo
+        .revoke_api_key(&bot.user_id, &key.key_id)
+        .await
+        .unwrap());
+    let revoked = repo.get_api_key("hash-1").await.unwrap().unwrap();
+    assert!(revoked.revoked_at.is_some());
+    assert_eq!(
+        repo.list_api_keys(&bot.user_id).await.unwrap(),
+        vec![revoked]
+    );
+}
+
+#[sqlx::test(fixtures("users", "roms"))]
+async fn messages_of_bots_are_marked(pool: PgPool) {
+    let bots = BotAdapter::new(pool.clone());
+    let chat = ChatAdapter::new(pool);
+    let bot = create_bot(&bots).await;
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+
+    let from_bot = chat
+        .create_message(&NewMessage {
+            user_id: bot.user_id,
+            room_id: room_alfa,
+            content: "build passed".parse().unwrap(),
+        })
+        .await
+        .unwrap();
+    let from_user = chat
+        .create_message(&NewMessage {
+            user_id: USER_1.parse().unwrap(),
+            room_id: room_alfa,
+            content: "thanks".parse().unwrap(),
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(from_bot.kind, MessageKind::Bot);
+    assert_eq!(from_user.kind, MessageKind::User);
+    let history = chat.get_messages(&room_alfa, 1).await.unwrap();
+    assert_eq!(history.len(), 1);
+}
+
+#[sqlx::test(fixtures("users", "roms"))]
+async fn webhook_is_found_by_token_hash_until_revoked(pool: PgPool) {
+    let repo = BotAdapter::new(pool);
+    let bot = create_bot(&repo).await;
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+
+    let (webhook, _) = repo
+        .create_webhook(&room_alfa, &bot.user_id, "hash-1")
+        .await
+        .unwrap();
+
+    let found = repo
+        .get_webhook(&webhook.webhook_id, "hash-1")
+        .await
+        .unwrap();
+    assert_eq!(found, Some(webhook.clone()));
+    assert!(repo
+        .get_webhook(&webhook.webhook_id, "hash-2")
+        .await
+        .unwrap()
+        .is_none());
+
+    assert!(repo
+        .revoke_webhook(&room_alfa, &webhook.webhook_id)
+        .await
+        .unwrap());
+    let revoked = repo.list_webhooks(&room_alfa).await.unwrap();
+    assert_eq!(revoked.len(), 1);
+    assert!(revoked[0].revoked_at.is_some());
+}
+
+#[sqlx::test(fixtures("users", "roms"))]
+async fn missing_rooms_are_reported(pool: PgPool) {
+    let repo = BotAdapter::new(pool);
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+    let unknown: RoomId = uuid::Uuid::new_v4().into();
+
+    let missing = repo.missing_rooms(&[room_alfa, unknown]).await.unwrap();
+
+    assert_eq!(missing, vec![unknown]);
+}
+
+#[sqlx::test(fixtures("users", "roms"))]
+async fn bots_join_the_rooms_of_their_keys_and_webhooks(pool: PgPool) {
+    let repo = BotAdapter::new(pool.clone());
+    let chat = ChatAdapter::new(pool);
+    let bot = create_bot(&repo).await;
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+    let room_beta: RoomId = ROOM_BETA.parse().unwrap();
+    let scope = ApiKeyScope {
+        room_ids: vec![room_alfa],
+        send: true,
+        read: false,
+    };
+
+    let (_, joined) = repo
+        .create_api_key(&bot.user_id, "hash", "prefix", &scope)
+        .await
+        .unwrap();
+    assert_eq!(joined, vec![room_alfa]);
+    let (_, joined) = repo
+        .create_webhook(&room_beta, &bot.user_id, "token-hash")
+        .await
+        .unwrap();
+    assert!(joined);
+    let (_, joined) = repo
+        .create_webhook(&room_alfa, &bot.user_id, "other-hash")
+        .await
+        .unwrap();
+    assert!(!joined);
+
+    for room_id in [room_alfa, room_beta] {
+        let membership = chat.get_membership(&room_id, &bot.user_id).await.unwrap();
+        assert!(membership.is_some());
+    }
+}
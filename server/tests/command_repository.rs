@@ -0,0 +1,84 @@
+use chrono::{Duration, Utc};
+use server::repository::postgres::{ChatAdapter, CommandAdapter};
+use server::service::{ChatRepository, CommandRepository};
+use shared::domain::{RoomId, UserId};
+use sqlx::PgPool;
+
+const USER_1: &str = "2a58d48e-91c2-47e7-9c65-f653c4d4932f";
+const USER_3: &str = "d4e3c6a7-9715-4a45-93c8-7b1b14ebf831";
+const ROOM_ALFA: &str = "6c76b96f-87bf-4909-8ef7-3c9f4f8312e9";
+
+#[sqlx::test(fixtures("users", "roms", "members"))]
+async fn users_are_found_by_name(pool: PgPool) {
+    let repo = CommandAdapter::new(pool);
+    let user_1: UserId = USER_1.parse().unwrap();
+
+    let user = repo
+        .get_user_by_name(&"user1".parse().unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(user.user_id, user_1);
+    assert!(repo
+        .get_user_by_name(&"nobody".parse().unwrap())
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[sqlx::test(fixtures("users", "roms", "members"))]
+async fn members_are_added_once_and_removed(pool: PgPool) {
+    let repo = CommandAdapter::new(pool.clone());
+    let chat = ChatAdapter::new(pool);
+    let user_3: UserId = USER_3.parse().unwrap();
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+
+    assert!(repo.add_member(&room_alfa, &user_3).await.unwrap());
+    assert!(!repo.add_member(&room_alfa, &user_3).await.unwrap());
+    assert!(chat
+        .get_membership(&room_alfa, &user_3)
+        .await
+        .unwrap()
+        .is_some());
+
+    assert!(repo.remove_member(&room_alfa, &user_3).await.unwrap());
+    assert!(!repo.remove_member(&room_alfa, &user_3).await.unwrap());
+    assert!(chat
+        .get_membership(&room_alfa, &user_3)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[sqlx::test(fixtures("users", "roms", "members"))]
+async fn mutes_apply_to_members_only(pool: PgPool) {
+    let repo = CommandAdapter::new(pool.clone());
+    let chat = ChatAdapter::new(pool);
+    let user_1: UserId = USER_1.parse().unwrap();
+    let user_3: UserId = USER_3.parse().unwrap();
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+    let until = Utc::now() + Duration::minutes(10);
+
+    assert!(repo
+        .mute_member(&room_alfa, &user_1, Some(until))
+        .await
+        .unwrap());
+    assert!(!repo
+        .mute_member(&room_alfa, &user_3, Some(until))
+        .await
+        .unwrap());
+
+    let muted_until = chat.muted_until(&room_alfa, &user_1).await.unwrap();
+    assert_eq!(
+        muted_until.map(|until| until.timestamp()),
+        Some(until.timestamp())
+    );
+
+    repo.mute_member(&room_alfa, &user_1, None).await.unwrap();
+    assert!(chat
+        .muted_until(&room_alfa, &user_1)
+        .await
+        .unwrap()
+        .is_none());
+}
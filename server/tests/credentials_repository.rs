@@ -0,0 +1,104 @@
+use secrecy::{ExposeSecret, Secret};
+use server::repository::postgres::CredentialsAdapter;
+use server::service::{CredentialsRepository, ExternalIdentity};
+use shared::domain::UserId;
+use sqlx::PgPool;
+
+const USER_1: &str = "2a58d48e-91c2-47e7-9c65-f653c4d4932f";
+
+#[sqlx::test(fixtures("users"))]
+async fn updated_password_hash_is_returned(pool: PgPool) {
+    let repo = CredentialsAdapter::new(pool);
+    let user_1: UserId = USER_1.parse().unwrap();
+
+    repo.update_password(&user_1, Secret::new("new_hash".to_string()))
+        .await
+        .unwrap();
+
+    let hash = repo.get_password_hash(&user_1).await.unwrap().unwrap();
+    assert_eq!(hash.expose_secret(), "new_hash");
+}
+
+#[sqlx::test(fixtures("users"))]
+async fn verified_email_is_reported(pool: PgPool) {
+    let repo = CredentialsAdapter::new(pool);
+    let user_1: UserId = USER_1.parse().unwrap();
+    let (email, verified) = repo.get_email_status(&user_1).await.unwrap().unwrap();
+    assert_eq!(email, "user1@example.com");
+    assert!(!verified);
+
+    repo.verify_email(&user_1).await.unwrap();
+
+    let (_, verified) = repo.get_email_status(&user_1).await.unwrap().unwrap();
+    assert!(verified);
+}
+
+#[sqlx::test(fixtures("users"))]
+async fn totp_step_is_accepted_once(pool: PgPool) {
+    let repo = CredentialsAdapter::new(pool);
+    let user_1: UserId = USER_1.parse().unwrap();
+    assert!(repo.store_totp(&user_1, b"encrypted").await.unwrap());
+    assert!(!repo.use_totp_step(&user_1, 11).await.unwrap());
+
+    repo.confirm_totp(&user_1, 10, &[]).await.unwrap();
+
+    assert!(!repo.store_totp(&user_1, b"other").await.unwrap());
+    assert!(!repo.use_totp_step(&user_1, 10).await.unwrap());
+    assert!(repo.use_totp_step(&user_1, 11).await.unwrap());
+    assert!(!repo.use_totp_step(&user_1, 11).await.unwrap());
+    let totp = repo.get_totp(&user_1).await.unwrap().unwrap();
+    assert_eq!(totp.secret, b"encrypted");
+    assert!(totp.confirmed);
+}
+
+#[sqlx::test(fixtures("users"))]
+async fn recovery_code_is_accepted_once(pool: PgPool) {
+    let repo = CredentialsAdapter::new(pool);
+    let user_1: UserId = USER_1.parse().unwrap();
+    repo.store_totp(&user_1, b"encrypted").await.unwrap();
+    repo.confirm_totp(&user_1, 10, &["a".to_string(), "b".to_string()])
+        .await
+        .unwrap();
+
+    assert!(repo.use_recovery_code(&user_1, "a").await.unwrap());
+    assert!(!repo.use_recovery_code(&user_1, "a").await.unwrap());
+    assert!(!repo.use_recovery_code(&user_1, "c").await.unwrap());
+
+    repo.delete_totp(&user_1).await.unwrap();
+
+    assert!(!repo.use_recovery_code(&user_1, "b").await.unwrap());
+    assert!(repo.get_totp(&user_1).await.unwrap().is_none());
+}
+
+#[sqlx::test(fixtures("users"))]
+async fn external_user_is_found_by_identity(pool: PgPool) {
+    let repo = CredentialsAdapter::new(pool);
+    let identity = ExternalIdentity {
+        provider: "corp".to_string(),
+        subject: "external-1".to_string(),
+        email: Some("external@example.com".to_string()),
+        email_verified: true,
+        name: None,
+    };
+
+    let user = repo
+        .create_external_user(
+            &"external".parse().unwrap(),
+            &"external@example.com".parse().unwrap(),
+            Secret::new("hash".to_string()),
+            &"code".parse().unwrap(),
+            &identity,
+        )
+        .await
+        .unwrap();
+
+    let user_id = repo.get_identity_user("corp", "external-1").await.unwrap();
+    assert_eq!(user_id, Some(user.user_id));
+    let (_, verified) = repo.get_email_status(&user.user_id).await.unwrap().unwrap();
+    assert!(verified);
+    assert!(repo
+        .get_identity_user("other", "external-1")
+        .await
+        .unwrap()
+        .is_none());
+}
@@ -0,0 +1,50 @@
+//! Runs against the Redis from `docker-compose.yml`: `cargo test -- --ignored`.
+
+use std::time::Duration;
+
+use server::configuration::RedisSettings;
+use server::repository::redis::{get_redis_pool, LoginAttemptAdapter};
+use server::service::LoginAttemptRepository;
+use uuid::Uuid;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+fn adapter() -> LoginAttemptAdapter {
+    let settings = RedisSettings {
+        username: None,
+        password: None,
+        port: 6379,
+        host: "127.0.0.1".to_string(),
+        database: None,
+    };
+    LoginAttemptAdapter::new(get_redis_pool(&settings))
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis"]
+async fn failures_are_counted_until_cleared() {
+    let repo = adapter();
+    let key = format!("account:{}", Uuid::new_v4());
+
+    assert_eq!(repo.record_failure(&key, WINDOW).await.unwrap(), 1);
+    assert_eq!(repo.record_failure(&key, WINDOW).await.unwrap(), 2);
+
+    repo.clear(&key).await.unwrap();
+
+    assert_eq!(repo.record_failure(&key, WINDOW).await.unwrap(), 1);
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis"]
+async fn lock_expires_after_its_duration() {
+    let repo = adapter();
+    let key = format!("account:{}", Uuid::new_v4());
+    assert!(repo.locked_for(&key).await.unwrap().is_none());
+
+    repo.lock(&key, Duration::from_millis(200)).await.unwrap();
+
+    let remaining = repo.locked_for(&key).await.unwrap().unwrap();
+    assert!(remaining <= Duration::from_millis(200));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(repo.locked_for(&key).await.unwrap().is_none());
+}
@@ -0,0 +1,53 @@
+use chrono::Utc;
+use server::repository::postgres::NotificationAdapter;
+use server::service::NotificationRepository;
+use shared::domain::{Message, MessageKind, UserId};
+use sqlx::PgPool;
+use std::str::FromStr;
+
+const USER_1: &str = "2a58d48e-91c2-47e7-9c65-f653c4d4932f";
+const USER_2: &str = "cf4ce7bf-624e-45a5-b41a-3988d2d6a926";
+const ROOM_ALFA: &str = "6c76b96f-87bf-4909-8ef7-3c9f4f8312e9";
+const MESSAGE_1: &str = "3e987fa9-7ef3-4c2e-8a34-2da2c1a2a1ca";
+
+fn message_from_user_1(content: &str) -> Message {
+    Message {
+        id: MESSAGE_1.parse().unwrap(),
+        user_id: USER_1.parse().unwrap(),
+        room_id: ROOM_ALFA.parse().unwrap(),
+        content: content.parse().unwrap(),
+        created_at: Utc::now(),
+        kind: MessageKind::User,
+    }
+}
+
+#[sqlx::test(fixtures("users", "roms", "members", "messages"))]
+async fn only_mentioned_room_members_are_notified(pool: PgPool) {
+    let repo = NotificationAdapter::new(pool);
+    let message = message_from_user_1("hi @user2 @user3 @user1");
+    let names = vec![
+        "user2".to_string(),
+        "user3".to_string(),
+        "user1".to_string(),
+    ];
+
+    let notifications = repo.create_for_mentions(&message, &names).await.unwrap();
+
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].user_id, UserId::from_str(USER_2).unwrap());
+}
+
+#[sqlx::test(fixtures("users", "roms", "members", "messages"))]
+async fn read_notifications_are_hidden_from_unread_list(pool: PgPool) {
+    let repo = NotificationAdapter::new(pool);
+    let user_2 = UserId::from_str(USER_2).unwrap();
+    let message = message_from_user_1("hi @user2");
+    repo.create_for_mentions(&message, &["user2".to_string()])
+        .await
+        .unwrap();
+
+    assert_eq!(repo.list(&user_2, true, 10).await.unwrap().len(), 1);
+    assert_eq!(repo.mark_all_read(&user_2).await.unwrap(), 1);
+    assert!(repo.list(&user_2, true, 10).await.unwrap().is_empty());
+    assert_eq!(repo.list(&user_2, false, 10).await.unwrap().len(), 1);
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose, Engine};
+use jsonwebtoken::Algorithm;
+use jwt_simple::prelude::*;
+use reqwest::Url;
+use serde_json::json;
+use server::configuration::{OidcProviderSettings, OidcSettings};
+use server::repository::oidc::HttpOidcClient;
+use server::service::{AuthorizationRequest, OidcClient};
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[derive(Serialize, Deserialize)]
+struct Profile {
+    email: String,
+    email_verified: bool,
+    name: String,
+}
+
+/// Provider that answers the code `code` with an ID token for `external-1`.
+async fn mock_provider(audience: &str, nonce: &str) -> MockServer {
+    let server = MockServer::start().await;
+    let key_pair = Ed25519KeyPair::generate().with_key_id("provider-key");
+
+    Mock::given(method("GET"))
+        .and(path("/.well-known/openid-configuration"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "issuer": server.uri(),
+            "authorization_endpoint": format!("{}/authorize", server.uri()),
+            "token_endpoint": format!("{}/token", server.uri()),
+            "jwks_uri": format!("{}/jwks", server.uri()),
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/jwks"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "keys": [{
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "alg": "EdDSA",
+                "use": "sig",
+                "kid": "provider-key",
+                "x": general_purpose::URL_SAFE_NO_PAD.encode(key_pair.public_key().to_bytes()),
+            }]
+        })))
+        .mount(&server)
+        .await;
+
+    let profile = Profile {
+        email: "user1@example.com".to_string(),
+        email_verified: true,
+        name: "User One".to_string(),
+    };
+    let claims = Claims::with_custom_claims(profile, Duration::from_mins(5))
+        .with_issuer(server.uri())
+        .with_audience(audience)
+        .with_subject("external-1")
+        .with_nonce(nonce);
+    let id_token = key_pair.sign(claims).unwrap();
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .and(body_string_contains("code=code"))
+        .and(body_string_contains("code_verifier=verifier"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "provider-access-token",
+            "token_type": "Bearer",
+            "id_token": id_token,
+        })))
+        .mount(&server)
+        .await;
+
+    server
+}
+
+fn client(issuer_url: String) -> HttpOidcClient {
+    client_allowing(issuer_url, vec![Algorithm::EdDSA])
+}
+
+fn client_allowing(issuer_url: String, id_token_algorithms: Vec<Algorithm>) -> HttpOidcClient {
+    let provider = OidcProviderSettings {
+        issuer_url,
+        client_id: "chat".to_string(),
+        client_secret: None,
+        redirect_url: "http://localhost:8080/oidc".to_string(),
+        scopes: vec!["openid".to_string(), "email".to_string()],
+        id_token_algorithms,
+    };
+    HttpOidcClient::new(&OidcSettings {
+        login_duration: 600,
+        providers: [("corp".to_string(), provider)].into(),
+    })
+}
+
+#[tokio::test]
+async fn authorization_url_carries_pkce_challenge() {
+    let provider = mock_provider("chat", "nonce").await;
+    let client = client(provider.uri());
+    let request = AuthorizationRequest {
+        state: "state".to_string(),
+        nonce: "nonce".to_string(),
+        code_challenge: "challenge".to_string(),
+    };
+
+    let url = client.authorization_url("corp", &request).await.unwrap();
+
+    let url = Url::parse(&url).unwrap();
+    let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+    assert_eq!(url.path(), "/authorize");
+    assert_eq!(query["response_type"], "code");
+    assert_eq!(query["client_id"], "chat");
+    assert_eq!(query["scope"], "openid email");
+    assert_eq!(query["state"], "state");
+    assert_eq!(query["code_challenge"], "challenge");
+    assert_eq!(query["code_challenge_method"], "S256");
+}
+
+#[tokio::test]
+async fn code_is_exchanged_for_identity_from_id_token() {
+    let provider = mock_provider("chat", "nonce").await;
+    let client = client(provider.uri());
+
+    let identity = client
+        .exchange_code("corp", "code", "verifier", "nonce")
+        .await
+        .unwrap();
+
+    assert_eq!(identity.provider, "corp");
+    assert_eq!(identity.subject, "external-1");
+    assert_eq!(identity.email.as_deref(), Some("user1@example.com"));
+    assert!(identity.email_verified);
+    assert_eq!(identity.name.as_deref(), Some("User One"));
+}
+
+#[tokio::test]
+async fn id_token_of_another_login_is_rejected() {
+    let provider = mock_provider("chat", "other-nonce").await;
+    let client = client(provider.uri());
+
+    let result = client
+        .exchange_code("corp", "code", "verifier", "nonce")
+        .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn id_token_for_another_client_is_rejected() {
+    let provider = mock_provider("other-client", "nonce").await;
+    let client = client(provider.uri());
+
+    let result = client
+        .exchange_code("corp", "code", "verifier", "nonce")
+        .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn id_token_signed_with_an_algorithm_not_allowed_is_rejected() {
+    let provider = mock_provider("chat", "nonce").await;
+    let client = client_allowing(provider.uri(), vec![Algorithm::RS256]);
+
+    let result = client
+        .exchange_code("corp", "code", "verifier", "nonce")
+        .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn provider_keys_are_fetched_once() {
+    let provider = mock_provider("chat", "nonce").await;
+    let client = client(provider.uri());
+
+    for _ in 0..2 {
+        client
+            .exchange_code("corp", "code", "verifier", "nonce")
+            .await
+            .unwrap();
+    }
+
+    let key_fetches = provider
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|request| request.url.path() == "/jwks")
+        .count();
+    assert_eq!(key_fetches, 1);
+}
@@ -0,0 +1,163 @@
+use std::time::Duration;
+
+use server::repository::postgres::OutgoingWebhookAdapter;
+use server::service::{HookEventKind, OutgoingWebhookRepository, WebhookEndpoint};
+use shared::domain::{RoomId, UserId};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const USER_1: &str = "2a58d48e-91c2-47e7-9c65-f653c4d4932f";
+const ROOM_ALFA: &str = "6c76b96f-87bf-4909-8ef7-3c9f4f8312e9";
+
+async fn create_endpoint(
+    repo: &OutgoingWebhookAdapter,
+    events: &[HookEventKind],
+) -> WebhookEndpoint {
+    repo.create_endpoint(
+        &Uuid::new_v4(),
+        &ROOM_ALFA.parse().unwrap(),
+        "https://example.com/hook",
+        events,
+        b"encrypted",
+    )
+    .await
+    .unwrap()
+}
+
+#[sqlx::test(fixtures("users", "roms", "members"))]
+async fn only_room_admins_manage_room(pool: PgPool) {
+    let repo = OutgoingWebhookAdapter::new(pool.clone());
+    let user_1: UserId = USER_1.parse().unwrap();
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+
+    assert!(!repo.can_manage_room(&user_1, &room_alfa).await.unwrap());
+
+    sqlx::query("UPDATE members SET is_admin = TRUE WHERE user_id = $1 AND room_id = $2")
+        .bind(user_1.as_ref())
+        .bind(room_alfa.as_ref())
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    assert!(repo.can_manage_room(&user_1, &room_alfa).await.unwrap());
+}
+
+#[sqlx::test(fixtures("users", "roms"))]
+async fn events_are_queued_for_subscribed_endpoints(pool: PgPool) {
+    let repo = OutgoingWebhookAdapter::new(pool);
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+    let endpoint = create_endpoint(&repo, &[HookEventKind::MessageCreated]).await;
+    create_endpoint(&repo, &[HookEventKind::MemberJoined]).await;
+
+    let queued = repo
+        .enqueue(&room_alfa, HookEventKind::MessageCreated, "{}")
+        .await
+        .unwrap();
+
+    assert_eq!(queued, 1);
+    let claimed = repo.claim_due(10, Duration::from_secs(60)).await.unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].delivery.endpoint_id, endpoint.endpoint_id);
+    assert_eq!(claimed[0].delivery.event, HookEventKind::MessageCreated);
+    assert_eq!(claimed[0].encrypted_secret, b"encrypted");
+}
+
+#[sqlx::test(fixtures("users", "roms"))]
+async fn claimed_deliveries_are_leased(pool: PgPool) {
+    let repo = OutgoingWebhookAdapter::new(pool);
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+    create_endpoint(&repo, &[HookEventKind::MemberLeft]).await;
+    repo.enqueue(&room_alfa, HookEventKind::MemberLeft, "{}")
+        .await
+        .unwrap();
+
+    let claimed = repo.claim_due(10, Duration::from_secs(60)).await.unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert!(repo
+        .claim_due(10, Duration::from_secs(60))
+        .await
+        .unwrap()
+        .is_empty());
+
+    let delivery_id = claimed[0].delivery.delivery_id;
+    repo.mark_failed(&delivery_id, "500", Some(chrono::Utc::now()))
+        .await
+        .unwrap();
+    let retried = repo.claim_due(10, Duration::from_secs(60)).await.unwrap();
+    assert_eq!(retried[0].delivery.attempts, 1);
+    assert_eq!(retried[0].delivery.last_error.as_deref(), Some("500"));
+}
+
+#[sqlx::test(fixtures("users", "roms"))]
+async fn delivered_deliveries_are_not_claimed_again(pool: PgPool) {
+    let repo = OutgoingWebhookAdapter::new(pool);
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+    create_endpoint(&repo, &[HookEventKind::MemberLeft]).await;
+    repo.enqueue(&room_alfa, HookEventKind::MemberLeft, "{}")
+        .await
+        .unwrap();
+    let claimed = repo.claim_due(10, Duration::from_secs(0)).await.unwrap();
+
+    repo.mark_delivered(&claimed[0].delivery.delivery_id)
+        .await
+        .unwrap();
+
+    assert!(repo
+        .claim_due(10, Duration::from_secs(0))
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[sqlx::test(fixtures("users", "roms"))]
+async fn dead_letters_can_be_retried(pool: PgPool) {
+    let repo = OutgoingWebhookAdapter::new(pool);
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+    create_endpoint(&repo, &[HookEventKind::MemberJoined]).await;
+    repo.enqueue(&room_alfa, HookEventKind::MemberJoined, "{}")
+        .await
+        .unwrap();
+    let claimed = repo.claim_due(10, Duration::from_secs(0)).await.unwrap();
+    let delivery_id = claimed[0].delivery.delivery_id;
+
+    repo.mark_failed(&delivery_id, "timeout", None)
+        .await
+        .unwrap();
+
+    assert!(repo
+        .claim_due(10, Duration::from_secs(0))
+        .await
+        .unwrap()
+        .is_empty());
+    let dead = repo.list_dead(&room_alfa).await.unwrap();
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0].last_error.as_deref(), Some("timeout"));
+
+    assert!(repo.retry_dead(&room_alfa, &delivery_id).await.unwrap());
+
+    assert!(repo.list_dead(&room_alfa).await.unwrap().is_empty());
+    let claimed = repo.claim_due(10, Duration::from_secs(0)).await.unwrap();
+    assert_eq!(claimed[0].delivery.attempts, 0);
+}
+
+#[sqlx::test(fixtures("users", "roms"))]
+async fn deleting_endpoint_drops_its_deliveries(pool: PgPool) {
+    let repo = OutgoingWebhookAdapter::new(pool);
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+    let endpoint = create_endpoint(&repo, &[HookEventKind::MemberJoined]).await;
+    repo.enqueue(&room_alfa, HookEventKind::MemberJoined, "{}")
+        .await
+        .unwrap();
+
+    assert!(repo
+        .delete_endpoint(&room_alfa, &endpoint.endpoint_id)
+        .await
+        .unwrap());
+
+    assert!(repo.list_endpoints(&room_alfa).await.unwrap().is_empty());
+    assert!(repo
+        .claim_due(10, Duration::from_secs(0))
+        .await
+        .unwrap()
+        .is_empty());
+}
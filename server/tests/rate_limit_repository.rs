@@ -0,0 +1,72 @@
+//! Runs against the Redis from `docker-compose.yml`: `cargo test -- --ignored`.
+
+use std::time::Duration;
+
+use server::configuration::{RedisSettings, TokenBucketSettings};
+use server::repository::redis::{get_redis_pool, RateLimitAdapter};
+use server::service::RateLimitRepository;
+use uuid::Uuid;
+
+fn adapter() -> RateLimitAdapter {
+    let settings = RedisSettings {
+        username: None,
+        password: None,
+        port: 6379,
+        host: "127.0.0.1".to_string(),
+        database: None,
+    };
+    RateLimitAdapter::new(get_redis_pool(&settings))
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis"]
+async fn bucket_empties_after_its_capacity() {
+    let repo = adapter();
+    let key = format!("test:{}", Uuid::new_v4());
+    let bucket = TokenBucketSettings {
+        capacity: 2,
+        refill_per_second: 0.5,
+    };
+
+    assert!(repo.acquire(&key, &bucket).await.unwrap().is_none());
+    assert!(repo.acquire(&key, &bucket).await.unwrap().is_none());
+
+    let retry_after = repo.acquire(&key, &bucket).await.unwrap().unwrap();
+    assert!(retry_after > Duration::from_millis(1500));
+    assert!(retry_after <= Duration::from_secs(2));
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis"]
+async fn bucket_refills_over_time() {
+    let repo = adapter();
+    let key = format!("test:{}", Uuid::new_v4());
+    let bucket = TokenBucketSettings {
+        capacity: 1,
+        refill_per_second: 10.0,
+    };
+
+    assert!(repo.acquire(&key, &bucket).await.unwrap().is_none());
+    assert!(repo.acquire(&key, &bucket).await.unwrap().is_some());
+
+    tokio::time::sleep(Duration::from_millis(150)).await;
+
+    assert!(repo.acquire(&key, &bucket).await.unwrap().is_none());
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis"]
+async fn buckets_are_independent_per_key() {
+    let repo = adapter();
+    let bucket = TokenBucketSettings {
+        capacity: 1,
+        refill_per_second: 0.1,
+    };
+    let first = format!("test:{}", Uuid::new_v4());
+    let second = format!("test:{}", Uuid::new_v4());
+
+    assert!(repo.acquire(&first, &bucket).await.unwrap().is_none());
+    assert!(repo.acquire(&first, &bucket).await.unwrap().is_some());
+
+    assert!(repo.acquire(&second, &bucket).await.unwrap().is_none());
+}
@@ -0,0 +1,183 @@
+use chrono::{TimeZone, Utc};
+use server::repository::postgres::ChatAdapter;
+use server::service::{Attachment, ChatRepository, RoomChange};
+use shared::domain::{MessageKind, NewMessage, RoomId, UserId};
+use sqlx::PgPool;
+
+const USER_1: &str = "2a58d48e-91c2-47e7-9c65-f653c4d4932f";
+const USER_3: &str = "d4e3c6a7-9715-4a45-93c8-7b1b14ebf831";
+const ROOM_ALFA: &str = "6c76b96f-87bf-4909-8ef7-3c9f4f8312e9";
+
+fn record(content: &str) -> NewMessage {
+    NewMessage {
+        user_id: USER_1.parse().unwrap(),
+        room_id: ROOM_ALFA.parse().unwrap(),
+        content: content.parse().unwrap(),
+    }
+}
+
+fn avatar(data: &[u8]) -> RoomChange {
+    RoomChange::Avatar(Some(Attachment {
+        content_type: "image/png".to_string(),
+        data: data.to_vec(),
+    }))
+}
+
+#[sqlx::test(fixtures("users", "roms", "members"))]
+async fn rooms_keep_their_creation_time(pool: PgPool) {
+    let repo = ChatAdapter::new(pool);
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+
+    let room = repo.get_room(&room_alfa).await.unwrap().unwrap();
+
+    assert_eq!(
+        room.created_at,
+        Utc.with_ymd_and_hms(2023, 10, 6, 12, 0, 0).unwrap()
+    );
+    assert_eq!(room.created_by, None);
+    assert!(room.topic.is_none() && room.description.is_none() && room.avatar.is_none());
+}
+
+#[sqlx::test(fixtures("users", "roms", "members"))]
+async fn created_rooms_record_their_creator(pool: PgPool) {
+    let repo = ChatAdapter::new(pool);
+    let user_1: UserId = USER_1.parse().unwrap();
+
+    let room = repo
+        .create_room(user_1, "delta".parse().unwrap(), "5".parse().unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(room.created_by, Some(user_1));
+    assert!(repo.can_manage_room(&user_1, &room.id).await.unwrap());
+}
+
+#[sqlx::test(fixtures("users", "roms", "members"))]
+async fn changes_are_stored_with_a_system_message(pool: PgPool) {
+    let repo = ChatAdapter::new(pool);
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+    let topic = "Release on Friday".parse().unwrap();
+
+    let (room, message) = repo
+        .update_room(
+            &RoomChange::Topic(Some(topic)),
+            &record("changed the room topic"),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(room.topic.unwrap().as_ref(), "Release on Friday");
+    assert_eq!(message.kind, MessageKind::System);
+    let history = repo.get_messages(&room_alfa, 10).await.unwrap();
+    assert_eq!(history.last().unwrap().id, message.id);
+}
+
+#[sqlx::test(fixtures("users", "roms", "members"))]
+async fn replaced_avatars_are_deleted(pool: PgPool) {
+    let repo = ChatAdapter::new(pool);
+    let user_1: UserId = USER_1.parse().unwrap();
+
+    let (first, _) = repo
+        .update_room(&avatar(b"first"), &record("changed the room avatar"))
+        .await
+        .unwrap()
+        .unwrap();
+    let (second, _) = repo
+        .update_room(&avatar(b"second"), &record("changed the room avatar"))
+        .await
+        .unwrap()
+        .unwrap();
+    let first = first.avatar.unwrap();
+    let second = second.avatar.unwrap();
+
+    assert!(repo
+        .get_attachment(&first, &user_1)
+        .await
+        .unwrap()
+        .is_none());
+    let stored = repo
+        .get_attachment(&second, &user_1)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.data, b"second");
+
+    let (room, _) = repo
+        .update_room(
+            &RoomChange::Avatar(None),
+            &record("removed the room avatar"),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(room.avatar.is_none());
+    assert!(repo
+        .get_attachment(&second, &user_1)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[sqlx::test(fixtures("users", "roms", "members"))]
+async fn missing_rooms_are_not_updated(pool: PgPool) {
+    let repo = ChatAdapter::new(pool);
+    let record = NewMessage {
+        room_id: uuid::Uuid::new_v4().into(),
+        ..record("changed the room avatar")
+    };
+
+    let updated = repo.update_room(&avatar(b"avatar"), &record).await.unwrap();
+
+    assert!(updated.is_none());
+}
+
+#[sqlx::test(fixtures("users", "roms", "members"))]
+async fn attachments_are_only_given_to_members_of_their_rooms(pool: PgPool) {
+    let repo = ChatAdapter::new(pool);
+    let member: UserId = USER_1.parse().unwrap();
+    let outsider: UserId = USER_3.parse().unwrap();
+
+    let (room, _) = repo
+        .update_room(&avatar(b"avatar"), &record("changed the room avatar"))
+        .await
+        .unwrap()
+        .unwrap();
+    let avatar = room.avatar.unwrap();
+
+    assert!(repo
+        .get_attachment(&avatar, &member)
+        .await
+        .unwrap()
+        .is_some());
+    assert!(repo
+        .get_attachment(&avatar, &outsider)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[sqlx::test(fixtures("users", "roms", "members"))]
+async fn members_are_made_room_admins(pool: PgPool) {
+    let repo = ChatAdapter::new(pool);
+    let user_1: UserId = USER_1.parse().unwrap();
+    let room_alfa: RoomId = ROOM_ALFA.parse().unwrap();
+    let stranger: UserId = uuid::Uuid::new_v4().into();
+    assert!(!repo.can_manage_room(&user_1, &room_alfa).await.unwrap());
+
+    assert!(repo
+        .set_room_admin(&room_alfa, &user_1, true)
+        .await
+        .unwrap());
+    assert!(repo.can_manage_room(&user_1, &room_alfa).await.unwrap());
+
+    assert!(repo
+        .set_room_admin(&room_alfa, &user_1, false)
+        .await
+        .unwrap());
+    assert!(!repo.can_manage_room(&user_1, &room_alfa).await.unwrap());
+    assert!(!repo
+        .set_room_admin(&room_alfa, &stranger, true)
+        .await
+        .unwrap());
+}
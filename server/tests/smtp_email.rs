@@ -0,0 +1,106 @@
+//! Sends through a minimal SMTP server listening on localhost.
+
+use secrecy::Secret;
+use server::configuration::EmailSettings;
+use server::repository::smtp::SmtpEmailSender;
+use server::service::{Email, EmailSender};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+/// Accepts a single session and returns the envelope recipients and the message data.
+async fn mock_smtp_server() -> (u16, oneshot::Receiver<(Vec<String>, String)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (tx, rx) = oneshot::channel();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = socket.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut recipients = Vec::new();
+        let mut data = String::new();
+
+        writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            let command = line.to_ascii_uppercase();
+            let reply: &[u8] = if command.starts_with("EHLO") || command.starts_with("HELO") {
+                b"250-localhost\r\n250 AUTH PLAIN\r\n"
+            } else if command.starts_with("AUTH") {
+                b"235 Authenticated\r\n"
+            } else if command.starts_with("RCPT TO:") {
+                recipients.push(line["RCPT TO:".len()..].to_string());
+                b"250 OK\r\n"
+            } else if command.starts_with("DATA") {
+                writer
+                    .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                    .await
+                    .unwrap();
+                while let Some(line) = lines.next_line().await.unwrap() {
+                    if line == "." {
+                        break;
+                    }
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                b"250 OK\r\n"
+            } else if command.starts_with("QUIT") {
+                writer.write_all(b"221 Bye\r\n").await.unwrap();
+                break;
+            } else {
+                b"250 OK\r\n"
+            };
+            writer.write_all(reply).await.unwrap();
+        }
+        let _ = tx.send((recipients, data));
+    });
+
+    (port, rx)
+}
+
+fn settings(port: u16) -> EmailSettings {
+    EmailSettings {
+        host: "127.0.0.1".to_string(),
+        port,
+        username: Some("chat".to_string()),
+        password: Some(Secret::new("password".to_string())),
+        sender: "Chat <no-reply@chat.local>".to_string(),
+        starttls: false,
+    }
+}
+
+#[tokio::test]
+async fn email_is_delivered_over_smtp() {
+    let (port, received) = mock_smtp_server().await;
+    let sender = SmtpEmailSender::new(&settings(port)).unwrap();
+
+    sender
+        .send(Email {
+            to: "user1@example.com".to_string(),
+            subject: "Reset your password".to_string(),
+            body: "Open the link.".to_string(),
+        })
+        .await
+        .unwrap();
+
+    let (recipients, data) = received.await.unwrap();
+    assert_eq!(recipients, vec!["<user1@example.com>".to_string()]);
+    assert!(data.contains("From: Chat <no-reply@chat.local>"));
+    assert!(data.contains("Subject: Reset your password"));
+    assert!(data.contains("Open the link."));
+}
+
+#[tokio::test]
+async fn invalid_recipient_is_rejected_before_sending() {
+    let sender = SmtpEmailSender::new(&settings(1)).unwrap();
+
+    let result = sender
+        .send(Email {
+            to: "not an address".to_string(),
+            subject: "Hi".to_string(),
+            body: "Hi".to_string(),
+        })
+        .await;
+
+    assert!(result.is_err());
+}
@@ -0,0 +1,240 @@
+//! Runs against the Redis from `docker-compose.yml`: `cargo test -- --ignored`.
+
+use std::time::Duration;
+
+use server::configuration::RedisSettings;
+use server::repository::redis::{get_redis_pool, TokenAdapter};
+use server::service::{ClientInfo, Rotation, SocketTicket, TokenRepository, UserTokenKind};
+use shared::domain::UserId;
+use uuid::Uuid;
+
+const TTL: Duration = Duration::from_secs(60);
+
+fn adapter() -> TokenAdapter {
+    let settings = RedisSettings {
+        username: None,
+        password: None,
+        port: 6379,
+        host: "127.0.0.1".to_string(),
+        database: None,
+    };
+    TokenAdapter::new(get_redis_pool(&settings))
+}
+
+fn client() -> ClientInfo {
+    ClientInfo {
+        device_label: Some("laptop".to_string()),
+        ip: Some([127, 0, 0, 1].into()),
+        user_agent: Some("tests".to_string()),
+    }
+}
+
+fn user() -> UserId {
+    Uuid::new_v4().into()
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis"]
+async fn rotated_out_token_is_reported_as_reused() {
+    let repo = adapter();
+    let user_id = user();
+    let (family_id, first, second) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    repo.create(&family_id, &first, &user_id, TTL, &client())
+        .await
+        .unwrap();
+
+    let rotated = repo
+        .rotate(&family_id, &first, &second, &user_id, TTL, &client())
+        .await
+        .unwrap();
+    let replayed = repo
+        .rotate(
+            &family_id,
+            &first,
+            &Uuid::new_v4(),
+            &user_id,
+            TTL,
+            &client(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(rotated, Rotation::Rotated);
+    assert_eq!(replayed, Rotation::Reused);
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis"]
+async fn missing_family_is_not_found() {
+    let repo = adapter();
+    let user_id = user();
+    let family_id = Uuid::new_v4();
+
+    let session = repo.get(&family_id, &user_id).await.unwrap();
+    let rotation = repo
+        .rotate(
+            &family_id,
+            &Uuid::new_v4(),
+            &Uuid::new_v4(),
+            &user_id,
+            TTL,
+            &client(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(session, None);
+    assert_eq!(rotation, Rotation::Unknown);
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis"]
+async fn family_expires_after_given_ttl() {
+    let repo = adapter();
+    let user_id = user();
+    let family_id = Uuid::new_v4();
+    repo.create(
+        &family_id,
+        &Uuid::new_v4(),
+        &user_id,
+        Duration::from_secs(1),
+        &client(),
+    )
+    .await
+    .unwrap();
+    assert!(repo.get(&family_id, &user_id).await.unwrap().is_some());
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+
+    assert!(repo.get(&family_id, &user_id).await.unwrap().is_none());
+    assert!(repo.list(&user_id).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis"]
+async fn revoke_all_ends_every_session_of_user() {
+    let repo = adapter();
+    let user_id = user();
+    let other_user = user();
+    for user_id in [user_id, user_id, other_user] {
+        repo.create(&Uuid::new_v4(), &Uuid::new_v4(), &user_id, TTL, &client())
+            .await
+            .unwrap();
+    }
+    assert_eq!(repo.list(&user_id).await.unwrap().len(), 2);
+
+    let revoked = repo.revoke_all(&user_id).await.unwrap();
+
+    assert_eq!(revoked, 2);
+    assert!(repo.list(&user_id).await.unwrap().is_empty());
+    assert_eq!(repo.list(&other_user).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis"]
+async fn rotation_records_latest_client() {
+    let repo = adapter();
+    let user_id = user();
+    let (family_id, first, second) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    repo.create(&family_id, &first, &user_id, TTL, &client())
+        .await
+        .unwrap();
+
+    let moved = ClientInfo {
+        ip: Some([10, 0, 0, 2].into()),
+        user_agent: None,
+        ..client()
+    };
+    repo.rotate(&family_id, &first, &second, &user_id, TTL, &moved)
+        .await
+        .unwrap();
+
+    let session = repo.get(&family_id, &user_id).await.unwrap().unwrap();
+    assert_eq!(session.client, moved);
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis"]
+async fn revoked_access_tokens_are_reported() {
+    let repo = adapter();
+    let user_id = user();
+    let (revoked, kept) = (Uuid::new_v4(), Uuid::new_v4());
+    let issued_at = chrono::Utc::now().timestamp() as u64;
+
+    repo.revoke_access(&revoked, TTL).await.unwrap();
+
+    assert!(repo
+        .is_access_revoked(&revoked, &user_id, issued_at)
+        .await
+        .unwrap());
+    assert!(!repo
+        .is_access_revoked(&kept, &user_id, issued_at)
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis"]
+async fn user_revocation_rejects_tokens_issued_before_it() {
+    let repo = adapter();
+    let user_id = user();
+    let now = chrono::Utc::now().timestamp() as u64;
+
+    repo.revoke_user_access(&user_id, TTL).await.unwrap();
+
+    assert!(repo
+        .is_access_revoked(&Uuid::new_v4(), &user_id, now - 10)
+        .await
+        .unwrap());
+    assert!(!repo
+        .is_access_revoked(&Uuid::new_v4(), &user_id, now + 10)
+        .await
+        .unwrap());
+    let reissued_at = chrono::Utc::now().timestamp() as u64;
+    assert!(!repo
+        .is_access_revoked(&Uuid::new_v4(), &user_id, reissued_at)
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis"]
+async fn ticket_can_be_taken_once() {
+    let repo = adapter();
+    let ticket = Uuid::new_v4().to_string();
+    let owner = SocketTicket {
+        user_id: user(),
+        session_id: Uuid::new_v4(),
+    };
+
+    repo.store_ticket(&ticket, &owner, TTL).await.unwrap();
+
+    assert_eq!(repo.take_ticket(&ticket).await.unwrap(), Some(owner));
+    assert_eq!(repo.take_ticket(&ticket).await.unwrap(), None);
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis"]
+async fn user_token_can_be_taken_once_for_its_kind() {
+    let repo = adapter();
+    let token = Uuid::new_v4().to_string();
+    let user_id = user();
+
+    let kind = UserTokenKind::PasswordReset;
+
+    repo.store_user_token(kind, &token, &user_id, TTL)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        repo.take_user_token(UserTokenKind::EmailVerification, &token)
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        repo.take_user_token(kind, &token).await.unwrap(),
+        Some(user_id)
+    );
+    assert_eq!(repo.take_user_token(kind, &token).await.unwrap(), None);
+}
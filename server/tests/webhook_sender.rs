@@ -0,0 +1,145 @@
+use secrecy::Secret;
+use server::configuration::WebhookSettings;
+use server::repository::http::HttpWebhookSender;
+use server::service::{WebhookRequest, WebhookSender, SIGNATURE_HEADER};
+use wiremock::matchers::{body_string, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn sender() -> HttpWebhookSender {
+    sender_allowing_private_addresses(true)
+}
+
+fn sender_allowing_private_addresses(allow_private_addresses: bool) -> HttpWebhookSender {
+    HttpWebhookSender::new(&WebhookSettings {
+        encryption_key: Secret::new(String::new()),
+        poll_interval_ms: 1000,
+        batch_size: 10,
+        timeout_ms: 1000,
+        max_attempts: 3,
+        base_backoff_secs: 10,
+        max_backoff_secs: 60,
+        allow_private_addresses,
+    })
+    .unwrap()
+}
+
+fn request(server: &MockServer) -> WebhookRequest {
+    WebhookRequest {
+        url: format!("{}/hook", server.uri()),
+        headers: vec![(SIGNATURE_HEADER, "sha256=abc".to_string())],
+        body: r#"{"event":"member.joined"}"#.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn delivery_is_posted_as_json_with_signature() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .and(header("content-type", "application/json"))
+        .and(header(SIGNATURE_HEADER, "sha256=abc"))
+        .and(body_string(r#"{"event":"member.joined"}"#))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    sender().send(&request(&server)).await.unwrap();
+}
+
+#[tokio::test]
+async fn error_status_fails_delivery() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+
+    let result = sender().send(&request(&server)).await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn slow_endpoint_times_out() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(3)))
+        .mount(&server)
+        .await;
+
+    let result = sender().send(&request(&server)).await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn redirect_is_not_followed() {
+    let server = MockServer::start().await;
+    Mock::given(path("/hook"))
+        .respond_with(ResponseTemplate::new(307).insert_header("location", "/elsewhere"))
+        .mount(&server)
+        .await;
+    Mock::given(path("/elsewhere"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let result = sender().send(&request(&server)).await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn urls_into_the_network_of_the_server_are_refused() {
+    let sender = sender_allowing_private_addresses(false);
+    for url in [
+        "http://127.0.0.1/hook",
+        "http://localhost:8080/hook",
+        "http://10.1.2.3/hook",
+        "http://172.16.0.1/hook",
+        "http://192.168.1.1/hook",
+        "http://169.254.169.254/latest/meta-data",
+        "http://100.64.0.1/hook",
+        "http://0.0.0.0/hook",
+        "http://[::1]/hook",
+        "http://[fe80::1]/hook",
+        "http://[fd00::1]/hook",
+        "http://[::ffff:127.0.0.1]/hook",
+    ] {
+        assert!(sender.check_url(url).await.is_err(), "{} was allowed", url);
+    }
+}
+
+#[tokio::test]
+async fn public_address_is_allowed() {
+    let sender = sender_allowing_private_addresses(false);
+
+    sender
+        .check_url("https://93.184.216.34/hook")
+        .await
+        .unwrap();
+    sender
+        .check_url("http://[2606:2800:220:1::1]/hook")
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn delivery_to_private_address_is_refused() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(0)
+        .mount(&server)
+        .await;
+    let sender = sender_allowing_private_addresses(false);
+    let by_name = WebhookRequest {
+        url: format!("http://localhost:{}/hook", server.address().port()),
+        ..request(&server)
+    };
+
+    assert!(sender.send(&request(&server)).await.is_err());
+    assert!(sender.send(&by_name).await.is_err());
+}
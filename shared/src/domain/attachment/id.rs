@@ -0,0 +1,28 @@
+use std::str::FromStr;
+
+use crate::domain;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct AttachmentId(uuid::Uuid);
+
+impl FromStr for AttachmentId {
+    type Err = domain::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(uuid::Uuid::from_str(s).map_err(|e| {
+            domain::Error::ValidationError(e.to_string())
+        })?))
+    }
+}
+
+impl From<uuid::Uuid> for AttachmentId {
+    fn from(v: uuid::Uuid) -> Self {
+        Self(v)
+    }
+}
+
+impl AsRef<uuid::Uuid> for AttachmentId {
+    fn as_ref(&self) -> &uuid::Uuid {
+        &self.0
+    }
+}
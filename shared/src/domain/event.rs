@@ -1,28 +1,210 @@
+use std::{collections::BTreeMap, time::Duration};
+
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{Message, MessageContent, MessageKind, Notification, Room, RoomId, UserId};
 
-use super::{Message, MessageContent, Room, User, UserId};
+/// Version of the socket protocol spoken by this build.
+pub const PROTOCOL_VERSION: u16 = 1;
+/// Oldest protocol version this build still accepts from a peer.
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub enum ClientEvent {
     Join(JoinRequest),
     SendMessage(MessageContent),
+    Hello(Hello),
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum ServerEvent {
     ErrMessage(ServerError),
     Join(JoinResponse),
     UserJoin(UserJoinResponse),
     ReceivedMessage(Message),
+    Notification(Notification),
+    Welcome(Welcome),
+    SessionRevoked(SessionRevoked),
+    CommandReply(CommandReply),
+    /// The room's details after a change, followed by the system message recording it.
+    RoomUpdated(Room),
+    /// The user was removed from the room, their connections to it end.
+    RemovedFromRoom(RemovedFromRoom),
+}
+
+impl ServerEvent {
+    /// Whether the server closes the connection after sending this event.
+    pub fn ends_connection(&self) -> bool {
+        matches!(self, Self::SessionRevoked(_))
+    }
+
+    /// The room this event ends the membership of, connections to other rooms ignore it.
+    pub fn removed_from(&self) -> Option<&RoomId> {
+        match self {
+            Self::RemovedFromRoom(ev) => Some(&ev.room_id),
+            _ => None,
+        }
+    }
+
+    /// Whether a connection opened with the tokens of `session_id` receives this event.
+    pub fn is_for_session(&self, session_id: &Uuid) -> bool {
+        match self {
+            Self::SessionRevoked(ev) => ev.applies_to(session_id),
+            _ => true,
+        }
+    }
+
+    /// Capability a client must have negotiated to receive this event.
+    pub fn required_capability(&self) -> Option<Capability> {
+        match self {
+            Self::Notification(_) => Some(Capability::Notifications),
+            Self::CommandReply(_) => Some(Capability::Commands),
+            Self::RoomUpdated(_) => Some(Capability::RoomUpdates),
+            Self::ReceivedMessage(message) if message.kind == MessageKind::System => {
+                Some(Capability::RoomUpdates)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Optional protocol features. Clients list the ones they understand in their `Hello`,
+/// the server answers with the ones it will use.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Capability {
+    Notifications,
+    /// Replies to slash commands, shown to the user who ran them only.
+    Commands,
+    /// Room detail updates and the system messages recording them.
+    RoomUpdates,
+    /// Any capability this build does not know about.
+    #[serde(other)]
+    Unknown,
+}
+
+impl Capability {
+    /// Capabilities supported by this build.
+    pub fn supported() -> Vec<Capability> {
+        vec![
+            Capability::Notifications,
+            Capability::Commands,
+            Capability::RoomUpdates,
+        ]
+    }
+}
+
+/// First event a client sends on a socket.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Hello {
+    pub version: u16,
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+}
+
+impl Default for Hello {
+    fn default() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            capabilities: Capability::supported(),
+        }
+    }
+}
+
+/// Server answer to `Hello` with the negotiated version and capabilities.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Welcome {
+    pub version: u16,
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+}
+
+/// Decodes an event while tolerating variants added by newer peers.
+///
+/// Only self-describing formats (JSON) can skip an unknown event, binary formats fail
+/// to decode it instead.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum Tolerant<E> {
+    Known(E),
+    Unknown(UnknownEvent),
+}
+
+impl<E> Tolerant<E> {
+    pub fn known(self) -> Option<E> {
+        match self {
+            Self::Known(event) => Some(event),
+            Self::Unknown(_) => None,
+        }
+    }
+}
+
+/// Event this build could not decode. `name` is its tag, when it has one.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "RawUnknownEvent")]
+pub struct UnknownEvent {
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawUnknownEvent {
+    Unit(String),
+    Tagged(BTreeMap<String, IgnoredAny>),
+    Other(IgnoredAny),
+}
+
+impl From<RawUnknownEvent> for UnknownEvent {
+    fn from(raw: RawUnknownEvent) -> Self {
+        let name = match raw {
+            RawUnknownEvent::Unit(name) => Some(name),
+            RawUnknownEvent::Tagged(map) => map.into_keys().next(),
+            RawUnknownEvent::Other(_) => None,
+        };
+        Self { name }
+    }
+}
+
+/// Event sent on the per-user stream, which multiplexes every subscribed room.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum StreamClientEvent {
+    Subscribe(RoomId),
+    Unsubscribe(RoomId),
+    Room(RoomEvent<ClientEvent>),
+    Hello(Hello),
+}
+
+/// Event received on the per-user stream. Room events are tagged with their room,
+/// events addressed to the user directly are not.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum StreamServerEvent {
+    Subscribed(RoomId),
+    Unsubscribed(RoomId),
+    Room(RoomEvent<ServerEvent>),
+    User(ServerEvent),
+    Welcome(Welcome),
+}
+
+impl StreamServerEvent {
+    /// Whether the server closes the stream after sending this event.
+    pub fn ends_stream(&self) -> bool {
+        matches!(self, Self::User(event) if event.ends_connection())
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RoomEvent<E> {
+    pub room_id: RoomId,
+    pub event: E,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct JoinRequest {
     pub join_at: DateTime<Utc>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct JoinResponse {
     pub user_id: UserId,
     // pub room: Room,
@@ -30,7 +212,7 @@ pub struct JoinResponse {
     // pub messages: Vec<Message>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct UserJoinResponse {
     pub user_id: UserId,
     // pub room: Room,
@@ -38,7 +220,104 @@ pub struct UserJoinResponse {
     // pub messages: Vec<Message>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Sent to the connections of a signed out session right before they are closed.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SessionRevoked {
+    /// The revoked session, `None` when every session of the user was revoked.
+    pub session_id: Option<Uuid>,
+}
+
+impl SessionRevoked {
+    pub fn applies_to(&self, session_id: &Uuid) -> bool {
+        self.session_id.map_or(true, |id| id == *session_id)
+    }
+}
+
+/// Sent to the connections of a user removed from a room, before those to the room close.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RemovedFromRoom {
+    pub room_id: RoomId,
+}
+
+/// Answer to a slash command, for the user who ran it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CommandReply {
+    pub command: String,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ServerError {
+    /// First, so that binary clients from before error codes still decode it.
     pub message: String,
+    #[serde(default)]
+    pub code: ErrorCode,
+    /// Set on `RateLimited` errors, the event can be sent again after this delay. Always
+    /// encoded, binary formats cannot skip a field.
+    #[serde(default)]
+    pub retry_after_ms: Option<u64>,
+}
+
+impl ServerError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            retry_after_ms: None,
+        }
+    }
+
+    pub fn rate_limited(retry_after: Duration) -> Self {
+        Self {
+            code: ErrorCode::RateLimited,
+            message: "Too many events, slow down.".to_string(),
+            retry_after_ms: Some(retry_after.as_millis() as u64),
+        }
+    }
+}
+
+/// Machine readable kind of a `ServerError`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(from = "WireErrorCode")]
+pub enum ErrorCode {
+    NotFound,
+    NotSubscribed,
+    RateLimited,
+    UnsupportedEvent,
+    UnsupportedVersion,
+    /// Errors without a dedicated code, including codes added by newer servers.
+    #[default]
+    Other,
+    /// The user may not do this, e.g. before verifying their email.
+    Forbidden,
+}
+
+/// Decodes an `ErrorCode`, with a last catch-all for codes added by newer servers since
+/// `Other` cannot move.
+#[derive(Deserialize)]
+#[serde(rename = "ErrorCode")]
+enum WireErrorCode {
+    NotFound,
+    NotSubscribed,
+    RateLimited,
+    UnsupportedEvent,
+    UnsupportedVersion,
+    Other,
+    Forbidden,
+    #[serde(other)]
+    Unknown,
+}
+
+impl From<WireErrorCode> for ErrorCode {
+    fn from(code: WireErrorCode) -> Self {
+        match code {
+            WireErrorCode::NotFound => Self::NotFound,
+            WireErrorCode::NotSubscribed => Self::NotSubscribed,
+            WireErrorCode::RateLimited => Self::RateLimited,
+            WireErrorCode::UnsupportedEvent => Self::UnsupportedEvent,
+            WireErrorCode::UnsupportedVersion => Self::UnsupportedVersion,
+            WireErrorCode::Forbidden => Self::Forbidden,
+            WireErrorCode::Other | WireErrorCode::Unknown => Self::Other,
+        }
+    }
 }
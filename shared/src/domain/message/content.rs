@@ -4,8 +4,10 @@ use unicode_segmentation::UnicodeSegmentation;
 use crate::domain;
 
 const MAX_MESSAGE_CONTENT_SIZE: usize = 255;
+const MENTION_PREFIX: char = '@';
+const COMMAND_PREFIX: char = '/';
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, Clone)]
 pub struct MessageContent(String);
 
 impl FromStr for MessageContent {
@@ -35,6 +37,35 @@ impl TryFrom<String> for MessageContent {
     }
 }
 
+impl MessageContent {
+    /// Returns the distinct user names mentioned as `@name`, in order of appearance.
+    pub fn mentions(&self) -> Vec<&str> {
+        let mut mentions: Vec<&str> = Vec::new();
+        for word in self.0.split_whitespace() {
+            let Some(name) = word.strip_prefix(MENTION_PREFIX) else {
+                continue;
+            };
+            let name = name.trim_end_matches(|c: char| c.is_ascii_punctuation());
+            if !name.is_empty() && !mentions.contains(&name) {
+                mentions.push(name);
+            }
+        }
+        mentions
+    }
+
+    /// Splits `/name args` into the command name and its trimmed arguments, returns
+    /// `None` for plain messages such as `/usr/bin` or `// comment`.
+    pub fn command(&self) -> Option<(&str, &str)> {
+        let rest = self.0.strip_prefix(COMMAND_PREFIX)?;
+        let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let is_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        is_name.then(|| (name, args.trim()))
+    }
+}
+
 impl AsRef<str> for MessageContent {
     fn as_ref(&self) -> &str {
         &self.0
@@ -75,4 +106,33 @@ mod room_code_tests {
         let name = "Code".to_string();
         assert_ok!(name.parse::<MessageContent>());
     }
+
+    #[test]
+    fn mentions_are_extracted_without_punctuation() {
+        let content: MessageContent = "hi @alice, @bob! and @alice again".parse().unwrap();
+        assert_eq!(content.mentions(), vec!["alice", "bob"]);
+    }
+
+    #[test]
+    fn commands_are_split_into_name_and_arguments() {
+        let content: MessageContent = "/kick  @bob  spamming ".parse().unwrap();
+        assert_eq!(content.command(), Some(("kick", "@bob  spamming")));
+
+        let content: MessageContent = "/help".parse().unwrap();
+        assert_eq!(content.command(), Some(("help", "")));
+    }
+
+    #[test]
+    fn paths_and_slash_prefixed_text_are_not_commands() {
+        for text in ["/usr/bin is full", "// comment", "/ hi", "hi /me"] {
+            let content: MessageContent = text.parse().unwrap();
+            assert_eq!(content.command(), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn bare_at_sign_and_emails_are_not_mentions() {
+        let content: MessageContent = "@ mail me at bob@example.com".parse().unwrap();
+        assert!(content.mentions().is_empty());
+    }
 }
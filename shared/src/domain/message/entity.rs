@@ -4,13 +4,48 @@ use crate::domain::{RoomId, UserId};
 
 use super::{MessageContent, MessageId};
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct Message {
     pub id: MessageId,
     pub user_id: UserId,
     pub room_id: RoomId,
     pub content: MessageContent,
     pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub kind: MessageKind,
+}
+
+/// Who a message was sent by, for clients to render bot and system messages apart. Kinds
+/// added by newer servers decode as `User`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", from = "WireMessageKind")]
+pub enum MessageKind {
+    #[default]
+    User,
+    Bot,
+    /// Records a change to the room, made by the message's user.
+    System,
+}
+
+/// Decodes a `MessageKind`, with a last catch-all for kinds added by newer servers.
+#[derive(serde::Deserialize)]
+#[serde(rename = "MessageKind", rename_all = "snake_case")]
+enum WireMessageKind {
+    User,
+    Bot,
+    System,
+    #[serde(other)]
+    Unknown,
+}
+
+impl From<WireMessageKind> for MessageKind {
+    fn from(kind: WireMessageKind) -> Self {
+        match kind {
+            WireMessageKind::User | WireMessageKind::Unknown => Self::User,
+            WireMessageKind::Bot => Self::Bot,
+            WireMessageKind::System => Self::System,
+        }
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -19,3 +54,45 @@ pub struct NewMessage {
     pub room_id: RoomId,
     pub content: MessageContent,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_from_before_kinds_are_user_messages() {
+        let message: Message = serde_json::from_value(serde_json::json!({
+            "id": "5e6f7a8b-9c0d-4e1f-a2b3-c4d5e6f7a8b9",
+            "user_id": "1f2e3d4c-5b6a-4978-8a9b-0c1d2e3f4a5b",
+            "room_id": "3a7b9c1d-2e4f-4a6b-8c0d-1e2f3a4b5c6d",
+            "content": "hi",
+            "created_at": "2023-10-21T10:00:00Z"
+        }))
+        .unwrap();
+
+        assert_eq!(message.kind, MessageKind::User);
+    }
+
+    #[test]
+    fn kinds_from_newer_servers_are_user_messages() {
+        let message: Message = serde_json::from_value(serde_json::json!({
+            "id": "5e6f7a8b-9c0d-4e1f-a2b3-c4d5e6f7a8b9",
+            "user_id": "1f2e3d4c-5b6a-4978-8a9b-0c1d2e3f4a5b",
+            "room_id": "3a7b9c1d-2e4f-4a6b-8c0d-1e2f3a4b5c6d",
+            "content": "hi",
+            "created_at": "2023-10-21T10:00:00Z",
+            "kind": "poll"
+        }))
+        .unwrap();
+
+        assert_eq!(message.kind, MessageKind::User);
+    }
+
+    #[test]
+    fn system_messages_keep_their_kind() {
+        let kind: MessageKind = serde_json::from_value(serde_json::json!("system")).unwrap();
+
+        assert_eq!(kind, MessageKind::System);
+        assert_eq!(serde_json::to_value(kind).unwrap(), "system");
+    }
+}
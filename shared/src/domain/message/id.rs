@@ -2,7 +2,7 @@ use std::str::FromStr;
 
 use crate::domain;
 
-#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct MessageId(uuid::Uuid);
 
 impl FromStr for MessageId {
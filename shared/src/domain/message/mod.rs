@@ -3,6 +3,7 @@ mod entity;
 mod id;
 
 pub use entity::Message;
+pub use entity::MessageKind;
 pub use entity::NewMessage;
 
 pub use content::MessageContent;
@@ -1,5 +1,11 @@
+//! Binary formats such as bincode encode enum variants by their position. New variants of
+//! the enums sent between server and clients always go last, existing ones are never
+//! reordered or removed.
+
+mod attachment;
 mod error;
 mod message;
+mod notification;
 mod room;
 mod user;
 mod utils;
@@ -17,10 +23,18 @@ pub use user::UserName;
 
 pub use room::Room;
 pub use room::RoomCode;
+pub use room::RoomDescription;
 pub use room::RoomId;
 pub use room::RoomName;
+pub use room::RoomTopic;
+
+pub use attachment::AttachmentId;
 
 pub use message::Message;
 pub use message::MessageContent;
 pub use message::MessageId;
+pub use message::MessageKind;
 pub use message::NewMessage;
+
+pub use notification::Notification;
+pub use notification::NotificationId;
@@ -0,0 +1,16 @@
+use chrono::{DateTime, Utc};
+
+use crate::domain::{MessageId, RoomId, UserId};
+
+use super::NotificationId;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Notification {
+    pub id: NotificationId,
+    pub user_id: UserId,
+    pub sender_id: UserId,
+    pub room_id: RoomId,
+    pub message_id: MessageId,
+    pub created_at: DateTime<Utc>,
+    pub read_at: Option<DateTime<Utc>>,
+}
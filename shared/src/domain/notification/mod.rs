@@ -0,0 +1,6 @@
+mod entity;
+mod id;
+
+pub use entity::Notification;
+
+pub use id::NotificationId;
@@ -5,7 +5,7 @@ use crate::domain;
 
 const MAX_ROOM_CODE_SIZE: usize = 255;
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
 pub struct RoomCode(String);
 
 impl FromStr for RoomCode {
@@ -0,0 +1,65 @@
+use std::str::FromStr;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::domain;
+
+const MAX_ROOM_DESCRIPTION_SIZE: usize = 2000;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, Clone)]
+pub struct RoomDescription(String);
+
+impl FromStr for RoomDescription {
+    type Err = domain::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.to_owned().try_into()
+    }
+}
+
+impl TryFrom<String> for RoomDescription {
+    type Error = domain::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let is_empty_or_whitespace = s.trim().is_empty();
+
+        let is_too_long = s.graphemes(true).count() > MAX_ROOM_DESCRIPTION_SIZE;
+
+        if is_empty_or_whitespace || is_too_long {
+            Err(domain::Error::ValidationError(format!(
+                "Descriptions are at most {} characters long and not blank.",
+                MAX_ROOM_DESCRIPTION_SIZE
+            )))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+impl AsRef<str> for RoomDescription {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod room_description_tests {
+    use super::*;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn a_size_grapheme_long_description_is_valid() {
+        let description = "a̐".repeat(MAX_ROOM_DESCRIPTION_SIZE);
+        assert_ok!(description.parse::<RoomDescription>());
+    }
+
+    #[test]
+    fn a_description_longer_than_size_graphemes_is_rejected() {
+        let description = "a".repeat(MAX_ROOM_DESCRIPTION_SIZE + 1);
+        assert_err!(description.parse::<RoomDescription>());
+    }
+
+    #[test]
+    fn whitespace_only_descriptions_are_rejected() {
+        assert_err!(" ".parse::<RoomDescription>());
+    }
+}
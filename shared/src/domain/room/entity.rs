@@ -1,8 +1,19 @@
-use super::{RoomCode, RoomId, RoomName};
+use chrono::{DateTime, Utc};
 
-#[derive(serde::Serialize, serde::Deserialize)]
+use crate::domain::{AttachmentId, UserId};
+
+use super::{RoomCode, RoomDescription, RoomId, RoomName, RoomTopic};
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct Room {
     pub id: RoomId,
     pub name: RoomName,
     pub code: RoomCode,
+    pub topic: Option<RoomTopic>,
+    pub description: Option<RoomDescription>,
+    /// Image attachment shown as the room's picture.
+    pub avatar: Option<AttachmentId>,
+    pub created_at: DateTime<Utc>,
+    /// `None` for rooms whose creator was deleted.
+    pub created_by: Option<UserId>,
 }
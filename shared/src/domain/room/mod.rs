@@ -1,10 +1,14 @@
 mod code;
+mod description;
 mod entity;
 mod id;
 mod name;
+mod topic;
 
 pub use entity::Room;
 
 pub use code::RoomCode;
+pub use description::RoomDescription;
 pub use id::RoomId;
 pub use name::RoomName;
+pub use topic::RoomTopic;
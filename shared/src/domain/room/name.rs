@@ -6,7 +6,7 @@ use crate::domain::utils::contains_forbidden_characters;
 
 const MAX_ROOM_NAME_SIZE: usize = 255;
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
 pub struct RoomName(String);
 
 impl FromStr for RoomName {
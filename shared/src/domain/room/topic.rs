@@ -0,0 +1,65 @@
+use std::str::FromStr;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::domain;
+
+const MAX_ROOM_TOPIC_SIZE: usize = 255;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, Clone)]
+pub struct RoomTopic(String);
+
+impl FromStr for RoomTopic {
+    type Err = domain::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.to_owned().try_into()
+    }
+}
+
+impl TryFrom<String> for RoomTopic {
+    type Error = domain::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let is_empty_or_whitespace = s.trim().is_empty();
+
+        let is_too_long = s.graphemes(true).count() > MAX_ROOM_TOPIC_SIZE;
+
+        if is_empty_or_whitespace || is_too_long {
+            Err(domain::Error::ValidationError(format!(
+                "Topics are at most {} characters long and not blank.",
+                MAX_ROOM_TOPIC_SIZE
+            )))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+impl AsRef<str> for RoomTopic {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod room_topic_tests {
+    use super::*;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn a_size_grapheme_long_topic_is_valid() {
+        let topic = "a̐".repeat(MAX_ROOM_TOPIC_SIZE);
+        assert_ok!(topic.parse::<RoomTopic>());
+    }
+
+    #[test]
+    fn a_topic_longer_than_size_graphemes_is_rejected() {
+        let topic = "a".repeat(MAX_ROOM_TOPIC_SIZE + 1);
+        assert_err!(topic.parse::<RoomTopic>());
+    }
+
+    #[test]
+    fn whitespace_only_topics_are_rejected() {
+        assert_err!(" ".parse::<RoomTopic>());
+    }
+}
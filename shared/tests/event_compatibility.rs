@@ -0,0 +1,329 @@
+//! Golden fixtures for the socket protocol. Every event variant has a JSON fixture under
+//! `tests/fixtures/events` and its bincode encoding under `tests/fixtures/bincode`, a
+//! variant that no longer matches its fixtures breaks clients already deployed.
+
+use std::{fmt::Write, fs, path::PathBuf, time::Duration};
+
+use serde::{de::DeserializeOwned, Serialize};
+use shared::domain::event::{
+    Capability, ClientEvent, ErrorCode, Hello, ServerError, ServerEvent, StreamClientEvent,
+    StreamServerEvent, Tolerant,
+};
+
+fn fixtures_dir(kind: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/events")
+        .join(kind)
+}
+
+fn read_fixture(path: &PathBuf) -> serde_json::Value {
+    let raw = fs::read_to_string(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    serde_json::from_str(&raw).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+/// Decodes every fixture of `kind`, checks it is the variant its file is named after and
+/// that encoding it again yields the same JSON. Returns the variants found.
+fn assert_fixtures_round_trip<E>(kind: &str, variant: fn(&E) -> &'static str) -> Vec<String>
+where
+    E: Serialize + DeserializeOwned,
+{
+    let mut found = Vec::new();
+    for entry in fs::read_dir(fixtures_dir(kind)).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_stem().unwrap().to_str().unwrap().to_string();
+        let golden = read_fixture(&path);
+
+        let event: E = serde_json::from_value(golden.clone())
+            .unwrap_or_else(|e| panic!("{}/{} no longer decodes: {}", kind, name, e));
+        assert_eq!(
+            variant(&event),
+            name,
+            "{}/{} decodes to another variant",
+            kind,
+            name
+        );
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            golden,
+            "{}/{} encodes differently",
+            kind,
+            name
+        );
+
+        found.push(name);
+    }
+    found.sort();
+    found
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// Encodes every JSON fixture of `kind` with bincode and checks the bytes against the
+/// bincode fixture of the same name, which must decode to the same variant.
+fn assert_bincode_fixtures_match<E>(kind: &str, variant: fn(&E) -> &'static str)
+where
+    E: Serialize + DeserializeOwned,
+{
+    let bincode_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/bincode")
+        .join(kind);
+    for entry in fs::read_dir(fixtures_dir(kind)).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_stem().unwrap().to_str().unwrap().to_string();
+        let event: E = serde_json::from_value(read_fixture(&path)).unwrap();
+
+        let golden_path = bincode_dir.join(format!("{}.hex", name));
+        let golden = fs::read_to_string(&golden_path)
+            .unwrap_or_else(|e| panic!("{}: {}", golden_path.display(), e));
+        let golden = golden.trim();
+
+        assert_eq!(
+            to_hex(&bincode::serialize(&event).unwrap()),
+            golden,
+            "{}/{} encodes differently with bincode",
+            kind,
+            name
+        );
+        let decoded: E = bincode::deserialize(&from_hex(golden))
+            .unwrap_or_else(|e| panic!("{}/{} no longer decodes with bincode: {}", kind, name, e));
+        assert_eq!(
+            variant(&decoded),
+            name,
+            "{}/{} decodes to another variant",
+            kind,
+            name
+        );
+    }
+}
+
+fn sorted(names: &[&str]) -> Vec<String> {
+    let mut names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+    names.sort();
+    names
+}
+
+fn client_variant(event: &ClientEvent) -> &'static str {
+    match event {
+        ClientEvent::Hello(_) => "Hello",
+        ClientEvent::Join(_) => "Join",
+        ClientEvent::SendMessage(_) => "SendMessage",
+    }
+}
+
+fn server_variant(event: &ServerEvent) -> &'static str {
+    match event {
+        ServerEvent::Welcome(_) => "Welcome",
+        ServerEvent::ErrMessage(_) => "ErrMessage",
+        ServerEvent::Join(_) => "Join",
+        ServerEvent::UserJoin(_) => "UserJoin",
+        ServerEvent::ReceivedMessage(_) => "ReceivedMessage",
+        ServerEvent::Notification(_) => "Notification",
+        ServerEvent::SessionRevoked(_) => "SessionRevoked",
+        ServerEvent::CommandReply(_) => "CommandReply",
+        ServerEvent::RoomUpdated(_) => "RoomUpdated",
+        ServerEvent::RemovedFromRoom(_) => "RemovedFromRoom",
+    }
+}
+
+fn stream_client_variant(event: &StreamClientEvent) -> &'static str {
+    match event {
+        StreamClientEvent::Hello(_) => "Hello",
+        StreamClientEvent::Subscribe(_) => "Subscribe",
+        StreamClientEvent::Unsubscribe(_) => "Unsubscribe",
+        StreamClientEvent::Room(_) => "Room",
+    }
+}
+
+fn stream_server_variant(event: &StreamServerEvent) -> &'static str {
+    match event {
+        StreamServerEvent::Welcome(_) => "Welcome",
+        StreamServerEvent::Subscribed(_) => "Subscribed",
+        StreamServerEvent::Unsubscribed(_) => "Unsubscribed",
+        StreamServerEvent::Room(_) => "Room",
+        StreamServerEvent::User(_) => "User",
+    }
+}
+
+#[test]
+fn client_events_match_golden_fixtures() {
+    let found = assert_fixtures_round_trip("client", client_variant);
+    assert_eq!(found, sorted(&["Hello", "Join", "SendMessage"]));
+}
+
+#[test]
+fn server_events_match_golden_fixtures() {
+    let found = assert_fixtures_round_trip("server", server_variant);
+    assert_eq!(
+        found,
+        sorted(&[
+            "Welcome",
+            "ErrMessage",
+            "Join",
+            "UserJoin",
+            "ReceivedMessage",
+            "Notification",
+            "SessionRevoked",
+            "CommandReply",
+            "RoomUpdated",
+            "RemovedFromRoom",
+        ])
+    );
+}
+
+#[test]
+fn stream_client_events_match_golden_fixtures() {
+    let found = assert_fixtures_round_trip("stream_client", stream_client_variant);
+    assert_eq!(
+        found,
+        sorted(&["Hello", "Subscribe", "Unsubscribe", "Room"])
+    );
+}
+
+#[test]
+fn stream_server_events_match_golden_fixtures() {
+    let found = assert_fixtures_round_trip("stream_server", stream_server_variant);
+    assert_eq!(
+        found,
+        sorted(&["Welcome", "Subscribed", "Unsubscribed", "Room", "User"])
+    );
+}
+
+#[test]
+fn events_match_bincode_fixtures() {
+    assert_bincode_fixtures_match("client", client_variant);
+    assert_bincode_fixtures_match("server", server_variant);
+    assert_bincode_fixtures_match("stream_client", stream_client_variant);
+    assert_bincode_fixtures_match("stream_server", stream_server_variant);
+}
+
+#[test]
+fn unknown_events_are_tolerated() {
+    let tagged = read_fixture(&fixtures_dir("unknown").join("tagged.json"));
+    let unit = read_fixture(&fixtures_dir("unknown").join("unit.json"));
+
+    let Tolerant::Unknown(tagged) =
+        serde_json::from_value::<Tolerant<ServerEvent>>(tagged).unwrap()
+    else {
+        panic!("unknown tagged event decoded as a known one");
+    };
+    let Tolerant::Unknown(unit) = serde_json::from_value::<Tolerant<ClientEvent>>(unit).unwrap()
+    else {
+        panic!("unknown unit event decoded as a known one");
+    };
+
+    assert_eq!(tagged.name.as_deref(), Some("Typing"));
+    assert_eq!(unit.name.as_deref(), Some("Ping"));
+}
+
+#[test]
+fn known_events_decode_through_tolerant() {
+    let golden = read_fixture(&fixtures_dir("server").join("ReceivedMessage.json"));
+
+    let event = serde_json::from_value::<Tolerant<ServerEvent>>(golden)
+        .unwrap()
+        .known();
+
+    assert!(matches!(event, Some(ServerEvent::ReceivedMessage(_))));
+}
+
+#[test]
+fn unknown_capabilities_from_newer_clients_are_tolerated() {
+    let golden = read_fixture(&fixtures_dir("unknown").join("hello_with_unknown_capability.json"));
+
+    let ClientEvent::Hello(Hello {
+        version,
+        capabilities,
+    }) = serde_json::from_value(golden).unwrap()
+    else {
+        panic!("hello decoded to another variant");
+    };
+
+    assert_eq!(version, 2);
+    assert_eq!(
+        capabilities,
+        vec![Capability::Notifications, Capability::Unknown]
+    );
+}
+
+#[test]
+fn errors_from_servers_without_codes_are_tolerated() {
+    let golden = read_fixture(&fixtures_dir("unknown").join("legacy_error.json"));
+
+    let ServerEvent::ErrMessage(error) = serde_json::from_value(golden).unwrap() else {
+        panic!("error decoded to another variant");
+    };
+
+    assert_eq!(error.code, ErrorCode::Other);
+    assert_eq!(error.retry_after_ms, None);
+}
+
+#[test]
+fn errors_round_trip_through_bincode() {
+    for error in [
+        ServerError::new(ErrorCode::NotSubscribed, "Not subscribed to room."),
+        ServerError::rate_limited(Duration::from_millis(1500)),
+    ] {
+        let decoded: ServerError = bincode::deserialize(&bincode::serialize(&error).unwrap())
+            .unwrap_or_else(|e| panic!("{:?} no longer decodes: {}", error.code, e));
+
+        assert_eq!(decoded.code, error.code);
+        assert_eq!(decoded.retry_after_ms, error.retry_after_ms);
+    }
+}
+
+#[test]
+fn errors_decode_on_binary_clients_from_before_error_codes() {
+    #[derive(serde::Deserialize)]
+    struct LegacyServerError {
+        message: String,
+    }
+
+    let error = ServerError::rate_limited(Duration::from_secs(1));
+    let legacy: LegacyServerError = bincode::deserialize(&bincode::serialize(&error).unwrap())
+        .expect("legacy error no longer decodes");
+
+    assert_eq!(legacy.message, error.message);
+}
+
+#[test]
+fn error_codes_keep_their_binary_positions() {
+    let codes = [
+        ErrorCode::NotFound,
+        ErrorCode::NotSubscribed,
+        ErrorCode::RateLimited,
+        ErrorCode::UnsupportedEvent,
+        ErrorCode::UnsupportedVersion,
+        ErrorCode::Other,
+        ErrorCode::Forbidden,
+    ];
+
+    for (position, code) in codes.iter().enumerate() {
+        assert_eq!(
+            bincode::serialize(code).unwrap(),
+            (position as u32).to_le_bytes(),
+            "{:?} moved",
+            code
+        );
+    }
+}
+
+#[test]
+fn error_codes_from_newer_servers_decode_as_other() {
+    let json: ErrorCode = serde_json::from_str(r#""Banned""#).unwrap();
+    let binary: ErrorCode = bincode::deserialize(&99u32.to_le_bytes()).unwrap();
+
+    assert_eq!(json, ErrorCode::Other);
+    assert_eq!(binary, ErrorCode::Other);
+}